@@ -0,0 +1 @@
+complete -W "--help --version" hello-cli
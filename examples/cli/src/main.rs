@@ -0,0 +1,9 @@
+fn main() {
+    let name = std::env::args().nth(1);
+    match name.as_deref() {
+        Some("--version") => println!("hello-cli {}", env!("HELLO_VERSION")),
+        Some("--help") => println!("Usage: hello-cli [NAME]\n\nPrints a greeting."),
+        Some(name) => println!("Hello, {name}!"),
+        None => println!("Hello, world!"),
+    }
+}
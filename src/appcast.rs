@@ -0,0 +1,197 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::Sha256;
+
+use crate::{
+    atomic,
+    checksum::digest_file,
+    conf::{AppcastFormat, ShipConfig, Target},
+    error::{Context, ShipError},
+    gen_::{Artifacts, Generator},
+};
+
+/// Writes an update feed describing the artifacts built earlier in the same run
+pub struct AppcastGenerator<'a> {
+    pub conf: &'a ShipConfig,
+    pub built: &'a [(Target, PathBuf)],
+}
+
+#[derive(Serialize)]
+struct Manifest {
+    name: String,
+    version: Option<String>,
+    pub_date: String,
+    artifacts: Vec<ManifestArtifact>,
+}
+
+#[derive(Serialize)]
+struct ManifestArtifact {
+    target: Target,
+    os: &'static str,
+    file: String,
+    url: String,
+    size: u64,
+    sha256: String,
+}
+
+impl<'a> AppcastGenerator<'a> {
+    pub fn new(conf: &'a ShipConfig, built: &'a [(Target, PathBuf)]) -> Self {
+        Self { conf, built }
+    }
+
+    fn format(&self) -> AppcastFormat {
+        self.conf
+            .appcast
+            .as_ref()
+            .and_then(|a| a.format.clone())
+            .unwrap_or(AppcastFormat::Json)
+    }
+
+    fn output_path(&self) -> PathBuf {
+        Path::new(&self.conf.out.bin).join(feed_file(self.conf))
+    }
+
+    fn manifest(&self) -> std::io::Result<Manifest> {
+        let base_url = self
+            .conf
+            .appcast
+            .as_ref()
+            .map(|a| a.base_url.trim_end_matches('/'))
+            .unwrap_or_default();
+
+        let mut artifacts = Vec::new();
+        for (target, path) in self.built {
+            let file = path
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            artifacts.push(ManifestArtifact {
+                target: target.clone(),
+                os: target_os(target),
+                url: format!("{base_url}/{file}"),
+                file,
+                size: std::fs::metadata(path)?.len(),
+                sha256: digest_file::<Sha256>(path)?,
+            });
+        }
+
+        Ok(Manifest {
+            name: self.conf.prog.name.clone(),
+            version: self.conf.prog.version.clone(),
+            pub_date: chrono::Utc::now().to_rfc2822(),
+            artifacts,
+        })
+    }
+}
+
+impl<'a> Generator for AppcastGenerator<'a> {
+    fn run(&self) -> Result<Artifacts, ShipError> {
+        if self.conf.appcast.is_none() {
+            return Err("the Appcast target requires an [appcast] section with base_url".into());
+        }
+        if self.built.is_empty() {
+            warn!("no artifacts were built, the update feed will be empty");
+        }
+
+        let manifest = self
+            .manifest()
+            .context(|| "failed to read built artifacts for the update feed".to_string())?;
+
+        let contents = match self.format() {
+            AppcastFormat::Json => serde_json::to_string_pretty(&manifest)
+                .context(|| "failed to serialize update manifest".to_string())?,
+            AppcastFormat::Sparkle => sparkle(&manifest),
+        };
+
+        let output_path = self.output_path();
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(|| format!("failed to create output directory {}", parent.display()))?;
+        }
+
+        atomic::write(&output_path, contents)
+            .context(|| format!("failed to write update feed at {}", output_path.display()))?;
+
+        Ok(vec![output_path])
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        vec![self.output_path()]
+    }
+}
+
+/// File name of the update feed, as clients download it
+pub fn feed_file(conf: &ShipConfig) -> String {
+    let appcast = conf.appcast.as_ref();
+    appcast.and_then(|a| a.file.clone()).unwrap_or_else(|| {
+        match appcast.and_then(|a| a.format.clone()) {
+            Some(AppcastFormat::Sparkle) => "appcast.xml".to_string(),
+            _ => "latest.json".to_string(),
+        }
+    })
+}
+
+/// Whether `contents`, an update feed, announces `version`
+pub fn feed_announces(contents: &str, version: &str) -> bool {
+    match serde_json::from_str::<serde_json::Value>(contents) {
+        Ok(manifest) => manifest.get("version").and_then(|v| v.as_str()) == Some(version),
+        Err(_) => contents.contains(&format!(
+            "<sparkle:version>{}</sparkle:version>",
+            escape(version)
+        )),
+    }
+}
+
+fn target_os(target: &Target) -> &'static str {
+    match target {
+        Target::Exe | Target::Msi => "windows",
+        Target::Dmg | Target::Pkg => "macos",
+        _ => "linux",
+    }
+}
+
+// Sparkle reads a single enclosure per item, so every artifact gets an item of its own and
+// clients skip the ones whose sparkle:os isn't theirs
+fn sparkle(manifest: &Manifest) -> String {
+    let version = escape(manifest.version.as_deref().unwrap_or_default());
+
+    let mut items = String::new();
+    for artifact in &manifest.artifacts {
+        items.push_str(&format!(
+            "    <item>\n\
+             \x20     <title>Version {version}</title>\n\
+             \x20     <pubDate>{pub_date}</pubDate>\n\
+             \x20     <sparkle:version>{version}</sparkle:version>\n\
+             \x20     <enclosure url=\"{url}\" length=\"{size}\" type=\"application/octet-stream\" \
+             sparkle:os=\"{os}\" ship:sha256=\"{sha256}\" />\n\
+             \x20   </item>\n",
+            pub_date = manifest.pub_date,
+            url = escape(&artifact.url),
+            size = artifact.size,
+            os = artifact.os,
+            sha256 = artifact.sha256,
+        ));
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <rss version=\"2.0\" xmlns:sparkle=\"http://www.andymatuschak.org/xml-namespaces/sparkle\" \
+         xmlns:ship=\"https://github.com/Gamer069/ship\">\n\
+         \x20 <channel>\n\
+         \x20   <title>{name}</title>\n\
+         {items}\
+         \x20 </channel>\n\
+         </rss>\n",
+        name = escape(&manifest.name),
+    )
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
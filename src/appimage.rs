@@ -1,8 +1,31 @@
-use std::path::{Path, PathBuf};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
 
 use appimage::AppImage;
+use glob::Pattern;
+use sha2::{Digest, Sha256};
 
-use crate::{conf::ShipConfig, gen_::Generator};
+use crate::{
+    atomic, bundle, cache,
+    checksum::{digest_file, to_hex},
+    conf::{AppRun, AppRunSettings, Arch, FileEntry, Remote, ShipConfig, Signing},
+    desktop,
+    error::{Context, ShipError},
+    gen_::{Artifacts, Generator, Plan},
+    globs, icon,
+    layout::{Entry, collect_dir, is_executable},
+    license, metainfo, remote,
+    scripts::{self, InstallScripts},
+    signing,
+};
+
+/// Where the AppDir is staged before it's packed, `<name>.AppDir` inside the work directory
+pub fn staging_dir(conf: &ShipConfig) -> PathBuf {
+    cache::work_dir(conf).join("appimage")
+}
 
 pub struct AppImageGenerator<'a> {
     pub conf: &'a ShipConfig,
@@ -26,138 +49,923 @@ impl<'a> AppImageGenerator<'a> {
                 file_name.push_str(version);
             }
             file_name.push('_');
-            file_name.push_str(&format!("{:?}", self.conf.prog.arch).to_lowercase());
+            file_name.push_str(&format!("{:?}", self.arch()).to_lowercase());
             file_name.push_str(".AppImage");
             return out.join(file_name);
         }
 
         out
     }
+
+    fn arch(&self) -> &'a Arch {
+        self.conf
+            .appimage
+            .as_ref()
+            .and_then(|a| a.arch.as_ref())
+            .unwrap_or(&self.conf.prog.arch)
+    }
+
+    /// The runtime to use instead of the appimage crate's, which is only built for x86_64
+    fn runtime(&self) -> Result<Option<PathBuf>, String> {
+        let settings = self.conf.appimage.as_ref();
+        let pinned = settings.and_then(|a| a.runtime_sha256.as_deref());
+        let source = match settings.and_then(|a| a.runtime.as_deref()) {
+            Some(runtime) => runtime.to_string(),
+            None => {
+                let arch = self.arch();
+                match arch.appimage() {
+                    Some("x86_64") => return Ok(None),
+                    Some(name) => format!("{RUNTIME_RELEASES}/runtime-{name}"),
+                    None => {
+                        return Err(format!(
+                            "there is no AppImage runtime for {arch:?}, set [appimage].runtime"
+                        ));
+                    }
+                }
+            }
+        };
+
+        let path = if !source.starts_with("https://") && !source.starts_with("http://") {
+            PathBuf::from(&source)
+        } else if let Some(sha256) = pinned {
+            let remote = Remote {
+                url: source.clone(),
+                sha256: sha256.to_string(),
+                to: None,
+            };
+            remote::fetch_all(std::slice::from_ref(&remote))?;
+            return Ok(Some(remote::cached_path(&remote)));
+        } else {
+            // unpinned downloads are kept by URL, deleting .ship/cache fetches the latest again
+            let path = cache::cache_dir().join("downloads").join(format!(
+                "runtime-{}",
+                to_hex(&Sha256::digest(source.as_bytes()))
+            ));
+            if !path.is_file() {
+                info!("downloading {source}...");
+                std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
+                    .map_err(|err| format!("failed to create the download cache: {err}"))?;
+                remote::download(&source, &path)?;
+            }
+            path
+        };
+
+        if !path.is_file() {
+            return Err(format!(
+                "AppImage runtime {} does not exist",
+                path.display()
+            ));
+        }
+        if let Some(expected) = pinned {
+            let actual = digest_file::<Sha256>(&path)
+                .map_err(|err| format!("failed to hash {}: {err}", path.display()))?;
+            if actual != expected.to_lowercase() {
+                return Err(format!(
+                    "AppImage runtime {} has sha256 {actual}, [appimage].runtime_sha256 expects {expected}",
+                    path.display()
+                ));
+            }
+        }
+        Ok(Some(path))
+    }
+
+    fn update_info(&self) -> Option<&str> {
+        self.conf.appimage.as_ref()?.update_info.as_deref()
+    }
+
+    // every zsync transport (`zsync|`, `gh-releases-zsync|`, ...) is updated from a .zsync
+    fn zsync_output_path(&self) -> Option<PathBuf> {
+        let transport = self.update_info()?.split('|').next()?;
+        if !transport.ends_with("zsync") {
+            return None;
+        }
+        let mut path = self.appimage_output_path().into_os_string();
+        path.push(".zsync");
+        Some(PathBuf::from(path))
+    }
+
+    fn signing(&self) -> Option<&'a Signing> {
+        self.conf
+            .signing
+            .as_ref()
+            .filter(|signing| signing.appimage != Some(false) && signing.uses_gpg())
+    }
+
+    /// The detached `<file>.sig` written when signing is on
+    fn signature_output_path(&self) -> Option<PathBuf> {
+        self.signing()?;
+        let mut path = self.appimage_output_path().into_os_string();
+        path.push(".sig");
+        Some(PathBuf::from(path))
+    }
+
+    /// AppRun as a script that runs `setup` before starting the program, instead of a link
+    fn write_apprun(&self, image: &AppImage, setup: &[String]) -> Result<(), String> {
+        let name = &self.conf.prog.name;
+        let path = image.appdir().join("AppRun");
+        let script = format!(
+            "#!/bin/sh\n\
+             APPDIR=\"${{APPDIR:-$(dirname \"$(readlink -f \"$0\")\")}}\"\n\
+             {}\n\
+             exec \"$APPDIR/{name}\" \"$@\"\n",
+            setup.join("\n")
+        );
+        std::fs::write(&path, script)
+            .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+        apply_mode(&path, 0o755)
+            .map_err(|err| format!("failed to mark {} executable: {err}", path.display()))
+    }
+
+    /// Puts a launcher in front of the main executable that runs `postinstall` once per user.
+    ///
+    /// The executable itself stays at `primary_to`, where `[files]` puts it.
+    fn add_first_run_wrapper(
+        &self,
+        image: &AppImage,
+        primary_to: &Path,
+        postinstall: &str,
+    ) -> Result<(), String> {
+        let name = &self.conf.prog.name;
+        let exe = primary_to.display();
+
+        let work_dir = cache::work_dir(self.conf).join("appimage").join(name);
+        std::fs::create_dir_all(&work_dir)
+            .map_err(|err| format!("failed to create {}: {err}", work_dir.display()))?;
+
+        let script_path = work_dir.join("postinstall");
+        std::fs::write(&script_path, postinstall)
+            .map_err(|err| format!("failed to write {}: {err}", script_path.display()))?;
+
+        let wrapper_path = work_dir.join(name);
+        let wrapper = format!(
+            "#!/bin/sh\n\
+             APPDIR=\"${{APPDIR:-$(dirname \"$(readlink -f \"$0\")\")}}\"\n\
+             state=\"${{XDG_STATE_HOME:-$HOME/.local/state}}/{name}\"\n\
+             if [ ! -e \"$state/first-run-done\" ]; then\n\
+             \x20   mkdir -p \"$state\"\n\
+             \x20   sh \"$APPDIR/usr/share/{name}/postinstall\" && touch \"$state/first-run-done\"\n\
+             fi\n\
+             exec \"$APPDIR/{exe}\" \"$@\"\n"
+        );
+        std::fs::write(&wrapper_path, wrapper)
+            .map_err(|err| format!("failed to write {}: {err}", wrapper_path.display()))?;
+        apply_mode(&wrapper_path, 0o755).map_err(|err| {
+            format!(
+                "failed to mark {} executable: {err}",
+                wrapper_path.display()
+            )
+        })?;
+
+        image
+            .add_file(&wrapper_path, Path::new(name))
+            .map_err(|err| err.to_string())?;
+        image
+            .add_file(
+                &script_path,
+                &Path::new("usr")
+                    .join("share")
+                    .join(name)
+                    .join("postinstall"),
+            )
+            .map_err(|err| err.to_string())?;
+
+        Ok(())
+    }
 }
 
 impl<'a> Generator for AppImageGenerator<'a> {
-    fn run(&self) {
+    fn run(&self) -> Result<Artifacts, ShipError> {
         let output_path = self.appimage_output_path();
-        let build_dir = output_path.parent().unwrap_or_else(|| Path::new("."));
+        let output_dir = output_path.parent().unwrap_or_else(|| Path::new("."));
+        let build_dir = staging_dir(self.conf);
 
-        std::fs::create_dir_all(build_dir).unwrap_or_else(|err| {
-            eprintln!(
-                "error: failed to create output directory {}: {err}",
-                build_dir.display()
-            );
-            std::process::exit(-1);
-        });
+        for dir in [output_dir, &build_dir] {
+            std::fs::create_dir_all(dir)
+                .context(|| format!("failed to create directory {}", dir.display()))?;
+        }
 
-        let image = AppImage::new(build_dir, self.conf.prog.name.clone()).unwrap_or_else(|err| {
-            eprintln!("error: failed to initialize AppImage build directory: {err}");
-            std::process::exit(-1);
-        });
+        let image = AppImage::new(&build_dir, self.conf.prog.name.clone())
+            .map_err(|err| err.to_string())
+            .context(|| "failed to initialize AppImage build directory".to_string())?;
 
-        let primary = self
-            .conf
-            .files
-            .paths
-            .iter()
-            .find(|path| {
-                let p = Path::new(path);
-                p.is_file()
-                    && p.file_name().and_then(|n| n.to_str()) == Some(self.conf.prog.name.as_str())
-            })
-            .or_else(|| {
-                self.conf
-                    .files
-                    .paths
-                    .iter()
-                    .find(|path| Path::new(path).is_file())
-            });
+        let exclude = globs::exclude_patterns(self.conf)?;
 
-        if let Some(primary) = primary {
-            image
-                .add_file(Path::new(primary), Path::new(&self.conf.prog.name))
-                .unwrap_or_else(|err| {
-                    eprintln!("error: failed to add main executable {primary} to AppImage: {err}");
-                    std::process::exit(-1);
-                });
-        } else {
-            eprintln!("error: no file entries found in [files].paths for AppImage target");
-            std::process::exit(-1);
+        let Some((primary, _)) = primary_executable(self.conf)? else {
+            return Err("no file entries found in [files] for AppImage target".into());
+        };
+        // [prog].main doesn't have to be listed in [files], then it goes to usr/bin
+        let primary_to = match primary_path(self.conf, &primary) {
+            Some(to) => to,
+            None => {
+                let to = Path::new("usr/bin").join(primary.file_name().unwrap_or_default());
+                image
+                    .add_file(&primary, &to)
+                    .map_err(|err| err.to_string())
+                    .context(|| {
+                        format!(
+                            "failed to add main executable {} to AppImage",
+                            primary.display()
+                        )
+                    })?;
+                to
+            }
+        };
+
+        // a staged root already has the AppDir layout (usr/bin, usr/share, ...)
+        if let Some(root) = &self.conf.files.root {
+            let entries = std::fs::read_dir(root)
+                .context(|| format!("failed to read [files].root {root}"))?;
+
+            for entry in entries.flatten() {
+                let from = entry.path();
+                let to = PathBuf::from(entry.file_name());
+
+                if from.is_dir() {
+                    add_tree(&image, &from, &to, &exclude)
+                        .context(|| format!("failed to add directory {:?} to AppImage", from))?;
+                } else {
+                    image
+                        .add_file(&from, &to)
+                        .map_err(|err| err.to_string())
+                        .context(|| format!("failed to add file {:?} to AppImage", from))?;
+                }
+            }
         }
 
         for file in &self.conf.files.paths {
-            let from = Path::new(file);
+            let from = Path::new(&file.from);
             let fname = match from.file_name() {
                 Some(name) => name,
                 None => {
-                    eprintln!("error: invalid path in [files].paths: {file}");
-                    std::process::exit(-1);
+                    return Err(format!("invalid path in [files].paths: {}", file.from).into());
                 }
             };
 
-            let to = Path::new("usr").join("bin").join(fname);
+            let to = appdir_path(self.conf, file, fname);
 
             if from.is_dir() {
-                image.add_directory(from, &to).unwrap_or_else(|err| {
-                    eprintln!("error: failed to add directory {:?} to AppImage: {err}", from);
-                    std::process::exit(-1);
-                });
+                add_tree(&image, from, &to, &exclude)
+                    .context(|| format!("failed to add directory {:?} to AppImage", from))?;
             } else {
-                image.add_file(from, &to).unwrap_or_else(|err| {
-                    eprintln!("error: failed to add file {:?} to AppImage: {err}", from);
-                    std::process::exit(-1);
-                });
+                image
+                    .add_file(from, &to)
+                    .map_err(|err| err.to_string())
+                    .context(|| format!("failed to add file {:?} to AppImage", from))?;
+            }
+
+            if let Some(mode) = file.mode {
+                let path = image.appdir().join(&to);
+                apply_mode(&path, mode)
+                    .context(|| format!("failed to set mode of {}", path.display()))?;
             }
         }
 
-        image.add_apprun().unwrap_or_else(|err| {
-            eprintln!("error: failed to create AppRun symlink: {err}");
-            std::process::exit(-1);
-        });
+        let scripts = InstallScripts::load(self.conf)?;
+        if let Some(postinstall) = &scripts.postinstall {
+            // AppImages are never installed, so postinstall runs on the first launch instead
+            self.add_first_run_wrapper(&image, &primary_to, postinstall)
+                .context(|| "failed to add first-run hook to AppImage".to_string())?;
+        } else {
+            // AppRun runs `<name>` at the root, the executable itself stays where it was put
+            link_primary(&image, &primary_to, &self.conf.prog.name)
+                .context(|| "failed to link the main executable into the AppDir".to_string())?;
+        }
+
+        // the AppDir stands in for /usr, so the copyright goes where a .deb would put it
+        if let Some(copyright) = license::write(self.conf)? {
+            let to = Path::new("usr/share").join(license::install_path(self.conf));
+            image
+                .add_file(&copyright, &to)
+                .map_err(|err| err.to_string())
+                .context(|| "failed to add license to AppImage".to_string())?;
+        }
+
+        // AppImages always have a desktop entry, so they're desktop applications
+        if self.conf.metadata.is_some() {
+            let metainfo = metainfo::write(self.conf, true)?;
+            let to = Path::new("usr/share").join(metainfo::install_path(self.conf));
+            image
+                .add_file(&metainfo, &to)
+                .map_err(|err| err.to_string())
+                .context(|| "failed to add metainfo to AppImage".to_string())?;
+        }
+
+        for r in &self.conf.files.remote {
+            let from = remote::cached_path(r);
+            let to = appdir_dest(self.conf, &r.install_path());
+
+            image
+                .add_file(&from, &to)
+                .map_err(|err| err.to_string())
+                .context(|| format!("failed to add file {} to AppImage", r.url))?;
+        }
+
+        // a bundled toolkit needs its environment set up first, see write_apprun
+        let profile = self.conf.appimage.as_ref().and_then(|a| a.bundle);
+        let apprun = self.conf.appimage.as_ref().and_then(|a| a.apprun.as_ref());
+        if profile.is_none() && apprun.is_none() {
+            image
+                .add_apprun()
+                .map_err(|err| err.to_string())
+                .context(|| "failed to create AppRun symlink".to_string())?;
+        }
 
-        image.add_desktop().unwrap_or_else(|err| {
-            eprintln!("error: failed to generate desktop entry: {err}");
-            std::process::exit(-1);
-        });
+        // the runtime launches AppRun whatever Exec says, it's only read by desktop integration
+        let name = &self.conf.prog.name;
+        let mut entry = desktop::render(self.conf, name, name);
+        if self
+            .conf
+            .desktop
+            .as_ref()
+            .is_none_or(|d| d.categories.is_empty())
+        {
+            // appimagetool and AppImageHub reject entries without any category
+            entry.push_str("Categories=Utility;\n");
+        }
+        let entry_path = desktop::generated_path(self.conf, &format!("{name}-appimage"));
+        if let Some(parent) = entry_path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(|| format!("failed to create {}", parent.display()))?;
+        }
+        std::fs::write(&entry_path, entry)
+            .context(|| format!("failed to write desktop entry {}", entry_path.display()))?;
+        image
+            .add_file(&entry_path, Path::new(&format!("{name}.desktop")))
+            .map_err(|err| err.to_string())
+            .context(|| "failed to add desktop entry to AppImage".to_string())?;
 
         let generated_icon_path = if let Some(icon) = &self.conf.files.icon {
-            image.add_icon(Path::new(icon)).unwrap_or_else(|err| {
-                eprintln!("error: failed to add icon {icon}: {err}");
-                std::process::exit(-1);
-            });
+            let name = &self.conf.prog.name;
+            let root_icon = icon::appimage_icon(self.conf, Path::new(icon), name)?;
+            image
+                .add_icon(&root_icon)
+                .map_err(|err| err.to_string())
+                .context(|| format!("failed to add icon {icon}"))?;
+            // desktop integration installs the icon theme's copies along with the entry
+            for themed in icon::theme_icons(self.conf, Path::new(icon), name)? {
+                let to = Path::new("usr/share/icons").join(&themed.themed);
+                image
+                    .add_file(&themed.source, &to)
+                    .map_err(|err| err.to_string())
+                    .context(|| format!("failed to add icon {}", to.display()))?;
+            }
             None
         } else {
-            let fallback = std::env::temp_dir().join(format!(
+            let fallback = build_dir.join(format!(
                 "{}-{}-fallback-icon.svg",
                 self.conf.prog.name,
                 std::process::id()
             ));
-            std::fs::write(&fallback, fallback_icon_svg(&self.conf.prog.name)).unwrap_or_else(
-                |err| {
-                    eprintln!(
-                        "error: failed to generate fallback icon at {}: {err}",
-                        fallback.display()
-                    );
-                    std::process::exit(-1);
-                },
-            );
-            image.add_icon(&fallback).unwrap_or_else(|err| {
-                eprintln!("error: failed to add fallback icon: {err}");
-                std::process::exit(-1);
-            });
+            std::fs::write(&fallback, fallback_icon_svg(&self.conf.prog.name)).context(|| {
+                format!("failed to generate fallback icon at {}", fallback.display())
+            })?;
+            image
+                .add_icon(&fallback)
+                .map_err(|err| err.to_string())
+                .context(|| "failed to add fallback icon".to_string())?;
             Some(fallback)
         };
 
-        image.build(&output_path, None).unwrap_or_else(|err| {
-            eprintln!(
-                "error: failed to build AppImage at {}: {err}",
-                output_path.display()
-            );
-            std::process::exit(-1);
-        });
+        let mut setup = Vec::new();
+        if profile.is_some() || self.conf.appimage.as_ref().is_some_and(|a| a.bundle_libs) {
+            let bundled = bundle::bundle_libraries(image.appdir(), &self.conf.prog.name, profile)
+                .context(|| "failed to bundle shared libraries".to_string())?;
+            debug!("bundled {} into the AppDir", bundled.libraries.join(", "));
+            setup = bundled.setup;
+        }
+
+        match apprun {
+            Some(AppRun::Script(script)) => {
+                if !setup.is_empty() {
+                    warn!(
+                        "[appimage].apprun replaces the generated AppRun, it has to set up the bundled plugins itself"
+                    );
+                }
+                let to = image.appdir().join("AppRun");
+                std::fs::copy(script, &to).context(|| format!("failed to add AppRun {script}"))?;
+                apply_mode(&to, 0o755)
+                    .context(|| format!("failed to mark {} executable", to.display()))?;
+            }
+            Some(AppRun::Generated(settings)) => {
+                setup.extend(apprun_setup(settings)?);
+                self.write_apprun(&image, &setup)
+                    .context(|| "failed to write AppRun".to_string())?;
+            }
+            None if profile.is_some() => self
+                .write_apprun(&image, &setup)
+                .context(|| "failed to write AppRun".to_string())?,
+            None => {}
+        }
+
+        let update_info = self.update_info();
+        if let Some(info) = update_info
+            && info.len() >= UPDATE_INFO_SIZE
+        {
+            return Err(format!(
+                "[appimage].update_info is {} bytes, the runtime has room for {}",
+                info.len(),
+                UPDATE_INFO_SIZE - 1
+            )
+            .into());
+        }
+
+        let runtime = self.runtime()?;
+        atomic::write_with(&output_path, |tmp| {
+            image
+                .build(tmp, None)
+                .map_err(|err| std::io::Error::other(err.to_string()))?;
+            if let Some(runtime) = &runtime {
+                swap_runtime(tmp, runtime)?;
+            }
+            match update_info {
+                Some(info) => embed_update_info(tmp, info),
+                None => Ok(()),
+            }
+        })
+        .context(|| format!("failed to build AppImage at {}", output_path.display()))?;
+        // signed once complete, the update information is part of what's signed
+        if let Some(signing) = self.signing() {
+            embed_signature(self.conf, signing, &output_path)?;
+        }
 
         if let Some(path) = generated_icon_path {
             std::fs::remove_file(path).ok();
         }
+
+        let mut artifacts = vec![output_path];
+        if let (Some(signing), Some(signature)) = (self.signing(), self.signature_output_path()) {
+            signing::detach_sign(signing, &artifacts[0], &signature, false)?;
+            artifacts.push(signature);
+        }
+        // after signing, so the .zsync describes the file as released
+        if let Some(zsync) = self.zsync_output_path() {
+            write_zsync(&artifacts[0], &zsync)?;
+            artifacts.push(zsync);
+        }
+        Ok(artifacts)
     }
+
+    fn plan(&self) -> Result<Plan, ShipError> {
+        let Some((primary, _)) = primary_executable(self.conf)? else {
+            return Err("no file entries found in [files] for AppImage target".into());
+        };
+        let appdir = format!("{}.AppDir", self.conf.prog.name);
+        let entry = |source: PathBuf, to: &Path| Entry {
+            dest: format!("{appdir}/{}", to.display()),
+            source,
+            mode: None,
+            owner: None,
+            group: None,
+        };
+
+        let mut files = Vec::new();
+        let primary_to = match primary_path(self.conf, &primary) {
+            Some(to) => to,
+            None => {
+                let to = Path::new("usr/bin").join(primary.file_name().unwrap_or_default());
+                files.push(entry(primary.clone(), &to));
+                to
+            }
+        };
+        if let Some(root) = &self.conf.files.root {
+            files.push(entry(PathBuf::from(root), Path::new("")));
+        }
+        for file in &self.conf.files.paths {
+            let from = Path::new(&file.from);
+            let Some(fname) = from.file_name() else {
+                return Err(format!("invalid path in [files].paths: {}", file.from).into());
+            };
+            files.push(Entry {
+                mode: file.mode,
+                ..entry(from.to_path_buf(), &appdir_path(self.conf, file, fname))
+            });
+        }
+        for r in &self.conf.files.remote {
+            let to = appdir_dest(self.conf, &r.install_path());
+            files.push(entry(remote::cached_path(r), &to));
+        }
+
+        let mut notes = vec![
+            "AppRun, the desktop entry and the icon at the root of the AppDir are generated while building"
+                .to_string(),
+        ];
+        let appimage = self.conf.appimage.as_ref();
+        if appimage.is_some_and(|a| a.bundle_libs || a.bundle.is_some()) {
+            notes.push(
+                "adds the non-system libraries the executables link against to usr/lib".to_string(),
+            );
+        }
+        let scripts: Vec<(String, String)> =
+            scripts::planned(self.conf, ["", "AppRun, on the first launch", "", ""])
+                .into_iter()
+                .filter(|(name, _)| !name.is_empty())
+                .collect();
+        notes.push(format!("AppRun starts {}", primary_to.display()));
+
+        Ok(Plan {
+            artifacts: self.artifacts(),
+            files,
+            scripts,
+            notes,
+            ..Default::default()
+        })
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        let mut artifacts = vec![self.appimage_output_path()];
+        artifacts.extend(self.signature_output_path());
+        artifacts.extend(self.zsync_output_path());
+        artifacts
+    }
+}
+
+/// Size of the runtime's `.upd_info` section, including the terminating NUL
+const UPDATE_INFO_SIZE: usize = 1024;
+
+/// Writes `info` into the `.upd_info` section of the AppImage runtime at the start of
+/// `path`, where AppImageUpdate looks for it
+fn embed_update_info(path: &Path, info: &str) -> std::io::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+    write_section(&mut file, ".upd_info", info.as_bytes())
+}
+
+/// Upstream's builds of the type-2 runtime, `runtime-<arch>`
+const RUNTIME_RELEASES: &str =
+    "https://github.com/AppImage/type2-runtime/releases/download/continuous";
+
+/// Puts `runtime` in front of the AppImage at `path` in place of the one it was built with.
+/// The runtime finds the squashfs right where its own ELF ends, so that's where the old one
+/// is cut off.
+fn swap_runtime(path: &Path, runtime: &Path) -> std::io::Result<()> {
+    use std::io::Error;
+
+    let image = std::fs::read(path)?;
+    let old_size = ElfHeader::parse(&image)
+        .map(|elf| elf.size() as usize)
+        .filter(|size| *size <= image.len())
+        .ok_or_else(|| Error::other("the built AppImage does not start with an ELF runtime"))?;
+    let mut swapped = std::fs::read(runtime)?;
+    if ElfHeader::parse(&swapped).is_none() {
+        return Err(Error::other(format!(
+            "AppImage runtime {} is not a little-endian ELF",
+            runtime.display()
+        )));
+    }
+    swapped.extend_from_slice(&image[old_size..]);
+    std::fs::write(path, swapped)
+}
+
+/// Where the section headers of a little-endian ELF are, 32-bit for the i686 and armhf
+/// runtimes and 64-bit otherwise
+struct ElfHeader {
+    is_64: bool,
+    sh_offset: u64,
+    sh_size: u64,
+    sh_count: u64,
+    names_index: u64,
+}
+
+impl ElfHeader {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.get(..4)? != b"\x7fELF" || *data.get(5)? != 1 {
+            return None;
+        }
+        let u16_at =
+            |at: usize| Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?) as u64);
+        match data.get(4)? {
+            1 => Some(Self {
+                is_64: false,
+                sh_offset: u32::from_le_bytes(data.get(0x20..0x24)?.try_into().ok()?) as u64,
+                sh_size: u16_at(0x2e)?,
+                sh_count: u16_at(0x30)?,
+                names_index: u16_at(0x32)?,
+            }),
+            2 => Some(Self {
+                is_64: true,
+                sh_offset: u64::from_le_bytes(data.get(0x28..0x30)?.try_into().ok()?),
+                sh_size: u16_at(0x3a)?,
+                sh_count: u16_at(0x3c)?,
+                names_index: u16_at(0x3e)?,
+            }),
+            _ => None,
+        }
+    }
+
+    // the section headers come last, as in every AppImage runtime
+    fn size(&self) -> u64 {
+        self.sh_offset + self.sh_size * self.sh_count
+    }
+
+    /// Offset and size in the file of the section whose header is `entry`
+    fn section(&self, entry: &[u8]) -> (u64, u64) {
+        let u32_at =
+            |at: usize| u32::from_le_bytes(entry[at..at + 4].try_into().unwrap_or_default()) as u64;
+        let u64_at =
+            |at: usize| u64::from_le_bytes(entry[at..at + 8].try_into().unwrap_or_default());
+        if self.is_64 {
+            (u64_at(0x18), u64_at(0x20))
+        } else {
+            (u32_at(0x10), u32_at(0x14))
+        }
+    }
+}
+
+/// Offset and size of the runtime's section called `name`
+fn find_section(file: &mut std::fs::File, name: &str) -> std::io::Result<(u64, u64)> {
+    use std::io::{Error, Read, Seek, SeekFrom};
+
+    let mut header = [0u8; 64];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    let elf = ElfHeader::parse(&header)
+        .ok_or_else(|| Error::other("the AppImage runtime is not a little-endian ELF"))?;
+
+    let mut sections = vec![0u8; (elf.sh_size * elf.sh_count) as usize];
+    file.seek(SeekFrom::Start(elf.sh_offset))?;
+    file.read_exact(&mut sections)?;
+    let entry = |index: u64| &sections[(index * elf.sh_size) as usize..][..elf.sh_size as usize];
+
+    let (names_offset, names_size) = elf.section(entry(elf.names_index));
+    let mut names = vec![0u8; names_size as usize];
+    file.seek(SeekFrom::Start(names_offset))?;
+    file.read_exact(&mut names)?;
+
+    (0..elf.sh_count)
+        .find(|index| {
+            let name_at =
+                u32::from_le_bytes(entry(*index)[..4].try_into().unwrap_or_default()) as usize;
+            names
+                .get(name_at..)
+                .and_then(|rest| rest.split(|b| *b == 0).next())
+                == Some(name.as_bytes())
+        })
+        .map(|index| elf.section(entry(index)))
+        .ok_or_else(|| Error::other(format!("the AppImage runtime has no {name} section")))
+}
+
+// `contents` padded with NULs to fill the section
+fn write_section(file: &mut std::fs::File, name: &str, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::{Error, Seek, SeekFrom, Write};
+
+    let (offset, size) = find_section(file, name)?;
+    if contents.len() as u64 >= size {
+        return Err(Error::other(format!(
+            "{} bytes don't fit the runtime's {size} byte {name} section",
+            contents.len()
+        )));
+    }
+    let mut padded = contents.to_vec();
+    padded.resize(size as usize, 0);
+    file.seek(SeekFrom::Start(offset))?;
+    file.write_all(&padded)
+}
+
+/// Signs the AppImage at `path` the way appimagetool does: the SHA-256 of the file, with
+/// the `.sha256_sig` and `.sig_key` sections read as zeros, is signed as a hex string, and
+/// the armored signature and public key go into those sections for
+/// `validate` and AppImageUpdate to check
+fn embed_signature(conf: &ShipConfig, signing: &Signing, path: &Path) -> Result<(), String> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let failed = |err: std::io::Error| format!("failed to sign AppImage {}: {err}", path.display());
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(failed)?;
+    let skipped = [
+        find_section(&mut file, ".sha256_sig").map_err(failed)?,
+        find_section(&mut file, ".sig_key").map_err(failed)?,
+    ];
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut position = 0u64;
+    file.seek(SeekFrom::Start(0)).map_err(failed)?;
+    loop {
+        let read = file.read(&mut buffer).map_err(failed)?;
+        if read == 0 {
+            break;
+        }
+        let chunk = &mut buffer[..read];
+        for (offset, size) in skipped {
+            let start = offset.max(position);
+            let end = (offset + size).min(position + read as u64);
+            if start < end {
+                chunk[(start - position) as usize..(end - position) as usize].fill(0);
+            }
+        }
+        hasher.update(&chunk[..]);
+        position += read as u64;
+    }
+    let digest = to_hex(&hasher.finalize());
+
+    let work_dir = cache::work_dir(conf).join("appimage");
+    std::fs::create_dir_all(&work_dir).map_err(failed)?;
+    let digest_path = work_dir.join("digest");
+    let signature_path = work_dir.join("digest.asc");
+    std::fs::write(&digest_path, &digest).map_err(failed)?;
+    signing::detach_sign(signing, &digest_path, &signature_path, true)?;
+    let signature = std::fs::read(&signature_path).map_err(failed)?;
+    let key = signing::public_key(signing)?;
+
+    write_section(&mut file, ".sha256_sig", &signature).map_err(failed)?;
+    write_section(&mut file, ".sig_key", key.as_bytes()).map_err(failed)
+}
+
+/// Makes the `.zsync` file AppImageUpdate downloads to update `appimage` by delta
+fn write_zsync(appimage: &Path, zsync: &Path) -> Result<(), String> {
+    // relative to the .zsync file, which is published next to the AppImage
+    let url = appimage
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let output = crate::log::command(
+        Command::new("zsyncmake")
+            .arg("-u")
+            .arg(&url)
+            .arg("-o")
+            .arg(zsync)
+            .arg(appimage)
+            .stdin(Stdio::null()),
+    )
+    .output()
+    .map_err(|err| {
+        format!("failed to run zsyncmake, which makes the .zsync for [appimage].update_info: {err}")
+    })?;
+    if !output.status.success() {
+        return Err(format!(
+            "zsyncmake failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+// relative, so it still points into the AppDir once that's mounted
+#[cfg(unix)]
+fn link_primary(image: &AppImage, primary_to: &Path, name: &str) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(primary_to, image.appdir().join(name))
+}
+
+#[cfg(not(unix))]
+fn link_primary(image: &AppImage, primary_to: &Path, name: &str) -> std::io::Result<()> {
+    std::fs::copy(image.appdir().join(primary_to), image.appdir().join(name)).map(|_| ())
+}
+
+/// Sets `mode` on the file, or on every file below the directory
+#[cfg(unix)]
+fn apply_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            apply_mode(&entry?.path(), mode)?;
+        }
+        return Ok(());
+    }
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+#[cfg(not(unix))]
+fn apply_mode(_path: &Path, _mode: u32) -> std::io::Result<()> {
+    Ok(())
+}
+
+// env values stay in double quotes, so variables like $APPDIR still expand
+fn apprun_setup(settings: &AppRunSettings) -> Result<Vec<String>, String> {
+    let mut env: Vec<_> = settings.env.iter().collect();
+    env.sort();
+    if let Some((key, _)) = env.iter().find(|(key, _)| {
+        key.is_empty()
+            || key.starts_with(|c: char| c.is_ascii_digit())
+            || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    }) {
+        return Err(format!(
+            "[appimage].apprun.env: `{key}` is not a variable name"
+        ));
+    }
+    let mut setup: Vec<String> = env
+        .into_iter()
+        .map(|(key, value)| format!("export {key}=\"{}\"", escape(value)))
+        .collect();
+    if let Some(dir) = &settings.working_dir {
+        setup.push(format!(
+            "cd \"$APPDIR/{}\" || exit 1",
+            escape(dir.trim_start_matches('/'))
+        ));
+    }
+    Ok(setup)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('`', "\\`")
+}
+
+/// Where a `[files].paths` entry goes in the AppDir, whose usr stands in for the install
+/// prefix: `to` like in a .deb, otherwise executables go to usr/bin, shared libraries to
+/// usr/lib and everything else to usr/share/<name>
+fn appdir_path(conf: &ShipConfig, file: &FileEntry, fname: &OsStr) -> PathBuf {
+    let from = Path::new(&file.from);
+    let usr = Path::new("usr");
+    match &file.to {
+        Some(to) => appdir_dest(conf, to),
+        None if is_shared_library(fname) => usr.join("lib").join(fname),
+        None if is_executable(from, file.mode) => usr.join("bin").join(fname),
+        None => usr.join("share").join(&conf.prog.name).join(fname),
+    }
+}
+
+// an install path as a .deb takes it, absolute or relative to the prefix, moved under usr
+fn appdir_dest(conf: &ShipConfig, to: &str) -> PathBuf {
+    let usr = Path::new("usr");
+    if !to.starts_with('/') {
+        return usr.join(to.trim_start_matches("./"));
+    }
+    let to = Path::new(to);
+    let rest = to
+        .strip_prefix(conf.install_prefix())
+        .or_else(|_| to.strip_prefix("/usr"))
+        .or_else(|_| to.strip_prefix("/"))
+        .unwrap_or(to);
+    usr.join(rest)
+}
+
+// `libfoo.so` or a versioned `libfoo.so.1.2`
+fn is_shared_library(fname: &OsStr) -> bool {
+    let fname = fname.to_string_lossy();
+    fname.ends_with(".so") || fname.contains(".so.")
+}
+
+/// Where the main executable ends up in the AppDir, if `[files]` packages it
+fn primary_path(conf: &ShipConfig, primary: &Path) -> Option<PathBuf> {
+    let plain = |path: &Path| path.strip_prefix(".").unwrap_or(path).to_path_buf();
+    let primary = plain(primary);
+    for file in &conf.files.paths {
+        let from = plain(Path::new(&file.from));
+        if let Ok(rest) = primary.strip_prefix(&from) {
+            let to = appdir_path(conf, file, from.file_name()?);
+            // a directory's files keep their place inside it
+            return Some(if rest.as_os_str().is_empty() {
+                to
+            } else {
+                to.join(rest)
+            });
+        }
+    }
+    let root = conf.files.root.as_ref()?;
+    primary
+        .strip_prefix(plain(Path::new(root)))
+        .ok()
+        .map(Path::to_path_buf)
+}
+
+/// The file the AppImage runs: `[prog].main`, else the listed file or staged `usr/bin` entry
+/// named after the program, otherwise the first of them. The flag tells whether it was guessed that way.
+pub fn primary_executable(conf: &ShipConfig) -> Result<Option<(PathBuf, bool)>, String> {
+    if let Some(main) = &conf.prog.main {
+        let main = PathBuf::from(main);
+        if !main.is_file() {
+            return Err(format!(
+                "[prog].main points to `{}`, which does not exist",
+                main.display()
+            ));
+        }
+        return Ok(Some((main, false)));
+    }
+
+    // listed files first, then the staged tree's usr/bin
+    let mut candidates: Vec<PathBuf> = conf
+        .files
+        .paths
+        .iter()
+        .map(|file| PathBuf::from(&file.from))
+        .collect();
+    if let Some(root) = &conf.files.root
+        && let Ok(entries) = std::fs::read_dir(Path::new(root).join("usr").join("bin"))
+    {
+        let mut staged: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+        staged.sort();
+        candidates.extend(staged);
+    }
+
+    let named = candidates.iter().find(|p| {
+        p.is_file() && p.file_name().and_then(|n| n.to_str()) == Some(conf.prog.name.as_str())
+    });
+    Ok(match named {
+        Some(primary) => Some((primary.clone(), false)),
+        None => candidates
+            .into_iter()
+            .find(|p| p.is_file())
+            .map(|primary| (primary, true)),
+    })
 }
 
 fn fallback_icon_svg(app_name: &str) -> String {
@@ -174,3 +982,18 @@ fn fallback_icon_svg(app_name: &str) -> String {
          </svg>"
     )
 }
+
+// adds a directory file by file, leaving out [files].exclude
+fn add_tree(
+    image: &AppImage,
+    from: &Path,
+    to: &Path,
+    exclude: &[Pattern],
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut files = Vec::new();
+    collect_dir(&mut files, from, &to.to_string_lossy(), exclude)?;
+    for file in &files {
+        image.add_file(&file.source, Path::new(&file.dest))?;
+    }
+    Ok(())
+}
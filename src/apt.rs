@@ -0,0 +1,203 @@
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::Sha256;
+
+use crate::{
+    atomic,
+    checksum::digest_file,
+    conf::{ShipConfig, Target},
+    inspect::read_deb_member,
+    report::BuildReport,
+    signing,
+};
+
+/// The armored public key written next to a signed repository, for `signed-by`
+pub const KEY_FILE: &str = "key.asc";
+
+/// Copies the debs of the last build into `dir` and regenerates the flat APT repository
+/// there, `deb [signed-by=...] <url> ./` in sources.list, from every deb in it.
+///
+/// Debs of earlier releases stay, so apt can still install older versions. The indexes are
+/// signed with `[signing]` when it's set; returns the files that were written.
+pub fn update(conf: &ShipConfig, dir: &Path) -> Result<Vec<PathBuf>, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+
+    let report = BuildReport::load(&conf.prog.name)?;
+    let debs: Vec<_> = report
+        .artifacts
+        .iter()
+        .filter(|artifact| artifact.target == Target::Deb)
+        .collect();
+    if debs.is_empty() {
+        return Err("the last build has no debs to add, build the deb target first".to_string());
+    }
+    for deb in debs {
+        let dest = dir.join(&deb.file);
+        atomic::write_with(&dest, |tmp| std::fs::copy(&deb.path, tmp).map(|_| ()))
+            .map_err(|err| format!("failed to copy {} into the repository: {err}", deb.file))?;
+        info!("added {}", deb.file);
+    }
+
+    let mut written = Vec::new();
+    let (packages, architectures) = packages(dir)?;
+    let packages_path = dir.join("Packages");
+    atomic::write(&packages_path, &packages)
+        .map_err(|err| format!("failed to write {}: {err}", packages_path.display()))?;
+    written.push(packages_path);
+
+    let gz_path = dir.join("Packages.gz");
+    atomic::write_with(&gz_path, |tmp| {
+        let mut gz =
+            flate2::write::GzEncoder::new(std::fs::File::create(tmp)?, flate2::Compression::best());
+        gz.write_all(packages.as_bytes())?;
+        gz.finish().map(|_| ())
+    })
+    .map_err(|err| format!("failed to write {}: {err}", gz_path.display()))?;
+    written.push(gz_path);
+
+    let release_path = dir.join("Release");
+    let release = release(conf, &architectures, &written)?;
+    atomic::write(&release_path, release)
+        .map_err(|err| format!("failed to write {}: {err}", release_path.display()))?;
+    written.push(release_path.clone());
+
+    let in_release = dir.join("InRelease");
+    let release_gpg = dir.join("Release.gpg");
+    match &conf.signing {
+        Some(settings) => {
+            signing::clear_sign(settings, &release_path, &in_release)?;
+            signing::detach_sign(settings, &release_path, &release_gpg, true)?;
+            let key_path = dir.join(KEY_FILE);
+            atomic::write(&key_path, signing::public_key(settings)?)
+                .map_err(|err| format!("failed to write {}: {err}", key_path.display()))?;
+            written.extend([in_release, release_gpg, key_path]);
+        }
+        None => {
+            // signatures of an earlier Release would no longer match
+            std::fs::remove_file(&in_release).ok();
+            std::fs::remove_file(&release_gpg).ok();
+            warn!(
+                "no [signing] section, the repository is unsigned and apt will refuse it unless marked trusted"
+            );
+        }
+    }
+
+    Ok(written)
+}
+
+/// The Packages index of every deb in `dir`, by file name, and the architectures they're for
+fn packages(dir: &Path) -> Result<(String, Vec<String>), String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|err| format!("failed to read {}: {err}", dir.display()))?;
+    let mut debs: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "deb") && path.is_file())
+        .collect();
+    debs.sort();
+
+    let mut index = String::new();
+    let mut architectures = Vec::new();
+    for deb in &debs {
+        let read_err = |err| format!("failed to read {}: {err}", deb.display());
+        let control = read_deb_member(deb, "control.tar", control_file)
+            .map_err(read_err)?
+            .flatten()
+            .ok_or_else(|| format!("{} has no control file", deb.display()))?;
+        let control = control.trim_end();
+        if let Some(arch) = field(control, "Architecture")
+            && !architectures.iter().any(|known| known == arch)
+        {
+            architectures.push(arch.to_string());
+        }
+
+        let name = deb
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let size = std::fs::metadata(deb).map_err(read_err)?.len();
+        index.push_str(&format!(
+            "{control}\n\
+             Filename: ./{name}\n\
+             Size: {size}\n\
+             MD5sum: {}\n\
+             SHA1: {}\n\
+             SHA256: {}\n\n",
+            digest_file::<Md5>(deb).map_err(read_err)?,
+            digest_file::<Sha1>(deb).map_err(read_err)?,
+            digest_file::<Sha256>(deb).map_err(read_err)?,
+        ));
+    }
+    architectures.sort();
+    Ok((index, architectures))
+}
+
+// `./control` out of control.tar
+fn control_file(control: &mut dyn Read) -> std::io::Result<Option<String>> {
+    let mut archive = tar::Archive::new(control);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy().trim_start_matches("./") == "control" {
+            let mut text = String::new();
+            entry.read_to_string(&mut text)?;
+            return Ok(Some(text));
+        }
+    }
+    Ok(None)
+}
+
+fn field<'a>(control: &'a str, name: &str) -> Option<&'a str> {
+    control.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+/// The Release file, listing the checksums of the indexes in `files`
+fn release(
+    conf: &ShipConfig,
+    architectures: &[String],
+    files: &[PathBuf],
+) -> Result<String, String> {
+    let mut md5 = String::new();
+    let mut sha1 = String::new();
+    let mut sha256 = String::new();
+    for file in files {
+        let read_err = |err| format!("failed to read {}: {err}", file.display());
+        let size = std::fs::metadata(file).map_err(read_err)?.len();
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        md5.push_str(&format!(
+            " {} {size} {name}\n",
+            digest_file::<Md5>(file).map_err(read_err)?
+        ));
+        sha1.push_str(&format!(
+            " {} {size} {name}\n",
+            digest_file::<Sha1>(file).map_err(read_err)?
+        ));
+        sha256.push_str(&format!(
+            " {} {size} {name}\n",
+            digest_file::<Sha256>(file).map_err(read_err)?
+        ));
+    }
+
+    Ok(format!(
+        "Origin: {name}\n\
+         Label: {name}\n\
+         Date: {date}\n\
+         Architectures: {architectures}\n\
+         MD5Sum:\n{md5}\
+         SHA1:\n{sha1}\
+         SHA256:\n{sha256}",
+        name = conf.prog.name,
+        date = chrono::Utc::now().format("%a, %d %b %Y %H:%M:%S UTC"),
+        architectures = architectures.join(" "),
+    ))
+}
@@ -0,0 +1,410 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    atomic,
+    compress::Encoder,
+    conf::{CompressionAlgorithm, InstallScope, ShipConfig, Target},
+    desktop,
+    error::{Context, ShipError},
+    gen_::{Artifacts, Generator, Plan},
+    layout::{Entry, Layout},
+    scripts::{self, InstallScripts, inline},
+};
+
+/// A plain tarball for users who don't want a package manager involved.
+///
+/// Files sit under `<name>-<version>/` at their path relative to the install prefix,
+/// next to an `install.sh` that copies them into `$PREFIX` and an `uninstall.sh` that
+/// removes exactly what was installed. `[archive].scope = "User"` makes both default to
+/// the XDG user directories, so no root is needed.
+pub struct ArchiveGenerator<'a> {
+    pub conf: &'a ShipConfig,
+}
+
+impl<'a> ArchiveGenerator<'a> {
+    pub fn new(conf: &'a ShipConfig) -> Self {
+        Self { conf }
+    }
+
+    fn algorithm(&self) -> CompressionAlgorithm {
+        self.conf
+            .compression(&Target::Archive)
+            .map(|c| c.algorithm)
+            .unwrap_or(CompressionAlgorithm::Gzip)
+    }
+
+    fn top_dir(&self) -> String {
+        match &self.conf.prog.version {
+            Some(version) => format!("{}-{version}", self.conf.prog.name),
+            None => self.conf.prog.name.clone(),
+        }
+    }
+
+    fn archive_output_path(&self) -> PathBuf {
+        // `foo.tar.gz` names the archive itself
+        let out = Path::new(&self.conf.out.bin);
+        let stem = Path::new(out.file_stem().unwrap_or_default());
+        if stem.extension().and_then(|ext| ext.to_str()) == Some("tar") {
+            return out.to_path_buf();
+        }
+
+        out.join(format!(
+            "{}-{}.tar.{}",
+            self.top_dir(),
+            self.conf.prog.arch.deb_name(),
+            self.algorithm().extension()
+        ))
+    }
+
+    fn scope(&self) -> InstallScope {
+        self.conf
+            .archive
+            .as_ref()
+            .map(|a| a.scope)
+            .unwrap_or_default()
+    }
+
+    // shell expressions, evaluated by install.sh on the user's machine
+    fn default_prefix(&self) -> String {
+        match (self.scope(), &self.conf.out.prefix) {
+            (InstallScope::User, None) => format!(
+                "${{XDG_DATA_HOME:-$HOME/.local/share}}/{}",
+                self.conf.prog.name
+            ),
+            _ => self.conf.install_prefix(),
+        }
+    }
+
+    fn default_bin_dir(&self) -> &'static str {
+        match self.scope() {
+            InstallScope::System => "/usr/local/bin",
+            InstallScope::User => "$HOME/.local/bin",
+        }
+    }
+
+    fn default_apps_dir(&self) -> &'static str {
+        match self.scope() {
+            InstallScope::System => "/usr/share/applications",
+            InstallScope::User => "${XDG_DATA_HOME:-$HOME/.local/share}/applications",
+        }
+    }
+
+    /// Where the icon goes inside the archive and the prefix, if the program has one
+    fn icon_path(&self) -> Option<(PathBuf, String)> {
+        let icon = PathBuf::from(self.conf.files.icon.as_ref()?);
+        let ext = icon.extension()?.to_string_lossy().into_owned();
+        Some((icon, format!("share/icons/{}.{ext}", self.conf.prog.name)))
+    }
+
+    // only programs with an icon get a launcher entry, command line tools don't need one
+    fn desktop_entry(&self, layout: &Layout) -> String {
+        let Some((_, icon)) = self.icon_path() else {
+            return String::new();
+        };
+        let Some((_, exe)) = layout
+            .launchers()
+            .find(|(link, _)| link.rsplit('/').next() == Some(self.conf.prog.name.as_str()))
+            .or(layout.launchers().next())
+        else {
+            return String::new();
+        };
+
+        // user strings are escaped for the unquoted heredoc, the paths must expand
+        let entry = heredoc_escape(&desktop::render(self.conf, "@EXEC@", "@ICON@"))
+            .replace(
+                "@EXEC@",
+                &format!("\"$PREFIX/{}\"", exe.trim_start_matches('/')),
+            )
+            .replace("@ICON@", &format!("$PREFIX/{icon}"));
+
+        format!(
+            "mkdir -p \"$APPS_DIR\"\n\
+             cat > \"$APPS_DIR/{name}.desktop\" <<EOF\n\
+             {entry}\
+             EOF\n\
+             echo \"$APPS_DIR/{name}.desktop\" >> \"$record.new\"\n",
+            name = self.conf.prog.name,
+        )
+    }
+
+    // the record lives inside the prefix so uninstall.sh finds it without extra arguments
+    fn record_name(&self) -> String {
+        format!(".{}-installed", self.conf.prog.name)
+    }
+
+    fn install_script(&self, layout: &Layout, scripts: &InstallScripts) -> String {
+        let mut steps = String::new();
+        for entry in &layout.files {
+            steps.push_str(&format!(
+                "install_file {}\n",
+                crate::shell_quote(entry.dest.trim_start_matches('/'))
+            ));
+        }
+        if let Some((_, icon)) = self.icon_path() {
+            steps.push_str(&format!("install_file {}\n", crate::shell_quote(&icon)));
+        }
+        for (link, target) in &layout.symlinks {
+            if layout.launchers().any(|(launcher, _)| launcher == link) {
+                let name = link.rsplit('/').next().unwrap_or(link);
+                steps.push_str(&format!(
+                    "link_executable {} {}\n",
+                    crate::shell_quote(target.trim_start_matches('/')),
+                    crate::shell_quote(name)
+                ));
+            } else {
+                steps.push_str(&format!(
+                    "link_file {} {}\n",
+                    crate::shell_quote(target),
+                    crate::shell_quote(link.trim_start_matches('/'))
+                ));
+            }
+        }
+
+        format!(
+            "#!/bin/sh\n\
+             # Installs {name} into $PREFIX, links its executables into $BIN_DIR and,\n\
+             # if it has an icon, adds a launcher entry to $APPS_DIR.\n\
+             # Every installed path is recorded so uninstall.sh can remove them again.\n\
+             set -eu\n\
+             \n\
+             PREFIX=\"${{PREFIX:-{prefix}}}\"\n\
+             BIN_DIR=\"${{BIN_DIR:-{bin_dir}}}\"\n\
+             APPS_DIR=\"${{APPS_DIR:-{apps_dir}}}\"\n\
+             here=\"$(cd \"$(dirname \"$0\")\" && pwd)\"\n\
+             record=\"$PREFIX/{record}\"\n\
+             \n\
+             export PREFIX BIN_DIR\n\
+             {preinstall}\
+             mkdir -p \"$PREFIX\"\n\
+             : > \"$record.new\"\n\
+             \n\
+             install_file() {{\n\
+             \x20   mkdir -p \"$(dirname \"$PREFIX/$1\")\"\n\
+             \x20   cp -p \"$here/$1\" \"$PREFIX/$1\"\n\
+             \x20   echo \"$PREFIX/$1\" >> \"$record.new\"\n\
+             }}\n\
+             \n\
+             link_file() {{\n\
+             \x20   mkdir -p \"$(dirname \"$PREFIX/$2\")\"\n\
+             \x20   ln -sfn \"$1\" \"$PREFIX/$2\"\n\
+             \x20   echo \"$PREFIX/$2\" >> \"$record.new\"\n\
+             }}\n\
+             \n\
+             link_executable() {{\n\
+             \x20   mkdir -p \"$BIN_DIR\"\n\
+             \x20   ln -sf \"$PREFIX/$1\" \"$BIN_DIR/$2\"\n\
+             \x20   echo \"$BIN_DIR/$2\" >> \"$record.new\"\n\
+             }}\n\
+             \n\
+             {steps}\
+             {desktop}\
+             cp \"$here/uninstall.sh\" \"$PREFIX/uninstall.sh\"\n\
+             echo \"$PREFIX/uninstall.sh\" >> \"$record.new\"\n\
+             mv \"$record.new\" \"$record\"\n\
+             {postinstall}\
+             \n\
+             echo \"installed {name} into $PREFIX, run $PREFIX/uninstall.sh to remove it\"\n",
+            name = self.conf.prog.name,
+            prefix = self.default_prefix(),
+            bin_dir = self.default_bin_dir(),
+            apps_dir = self.default_apps_dir(),
+            record = self.record_name(),
+            desktop = self.desktop_entry(layout),
+            preinstall = scripts
+                .preinstall
+                .as_deref()
+                .map(inline)
+                .unwrap_or_default(),
+            postinstall = scripts
+                .postinstall
+                .as_deref()
+                .map(inline)
+                .unwrap_or_default(),
+        )
+    }
+
+    fn uninstall_script(&self, scripts: &InstallScripts) -> String {
+        format!(
+            "#!/bin/sh\n\
+             # Removes everything install.sh recorded, then any directories it left empty.\n\
+             set -eu\n\
+             \n\
+             # the installed copy sits in the prefix it was installed into, whichever that was\n\
+             here=\"$(cd \"$(dirname \"$0\")\" && pwd)\"\n\
+             if [ -z \"${{PREFIX:-}}\" ] && [ -f \"$here/{record}\" ]; then\n\
+             \x20   PREFIX=\"$here\"\n\
+             fi\n\
+             PREFIX=\"${{PREFIX:-{prefix}}}\"\n\
+             record=\"$PREFIX/{record}\"\n\
+             \n\
+             if [ ! -f \"$record\" ]; then\n\
+             \x20   echo \"error: $record not found, is {name} installed in $PREFIX?\" >&2\n\
+             \x20   exit 1\n\
+             fi\n\
+             \n\
+             export PREFIX\n\
+             {preremove}\
+             while IFS= read -r path; do\n\
+             \x20   rm -f \"$path\"\n\
+             done < \"$record\"\n\
+             \n\
+             # only directories inside the prefix are cleaned up, never $BIN_DIR\n\
+             while IFS= read -r path; do\n\
+             \x20   case \"$path\" in\n\
+             \x20   \"$PREFIX\"/*)\n\
+             \x20       dir=\"$(dirname \"$path\")\"\n\
+             \x20       while [ \"$dir\" != \"$PREFIX\" ] && rmdir \"$dir\" 2>/dev/null; do\n\
+             \x20           dir=\"$(dirname \"$dir\")\"\n\
+             \x20       done\n\
+             \x20       ;;\n\
+             \x20   esac\n\
+             done < \"$record\"\n\
+             \n\
+             rm -f \"$record\"\n\
+             rmdir \"$PREFIX\" 2>/dev/null || true\n\
+             {postremove}\
+             \n\
+             echo \"removed {name} from $PREFIX\"\n",
+            name = self.conf.prog.name,
+            prefix = self.default_prefix(),
+            record = self.record_name(),
+            preremove = scripts.preremove.as_deref().map(inline).unwrap_or_default(),
+            postremove = scripts
+                .postremove
+                .as_deref()
+                .map(inline)
+                .unwrap_or_default(),
+        )
+    }
+
+    fn write_archive(
+        &self,
+        path: &Path,
+        layout: &Layout,
+        scripts: &InstallScripts,
+    ) -> std::io::Result<()> {
+        let encoder = Encoder::for_config(
+            File::create(path)?,
+            self.conf.compression(&Target::Archive),
+            (CompressionAlgorithm::Gzip, 9),
+            self.conf.compression_threads(),
+        )?;
+        let mut tar = tar::Builder::new(encoder);
+        let top_dir = PathBuf::from(self.top_dir());
+
+        for entry in &layout.files {
+            entry.append_to(&mut tar, top_dir.join(entry.dest.trim_start_matches('/')))?;
+        }
+
+        if let Some((icon, rel)) = self.icon_path() {
+            tar.append_path_with_name(icon, top_dir.join(rel))?;
+        }
+
+        for (name, contents) in [
+            ("install.sh", self.install_script(layout, scripts)),
+            ("uninstall.sh", self.uninstall_script(scripts)),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(top_dir.join(name))?;
+            header.set_mode(0o755);
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            tar.append(&header, contents.as_bytes())?;
+        }
+
+        tar.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+impl<'a> Generator for ArchiveGenerator<'a> {
+    fn run(&self) -> Result<Artifacts, ShipError> {
+        // paths are relative to the prefix chosen at install time
+        let layout = Layout::resolve_in(self.conf, "", "/bin")?;
+
+        let scripts = InstallScripts::load(self.conf)?;
+
+        let output_path = self.archive_output_path();
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(|| format!("failed to create output directory {}", parent.display()))?;
+        }
+
+        atomic::write_with(&output_path, |tmp| {
+            self.write_archive(tmp, &layout, &scripts)
+        })
+        .context(|| format!("failed to write archive at {}", output_path.display()))?;
+
+        Ok(vec![output_path])
+    }
+
+    fn plan(&self) -> Result<Plan, ShipError> {
+        let layout = Layout::resolve_in(self.conf, "", "/bin")?;
+        let prefix = self.default_prefix();
+        let mut files: Vec<Entry> = layout
+            .files
+            .iter()
+            .map(|entry| Entry {
+                dest: format!("{prefix}{}", entry.dest),
+                ..entry.clone()
+            })
+            .collect();
+        if let Some((source, icon)) = self.icon_path() {
+            files.push(Entry {
+                source,
+                dest: format!("{prefix}/{icon}"),
+                mode: None,
+                owner: None,
+                group: None,
+            });
+        }
+        let symlinks = layout
+            .symlinks
+            .iter()
+            .map(|(link, target)| {
+                if !layout.launchers().any(|(launcher, _)| launcher == link) {
+                    return (format!("{prefix}{link}"), target.clone());
+                }
+                let name = link.rsplit('/').next().unwrap_or(link);
+                (
+                    format!("{}/{name}", self.default_bin_dir()),
+                    format!("{prefix}{target}"),
+                )
+            })
+            .collect();
+
+        Ok(Plan {
+            artifacts: self.artifacts(),
+            files,
+            symlinks,
+            scripts: scripts::planned(
+                self.conf,
+                [
+                    "install.sh, before copying",
+                    "install.sh, after copying",
+                    "uninstall.sh, before removing",
+                    "uninstall.sh, after removing",
+                ],
+            ),
+            notes: vec![
+                "install.sh puts the files wherever PREFIX and BIN_DIR say, the defaults are shown"
+                    .to_string(),
+            ],
+            ..Default::default()
+        })
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        vec![self.archive_output_path()]
+    }
+}
+
+fn heredoc_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('$', "\\$")
+        .replace('`', "\\`")
+}
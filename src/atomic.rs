@@ -0,0 +1,35 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Where an artifact is written before it's renamed into place.
+///
+/// Same directory so the rename stays on one filesystem, hidden and `.tmp` so globs like
+/// `*.deb` never match a half-written file.
+pub fn temp_path(dest: &Path) -> PathBuf {
+    let name = dest
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    dest.with_file_name(format!(".{name}.{}.tmp", std::process::id()))
+}
+
+/// Runs `write` against a temporary path next to `dest` and renames the result over `dest`
+/// only once it succeeded, so a failed or interrupted build never leaves a truncated artifact.
+pub fn write_with<E: From<io::Error>>(
+    dest: &Path,
+    write: impl FnOnce(&Path) -> Result<(), E>,
+) -> Result<(), E> {
+    let tmp = temp_path(dest);
+    let result = write(&tmp).and_then(|()| std::fs::rename(&tmp, dest).map_err(E::from));
+    if result.is_err() {
+        std::fs::remove_file(&tmp).ok();
+    }
+    result
+}
+
+/// `std::fs::write`, through a temporary file
+pub fn write(dest: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    write_with(dest, |tmp| std::fs::write(tmp, contents))
+}
@@ -0,0 +1,378 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    conf::BundleProfile,
+    layout::{Entry, collect_dir, is_elf},
+};
+
+/// Libraries every distribution ships, or that have to match the host's drivers, and are
+/// never bundled; after the AppImage project's excludelist
+const EXCLUDED: &[&str] = &[
+    "ld-linux.so.2",
+    "ld-linux-x86-64.so.2",
+    "ld-linux-aarch64.so.1",
+    "ld-linux-armhf.so.3",
+    "libc.so.6",
+    "libdl.so.2",
+    "libm.so.6",
+    "libmvec.so.1",
+    "libpthread.so.0",
+    "librt.so.1",
+    "libresolv.so.2",
+    "libutil.so.1",
+    "libanl.so.1",
+    "libthread_db.so.1",
+    "libgcc_s.so.1",
+    "libasound.so.2",
+    "libexpat.so.1",
+    "libfontconfig.so.1",
+    "libfreetype.so.6",
+    "libharfbuzz.so.0",
+    "libEGL.so.1",
+    "libGL.so.1",
+    "libGLX.so.0",
+    "libGLdispatch.so.0",
+    "libOpenGL.so.0",
+    "libdrm.so.2",
+    "libgbm.so.1",
+    "libglapi.so.0",
+    "libX11.so.6",
+    "libX11-xcb.so.1",
+    "libxcb.so.1",
+    "libICE.so.6",
+    "libSM.so.6",
+    "libgpg-error.so.0",
+    "libcom_err.so.2",
+    "libjack.so.0",
+    "libp11-kit.so.0",
+    "libusb-1.0.so.0",
+    "libuuid.so.1",
+];
+
+/// Where bundled libraries go, relative to the AppDir
+pub const LIB_DIR: &str = "usr/lib";
+
+fn is_excluded(soname: &str) -> bool {
+    // glibc's name service modules are loaded by glibc itself, whichever one the host has
+    EXCLUDED.contains(&soname) || soname.starts_with("libnss_")
+}
+
+/// What [bundle_libraries] added to the AppDir
+#[derive(Debug, Default)]
+pub struct Bundled {
+    pub libraries: Vec<String>, // sonames copied into usr/lib
+    pub setup: Vec<String>,     // shell lines AppRun has to run first, for the profile's plugins
+}
+
+/// Copies the shared libraries the AppDir's ELF files need, other than the excluded system
+/// ones, into `usr/lib` and points every ELF file's RPATH at it, so the AppImage doesn't
+/// depend on what the host has installed. A `profile` first copies the toolkit's plugins
+/// in, so their libraries are bundled too. Needs `ldd` and `patchelf`.
+pub fn bundle_libraries(
+    appdir: &Path,
+    name: &str,
+    profile: Option<BundleProfile>,
+) -> Result<Bundled, String> {
+    let setup = match profile {
+        Some(profile) => {
+            let linked = resolve(&elf_files(appdir)?)?;
+            match profile {
+                BundleProfile::Qt => qt_plugins(appdir, &linked)?,
+                BundleProfile::Gtk => gtk_plugins(appdir, name, &linked)?,
+            }
+        }
+        None => Vec::new(),
+    };
+
+    let elfs = elf_files(appdir)?;
+    let needed: BTreeMap<String, PathBuf> = resolve(&elfs)?
+        .into_iter()
+        .filter(|(soname, _)| !is_excluded(soname))
+        .collect();
+
+    let lib_dir = appdir.join(LIB_DIR);
+    std::fs::create_dir_all(&lib_dir)
+        .map_err(|err| format!("failed to create {}: {err}", lib_dir.display()))?;
+    let mut libraries = Vec::new();
+    for (soname, path) in &needed {
+        let dest = lib_dir.join(soname);
+        if dest.exists() {
+            // already packaged by [files]
+            continue;
+        }
+        std::fs::copy(path, &dest)
+            .map_err(|err| format!("failed to bundle {} into the AppDir: {err}", path.display()))?;
+        set_rpath(&dest, "$ORIGIN")?;
+        libraries.push(soname.clone());
+    }
+
+    if !needed.is_empty() {
+        for elf in &elfs {
+            let rpath = origin_rpath(elf.dest.trim_start_matches('/'));
+            set_rpath(&elf.source, &rpath)?;
+        }
+    }
+    Ok(Bundled { libraries, setup })
+}
+
+// AppRun and .DirIcon are links to files that are already listed
+fn elf_files(appdir: &Path) -> Result<Vec<Entry>, String> {
+    let mut files = Vec::new();
+    collect_dir(&mut files, appdir, "", &[])?;
+    Ok(files
+        .into_iter()
+        .filter(|entry| !entry.source.is_symlink() && is_elf(&entry.source))
+        .collect())
+}
+
+// ldd resolves the whole tree, so the libraries' own dependencies are included
+fn resolve(elfs: &[Entry]) -> Result<BTreeMap<String, PathBuf>, String> {
+    let mut linked = BTreeMap::new();
+    for elf in elfs {
+        linked.extend(dependencies(&elf.source)?);
+    }
+    Ok(linked)
+}
+
+/// Qt's plugin directories ship with the AppImage, under `usr/plugins`
+const QT_PLUGIN_DIRS: &[&str] = &[
+    "platforms",
+    "platformthemes",
+    "platforminputcontexts",
+    "xcbglintegrations",
+    "wayland-shell-integration",
+    "wayland-graphics-integration-client",
+    "wayland-decoration-client",
+    "imageformats",
+    "iconengines",
+    "sqldrivers",
+    "styles",
+    "tls",
+];
+
+fn qt_plugins(appdir: &Path, linked: &BTreeMap<String, PathBuf>) -> Result<Vec<String>, String> {
+    let (major, core) = ["6", "5"]
+        .into_iter()
+        .find_map(|major| Some((major, linked.get(&format!("libQt{major}Core.so.{major}"))?)))
+        .ok_or("[appimage].bundle = \"qt\", but nothing in the AppDir links against QtCore")?;
+
+    // qmake knows, otherwise the plugins are next to the libraries as distributions lay them out
+    let lib_dir = core.parent().unwrap_or(Path::new("/usr/lib"));
+    let plugin_dir = qmake_plugins(major)
+        .into_iter()
+        .chain([
+            lib_dir.join(format!("qt{major}/plugins")),
+            lib_dir.join("qt/plugins"),
+        ])
+        .find(|dir| dir.join("platforms").is_dir())
+        .ok_or_else(|| format!("failed to find the Qt {major} plugins, is qmake on PATH?"))?;
+
+    for dir in QT_PLUGIN_DIRS {
+        let from = plugin_dir.join(dir);
+        if from.is_dir() {
+            copy_tree(&from, &appdir.join("usr/plugins").join(dir))?;
+        }
+    }
+    Ok(vec![
+        "export QT_PLUGIN_PATH=\"$APPDIR/usr/plugins\"".to_string(),
+    ])
+}
+
+// the versioned qmake first, a plain `qmake` may belong to the other major version
+fn qmake_plugins(major: &str) -> Option<PathBuf> {
+    [
+        format!("qmake{major}"),
+        format!("qmake-qt{major}"),
+        "qmake".to_string(),
+    ]
+    .iter()
+    .find_map(|qmake| {
+        let output = crate::log::command(Command::new(qmake).arg("-query").stdin(Stdio::null()))
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        let stdout = String::from_utf8(output.stdout).ok()?;
+        let query = |key: &str| {
+            stdout
+                .lines()
+                .find_map(|line| line.strip_prefix(key)?.strip_prefix(':'))
+        };
+        query("QT_VERSION")
+            .filter(|version| version.split('.').next() == Some(major))
+            .and(query("QT_INSTALL_PLUGINS"))
+            .map(PathBuf::from)
+    })
+}
+
+/// Where GDK finds its loaders, relative to the AppDir
+const PIXBUF_DIR: &str = "usr/lib/gdk-pixbuf-2.0/2.10.0";
+const SCHEMAS: &str = "/usr/share/glib-2.0/schemas/gschemas.compiled";
+
+fn gtk_plugins(
+    appdir: &Path,
+    name: &str,
+    linked: &BTreeMap<String, PathBuf>,
+) -> Result<Vec<String>, String> {
+    let pixbuf = linked
+        .get("libgdk_pixbuf-2.0.so.0")
+        .ok_or("[appimage].bundle = \"gtk\", but nothing in the AppDir links against GDK")?;
+    let lib_dir = pixbuf.parent().unwrap_or(Path::new("/usr/lib"));
+    let mut setup = vec![
+        "export GTK_EXE_PREFIX=\"$APPDIR/usr\"".to_string(),
+        "export GTK_DATA_PREFIX=\"$APPDIR/usr\"".to_string(),
+        "export XDG_DATA_DIRS=\"$APPDIR/usr/share:${XDG_DATA_DIRS:-/usr/local/share:/usr/share}\""
+            .to_string(),
+    ];
+
+    let host_pixbuf = lib_dir.join("gdk-pixbuf-2.0/2.10.0");
+    copy_tree(
+        &host_pixbuf.join("loaders"),
+        &appdir.join(PIXBUF_DIR).join("loaders"),
+    )?;
+    // the cache lists loaders by absolute path, so AppRun fills in where the AppDir is mounted
+    let cache = std::fs::read_to_string(host_pixbuf.join("loaders.cache"))
+        .map_err(|err| format!("failed to read the GDK pixbuf loaders cache: {err}"))?;
+    let cache: String = cache
+        .lines()
+        .map(|line| {
+            match line
+                .strip_prefix("\"/")
+                .and_then(|path| path.rsplit_once('/'))
+            {
+                Some((_, loader)) => format!("\"@APPDIR@/{PIXBUF_DIR}/loaders/{loader}\n"),
+                None => format!("{line}\n"),
+            }
+        })
+        .collect();
+    let template = appdir.join(PIXBUF_DIR).join("loaders.cache.in");
+    std::fs::write(&template, cache)
+        .map_err(|err| format!("failed to write {}: {err}", template.display()))?;
+    setup.extend([
+        format!("export GDK_PIXBUF_MODULEDIR=\"$APPDIR/{PIXBUF_DIR}/loaders\""),
+        // a fresh file per launch, as another instance may be mounted elsewhere; AppRun execs
+        // the program, so a watcher on its PID removes the file once it exits
+        format!(
+            "export GDK_PIXBUF_MODULE_FILE=\"$(mktemp \"${{XDG_RUNTIME_DIR:-${{TMPDIR:-/tmp}}}}/{name}-pixbuf-loaders.XXXXXX\")\""
+        ),
+        format!(
+            "sed \"s|@APPDIR@|$APPDIR|g\" \"$APPDIR/{PIXBUF_DIR}/loaders.cache.in\" > \"$GDK_PIXBUF_MODULE_FILE\""
+        ),
+        "(while kill -0 $$ 2>/dev/null; do sleep 10; done; rm -f \"$GDK_PIXBUF_MODULE_FILE\") >/dev/null 2>&1 &"
+            .to_string(),
+    ]);
+
+    // input methods and print backends, for whichever GTK the program uses
+    for gtk in ["gtk-4.0", "gtk-3.0"] {
+        let modules = lib_dir.join(gtk);
+        if modules.is_dir()
+            && linked
+                .keys()
+                .any(|soname| soname.starts_with(&format!("lib{}", &gtk[..5])))
+        {
+            copy_tree(&modules, &appdir.join("usr/lib").join(gtk))?;
+            setup.push(format!("export GTK_PATH=\"$APPDIR/usr/lib/{gtk}\""));
+            break;
+        }
+    }
+
+    if Path::new(SCHEMAS).is_file() {
+        let dest = appdir.join("usr/share/glib-2.0/schemas");
+        std::fs::create_dir_all(&dest)
+            .map_err(|err| format!("failed to create {}: {err}", dest.display()))?;
+        std::fs::copy(SCHEMAS, dest.join("gschemas.compiled"))
+            .map_err(|err| format!("failed to bundle {SCHEMAS}: {err}"))?;
+        setup
+            .push("export GSETTINGS_SCHEMA_DIR=\"$APPDIR/usr/share/glib-2.0/schemas\"".to_string());
+    }
+    Ok(setup)
+}
+
+fn copy_tree(from: &Path, to: &Path) -> Result<(), String> {
+    let mut files = Vec::new();
+    collect_dir(&mut files, from, "", &[])?;
+    for file in files {
+        let dest = to.join(file.dest.trim_start_matches('/'));
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+        }
+        std::fs::copy(&file.source, &dest)
+            .map_err(|err| format!("failed to bundle {}: {err}", file.source.display()))?;
+    }
+    Ok(())
+}
+
+// `$ORIGIN/../lib` for `usr/bin/<name>`, `$ORIGIN/usr/lib` for the AppDir's own root
+fn origin_rpath(dest: &str) -> String {
+    let depth = dest.matches('/').count();
+    let mut rpath = String::from("$ORIGIN");
+    let mut shared = 0;
+    for (component, lib) in dest.split('/').zip(LIB_DIR.split('/')).take(depth) {
+        if component != lib {
+            break;
+        }
+        shared += 1;
+    }
+    for _ in shared..depth {
+        rpath.push_str("/..");
+    }
+    for lib in LIB_DIR.split('/').skip(shared) {
+        rpath.push('/');
+        rpath.push_str(lib);
+    }
+    rpath
+}
+
+/// The shared libraries `binary` loads, by soname, as resolved on this host
+fn dependencies(binary: &Path) -> Result<Vec<(String, PathBuf)>, String> {
+    let output = crate::log::command(Command::new("ldd").arg(binary).stdin(Stdio::null()))
+        .output()
+        .map_err(|err| format!("failed to run ldd on {}: {err}", binary.display()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // statically linked executables make ldd fail, and need nothing
+    if !output.status.success() {
+        return Ok(Vec::new());
+    }
+
+    let mut libraries = Vec::new();
+    for line in stdout.lines() {
+        // `libfoo.so.1 => /usr/lib/libfoo.so.1 (0x...)`, the loader and vdso have no `=>`
+        let Some((soname, resolved)) = line.trim().split_once(" => ") else {
+            continue;
+        };
+        if resolved.starts_with("not found") {
+            return Err(format!(
+                "{} needs {soname}, which is not installed on this host",
+                binary.display()
+            ));
+        }
+        let path = resolved.split(" (").next().unwrap_or(resolved).trim();
+        if !path.is_empty() {
+            libraries.push((soname.to_string(), PathBuf::from(path)));
+        }
+    }
+    Ok(libraries)
+}
+
+fn set_rpath(elf: &Path, rpath: &str) -> Result<(), String> {
+    let status = crate::log::command(
+        Command::new("patchelf")
+            .arg("--set-rpath")
+            .arg(rpath)
+            .arg(elf)
+            .stdin(Stdio::null()),
+    )
+    .status()
+    .map_err(|err| format!("failed to run patchelf (is it installed?): {err}"))?;
+    if !status.success() {
+        return Err(format!(
+            "patchelf failed to set the RPATH of {} ({status})",
+            elf.display()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use crate::conf::ShipConfig;
+
+/// Root of ship's on-disk cache, shared by every target and run.
+///
+/// Defaults to `.ship/cache` next to the Shipfile's working directory and can be
+/// moved with `SHIP_CACHE_DIR`.
+pub fn cache_dir() -> PathBuf {
+    std::env::var_os("SHIP_CACHE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(".ship").join("cache"))
+}
+
+/// Scratch space for files ship generates while packaging, e.g. manpages, AppImage staging
+/// and the working directories of external tools.
+///
+/// `[out].work_dir`, or `.ship/work` by default, and `SHIP_WORK_DIR` overrides both, so a
+/// build machine can point the heavy I/O at a scratch volume without editing the Shipfile.
+pub fn work_dir(conf: &ShipConfig) -> PathBuf {
+    std::env::var_os("SHIP_WORK_DIR")
+        .map(PathBuf::from)
+        .or_else(|| conf.out.work_dir.as_ref().map(PathBuf::from))
+        .unwrap_or_else(|| PathBuf::from(".ship").join("work"))
+}
+
+/// Where the per-target manifests of the last successful build are kept
+pub fn manifest_dir() -> PathBuf {
+    PathBuf::from(".ship").join("manifests")
+}
+
+/// Where the report of the last build of each program is kept
+pub fn report_dir() -> PathBuf {
+    PathBuf::from(".ship").join("reports")
+}
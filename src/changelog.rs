@@ -0,0 +1,116 @@
+use std::{fs::File, io::Write, path::PathBuf, process::Command};
+
+use crate::{cache::work_dir, conf::ShipConfig};
+
+/// Where the gzipped changelog installed by .deb packages is written
+pub fn compressed_path(conf: &ShipConfig) -> PathBuf {
+    work_dir(conf).join("changelog").join("changelog.Debian.gz")
+}
+
+/// Where the changelog is installed, relative to `share_dir`
+pub fn install_path(conf: &ShipConfig) -> String {
+    format!("doc/{}/changelog.Debian.gz", conf.prog.name)
+}
+
+/// The distribution named in changelog entries, `[deb].distribution` or unstable
+pub fn distribution(conf: &ShipConfig) -> &str {
+    conf.deb
+        .as_ref()
+        .and_then(|deb| deb.distribution.as_deref())
+        .unwrap_or("unstable")
+}
+
+/// The changelog in Debian's format, with `version` and `distribution` in the generated entry.
+///
+/// `[changelog].file` is used as written, otherwise there's one entry for this release whose
+/// changes come from `[changelog].changes`, or from git with `from_git`.
+pub fn render(conf: &ShipConfig, version: &str, distribution: &str) -> Result<String, String> {
+    let changelog = conf.changelog.as_ref();
+    if let Some(file) = changelog.and_then(|c| c.file.as_ref()) {
+        return std::fs::read_to_string(file)
+            .map_err(|err| format!("failed to read [changelog].file {file}: {err}"));
+    }
+
+    let from_git = changelog.is_some_and(|c| c.from_git);
+    let mut changes = changelog.map(|c| c.changes.clone()).unwrap_or_default();
+    if from_git {
+        changes.extend(git_changes()?);
+    }
+    if changes.is_empty() {
+        changes.push("New upstream release.".to_string());
+    }
+
+    // the last commit's date keeps the changelog the same between builds of one commit
+    let date = from_git
+        .then(|| git(&["log", "-1", "--format=%aD"]).ok())
+        .flatten()
+        .unwrap_or_else(|| chrono::Utc::now().to_rfc2822());
+
+    let mut out = format!(
+        "{name} ({version}) {distribution}; urgency={urgency}\n\n",
+        name = conf.prog.name,
+        urgency = changelog
+            .and_then(|c| c.urgency.as_deref())
+            .unwrap_or("medium"),
+    );
+    for change in changes {
+        out.push_str(&format!("  * {change}\n"));
+    }
+    out.push_str(&format!("\n -- {}  {date}\n", conf.prog.author));
+
+    Ok(out)
+}
+
+/// Renders the changelog and gzips it to [compressed_path] at the maximum level, as Debian policy asks.
+pub fn write_compressed(
+    conf: &ShipConfig,
+    version: &str,
+    distribution: &str,
+) -> Result<PathBuf, String> {
+    let changelog = render(conf, version, distribution)?;
+    let out = compressed_path(conf);
+    if let Some(parent) = out.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+
+    let write = || -> std::io::Result<()> {
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&out)?, flate2::Compression::best());
+        encoder.write_all(changelog.as_bytes())?;
+        encoder.finish()?.flush()
+    };
+    write().map_err(|err| format!("failed to write {}: {err}", out.display()))?;
+
+    Ok(out)
+}
+
+// commit subjects since the last tag, oldest first; the whole history if nothing is tagged yet
+fn git_changes() -> Result<Vec<String>, String> {
+    let range = match git(&["describe", "--tags", "--abbrev=0"]) {
+        Ok(tag) => format!("{tag}..HEAD"),
+        Err(_) => "HEAD".to_string(),
+    };
+    let log = git(&["log", "--reverse", "--no-merges", "--format=%s", &range])?;
+
+    Ok(log
+        .lines()
+        .map(str::trim)
+        .filter(|subject| !subject.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+fn git(args: &[&str]) -> Result<String, String> {
+    let output = crate::log::command(Command::new("git").args(args))
+        .output()
+        .map_err(|err| format!("failed to run git for [changelog].from_git: {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "`git {}` failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
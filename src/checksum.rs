@@ -0,0 +1,57 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+/// sha256 of the packaged source files, shared by every target of a run so each file is
+/// read once however many targets package it.
+///
+/// An entry is reused only while the file's size and modification time are unchanged.
+#[derive(Default)]
+pub struct HashStore {
+    hashes: RefCell<HashMap<PathBuf, (Stamp, String)>>,
+}
+
+// size and modification time, what tells a file changed without reading it
+type Stamp = (u64, Option<SystemTime>);
+
+impl HashStore {
+    pub fn sha256(&self, path: &Path) -> io::Result<String> {
+        let metadata = std::fs::metadata(path)?;
+        let stamp = (metadata.len(), metadata.modified().ok());
+
+        if let Some((cached, hash)) = self.hashes.borrow().get(path)
+            && *cached == stamp
+        {
+            return Ok(hash.clone());
+        }
+
+        let hash = digest_file::<Sha256>(path)?;
+        self.hashes
+            .borrow_mut()
+            .insert(path.to_path_buf(), (stamp, hash.clone()));
+        Ok(hash)
+    }
+}
+
+/// Streams a file through the given digest and returns the lowercase hex hash.
+pub fn digest_file<D: Digest + io::Write>(path: &Path) -> io::Result<String> {
+    digest_reader::<D>(&mut File::open(path)?)
+}
+
+/// Streams everything left in `reader` through the given digest
+pub fn digest_reader<D: Digest + io::Write>(reader: &mut impl Read) -> io::Result<String> {
+    let mut hasher = D::new();
+    io::copy(reader, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,55 @@
+use std::path::Path;
+
+use crate::{
+    conf::ShipConfig,
+    layout::{Entry, masked_mode},
+};
+
+/// Debian's zsh only searches its own vendor directory
+pub const ZSH_DEBIAN: &str = "zsh/vendor-completions";
+/// Where every other distribution's zsh looks
+pub const ZSH_SITE: &str = "zsh/site-functions";
+
+/// The [files].completions scripts, installed under `share_dir` with the file names each
+/// shell loads them by: `<name>` for bash, `_<name>` for zsh and `<name>.fish` for fish
+pub fn entries(conf: &ShipConfig, share_dir: &Path, zsh_dir: &str) -> Result<Vec<Entry>, String> {
+    let Some(completions) = &conf.files.completions else {
+        return Ok(Vec::new());
+    };
+    let name = &conf.prog.name;
+
+    let scripts = [
+        (
+            "bash",
+            &completions.bash,
+            format!("bash-completion/completions/{name}"),
+        ),
+        ("zsh", &completions.zsh, format!("{zsh_dir}/_{name}")),
+        (
+            "fish",
+            &completions.fish,
+            format!("fish/vendor_completions.d/{name}.fish"),
+        ),
+    ];
+
+    let mut entries = Vec::new();
+    for (shell, source, dest) in scripts {
+        let Some(source) = source else {
+            continue;
+        };
+        if !Path::new(source).is_file() {
+            return Err(format!(
+                "{shell} completion {source} in [files].completions does not exist"
+            ));
+        }
+        entries.push(Entry {
+            source: source.into(),
+            dest: format!("{}/{dest}", share_dir.display()),
+            mode: masked_mode(conf, Path::new(source)),
+            owner: None,
+            group: None,
+        });
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,137 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+use crate::conf::{Compression, CompressionAlgorithm};
+
+/// A streaming encoder for any of the algorithms a Shipfile can pick
+pub enum Encoder<W: Write> {
+    Gzip(flate2::write::GzEncoder<W>),
+    Xz(xz2::write::XzEncoder<W>),
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl CompressionAlgorithm {
+    pub fn default_level(&self) -> u32 {
+        match self {
+            CompressionAlgorithm::Gzip => 6,
+            CompressionAlgorithm::Xz => 6,
+            CompressionAlgorithm::Zstd => 3,
+        }
+    }
+
+    /// File extension of a stream compressed with this algorithm
+    pub fn extension(&self) -> &'static str {
+        match self {
+            CompressionAlgorithm::Gzip => "gz",
+            CompressionAlgorithm::Xz => "xz",
+            CompressionAlgorithm::Zstd => "zst",
+        }
+    }
+}
+
+impl<W: Write> Encoder<W> {
+    /// An encoder using up to `threads` workers; gzip always uses one.
+    ///
+    /// Threaded xz and zstd split the stream into independently compressed blocks, so the
+    /// output differs from (and is slightly larger than) single-threaded output, but it's
+    /// the same for any number of threads above one.
+    pub fn new(
+        writer: W,
+        algorithm: CompressionAlgorithm,
+        level: u32,
+        threads: u32,
+    ) -> io::Result<Self> {
+        Ok(match algorithm {
+            CompressionAlgorithm::Gzip => Encoder::Gzip(flate2::write::GzEncoder::new(
+                writer,
+                flate2::Compression::new(level),
+            )),
+            CompressionAlgorithm::Xz if threads > 1 => {
+                let stream = xz2::stream::MtStreamBuilder::new()
+                    .threads(threads)
+                    .preset(level)
+                    .check(xz2::stream::Check::Crc64)
+                    .encoder()
+                    .map_err(io::Error::other)?;
+                Encoder::Xz(xz2::write::XzEncoder::new_stream(writer, stream))
+            }
+            CompressionAlgorithm::Xz => Encoder::Xz(xz2::write::XzEncoder::new(writer, level)),
+            CompressionAlgorithm::Zstd => {
+                let mut encoder = zstd::stream::write::Encoder::new(writer, level as i32)?;
+                if threads > 1 {
+                    encoder.multithread(threads)?;
+                }
+                Encoder::Zstd(encoder)
+            }
+        })
+    }
+
+    /// Uses `compression` if the Shipfile sets one, otherwise the format's own `default`
+    pub fn for_config(
+        writer: W,
+        compression: Option<&Compression>,
+        default: (CompressionAlgorithm, u32),
+        threads: u32,
+    ) -> io::Result<Self> {
+        let (algorithm, level) = match compression {
+            Some(c) => (c.algorithm, c.level.unwrap_or(c.algorithm.default_level())),
+            None => default,
+        };
+        Self::new(writer, algorithm, level, threads)
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Xz(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write(buf),
+            Encoder::Xz(encoder) => encoder.write(buf),
+            Encoder::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.flush(),
+            Encoder::Xz(encoder) => encoder.flush(),
+            Encoder::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Decompresses a gzip, xz or zstd stream as it's read, told apart by its magic bytes;
+/// anything else is passed through as is
+pub fn decoder<'a>(mut reader: impl BufRead + 'a) -> io::Result<Box<dyn Read + 'a>> {
+    let magic = reader.fill_buf()?;
+    let (gzip, xz, zstd) = (
+        magic.starts_with(&[0x1f, 0x8b]),
+        magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]),
+        magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]),
+    );
+
+    Ok(if gzip {
+        Box::new(flate2::bufread::GzDecoder::new(reader))
+    } else if xz {
+        Box::new(xz2::bufread::XzDecoder::new(reader))
+    } else if zstd {
+        Box::new(zstd::stream::read::Decoder::with_buffer(reader)?)
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Opens a file that may be compressed, to be read a buffer at a time however large it is
+pub fn open_decompressed(path: &Path) -> io::Result<Box<dyn Read>> {
+    decoder(BufReader::new(File::open(path)?))
+}
@@ -1,14 +1,18 @@
+use std::collections::HashMap;
+
 use clap::ValueEnum;
 use deb::DebArchitecture;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct Prog {
-    pub name: String,   // required
-    pub author: String, // required
+    pub name: String,       // required
+    pub author: String,     // required
+    pub id: Option<String>, // optional, reverse-DNS application id
     pub arch: Arch,
     pub version: Option<String>,     // optional
     pub description: Option<String>, // optional
+    pub main: Option<String>, // optional, the primary executable as listed in [files].paths, otherwise picked by name
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
@@ -68,23 +72,296 @@ impl Arch {
             Arch::KFreebsdAmd64 => DebArchitecture::KFreebsdAmd64,
         }
     }
+
+    /// Architecture name as spelled by dpkg
+    pub fn deb_name(&self) -> &'static str {
+        match self {
+            Arch::All => "all",
+            Arch::Alpha => "alpha",
+            Arch::Armel => "armel",
+            Arch::Armhf => "armhf",
+            Arch::Arm64 => "arm64",
+            Arch::Hppa => "hppa",
+            Arch::I386 => "i386",
+            Arch::Amd64 => "amd64",
+            Arch::Ia64 => "ia64",
+            Arch::M68k => "m68k",
+            Arch::Mips => "mips",
+            Arch::Mipsel => "mipsel",
+            Arch::Mips64el => "mips64el",
+            Arch::PowerPC => "powerpc",
+            Arch::Ppc64 => "ppc64",
+            Arch::Ppc64el => "ppc64el",
+            Arch::Riscv64 => "riscv64",
+            Arch::S390x => "s390x",
+            Arch::Sh4 => "sh4",
+            Arch::Sparc4 => "sparc64",
+            Arch::X32 => "x32",
+            Arch::HurdI386 => "hurd-i386",
+            Arch::KFreebsdI386 => "kfreebsd-i386",
+            Arch::KFreebsdAmd64 => "kfreebsd-amd64",
+        }
+    }
+
+    /// Gentoo keyword for this architecture, if Gentoo supports it
+    pub fn gentoo_keyword(&self) -> Option<&'static str> {
+        match self {
+            Arch::Alpha => Some("alpha"),
+            Arch::Armel | Arch::Armhf => Some("arm"),
+            Arch::Arm64 => Some("arm64"),
+            Arch::Hppa => Some("hppa"),
+            Arch::I386 => Some("x86"),
+            Arch::Amd64 => Some("amd64"),
+            Arch::Ia64 => Some("ia64"),
+            Arch::M68k => Some("m68k"),
+            Arch::Mips | Arch::Mipsel | Arch::Mips64el => Some("mips"),
+            Arch::PowerPC => Some("ppc"),
+            Arch::Ppc64 | Arch::Ppc64el => Some("ppc64"),
+            Arch::Riscv64 => Some("riscv"),
+            Arch::S390x => Some("s390"),
+            Arch::Sh4 => Some("sh"),
+            Arch::Sparc4 => Some("sparc"),
+            _ => None,
+        }
+    }
+
+    /// Termux architecture name, if Termux supports it
+    pub fn termux(&self) -> Option<&'static str> {
+        match self {
+            Arch::All => Some("all"),
+            Arch::Arm64 => Some("aarch64"),
+            Arch::Armhf | Arch::Armel => Some("arm"),
+            Arch::I386 => Some("i686"),
+            Arch::Amd64 => Some("x86_64"),
+            _ => None,
+        }
+    }
+
+    /// Architecture name of the AppImage type-2 runtime, if there is a runtime for it
+    pub fn appimage(&self) -> Option<&'static str> {
+        match self {
+            Arch::All | Arch::Amd64 => Some("x86_64"),
+            Arch::I386 => Some("i686"),
+            Arch::Arm64 => Some("aarch64"),
+            Arch::Armhf => Some("armhf"),
+            _ => None,
+        }
+    }
+
+    /// Void Linux architecture name, if Void supports it
+    pub fn xbps(&self) -> Option<&'static str> {
+        match self {
+            Arch::All => Some("noarch"),
+            Arch::Amd64 => Some("x86_64"),
+            Arch::I386 => Some("i686"),
+            Arch::Arm64 => Some("aarch64"),
+            Arch::Armhf => Some("armv7l"),
+            Arch::Armel => Some("armv6l"),
+            Arch::Ppc64el => Some("ppc64le"),
+            Arch::Ppc64 => Some("ppc64"),
+            Arch::PowerPC => Some("ppc"),
+            Arch::Riscv64 => Some("riscv64"),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct Files {
-    pub paths: Vec<String>,      // required
-    pub icon: Option<String>,    // optional
-    pub license: Option<String>, // optional
+    #[serde(default)]
+    pub paths: Vec<FileEntry>, // required unless root is set, entries may be globs like assets/**/*.png
+    #[serde(default)]
+    pub exclude: Vec<String>, // optional, globs like *.o or .DS_Store left out of globs and directories
+    pub root: Option<String>, // optional, DESTDIR-style staged install tree
+    pub icon: Option<String>, // optional
+    pub license: Option<String>, // optional, a license file or an SPDX expression like MIT OR Apache-2.0
+    #[serde(default)]
+    pub remote: Vec<Remote>, // optional, files downloaded at build time
+    #[serde(default)]
+    pub help2man: bool, // generate manpages from each executable's --help output
+    #[serde(default)]
+    pub man: Vec<String>, // optional, manpages like docs/ship.1, gzipped and installed by section
+    pub completions: Option<Completions>, // optional
+    #[serde(default, deserialize_with = "deserialize_umask")]
+    pub umask: Option<u32>, // optional, octal string, bits cleared from files that keep their own mode
+}
+
+/// A [files].paths entry: a bare path, installed at the same relative path under the install
+/// prefix, or a table like `{ from = "target/release/app", to = "/usr/bin/app", mode = "0755" }`.
+///
+/// `exec = true` or `false` in the table is short for mode 0755 or 0644.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct FileEntry {
+    pub from: String,          // required
+    pub to: Option<String>,    // optional, absolute, or relative to the install prefix
+    pub mode: Option<u32>, // optional, octal string in the Shipfile, applies to every file of a directory
+    pub owner: Option<String>, // optional, user name or uid that owns the installed files, defaults to root
+    pub group: Option<String>, // optional, group name or gid, defaults to root
+}
+
+impl<'de> Deserialize<'de> for FileEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Path(String),
+            Mapped {
+                from: String,
+                to: Option<String>,
+                mode: Option<String>,
+                exec: Option<bool>,
+                owner: Option<String>,
+                group: Option<String>,
+            },
+        }
+
+        let (from, to, mode, exec, owner, group) = match Raw::deserialize(deserializer)? {
+            Raw::Path(from) => (from, None, None, None, None, None),
+            Raw::Mapped {
+                from,
+                to,
+                mode,
+                exec,
+                owner,
+                group,
+            } => (from, to, mode, exec, owner, group),
+        };
+        let mode = mode
+            .map(|mode| {
+                parse_mode(&mode).ok_or_else(|| {
+                    serde::de::Error::custom(format!(
+                        "invalid mode `{mode}` for {from}, expected an octal string like \"0755\""
+                    ))
+                })
+            })
+            .transpose()?;
+        // `exec` doesn't rely on the file's own permissions, which a Windows checkout lacks
+        let mode = match (mode, exec) {
+            (Some(mode), Some(exec)) if (mode & 0o111 != 0) != exec => {
+                return Err(serde::de::Error::custom(format!(
+                    "mode `{mode:04o}` and exec = {exec} disagree for {from}, set only one"
+                )));
+            }
+            (None, Some(true)) => Some(0o755),
+            (None, Some(false)) => Some(0o644),
+            (mode, _) => mode,
+        };
+        for name in [&owner, &group].into_iter().flatten() {
+            if name.is_empty() || name.contains([':', '/', ' ']) {
+                return Err(serde::de::Error::custom(format!(
+                    "invalid owner or group `{name}` for {from}, expected a name like \"www-data\" or a numeric id"
+                )));
+            }
+        }
+
+        Ok(Self {
+            from,
+            to,
+            mode,
+            owner,
+            group,
+        })
+    }
+}
+
+/// Permission bits from an octal string like "0755" or "0o755"
+fn parse_mode(mode: &str) -> Option<u32> {
+    u32::from_str_radix(mode.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|bits| *bits <= 0o7777)
+}
+
+fn deserialize_umask<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|umask| {
+            parse_mode(&umask)
+                .filter(|bits| *bits <= 0o777)
+                .ok_or_else(|| {
+                    serde::de::Error::custom(format!(
+                        "invalid [files].umask `{umask}`, expected an octal string like \"0022\""
+                    ))
+                })
+        })
+        .transpose()
+}
+
+impl FileEntry {
+    /// Destination of the file: `to` if absolute, otherwise under `prefix`
+    pub fn dest(&self, prefix: &str) -> String {
+        match &self.to {
+            Some(to) if to.starts_with('/') => to.clone(),
+            Some(to) => format!("{prefix}/{}", to.trim_start_matches("./")),
+            None => format!(
+                "{prefix}/{}",
+                self.from.strip_prefix("./").unwrap_or(&self.from)
+            ),
+        }
+    }
+}
+
+/// Shell completion scripts, installed where each shell looks for vendor completions
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Completions {
+    pub bash: Option<String>, // optional
+    pub zsh: Option<String>,  // optional, a `#compdef` file
+    pub fish: Option<String>, // optional
+}
+
+/// A file fetched from a URL and verified against its sha256
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Remote {
+    pub url: String,        // required
+    pub sha256: String,     // required
+    pub to: Option<String>, // optional, path inside the install prefix, defaults to the URL's file name
+}
+
+impl Remote {
+    /// Path of the file relative to the install prefix
+    pub fn install_path(&self) -> String {
+        if let Some(to) = &self.to {
+            return to.trim_start_matches("./").to_string();
+        }
+
+        let url = self.url.split(['?', '#']).next().unwrap_or(&self.url);
+        url.rsplit('/').next().unwrap_or(url).to_string()
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub struct Build {
     pub cmd: Option<String>, // optional build command
     pub cwd: Option<String>, // optional working directory
+    #[serde(default)]
+    pub args: Vec<String>, // optional, appended to cmd, each quoted as a single argument
+    #[serde(default)]
+    pub stamp_version: bool, // write [prog].version into the built executables, see stamp.rs
+    #[serde(default)]
+    pub steps: Vec<BuildStep>, // optional, `[[build.steps]]` run in order after cmd
+    #[serde(default)]
+    pub allow_failure: bool, // package whatever is there even if cmd or a step fails
+}
+
+impl Build {
+    /// Whether there's anything to run
+    pub fn has_commands(&self) -> bool {
+        self.cmd.is_some() || !self.steps.is_empty()
+    }
+}
+
+/// One step of a multi-step build; the build stops at the first one that fails
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct BuildStep {
+    pub name: Option<String>, // optional, shown in the log, defaults to the command
+    pub cmd: String,          // required, run through the shell
+    pub cwd: Option<String>,  // optional working directory
+    #[serde(default)]
+    pub env: HashMap<String, String>, // optional, on top of [vars].env
+    #[serde(default)]
+    pub only_targets: Vec<Target>, // optional, skipped unless one of these targets is built
 }
 
 /// Supported installer target types
-#[derive(Serialize, Deserialize, ValueEnum, Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
 pub enum Target {
     Exe,
     Msi,
@@ -93,6 +370,53 @@ pub enum Target {
     Deb,
     AppImage,
     Rpm,
+    Ebuild,
+    Xbps,
+    DebSource,
+    Appcast,
+    Archive,
+    /// Any other name, built by the generator registered for it with [crate::gen_::register]
+    #[serde(untagged)]
+    Custom(String),
+}
+
+/// The built-in targets only, custom ones can't be named on the command line
+impl ValueEnum for Target {
+    fn value_variants<'a>() -> &'a [Self] {
+        &[
+            Target::Exe,
+            Target::Msi,
+            Target::Dmg,
+            Target::Pkg,
+            Target::Deb,
+            Target::AppImage,
+            Target::Rpm,
+            Target::Ebuild,
+            Target::Xbps,
+            Target::DebSource,
+            Target::Appcast,
+            Target::Archive,
+        ]
+    }
+
+    fn to_possible_value(&self) -> Option<clap::builder::PossibleValue> {
+        let name = match self {
+            Target::Exe => "exe",
+            Target::Msi => "msi",
+            Target::Dmg => "dmg",
+            Target::Pkg => "pkg",
+            Target::Deb => "deb",
+            Target::AppImage => "app-image",
+            Target::Rpm => "rpm",
+            Target::Ebuild => "ebuild",
+            Target::Xbps => "xbps",
+            Target::DebSource => "deb-source",
+            Target::Appcast => "appcast",
+            Target::Archive => "archive",
+            Target::Custom(_) => return None,
+        };
+        Some(clap::builder::PossibleValue::new(name))
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
@@ -100,16 +424,571 @@ pub struct Out {
     pub targets: Vec<Target>, // required
     #[serde(default = "default_bin_dir")]
     pub bin: String,
+    pub prefix: Option<String>, // optional, install prefix instead of /opt/<name>
+    pub work_dir: Option<String>, // optional, scratch space for staging and tools instead of .ship/work
+    pub compression_threads: Option<u32>, // optional, xz and zstd workers, 0 for one per CPU, defaults to 1
+    pub checksums: Option<bool>, // optional, write SHA256SUMS next to the artifacts after every build, defaults to true
+    pub manifest: Option<bool>, // optional, also write manifest.json listing each artifact's target, size and sha256
+    pub sbom: Option<SbomFormat>, // optional, write an SBOM of the packaged files and locked dependencies next to each artifact
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SbomFormat {
+    Spdx,      // <artifact>.spdx.json
+    CycloneDx, // <artifact>.cdx.json
+}
+
+/// A Debian version as file names carry it, without the epoch, the way dpkg-name writes them
+pub fn without_epoch(version: &str) -> &str {
+    version
+        .split_once(':')
+        .map_or(version, |(_, version)| version)
 }
 
 fn default_bin_dir() -> String {
     "./bin/".to_string()
 }
 
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
 pub struct Vars {
-    pub env: Option<Vec<String>>,   // optional
-    pub arg: Option<Vec<String>>,   // optional
+    pub env: Option<Vec<String>>, // optional, KEY=VALUE, set in the build command's environment
+    pub arg: Option<Vec<String>>, // optional, `name` or `name=default`, set with --var
+    pub cmake: Option<Vec<String>>, // optional, KEY=VALUE or KEY:TYPE=VALUE, passed to cmake as -D flags
+}
+
+impl Vars {
+    /// `[vars].env` split into key/value pairs
+    pub fn env_pairs(&self) -> Result<Vec<(String, String)>, String> {
+        self.env
+            .iter()
+            .flatten()
+            .map(|entry| {
+                entry
+                    .split_once('=')
+                    .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                    .ok_or_else(|| {
+                        format!("invalid [vars].env entry `{entry}`, expected KEY=VALUE")
+                    })
+            })
+            .collect()
+    }
+
+    /// `[vars].cmake` as cmake cache definitions
+    pub fn cmake_flags(&self) -> Result<Vec<String>, String> {
+        self.cmake
+            .iter()
+            .flatten()
+            .map(|entry| match entry.split_once('=') {
+                Some((key, _)) if !key.trim().is_empty() => Ok(format!("-D{entry}")),
+                _ => Err(format!(
+                    "invalid [vars].cmake entry `{entry}`, expected KEY=VALUE"
+                )),
+            })
+            .collect()
+    }
+}
+
+/// Debian package settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Deb {
+    #[serde(default)]
+    pub termux: bool, // relocate everything under the Termux prefix on Android
+    pub distribution: Option<String>, // optional, distribution named in the changelog, defaults to unstable
+    #[serde(default)]
+    pub shlibdeps: bool, // add the packages of linked shared libraries to Depends, needs dpkg-dev
+    #[serde(default)]
+    pub recommends: Vec<String>, // optional, e.g. ["ca-certificates"]
+    #[serde(default)]
+    pub suggests: Vec<String>, // optional
+    #[serde(default)]
+    pub conflicts: Vec<String>, // optional
+    #[serde(default)]
+    pub breaks: Vec<String>, // optional
+    #[serde(default)]
+    pub replaces: Vec<String>, // optional
+    #[serde(default)]
+    pub provides: Vec<String>, // optional
+    #[serde(default)]
+    pub conffiles: Vec<String>, // optional, installed paths under /etc whose local edits survive upgrades
+    pub section: Option<String>, // optional, archive section like "utils" or "net", defaults to misc
+    pub priority: Option<Priority>, // optional, defaults to optional
+    pub homepage: Option<String>, // optional
+    pub long_description: Option<String>, // optional, extended description shown below [prog].description, may span several lines
+    pub revision: Option<String>, // optional, Debian revision appended as <version>-<revision>, defaults to 1 for DebSource
+    pub epoch: Option<u32>, // optional, prepended as <epoch>:<version>, to make a lower version sort as newer
+    #[serde(default)]
+    pub strip: bool, // strip ELF files and ship their debug symbols in <name>-dbgsym, needs binutils
+    pub alternatives: Option<Alternatives>, // optional, link commands with update-alternatives so several versions can be installed side by side
+    #[serde(flatten)]
+    pub overrides: TargetOverrides,
+}
+
+impl Deb {
+    /// Package relationship fields that are set, in the order Debian policy lists them.
+    ///
+    /// `Depends` comes from the shared `depends` list.
+    pub fn relationships(&self) -> Vec<(&'static str, String)> {
+        [
+            ("Depends", &self.overrides.depends),
+            ("Recommends", &self.recommends),
+            ("Suggests", &self.suggests),
+            ("Breaks", &self.breaks),
+            ("Conflicts", &self.conflicts),
+            ("Provides", &self.provides),
+            ("Replaces", &self.replaces),
+        ]
+        .into_iter()
+        .filter(|(_, values)| !values.is_empty())
+        .map(|(field, values)| (field, values.join(", ")))
+        .collect()
+    }
+}
+
+/// Commands linked into /usr/bin by update-alternatives instead of shipped as symlinks
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Alternatives {
+    pub priority: u32, // required, the installed version with the highest priority provides the command
+    #[serde(default)]
+    pub commands: Vec<String>, // optional, which /usr/bin links to manage, defaults to all of them
+}
+
+/// How important a Debian package is to the system, as Debian policy ranks it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Required,
+    Important,
+    Standard,
+    #[default]
+    Optional,
+    Extra,
+}
+
+impl Priority {
+    pub fn deb_name(&self) -> &'static str {
+        match self {
+            Priority::Required => "required",
+            Priority::Important => "important",
+            Priority::Standard => "standard",
+            Priority::Optional => "optional",
+            Priority::Extra => "extra",
+        }
+    }
+}
+
+/// Plain tarball settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Archive {
+    #[serde(default)]
+    pub scope: InstallScope, // optional, where install.sh puts things by default
+    #[serde(flatten)]
+    pub overrides: TargetOverrides,
+}
+
+/// Whether an installer targets the whole system or only the current user
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum InstallScope {
+    #[default]
+    System, // the install prefix and /usr/local/bin, needs root
+    User, // XDG user directories: ~/.local/share/<name> and ~/.local/bin
+}
+
+/// Gentoo ebuild settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Ebuild {
+    pub src_uri: String, // required, where the generated tarball will be hosted
+    pub homepage: Option<String>, // optional
+    pub license: Option<String>, // optional
+    pub keywords: Option<Vec<String>>, // optional, derived from prog.arch by default
+    #[serde(flatten)]
+    pub overrides: TargetOverrides,
+}
+
+/// Void Linux xbps settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Xbps {
+    pub revision: Option<u32>,    // optional, defaults to 1
+    pub license: Option<String>,  // optional
+    pub homepage: Option<String>, // optional
+    #[serde(flatten)]
+    pub overrides: TargetOverrides,
+}
+
+/// AppImage settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct AppImage {
+    #[serde(default)]
+    pub bundle_libs: bool, // copy the non-system shared libraries the executables link against into usr/lib, needs patchelf
+    pub bundle: Option<BundleProfile>, // optional, also bundle a toolkit's plugins and set them up in AppRun; implies bundle_libs
+    pub apprun: Option<AppRun>,        // optional, replaces the AppRun link to the program
+    pub arch: Option<Arch>,            // optional, defaults to [prog].arch
+    pub runtime: Option<String>, // optional, path or URL of a type-2 runtime; defaults to the appimage crate's on x86_64, upstream's latest otherwise
+    pub runtime_sha256: Option<String>, // optional, pins the runtime, checked before it's used
+    pub update_info: Option<String>, // optional, embedded for AppImageUpdate, e.g. "gh-releases-zsync|user|repo|latest|app-*x86_64.AppImage.zsync"; zsync transports also get a .zsync file next to the AppImage
+    #[serde(flatten)]
+    pub overrides: TargetOverrides,
+}
+
+/// What the AppImage runs on launch: a script of your own, or a generated one that
+/// sets up the environment and then starts the program
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+#[serde(untagged)]
+pub enum AppRun {
+    Script(String), // path to the AppRun to use as is
+    Generated(AppRunSettings),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct AppRunSettings {
+    #[serde(default)]
+    pub env: HashMap<String, String>, // optional, exported before the program starts, $APPDIR and other variables expand, e.g. { LD_LIBRARY_PATH = "$APPDIR/usr/lib:$LD_LIBRARY_PATH" }
+    pub working_dir: Option<String>, // optional, relative to the AppDir, e.g. "usr/share/<name>"; defaults to wherever it was launched from
+}
+
+/// Toolkits whose runtime plugins can be bundled into an AppImage
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleProfile {
+    Qt,  // platform, image format, SQL driver, ... plugins, found through QT_PLUGIN_PATH
+    Gtk, // GDK pixbuf loaders, GTK modules and the compiled GSettings schemas
+}
+
+/// Settings any per-target section (`[deb]`, `[appimage]`, `[msi]`, ...) can override
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct TargetOverrides {
+    pub paths: Option<Vec<FileEntry>>, // optional, replaces [files].paths
+    pub icon: Option<String>,          // optional, replaces [files].icon
+    pub prefix: Option<String>,        // optional, install prefix instead of /opt/<name>
+    #[serde(default)]
+    pub depends: Vec<String>, // optional, runtime dependencies in the target's own naming
+    pub output: Option<String>,        // optional, artifact name inside [out].bin
+    pub compression: Option<Compression>, // optional, payload compression, see `ship tune-compression`
+    pub post_process: Option<String>, // optional, run on each artifact, `{artifact}` is replaced by its path
+}
+
+/// Payload compression for formats that let the packager choose; `compression = "zstd"`
+/// is short for the algorithm at its default level
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct Compression {
+    pub algorithm: CompressionAlgorithm, // required
+    pub level: Option<u32>,              // optional, the algorithm's default level if unset
+}
+
+impl<'de> Deserialize<'de> for Compression {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Table {
+            algorithm: CompressionAlgorithm,
+            level: Option<u32>,
+        }
+
+        let value = toml::Value::deserialize(deserializer)?;
+        let compression = match value {
+            toml::Value::String(_) => {
+                CompressionAlgorithm::deserialize(value).map(|algorithm| Self {
+                    algorithm,
+                    level: None,
+                })
+            }
+            value => Table::deserialize(value).map(|table| Self {
+                algorithm: table.algorithm,
+                level: table.level,
+            }),
+        };
+        compression.map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CompressionAlgorithm {
+    #[serde(alias = "gzip")]
+    Gzip,
+    #[serde(alias = "xz")]
+    Xz,
+    #[serde(alias = "zstd")]
+    Zstd,
+}
+
+/// Desktop entry installed by the Linux packages, so the program shows up in app launchers
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Desktop {
+    pub name: Option<String>,         // optional, defaults to [prog].name
+    pub generic_name: Option<String>, // optional, e.g. "Text Editor"
+    pub comment: Option<String>,      // optional, defaults to [prog].description
+    #[serde(default)]
+    pub categories: Vec<String>, // optional, e.g. ["Development", "Utility"]
+    #[serde(default)]
+    pub mime_types: Vec<String>, // optional, file types the program opens
+    #[serde(default)]
+    pub terminal: bool, // run inside a terminal emulator
+    pub startup_wm_class: Option<String>, // optional, the window class, so docks group the windows with the launcher
+    #[serde(default)]
+    pub localized_names: HashMap<String, String>, // optional, keyed by locale, e.g. { de = "Hallo" }
+}
+
+/// Shell scripts run around installation and removal, where the target supports it
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Scripts {
+    pub preinstall: Option<String>,  // optional, path to a shell script
+    pub postinstall: Option<String>, // optional, also run on the first launch of an AppImage
+    pub preremove: Option<String>,   // optional
+    pub postremove: Option<String>,  // optional
+}
+
+/// Debian changelog settings, for the Deb and DebSource targets
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Changelog {
+    pub file: Option<String>, // optional, a complete changelog in Debian's format, used instead of generating one
+    #[serde(default)]
+    pub changes: Vec<String>, // optional, the entries for this release, defaults to "New upstream release."
+    #[serde(default)]
+    pub from_git: bool, // fill the entries from the commit subjects since the last git tag
+    pub urgency: Option<String>, // optional, defaults to medium
+}
+
+/// How artifacts are signed: gpg with the top-level keys, plus a table per other backend.
+/// Every generator's artifacts go through it, see signing.rs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Signing {
+    pub key: Option<String>, // optional, key id or fingerprint, defaults to gpg's default key
+    pub passphrase_env: Option<String>, // optional, variable holding the key's passphrase, otherwise gpg-agent asks for it
+    pub appimage: Option<bool>, // optional, embed a signature into AppImages and write <file>.sig next to them, defaults to true
+    pub detach: Option<bool>, // optional, write an ASCII-armored <file>.asc next to every artifact with gpg, defaults to false
+    pub minisign: Option<Minisign>, // optional, write <file>.minisig next to every artifact
+    pub cosign: Option<Cosign>, // optional, write <file>.cosign.bundle next to every artifact
+    pub windows: Option<WindowsSigning>, // optional, Authenticode for .exe and .msi artifacts
+    pub macos: Option<MacosSigning>, // optional, codesign .dmg and productsign .pkg artifacts
+}
+
+/// A minisign secret key
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Minisign {
+    pub key: String, // required, secret key file, e.g. "keys/minisign.key"
+    pub password_env: Option<String>, // optional, variable holding the key's password, otherwise minisign asks for it
+}
+
+/// A cosign key: a key file, a KMS URI like awskms://... or a PKCS#11 URI like pkcs11:...,
+/// or none for keyless signing, with a short-lived Fulcio certificate for the OIDC identity
+/// of the CI job, logged in Rekor
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Cosign {
+    pub key: Option<String>,                // optional, omit to sign keylessly
+    pub password_env: Option<String>, // optional, variable holding a key file's password, handed to cosign as COSIGN_PASSWORD
+    pub identity_token_env: Option<String>, // optional, variable holding the OIDC token to sign keylessly with, defaults to what cosign finds in the CI environment
+    pub provenance: Option<bool>, // optional, also write <file>.intoto.bundle, a signed SLSA provenance attestation, defaults to false
+}
+
+/// The Authenticode certificate: a .pfx file, one in the Windows certificate store, a key
+/// on a PKCS#11 token or HSM, or one kept in Azure Key Vault
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct WindowsSigning {
+    pub cert: Option<String>, // optional, PKCS#12 file (.pfx/.p12) holding the certificate and its key
+    pub password_env: Option<String>, // optional, variable holding the .pfx password or the PKCS#11 PIN
+    pub thumbprint: Option<String>, // optional, SHA-1 thumbprint of a certificate in the Windows certificate store, signtool only
+    pub pkcs11_module: Option<String>, // optional, PKCS#11 library of the token or HSM, e.g. "/usr/lib/libykcs11.so"
+    pub pkcs11_key: Option<String>,    // optional, pkcs11: URI of the key on it
+    pub pkcs11_cert: Option<String>, // optional, PEM or DER file with the certificate chain of that key, required with pkcs11_key
+    pub azure_key_vault: Option<AzureKeyVault>, // optional
+    pub timestamp_url: Option<String>, // optional, RFC 3161 timestamp server, defaults to http://timestamp.digicert.com
+}
+
+/// A certificate kept in Azure Key Vault, signed with through AzureSignTool. The service
+/// principal comes from AZURE_CLIENT_ID, AZURE_TENANT_ID and AZURE_CLIENT_SECRET when
+/// they're set, otherwise the managed identity is used
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct AzureKeyVault {
+    pub url: String,         // required, e.g. "https://myvault.vault.azure.net"
+    pub certificate: String, // required, name of the certificate in the vault
+}
+
+/// The Developer ID identities in the keychain, and the credentials to notarize with
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MacosSigning {
+    pub identity: String, // required, e.g. "Developer ID Application: Jane Doe (TEAMID)", signs disk images
+    pub installer_identity: Option<String>, // optional, e.g. "Developer ID Installer: Jane Doe (TEAMID)", required to sign .pkg installers
+    pub keychain: Option<String>, // optional, keychain to find the identities in, defaults to the search list
+    pub team_id: Option<String>, // optional, Developer Team ID, checked against the identities and passed to notarytool
+    pub keychain_profile: Option<String>, // optional, notarytool credentials saved with `xcrun notarytool store-credentials`; notarizes and staples when set
+}
+
+impl Signing {
+    /// Whether gpg signs at all: with a key or passphrase set, or when no other backend is
+    pub fn uses_gpg(&self) -> bool {
+        self.key.is_some()
+            || self.passphrase_env.is_some()
+            || self.detach == Some(true)
+            || (self.minisign.is_none()
+                && self.cosign.is_none()
+                && self.windows.is_none()
+                && self.macos.is_none())
+    }
+}
+
+/// Where released artifacts end up
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Publish {
+    pub base_url: Option<String>, // optional, artifacts are downloadable as <base_url>/<file>, defaults to [appcast].base_url
+    pub github: Option<GithubRelease>, // optional
+    pub s3: Option<S3Upload>,     // optional
+    pub http: Option<HttpUpload>, // optional
+    pub ppa: Option<PpaUpload>,   // optional
+}
+
+/// The GitHub Release `ship publish github` uploads to, see github.rs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct GithubRelease {
+    pub repo: Option<String>, // optional, `owner/name`, defaults to the origin remote
+    pub token_env: Option<String>, // optional, variable holding the token, defaults to GITHUB_TOKEN, then GH_TOKEN
+    #[serde(default)]
+    pub draft: bool, // create the release as a draft
+    #[serde(default)]
+    pub prerelease: bool, // create the release as a prerelease
+    pub api_url: Option<String>, // optional, for GitHub Enterprise, defaults to https://api.github.com
+}
+
+/// The S3-compatible bucket `ship publish s3` and `ship release-all` upload to, see upload.rs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct S3Upload {
+    pub bucket: String,                 // required
+    pub region: Option<String>,         // optional, defaults to us-east-1
+    pub endpoint: Option<String>, // optional, for R2, MinIO, Spaces and the like, defaults to https://s3.<region>.amazonaws.com
+    pub key: Option<String>, // optional, object key of each file with ${file} for its name, defaults to "${name}/${version}/${file}"
+    pub acl: Option<String>, // optional, canned ACL such as "public-read"
+    pub access_key_env: Option<String>, // optional, variable holding the access key id, defaults to AWS_ACCESS_KEY_ID
+    pub secret_key_env: Option<String>, // optional, variable holding the secret key, defaults to AWS_SECRET_ACCESS_KEY
+}
+
+/// The server `ship publish http` and `ship release-all` PUT each file to, see upload.rs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct HttpUpload {
+    pub url: String, // required, with ${file} for the file name, e.g. "https://dl.example.org/releases/${version}/${file}"
+    pub token_env: Option<String>, // optional, variable holding a token sent as `Authorization: Bearer`
+}
+
+/// Where `ship publish ppa` uploads the Debian source package with dput, see ppa.rs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct PpaUpload {
+    pub target: String, // required, dput target such as "ppa:me/myapp" or a host from the dput config
+    pub dput_config: Option<String>, // optional, dput config file instead of ~/.dput.cf
+}
+
+/// Steps `ship release-all` runs after packaging
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Release {
+    pub checksums: Option<bool>, // optional, write a SHA256SUMS file for the artifacts, defaults to true
+    pub sign: Option<String>, // optional, run per artifact with {artifact} replaced, e.g. "gpg --armor --detach-sign {artifact}"
+    pub passphrase_env: Option<String>, // optional, variable the sign command reads its passphrase from, asked for if unset
+    pub sbom: Option<bool>, // optional, write an SPDX SBOM describing the artifacts, defaults to true
+    pub publish: Option<String>, // optional, run per released file with {artifact} replaced, e.g. "rclone copy {artifact} releases:"
+    pub promote: Option<String>, // optional, publish the update feed staged and make it live with `ship promote <version>`, which runs this with {artifact} replaced, e.g. "rclone copy {artifact} releases:"
+}
+
+/// Update feed settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Appcast {
+    pub base_url: String, // required, where the artifacts will be downloadable from
+    pub format: Option<AppcastFormat>, // optional, defaults to Json
+    pub file: Option<String>, // optional, file name inside [out].bin
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub enum AppcastFormat {
+    Json,
+    Sparkle,
+}
+
+/// Windows installer (MSI/NSIS) settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Windows {
+    pub ui: Option<WindowsUi>,               // optional
+    pub resources: Option<WindowsResources>, // optional
+}
+
+/// Icon and version strings stamped into every `.exe` in [files].paths with rcedit
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct WindowsResources {
+    #[serde(default)]
+    pub icons: Vec<String>, // optional, one .ico, or PNGs of several sizes packed into one
+    pub company: Option<String>,     // optional, CompanyName
+    pub product: Option<String>,     // optional, ProductName, defaults to [prog].name
+    pub description: Option<String>, // optional, FileDescription, defaults to [prog].description
+    pub copyright: Option<String>,   // optional, LegalCopyright
+}
+
+/// Images and text shown by the MSI/NSIS installer wizard
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct WindowsUi {
+    pub banner: Option<String>,       // optional, 493x58 top banner
+    pub dialog: Option<String>,       // optional, 493x312 welcome/finish background
+    pub icon: Option<String>,         // optional, .ico shown in the wizard and Add/Remove Programs
+    pub welcome_text: Option<String>, // optional
+    pub accent_text: Option<String>,  // optional
+}
+
+/// macOS installer (DMG/PKG) settings
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Macos {
+    pub ui: Option<MacosUi>, // optional
+}
+
+/// Images and text shown when the DMG is mounted or the PKG installer runs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MacosUi {
+    pub background: Option<String>,      // optional, DMG window background
+    pub volume_icon: Option<String>,     // optional, .icns for the mounted volume
+    pub window_size: Option<(u32, u32)>, // optional, DMG window width and height
+    pub icon_size: Option<u32>,          // optional
+    pub welcome_text: Option<String>,    // optional
+}
+
+/// Commands run through the shell around each target's generator, with `SHIP_TARGET` set to
+/// the target as spelled for `--target`, e.g. `app-image`, and after it `SHIP_ARTIFACT` to
+/// each artifact in turn
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Hooks {
+    pub pre_package: Option<String>, // optional, before the target's inputs are checked, e.g. to fetch assets
+    pub post_package: Option<String>, // optional, once per freshly built artifact, e.g. to sign or upload it
+}
+
+/// Builds and packages the program once per entry, see overlay::arch_matrix
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug, Default)]
+pub struct Matrix {
+    #[serde(default)]
+    pub arch: Vec<Arch>, // optional, each replaces [prog].arch, e.g. ["Amd64", "Arm64"]
+    pub triple: Option<String>, // from [matrix.<arch>].triple of the variant being built
+}
+
+/// An external generator for the custom target of the same name, see plugin.rs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Plugin {
+    pub command: String, // required, run through the shell, e.g. "python3 tools/ship-snap.py"
+}
+
+/// AppStream metainfo installed as `share/metainfo/<id>.appdata.xml`, see metainfo.rs
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Metadata {
+    pub id: Option<String>, // optional, defaults to [prog].id, then [prog].name
+    pub summary: Option<String>, // optional, defaults to [prog].description
+    pub description: Option<String>, // optional, blank lines separate paragraphs, defaults to the summary
+    pub homepage: Option<String>,    // optional
+    pub metadata_license: Option<String>, // optional, license of the metainfo itself, defaults to CC0-1.0
+    #[serde(default)]
+    pub screenshots: Vec<Screenshot>, // optional, the first one is the default
+    #[serde(default)]
+    pub releases: Vec<MetaRelease>, // optional, newest first
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct Screenshot {
+    pub image: String,           // required, URL of the image
+    pub caption: Option<String>, // optional
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct MetaRelease {
+    pub version: String,             // required
+    pub date: String,                // required, YYYY-MM-DD
+    pub description: Option<String>, // optional, blank lines separate paragraphs
 }
 
 /// Top-level config
@@ -120,4 +999,255 @@ pub struct ShipConfig {
     pub build: Option<Build>,
     pub out: Out,
     pub vars: Option<Vars>,
+    pub deb: Option<Deb>,
+    pub ebuild: Option<Ebuild>,
+    pub xbps: Option<Xbps>,
+    pub appimage: Option<AppImage>,
+    pub rpm: Option<TargetOverrides>,
+    pub msi: Option<TargetOverrides>,
+    pub exe: Option<TargetOverrides>,
+    pub dmg: Option<TargetOverrides>,
+    pub pkg: Option<TargetOverrides>,
+    pub archive: Option<Archive>,
+    pub appcast: Option<Appcast>,
+    pub publish: Option<Publish>,
+    pub release: Option<Release>,
+    pub signing: Option<Signing>,
+    pub changelog: Option<Changelog>,
+    pub scripts: Option<Scripts>,
+    pub desktop: Option<Desktop>,
+    pub windows: Option<Windows>,
+    pub macos: Option<Macos>,
+    pub metadata: Option<Metadata>,
+    pub plugins: Option<HashMap<String, Plugin>>, // optional, keyed by the custom target they build
+    pub matrix: Option<Matrix>,
+    pub hooks: Option<Hooks>,
+}
+
+impl Target {
+    /// Name of the Shipfile section holding this target's settings
+    pub fn section(&self) -> &str {
+        match self {
+            Target::Exe => "exe",
+            Target::Msi => "msi",
+            Target::Dmg => "dmg",
+            Target::Pkg => "pkg",
+            Target::Deb | Target::DebSource => "deb",
+            Target::AppImage => "appimage",
+            Target::Rpm => "rpm",
+            Target::Ebuild => "ebuild",
+            Target::Xbps => "xbps",
+            Target::Appcast => "appcast",
+            Target::Archive => "archive",
+            Target::Custom(name) => name,
+        }
+    }
+}
+
+impl ShipConfig {
+    /// The per-target section for `target`, if the Shipfile has one
+    pub fn overrides(&self, target: &Target) -> Option<&TargetOverrides> {
+        match target {
+            Target::Deb | Target::DebSource => self.deb.as_ref().map(|d| &d.overrides),
+            Target::Ebuild => self.ebuild.as_ref().map(|e| &e.overrides),
+            Target::Xbps => self.xbps.as_ref().map(|x| &x.overrides),
+            Target::AppImage => self.appimage.as_ref().map(|a| &a.overrides),
+            Target::Rpm => self.rpm.as_ref(),
+            Target::Msi => self.msi.as_ref(),
+            Target::Exe => self.exe.as_ref(),
+            Target::Dmg => self.dmg.as_ref(),
+            Target::Pkg => self.pkg.as_ref(),
+            Target::Archive => self.archive.as_ref().map(|a| &a.overrides),
+            Target::Appcast | Target::Custom(_) => None,
+        }
+    }
+
+    /// Every per-target section that's set, for rewriting them in place
+    pub fn all_overrides_mut(&mut self) -> Vec<&mut TargetOverrides> {
+        let mut all = Vec::new();
+        all.extend(self.deb.as_mut().map(|d| &mut d.overrides));
+        all.extend(self.ebuild.as_mut().map(|e| &mut e.overrides));
+        all.extend(self.xbps.as_mut().map(|x| &mut x.overrides));
+        all.extend(self.archive.as_mut().map(|a| &mut a.overrides));
+        all.extend(self.appimage.as_mut().map(|a| &mut a.overrides));
+        for section in [
+            &mut self.rpm,
+            &mut self.msi,
+            &mut self.exe,
+            &mut self.dmg,
+            &mut self.pkg,
+        ] {
+            all.extend(section.as_mut());
+        }
+        all
+    }
+
+    /// A copy of the config with the target's section applied over the global settings
+    pub fn for_target(&self, target: &Target) -> ShipConfig {
+        let mut conf = self.clone();
+        let Some(overrides) = self.overrides(target) else {
+            return conf;
+        };
+
+        if let Some(paths) = &overrides.paths {
+            conf.files.paths = paths.clone();
+        }
+        if let Some(icon) = &overrides.icon {
+            conf.files.icon = Some(icon.clone());
+        }
+        if let Some(prefix) = &overrides.prefix {
+            conf.out.prefix = Some(prefix.clone());
+        }
+        if let Some(output) = &overrides.output {
+            conf.out.bin = std::path::Path::new(&self.out.bin)
+                .join(output)
+                .to_string_lossy()
+                .into_owned();
+        }
+
+        conf
+    }
+
+    /// How many threads xz and zstd compress packages with, from [out].compression_threads
+    pub fn compression_threads(&self) -> u32 {
+        match self.out.compression_threads {
+            Some(0) => std::thread::available_parallelism()
+                .map(|threads| threads.get() as u32)
+                .unwrap_or(1),
+            Some(threads) => threads,
+            None => 1,
+        }
+    }
+
+    /// Absolute directory the packaged files are installed under, `[out].prefix` or a
+    /// target's own `prefix`, e.g. `/usr/lib/<name>`
+    pub fn install_prefix(&self) -> String {
+        match &self.out.prefix {
+            Some(prefix) if prefix != "/" => prefix.trim_end_matches('/').to_string(),
+            Some(prefix) => prefix.clone(),
+            None => format!("/opt/{}", self.prog.name),
+        }
+    }
+
+    /// Base URL the artifacts are published under, if the Shipfile names one
+    pub fn publish_url(&self) -> Option<&str> {
+        self.publish
+            .as_ref()
+            .and_then(|p| p.base_url.as_deref())
+            .or(self.appcast.as_ref().map(|a| a.base_url.as_str()))
+            .map(|url| url.trim_end_matches('/'))
+    }
+
+    /// Compression chosen for `target`, if any
+    pub fn compression(&self, target: &Target) -> Option<&Compression> {
+        self.overrides(target).and_then(|o| o.compression.as_ref())
+    }
+
+    /// Debian `Section`, `Priority` and `Homepage` fields, in control file order
+    pub fn deb_fields(&self) -> Vec<(&'static str, String)> {
+        let deb = self.deb.as_ref();
+        let mut fields = vec![
+            (
+                "Section",
+                deb.and_then(|d| d.section.clone())
+                    .unwrap_or_else(|| "misc".to_string()),
+            ),
+            (
+                "Priority",
+                deb.and_then(|d| d.priority)
+                    .unwrap_or_default()
+                    .deb_name()
+                    .to_string(),
+            ),
+        ];
+        if let Some(homepage) = deb.and_then(|d| d.homepage.as_ref()) {
+            fields.push(("Homepage", homepage.clone()));
+        }
+        fields
+    }
+
+    /// The Debian version, `[<epoch>:]<version>[-<revision>]`, with `default_revision` if
+    /// [deb].revision isn't set; `None` without [prog].version
+    pub fn deb_version(&self, default_revision: Option<&str>) -> Option<String> {
+        let deb = self.deb.as_ref();
+        let mut version = self.prog.version.clone()?;
+        if let Some(epoch) = deb.and_then(|d| d.epoch) {
+            version = format!("{epoch}:{version}");
+        }
+        if let Some(revision) = deb.and_then(|d| d.revision.as_deref()).or(default_revision) {
+            version = format!("{version}-{revision}");
+        }
+        Some(version)
+    }
+
+    /// Debian `Description` field: [prog].description as the synopsis, followed by
+    /// [deb].long_description as continuation lines, with blank lines written as ` .`
+    pub fn deb_description(&self) -> String {
+        let mut description = self
+            .prog
+            .description
+            .clone()
+            .unwrap_or_else(|| self.prog.name.clone());
+        let extended = self
+            .deb
+            .as_ref()
+            .and_then(|d| d.long_description.as_deref())
+            .map(|text| text.trim_matches('\n'))
+            .filter(|text| !text.trim().is_empty())
+            .unwrap_or_default();
+        for line in extended.lines() {
+            let line = line.trim_end();
+            description.push_str("\n ");
+            description.push_str(if line.is_empty() { "." } else { line });
+        }
+        description
+    }
+
+    /// Runtime dependencies declared for `target`
+    pub fn depends(&self, target: &Target) -> &[String] {
+        self.overrides(target)
+            .map(|o| o.depends.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Image files referenced by the installer UI sections of the enabled targets
+    pub fn ui_resources(&self) -> Vec<(&'static str, &str)> {
+        let mut resources = Vec::new();
+        let targets = &self.out.targets;
+
+        if (targets.contains(&Target::Msi) || targets.contains(&Target::Exe))
+            && let Some(ui) = self.windows.as_ref().and_then(|w| w.ui.as_ref())
+        {
+            for (key, value) in [
+                ("windows.ui.banner", &ui.banner),
+                ("windows.ui.dialog", &ui.dialog),
+                ("windows.ui.icon", &ui.icon),
+            ] {
+                if let Some(path) = value {
+                    resources.push((key, path.as_str()));
+                }
+            }
+        }
+
+        if let Some(res) = self.windows.as_ref().and_then(|w| w.resources.as_ref()) {
+            for icon in &res.icons {
+                resources.push(("windows.resources.icons", icon.as_str()));
+            }
+        }
+
+        if (targets.contains(&Target::Dmg) || targets.contains(&Target::Pkg))
+            && let Some(ui) = self.macos.as_ref().and_then(|m| m.ui.as_ref())
+        {
+            for (key, value) in [
+                ("macos.ui.background", &ui.background),
+                ("macos.ui.volume_icon", &ui.volume_icon),
+            ] {
+                if let Some(path) = value {
+                    resources.push((key, path.as_str()));
+                }
+            }
+        }
+
+        resources
+    }
 }
@@ -1,12 +1,30 @@
 use std::{
-    collections::{HashMap, HashSet},
-    io::{Cursor, Error, ErrorKind, Read, Write},
+    collections::HashSet,
+    io::{Cursor, Error, Read, Write},
     path::{Path, PathBuf},
 };
 
-use deb::{DebFile, binary::DebPackage};
+use deb::binary::DebPackage;
+use md5::{Digest, Md5};
+
+use crate::{
+    atomic, cache, changelog,
+    checksum::to_hex,
+    completions::{self, ZSH_DEBIAN, ZSH_SITE},
+    compress::Encoder,
+    conf::{Alternatives, Compression, CompressionAlgorithm, ShipConfig, Target, without_epoch},
+    desktop,
+    error::{Context, ShipError},
+    gen_::{Artifacts, Generator, Plan},
+    layout::{Entry, Layout, is_elf, share_dir},
+    license, metainfo,
+    scripts::{self, InstallScripts},
+    shlibdeps,
+    strip::{self, DebugInfo},
+};
 
-use crate::{conf::ShipConfig, gen_::Generator};
+/// Install root of the Termux environment on Android
+const TERMUX_PREFIX: &str = "/data/data/com.termux/files/usr";
 
 pub struct DebGenerator<'a> {
     pub conf: &'a ShipConfig,
@@ -19,163 +37,555 @@ impl<'a> DebGenerator<'a> {
 }
 
 impl<'a> Generator for DebGenerator<'a> {
-    fn run(&self) {
-        let files = self
-            .conf
-            .files
-            .paths
-            .iter()
-            .map(|file| {
-                let to = format!(
-                    "/opt/{}/{}",
-                    self.conf.prog.name,
-                    file.strip_prefix("./").unwrap_or(file)
-                );
+    fn run(&self) -> Result<Artifacts, ShipError> {
+        let termux = self.termux();
 
-                (file.clone(), to)
-            })
-            .collect::<Vec<(String, String)>>();
-
-        let mut pkg = DebPackage::new(&self.conf.prog.name);
-        let mut bin_symlinks: Vec<(String, String)> = Vec::new();
-        let mut seen_links: HashMap<String, String> = HashMap::new();
-
-        for (from, to) in &files {
-            if let Some(link_name) = executable_name(from) {
-                let link_path = format!("/usr/bin/{link_name}");
-
-                if let Some(existing_target) = seen_links.get(&link_path) {
-                    if existing_target != to {
-                        eprintln!(
-                            "error: conflicting binaries for {link_path}: {} and {}",
-                            existing_target, to
-                        );
-                        return;
-                    }
-                    continue;
-                }
+        let arch_name = self.arch_name()?;
 
-                seen_links.insert(link_path.clone(), to.clone());
-                bin_symlinks.push((link_path, to.clone()));
-            }
+        let bin_dir = self.bin_dir();
+        let mut layout = self.layout()?;
+
+        if self.conf.desktop.is_some() {
+            layout
+                .files
+                .extend(desktop::entries(self.conf, &layout, &share_dir(&bin_dir))?);
         }
 
-        for (from, to) in files {
-            let from_path = Path::new(&from);
+        if self.conf.metadata.is_some() {
+            let source = metainfo::write(self.conf, self.conf.desktop.is_some())?;
+            layout.files.push(Entry {
+                source,
+                dest: format!(
+                    "{}/{}",
+                    share_dir(&bin_dir).display(),
+                    metainfo::install_path(self.conf)
+                ),
+                mode: None,
+                owner: None,
+                group: None,
+            });
+        }
+
+        match license::write(self.conf) {
+            Ok(Some(source)) => layout.files.push(Entry {
+                source,
+                dest: format!(
+                    "{}/{}",
+                    share_dir(&bin_dir).display(),
+                    license::install_path(self.conf)
+                ),
+                mode: None,
+                owner: None,
+                group: None,
+            }),
+            Ok(None) => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        if self.conf.changelog.is_some() {
+            let Some(version) = &self.conf.deb_version(None) else {
+                return Err("[changelog] requires [prog].version".into());
+            };
+            let distribution = changelog::distribution(self.conf);
+            match changelog::write_compressed(self.conf, version, distribution) {
+                Ok(source) => layout.files.push(Entry {
+                    source,
+                    dest: format!(
+                        "{}/{}",
+                        share_dir(&bin_dir).display(),
+                        changelog::install_path(self.conf)
+                    ),
+                    mode: None,
+                    owner: None,
+                    group: None,
+                }),
+                Err(err) => return Err(err.into()),
+            }
+        }
 
-            if from_path.is_dir() {
-                pkg = add_dir_recursive(pkg, from_path, Path::new(&to));
-            } else {
-                let file = match DebFile::from_path(from, to) {
-                    Ok(f) => f,
-                    Err(err) => {
-                        eprintln!("error: failed to generate .deb! {err}");
-                        return; // exits run(), not just the closure
+        // the package gets stripped copies, the symbols go into <name>-dbgsym
+        let debug = if strip::enabled(self.conf, &Target::Deb) {
+            match strip::split_debug(self.conf, &mut layout) {
+                Ok(mut debug) => {
+                    if termux {
+                        for entry in &mut debug.files {
+                            entry.dest = termux_path(&entry.dest);
+                        }
                     }
-                };
-                pkg = pkg.with_file(file);
+                    Some(debug)
+                }
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            None
+        };
+
+        let scripts = InstallScripts::load(self.conf)?;
+
+        let mut rewrite = DebRewrite {
+            control_scripts: scripts.deb(),
+            compression: self.conf.compression(&Target::Deb).cloned(),
+            threads: self.conf.compression_threads(),
+            ..Default::default()
+        };
+        let mut relationships = Vec::new();
+        if let Some(deb) = &self.conf.deb {
+            rewrite.conffiles = conffiles(&deb.conffiles, &layout, termux)?;
+
+            relationships = deb.relationships();
+
+            if deb.shlibdeps && termux {
+                warn!("[deb].shlibdeps looks at the host's libraries, skipping it for Termux...");
+            } else if deb.shlibdeps {
+                let detected = shlibdeps::detect(self.conf, &layout)?;
+                let depends = shlibdeps::merge(&deb.overrides.depends, detected);
+                relationships.retain(|(field, _)| *field != "Depends");
+                if !depends.is_empty() {
+                    relationships.insert(0, ("Depends", depends.join(", ")));
+                }
+            }
+
+            if let Some(alternatives) = &deb.alternatives {
+                use_alternatives(
+                    alternatives,
+                    &mut layout.symlinks,
+                    &mut rewrite.control_scripts,
+                )?;
             }
         }
+        rewrite.control_fields = self.control_fields(relationships, &arch_name);
 
-        pkg = pkg
+        let mut pkg = DebPackage::new(&self.conf.prog.name)
             .set_name(&self.conf.prog.name)
             .set_maintainer(&self.conf.prog.author)
             .set_architecture(self.conf.prog.arch.deb());
 
-        if let Some(ref version) = self.conf.prog.version {
-            pkg = pkg.set_version(&version);
+        if let Some(ref version) = self.conf.deb_version(None) {
+            pkg = pkg.set_version(version);
         }
 
-        let output_path = self.deb_output_path();
-        if let Some(parent) = output_path.parent() {
-            std::fs::create_dir_all(parent).unwrap_or_else(|err| {
-                eprintln!(
-                    "error: failed to create output directory {}: {err}",
-                    parent.display()
+        let output_path = self.deb_output_path(&arch_name);
+        self.write_deb(pkg, &layout, &rewrite, &output_path)?;
+        let mut artifacts = vec![output_path];
+
+        if let Some(debug) = debug.filter(|debug| !debug.files.is_empty()) {
+            artifacts.push(self.write_dbgsym(debug, &rewrite, &arch_name)?);
+        }
+        Ok(artifacts)
+    }
+
+    fn plan(&self) -> Result<Plan, ShipError> {
+        let arch_name = self.arch_name()?;
+        let mut layout = self.layout()?;
+        let mut scripts = scripts::planned(self.conf, ["preinst", "postinst", "prerm", "postrm"]);
+        let mut notes = Vec::new();
+
+        let mut relationships = Vec::new();
+        if let Some(deb) = &self.conf.deb {
+            relationships = deb.relationships();
+            if deb.shlibdeps && !self.termux() {
+                notes.push(
+                    "Depends gets the packages of the linked libraries, found with dpkg-shlibdeps"
+                        .to_string(),
                 );
-                std::process::exit(-1);
-            });
+            }
+            if let Some(alternatives) = &deb.alternatives {
+                // only the names matter here, the snippets are written while building
+                let mut control_scripts: Vec<(&'static str, String)> = Vec::new();
+                use_alternatives(alternatives, &mut layout.symlinks, &mut control_scripts)?;
+                for (name, _) in control_scripts {
+                    if !scripts.iter().any(|(planned, _)| planned == name) {
+                        scripts.push((name.to_string(), "[deb].alternatives".to_string()));
+                    }
+                }
+            }
+        }
+        let mut fields = vec![
+            ("Package".to_string(), self.conf.prog.name.clone()),
+            ("Maintainer".to_string(), self.conf.prog.author.clone()),
+        ];
+        if let Some(version) = self.conf.deb_version(None) {
+            fields.push(("Version".to_string(), version));
+        }
+        if !self.termux() {
+            fields.push(("Architecture".to_string(), arch_name.clone()));
+        }
+        fields.extend(self.control_fields(relationships, &arch_name));
+
+        let generated = [
+            (
+                self.conf.desktop.is_some(),
+                "a desktop entry from [desktop]",
+            ),
+            (
+                self.conf.metadata.is_some(),
+                "AppStream metainfo from [metadata]",
+            ),
+            (
+                self.conf.files.license.is_some(),
+                "the copyright file from [files].license",
+            ),
+            (
+                self.conf.changelog.is_some(),
+                "changelog.Debian.gz from [changelog]",
+            ),
+        ];
+        for (_, what) in generated.iter().filter(|(set, _)| *set) {
+            notes.push(format!("adds {what}, generated while building"));
+        }
+        if strip::enabled(self.conf, &Target::Deb) {
+            notes.push(
+                "ELF files are stripped, their debug symbols go into the -dbgsym package"
+                    .to_string(),
+            );
         }
 
-        let archive = pkg.build().unwrap_or_else(|err| {
-            eprintln!("error: failed to build .deb package: {err}");
-            std::process::exit(-1);
-        });
+        Ok(Plan {
+            artifacts: self.artifacts(),
+            files: layout.files,
+            symlinks: layout.symlinks,
+            fields,
+            scripts,
+            notes,
+        })
+    }
 
-        let mut deb_bytes = Vec::new();
-        archive.write(&mut deb_bytes).unwrap_or_else(|err| {
-            eprintln!("error: failed to serialize .deb package: {err}");
-            std::process::exit(-1);
-        });
+    fn artifacts(&self) -> Vec<PathBuf> {
+        let Ok(arch) = self.arch_name() else {
+            return Vec::new();
+        };
+        let mut artifacts = vec![self.deb_output_path(&arch)];
+        // a dbgsym package only exists when there was something to strip
+        if strip::enabled(self.conf, &Target::Deb)
+            && Layout::resolve(self.conf)
+                .is_ok_and(|layout| layout.files.iter().any(|entry| is_elf(&entry.source)))
+        {
+            artifacts.push(self.dbgsym_output_path(&arch));
+        }
+        artifacts
+    }
+}
 
-        if !bin_symlinks.is_empty() {
-            deb_bytes = rewrite_deb_with_symlinks(&deb_bytes, &bin_symlinks).unwrap_or_else(
-                |err| {
-                    eprintln!("error: failed to add symlinks to .deb data archive: {err}");
-                    std::process::exit(-1);
-                },
-            );
+impl<'a> DebGenerator<'a> {
+    /// Writes the package with the control archive of `pkg`, edited by `rewrite`, and the
+    /// files of `layout` to `output_path`
+    fn write_deb(
+        &self,
+        pkg: DebPackage,
+        layout: &Layout,
+        rewrite: &DebRewrite,
+        output_path: &Path,
+    ) -> Result<(), ShipError> {
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(|| format!("failed to create output directory {}", parent.display()))?;
         }
 
-        std::fs::write(&output_path, deb_bytes).unwrap_or_else(|err| {
-            eprintln!(
-                "error: failed to write .deb package at {}: {err}",
-                output_path.display()
-            );
-            std::process::exit(-1);
+        // deb-rust has no files to hold in memory, it only writes the control file
+        let archive = pkg
+            .build()
+            .context(|| "failed to build .deb package".to_string())?;
+
+        let mut skeleton = Vec::new();
+        archive
+            .write(&mut skeleton)
+            .context(|| "failed to serialize .deb package".to_string())?;
+
+        let staging = cache::work_dir(self.conf).join("deb");
+        std::fs::create_dir_all(&staging)
+            .context(|| format!("failed to create staging directory {}", staging.display()))?;
+        let data_path = staging.join(format!(
+            "{}.data.tar",
+            output_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+        ));
+
+        let result = atomic::write_with(output_path, |tmp| {
+            assemble_deb(&skeleton, layout, rewrite, &data_path, tmp)
         });
+        std::fs::remove_file(&data_path).ok();
+        result.context(|| format!("failed to write .deb package at {}", output_path.display()))
     }
-}
 
-impl<'a> DebGenerator<'a> {
-    fn deb_output_path(&self) -> PathBuf {
+    /// Writes `<name>-dbgsym`, the debug symbols package Debian's own tooling would make,
+    /// and returns where it went
+    fn write_dbgsym(
+        &self,
+        debug: DebugInfo,
+        package_rewrite: &DebRewrite,
+        arch_name: &str,
+    ) -> Result<PathBuf, ShipError> {
+        let name = &self.conf.prog.name;
+        let mut pkg = DebPackage::new(&format!("{name}-dbgsym"))
+            .set_name(&format!("{name}-dbgsym"))
+            .set_maintainer(&self.conf.prog.author)
+            .set_architecture(self.conf.prog.arch.deb());
+
+        let mut fields = vec![
+            ("Section".to_string(), "debug".to_string()),
+            ("Priority".to_string(), "optional".to_string()),
+        ];
+        if let Some(version) = &self.conf.deb_version(None) {
+            pkg = pkg.set_version(version);
+            fields.push(("Depends".to_string(), format!("{name} (= {version})")));
+        }
+        fields.push((
+            "Auto-Built-Package".to_string(),
+            "debug-symbols".to_string(),
+        ));
+        if !debug.build_ids.is_empty() {
+            fields.push(("Build-Ids".to_string(), debug.build_ids.join(" ")));
+        }
+        fields.push((
+            "Description".to_string(),
+            format!("debug symbols for {name}"),
+        ));
+        if self.termux() {
+            fields.push(("Architecture".to_string(), arch_name.to_string()));
+        }
+
+        let rewrite = DebRewrite {
+            control_fields: fields,
+            compression: package_rewrite.compression.clone(),
+            threads: package_rewrite.threads,
+            ..Default::default()
+        };
+        let layout = Layout {
+            files: debug.files,
+            symlinks: Vec::new(),
+        };
+        let output_path = self.dbgsym_output_path(arch_name);
+        self.write_deb(pkg, &layout, &rewrite, &output_path)?;
+        Ok(output_path)
+    }
+
+    fn bin_dir(&self) -> String {
+        if self.termux() {
+            format!("{TERMUX_PREFIX}/bin")
+        } else {
+            "/usr/bin".to_string()
+        }
+    }
+
+    /// The files of [files] and their completions where this package installs them
+    fn layout(&self) -> Result<Layout, String> {
+        let termux = self.termux();
+        let bin_dir = self.bin_dir();
+        let prefix = if termux {
+            termux_path(&self.conf.install_prefix())
+        } else {
+            self.conf.install_prefix()
+        };
+        let mut layout = Layout::resolve_in(self.conf, &prefix, &bin_dir)?;
+        if termux {
+            // absolute `to` destinations in [files].paths name the usual system paths
+            for entry in &mut layout.files {
+                if !entry.dest.starts_with(TERMUX_PREFIX) {
+                    entry.dest = termux_path(&entry.dest);
+                }
+            }
+        }
+
+        // Termux builds its zsh like upstream, not like Debian
+        let zsh_dir = if termux { ZSH_SITE } else { ZSH_DEBIAN };
+        layout.files.extend(completions::entries(
+            self.conf,
+            &share_dir(&bin_dir),
+            zsh_dir,
+        )?);
+        Ok(layout)
+    }
+
+    /// The control fields ship adds to the ones deb-rust writes, after `relationships`
+    fn control_fields(
+        &self,
+        relationships: Vec<(&'static str, String)>,
+        arch_name: &str,
+    ) -> Vec<(String, String)> {
+        let mut fields: Vec<(String, String)> = relationships
+            .into_iter()
+            .map(|(field, value)| (field.to_string(), value))
+            .collect();
+        for (field, value) in self.conf.deb_fields() {
+            fields.push((field.to_string(), value));
+        }
+        fields.push(("Description".to_string(), self.conf.deb_description()));
+        if self.termux() {
+            // deb-rust only knows Debian architecture names, Termux's dpkg uses its own
+            fields.push(("Architecture".to_string(), arch_name.to_string()));
+        }
+        fields
+    }
+
+    fn termux(&self) -> bool {
+        self.conf.deb.as_ref().is_some_and(|deb| deb.termux)
+    }
+
+    fn arch_name(&self) -> Result<String, String> {
+        if self.termux() {
+            self.conf
+                .prog
+                .arch
+                .termux()
+                .map(|arch| arch.to_string())
+                .ok_or_else(|| {
+                    format!(
+                        "architecture {:?} is not supported by Termux",
+                        self.conf.prog.arch
+                    )
+                })
+        } else {
+            Ok(self.conf.prog.arch.deb_name().to_string())
+        }
+    }
+
+    fn deb_output_path(&self, arch_name: &str) -> PathBuf {
         let out = Path::new(&self.conf.out.bin);
         if out.extension().and_then(|ext| ext.to_str()) == Some("deb") {
             return out.to_path_buf();
         }
 
-        let mut file_name = self.conf.prog.name.clone();
-        if let Some(version) = &self.conf.prog.version {
+        out.join(self.deb_file_name(&self.conf.prog.name, arch_name))
+    }
+
+    fn dbgsym_output_path(&self, arch_name: &str) -> PathBuf {
+        let out = Path::new(&self.conf.out.bin);
+        // next to a package with a fixed name, named after it
+        if out.extension().and_then(|ext| ext.to_str()) == Some("deb") {
+            let stem = out.file_stem().unwrap_or_default().to_string_lossy();
+            return out.with_file_name(format!("{stem}-dbgsym.deb"));
+        }
+
+        let package = format!("{}-dbgsym", self.conf.prog.name);
+        out.join(self.deb_file_name(&package, arch_name))
+    }
+
+    // `<package>_<version>_<arch>.deb`, as dpkg-name would call it
+    fn deb_file_name(&self, package: &str, arch_name: &str) -> String {
+        let mut file_name = package.to_string();
+        if let Some(version) = &self.conf.deb_version(None) {
             file_name.push('_');
-            file_name.push_str(version);
+            file_name.push_str(without_epoch(version));
         }
         file_name.push('_');
-        file_name.push_str(&format!("{:?}", self.conf.prog.arch).to_lowercase());
+        file_name.push_str(arch_name);
         file_name.push_str(".deb");
 
-        out.join(file_name)
+        file_name
     }
 }
 
-#[cfg(unix)]
-fn executable_name(path: &str) -> Option<String> {
-    use std::os::unix::fs::PermissionsExt;
+/// Changes applied on top of the control archive produced by deb-rust
+#[derive(Default)]
+struct DebRewrite {
+    control_fields: Vec<(String, String)>, // replaced in place or appended to the control file
+    control_scripts: Vec<(&'static str, String)>, // maintainer scripts added to the control archive
+    conffiles: Vec<String>, // absolute paths listed in the control archive's conffiles
+    compression: Option<Compression>, // compress both archives with this instead of what deb-rust chose
+    threads: u32, // workers compressing the data archive, the control archive is too small to need them
+}
 
-    let path = Path::new(path);
-    let metadata = std::fs::metadata(path).ok()?;
-    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
-        return None;
+/// Replaces the `/usr/bin` symlinks named in [deb].alternatives with postinst and prerm
+/// snippets that register and drop them with update-alternatives
+fn use_alternatives(
+    alternatives: &Alternatives,
+    symlinks: &mut Vec<(String, String)>,
+    scripts: &mut Vec<(&'static str, String)>,
+) -> Result<(), String> {
+    let name_of = |link: &str| link.rsplit('/').next().unwrap_or(link).to_string();
+
+    for command in &alternatives.commands {
+        if !symlinks.iter().any(|(link, _)| name_of(link) == *command) {
+            return Err(format!(
+                "[deb].alternatives names `{command}`, which isn't an executable linked into the bin directory"
+            ));
+        }
     }
-    path.file_name()
-        .and_then(|name| name.to_str())
-        .map(|name| name.to_string())
+
+    let (managed, kept): (Vec<_>, Vec<_>) =
+        std::mem::take(symlinks).into_iter().partition(|(link, _)| {
+            alternatives.commands.is_empty() || alternatives.commands.contains(&name_of(link))
+        });
+    *symlinks = kept;
+    if managed.is_empty() {
+        return Ok(());
+    }
+
+    let mut install = String::from("if [ \"$1\" = configure ]; then\n");
+    let mut remove = String::from("if [ \"$1\" = remove ] || [ \"$1\" = deconfigure ]; then\n");
+    for (link, target) in &managed {
+        let (link, name, target) = (
+            crate::shell_quote(link),
+            crate::shell_quote(&name_of(link)),
+            crate::shell_quote(target),
+        );
+        install.push_str(&format!(
+            "    update-alternatives --install {link} {name} {target} {}\n",
+            alternatives.priority
+        ));
+        remove.push_str(&format!(
+            "    update-alternatives --remove {name} {target}\n"
+        ));
+    }
+    install.push_str("fi\n");
+    remove.push_str("fi\n");
+
+    scripts::add_deb_snippet(scripts, "postinst", &install);
+    scripts::add_deb_snippet(scripts, "prerm", &remove);
+    Ok(())
 }
 
-#[cfg(not(unix))]
-fn executable_name(_path: &str) -> Option<String> {
-    None
+/// Checks [deb].conffiles against the install tree, relocating them along with it for Termux
+fn conffiles(paths: &[String], layout: &Layout, termux: bool) -> Result<Vec<String>, String> {
+    let mut conffiles = Vec::new();
+
+    for path in paths {
+        let path = if termux && !path.starts_with(TERMUX_PREFIX) {
+            termux_path(path)
+        } else {
+            path.clone()
+        };
+
+        if !layout.files.iter().any(|entry| entry.dest == path) {
+            return Err(format!(
+                "conffile {path} in [deb].conffiles is not a file installed by the package"
+            ));
+        }
+        let etc = if termux {
+            format!("{TERMUX_PREFIX}/etc/")
+        } else {
+            "/etc/".to_string()
+        };
+        if !path.starts_with(&etc) {
+            warn!("conffile {path} is outside {etc}, Debian policy expects them there");
+        }
+        if !conffiles.contains(&path) {
+            conffiles.push(path);
+        }
+    }
+
+    Ok(conffiles)
 }
 
-enum DataCompression {
-    Xz,
-    Zstd,
+/// Relocates a system path under Termux's prefix, whose usr stands in for /usr
+fn termux_path(path: &str) -> String {
+    let relative = path.strip_prefix("/usr").unwrap_or(path);
+    format!("{TERMUX_PREFIX}{relative}")
 }
 
-fn rewrite_deb_with_symlinks(
-    deb_bytes: &[u8],
-    bin_symlinks: &[(String, String)],
-) -> std::io::Result<Vec<u8>> {
-    let mut archive = ar::Archive::new(Cursor::new(deb_bytes));
+/// Writes the .deb to `dest`: `skeleton`'s debian-binary, its control archive with
+/// `rewrite` applied, and a data archive streamed from the files of `layout` through
+/// `data_path`, so no file is ever held in memory whole
+fn assemble_deb(
+    skeleton: &[u8],
+    layout: &Layout,
+    rewrite: &DebRewrite,
+    data_path: &Path,
+    dest: &Path,
+) -> std::io::Result<()> {
+    let mut archive = ar::Archive::new(Cursor::new(skeleton));
     let mut entries: Vec<(Vec<u8>, u32, Vec<u8>)> = Vec::new();
 
     while let Some(entry_result) = archive.next_entry() {
@@ -187,116 +597,366 @@ fn rewrite_deb_with_symlinks(
         entries.push((identifier, mode, contents));
     }
 
-    let data_index = entries
-        .iter()
-        .position(|(identifier, _, _)| {
-            let name = ar_identifier_to_name(identifier);
-            name == "data.tar.zst" || name == "data.tar.xz"
-        })
-        .ok_or_else(|| Error::new(ErrorKind::Other, "deb package missing data archive"))?;
+    // the algorithm deb-rust picked for a member, unless [deb].compression says otherwise
+    let compression_of = |index: usize,
+                          member: &str|
+     -> std::io::Result<(String, Option<CompressionAlgorithm>, u32)> {
+        match &rewrite.compression {
+            // renamed after the algorithm, which is how dpkg tells them apart
+            Some(c) => Ok((
+                format!("{member}.{}", c.algorithm.extension()),
+                Some(c.algorithm),
+                c.level.unwrap_or(c.algorithm.default_level()),
+            )),
+            None => {
+                let name = ar_identifier_to_name(&entries[index].0);
+                let algorithm = archive_compression(&name)?;
+                Ok((name, algorithm, algorithm.map_or(0, default_level)))
+            }
+        }
+    };
 
-    let data_name = ar_identifier_to_name(&entries[data_index].0);
-    entries[data_index].2 = rewrite_data_archive(&entries[data_index].2, &data_name, bin_symlinks)?;
+    let data_index = find_member(&entries, "data.tar")
+        .ok_or_else(|| Error::other("deb package missing data archive"))?;
+    let (data_name, data_compression, data_level) = compression_of(data_index, "data.tar")?;
+    let summary = write_data_archive(
+        layout,
+        data_compression,
+        data_level,
+        rewrite.threads,
+        data_path,
+    )?;
+
+    let control_index = find_member(&entries, "control.tar")
+        .ok_or_else(|| Error::other("deb package missing control archive"))?;
+    let control_name = ar_identifier_to_name(&entries[control_index].0);
+    let control = decompress(
+        &entries[control_index].2,
+        archive_compression(&control_name)?,
+    )?;
+    let mut fields = rewrite.control_fields.clone();
+    fields.push((
+        "Installed-Size".to_string(),
+        summary.installed_size.to_string(),
+    ));
+    let control = rewrite_control_archive(
+        control,
+        &fields,
+        &rewrite.control_scripts,
+        &rewrite.conffiles,
+        &summary.md5sums,
+    )?;
+    let (control_name, control_compression, control_level) =
+        compression_of(control_index, "control.tar")?;
+    entries[control_index].0 = control_name.into_bytes();
+    entries[control_index].2 = compress(control, control_compression, control_level)?;
+
+    let mut builder = ar::Builder::new(std::fs::File::create(dest)?);
+    for (index, (identifier, mode, contents)) in entries.into_iter().enumerate() {
+        if index == data_index {
+            let data = std::fs::File::open(data_path)?;
+            let mut header =
+                ar::Header::new(data_name.clone().into_bytes(), data.metadata()?.len());
+            header.set_mode(mode);
+            builder.append(&header, data)?;
+        } else {
+            let mut header = ar::Header::new(identifier, contents.len().try_into().unwrap());
+            header.set_mode(mode);
+            builder.append(&header, contents.as_slice())?;
+        }
+    }
 
-    let mut output = Vec::new();
-    let mut builder = ar::Builder::new(&mut output);
-    for (identifier, mode, contents) in entries {
-        let mut header = ar::Header::new(identifier, contents.len().try_into().unwrap());
-        header.set_mode(mode);
-        builder.append(&header, contents.as_slice())?;
+    builder.into_inner()?.sync_all()
+}
+
+/// What the control archive records about the data archive
+struct DataSummary {
+    md5sums: String,     // `<md5>  <path>` for every regular file, like dpkg writes them
+    installed_size: u64, // in KiB, counted the way dpkg-gencontrol does
+}
+
+fn write_data_archive(
+    layout: &Layout,
+    compression: Option<CompressionAlgorithm>,
+    level: u32,
+    threads: u32,
+    path: &Path,
+) -> std::io::Result<DataSummary> {
+    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    match compression {
+        Some(algorithm) => {
+            let (encoder, summary) =
+                write_data_tar(layout, Encoder::new(file, algorithm, level, threads)?)?;
+            encoder.finish()?.flush()?;
+            Ok(summary)
+        }
+        None => {
+            let (mut file, summary) = write_data_tar(layout, file)?;
+            file.flush()?;
+            Ok(summary)
+        }
     }
-    drop(builder);
+}
+
+// directories first, then files and symlinks, each file read straight from its source
+fn write_data_tar<W: Write>(layout: &Layout, writer: W) -> std::io::Result<(W, DataSummary)> {
+    let mut tar = tar::Builder::new(writer);
+    let mut summary = DataSummary {
+        md5sums: String::new(),
+        installed_size: 0,
+    };
 
-    Ok(output)
+    let dirs = std::iter::once(String::new()).chain(layout.dirs());
+    for dir in dirs {
+        let mut header = tar::Header::new_gnu();
+        header.set_path(format!(".{dir}/"))?;
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_size(0);
+        header.set_cksum();
+        tar.append(&header, std::io::empty())?;
+        summary.installed_size += 1;
+    }
+
+    for entry in &layout.files {
+        // hashed and measured as it's archived, so md5sums matches the payload byte for byte
+        let mut md5 = Md5::new();
+        let size = entry.append_inspecting(&mut tar, format!(".{}", entry.dest), |bytes| {
+            md5.update(bytes)
+        })?;
+        summary.installed_size += size.div_ceil(1024);
+        summary.md5sums.push_str(&format!(
+            "{}  {}\n",
+            to_hex(&md5.finalize()),
+            entry.dest.trim_start_matches('/')
+        ));
+    }
+
+    for (link, target) in &layout.symlinks {
+        if layout.files.iter().any(|entry| entry.dest == *link) {
+            return Err(Error::other(format!(
+                "data archive already contains path: {link}"
+            )));
+        }
+
+        let mut header = tar::Header::new_gnu();
+        header.set_path(format!(".{link}"))?;
+        header.set_entry_type(tar::EntryType::symlink());
+        header.set_link_name(target)?;
+        header.set_mode(0o777);
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_size(0);
+        header.set_cksum();
+        tar.append(&header, std::io::empty())?;
+        summary.installed_size += 1;
+    }
+
+    Ok((tar.into_inner()?, summary))
 }
 
-fn rewrite_data_archive(
-    data_archive: &[u8],
-    data_name: &str,
-    bin_symlinks: &[(String, String)],
-) -> std::io::Result<Vec<u8>> {
-    let compression = if data_name.ends_with(".zst") {
-        DataCompression::Zstd
-    } else if data_name.ends_with(".xz") {
-        DataCompression::Xz
+fn find_member(entries: &[(Vec<u8>, u32, Vec<u8>)], prefix: &str) -> Option<usize> {
+    entries
+        .iter()
+        .position(|(identifier, _, _)| ar_identifier_to_name(identifier).starts_with(prefix))
+}
+
+// `None` for a plain tar
+fn archive_compression(name: &str) -> std::io::Result<Option<CompressionAlgorithm>> {
+    if name.ends_with(".zst") {
+        Ok(Some(CompressionAlgorithm::Zstd))
+    } else if name.ends_with(".xz") {
+        Ok(Some(CompressionAlgorithm::Xz))
+    } else if name.ends_with(".gz") {
+        Ok(Some(CompressionAlgorithm::Gzip))
+    } else if name.ends_with(".tar") {
+        Ok(None)
     } else {
-        return Err(Error::new(
-            ErrorKind::Other,
-            format!("unsupported data archive format: {data_name}"),
-        ));
-    };
+        Err(Error::other(format!("unsupported archive format: {name}")))
+    }
+}
+
+// what archives are recompressed with when [deb].compression doesn't say
+fn default_level(algorithm: CompressionAlgorithm) -> u32 {
+    match algorithm {
+        CompressionAlgorithm::Xz => 9,
+        algorithm => algorithm.default_level(),
+    }
+}
 
+fn decompress(
+    archive: &[u8],
+    compression: Option<CompressionAlgorithm>,
+) -> std::io::Result<Vec<u8>> {
     let mut tar_buf = Vec::new();
     match compression {
-        DataCompression::Zstd => {
-            zstd::stream::copy_decode(Cursor::new(data_archive), &mut tar_buf)?;
+        None => tar_buf.extend_from_slice(archive),
+        Some(CompressionAlgorithm::Zstd) => {
+            zstd::stream::copy_decode(Cursor::new(archive), &mut tar_buf)?;
         }
-        DataCompression::Xz => {
-            xz2::read::XzDecoder::new(Cursor::new(data_archive)).read_to_end(&mut tar_buf)?;
+        Some(CompressionAlgorithm::Xz) => {
+            xz2::read::XzDecoder::new(Cursor::new(archive)).read_to_end(&mut tar_buf)?;
+        }
+        Some(CompressionAlgorithm::Gzip) => {
+            flate2::read::GzDecoder::new(Cursor::new(archive)).read_to_end(&mut tar_buf)?;
         }
     }
+    Ok(tar_buf)
+}
 
-    let mut old_tar = tar::Archive::new(Cursor::new(tar_buf));
-    let mut new_tar = tar::Builder::new(Vec::new());
+fn compress(
+    tar_buf: Vec<u8>,
+    compression: Option<CompressionAlgorithm>,
+    level: u32,
+) -> std::io::Result<Vec<u8>> {
+    let Some(algorithm) = compression else {
+        return Ok(tar_buf);
+    };
+    let mut encoder = Encoder::new(Vec::new(), algorithm, level, 1)?;
+    encoder.write_all(&tar_buf)?;
+    encoder.finish()
+}
+
+// copies every entry of `old_tar` into `new_tar`, letting `edit` replace file contents
+fn copy_entries(
+    old_tar: &mut tar::Archive<Cursor<Vec<u8>>>,
+    new_tar: &mut tar::Builder<Vec<u8>>,
+    mut edit: impl FnMut(&str, Vec<u8>) -> Vec<u8>,
+) -> std::io::Result<HashSet<String>> {
     let mut existing_paths = HashSet::new();
 
     for entry_result in old_tar.entries()? {
         let mut entry = entry_result?;
         let entry_path = entry.path()?.into_owned();
-        existing_paths.insert(entry_path.to_string_lossy().into_owned());
+        let path_str = entry_path.to_string_lossy().into_owned();
 
         let entry_type = entry.header().entry_type();
         let mode = entry.header().mode()?;
         let mut contents = Vec::new();
         entry.read_to_end(&mut contents)?;
+        let contents = edit(&path_str, contents);
 
         let mut header = tar::Header::new_gnu();
         header.set_path(&entry_path)?;
         header.set_mode(mode);
         header.set_entry_type(entry_type);
-        if entry_type.is_symlink() || entry_type.is_hard_link() {
-            if let Some(link_name) = entry.link_name()? {
-                header.set_link_name(link_name.as_ref())?;
-            }
+        if (entry_type.is_symlink() || entry_type.is_hard_link())
+            && let Some(link_name) = entry.link_name()?
+        {
+            header.set_link_name(link_name.as_ref())?;
         }
         header.set_size(contents.len().try_into().unwrap());
         header.set_cksum();
         new_tar.append(&header, contents.as_slice())?;
+
+        existing_paths.insert(path_str);
     }
 
-    for (link, target) in bin_symlinks {
-        let link_path = link.strip_prefix('/').unwrap_or(link);
-        if existing_paths.contains(link_path) {
-            return Err(Error::new(
-                ErrorKind::Other,
-                format!("data archive already contains path: {link_path}"),
-            ));
+    Ok(existing_paths)
+}
+
+fn rewrite_control_archive(
+    control_tar: Vec<u8>,
+    fields: &[(String, String)],
+    scripts: &[(&str, String)],
+    conffiles: &[String],
+    md5sums: &str,
+) -> std::io::Result<Vec<u8>> {
+    let mut old_tar = tar::Archive::new(Cursor::new(control_tar));
+    let mut new_tar = tar::Builder::new(Vec::new());
+    let existing_paths = copy_entries(&mut old_tar, &mut new_tar, |path, contents| {
+        let name = path.trim_start_matches("./");
+        if name == "control" {
+            set_control_fields(&String::from_utf8_lossy(&contents), fields).into_bytes()
+        } else if name == "md5sums" {
+            md5sums.as_bytes().to_vec()
+        } else {
+            contents
+        }
+    })?;
+
+    // dpkg only runs maintainer scripts that are executable
+    for (name, contents) in scripts {
+        if existing_paths
+            .iter()
+            .any(|path| path.trim_start_matches("./") == *name)
+        {
+            return Err(Error::other(format!(
+                "control archive already contains {name}"
+            )));
         }
 
         let mut header = tar::Header::new_gnu();
-        header.set_path(link_path)?;
-        header.set_entry_type(tar::EntryType::symlink());
-        header.set_link_name(target)?;
-        header.set_mode(0o777);
-        header.set_size(0);
+        header.set_path(format!("./{name}"))?;
+        header.set_mode(0o755);
+        header.set_size(contents.len() as u64);
         header.set_cksum();
-        new_tar.append(&header, std::io::empty())?;
+        new_tar.append(&header, contents.as_bytes())?;
     }
 
-    let new_tar_buf = new_tar.into_inner()?;
-    let mut output = Vec::new();
-    match compression {
-        DataCompression::Zstd => {
-            zstd::stream::copy_encode(Cursor::new(new_tar_buf), &mut output, 0)?;
+    if !conffiles.is_empty() {
+        if existing_paths
+            .iter()
+            .any(|path| path.trim_start_matches("./") == "conffiles")
+        {
+            return Err(Error::other("control archive already contains conffiles"));
         }
-        DataCompression::Xz => {
-            let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 9);
-            encoder.write_all(&new_tar_buf)?;
-            output = encoder.finish()?;
+
+        let contents: String = conffiles.iter().map(|path| format!("{path}\n")).collect();
+        let mut header = tar::Header::new_gnu();
+        header.set_path("./conffiles")?;
+        header.set_mode(0o644);
+        header.set_size(contents.len() as u64);
+        header.set_cksum();
+        new_tar.append(&header, contents.as_bytes())?;
+    }
+
+    if !existing_paths
+        .iter()
+        .any(|path| path.trim_start_matches("./") == "md5sums")
+    {
+        let mut header = tar::Header::new_gnu();
+        header.set_path("./md5sums")?;
+        header.set_mode(0o644);
+        header.set_size(md5sums.len() as u64);
+        header.set_cksum();
+        new_tar.append(&header, md5sums.as_bytes())?;
+    }
+
+    new_tar.into_inner()
+}
+
+// replaces `Key: value` stanzas (including continuation lines) or appends new ones
+fn set_control_fields(control: &str, fields: &[(String, String)]) -> String {
+    let mut stanzas: Vec<(String, String)> = Vec::new();
+
+    for line in control.lines() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some((_, value)) = stanzas.last_mut() {
+                value.push('\n');
+                value.push_str(line);
+            }
+        } else if let Some((key, value)) = line.split_once(':') {
+            stanzas.push((key.to_string(), value.trim_start().to_string()));
         }
     }
 
-    Ok(output)
+    for (key, value) in fields {
+        match stanzas
+            .iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+        {
+            Some(stanza) => stanza.1 = value.clone(),
+            None => stanzas.push((key.clone(), value.clone())),
+        }
+    }
+
+    stanzas
+        .into_iter()
+        .map(|(key, value)| format!("{key}: {value}\n"))
+        .collect()
 }
 
 fn ar_identifier_to_name(identifier: &[u8]) -> String {
@@ -310,33 +970,3 @@ fn ar_identifier_to_name(identifier: &[u8]) -> String {
         name
     }
 }
-
-// helper function to recursively add a directory to the package
-fn add_dir_recursive(mut pkg: DebPackage, from: &Path, to: &Path) -> DebPackage {
-    for entry in std::fs::read_dir(from).unwrap_or_else(|err| {
-        eprintln!("error: failed to read directory {from:?}! {err}");
-        std::process::exit(-1);
-    }) {
-        let entry = entry.unwrap_or_else(|err| {
-            eprintln!("error: failed to read directory entry in {from:?}! {err}");
-            std::process::exit(-1);
-        });
-
-        let path = entry.path();
-        let target_path = to.join(entry.file_name());
-
-        if path.is_file() {
-            let file = match DebFile::from_path(&path, &target_path) {
-                Ok(f) => f,
-                Err(err) => {
-                    eprintln!("error: failed to generate .deb! {err}");
-                    std::process::exit(-1);
-                }
-            };
-            pkg = pkg.with_file(file);
-        } else if path.is_dir() {
-            pkg = add_dir_recursive(pkg, &path, &target_path);
-        }
-    }
-    pkg
-}
@@ -0,0 +1,344 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::Sha256;
+
+use crate::{
+    atomic, changelog,
+    checksum::digest_file,
+    conf::{ShipConfig, Target, without_epoch},
+    error::{Context, ShipError},
+    gen_::{Artifacts, Generator, Plan},
+    globs,
+    scripts::{self, InstallScripts},
+    source_tree::{self, STAGED_ROOT, chmod_commands, entries, write_source_tarball},
+};
+
+/// Debian revision appended to the upstream version, unless [deb].revision sets one
+const DEBIAN_REVISION: &str = "1";
+
+const STANDARDS_VERSION: &str = "4.6.2";
+const BUILD_DEPENDS: &str = "debhelper-compat (= 13)";
+
+pub struct DebSourceGenerator<'a> {
+    pub conf: &'a ShipConfig,
+}
+
+impl<'a> DebSourceGenerator<'a> {
+    pub fn new(conf: &'a ShipConfig) -> Self {
+        Self { conf }
+    }
+
+    // only called once [prog].version is known to be set
+    fn debian_version(&self) -> String {
+        self.conf
+            .deb_version(Some(DEBIAN_REVISION))
+            .unwrap_or_default()
+    }
+
+    /// The orig tarball, debian tarball and .dsc, in that order
+    fn source_paths(&self, version: &str) -> [PathBuf; 3] {
+        let out_dir = PathBuf::from(&self.conf.out.bin);
+        let name = &self.conf.prog.name;
+        let debian_version = self.debian_version();
+        let debian_version = without_epoch(&debian_version);
+
+        [
+            out_dir.join(format!("{name}_{version}.orig.tar.xz")),
+            out_dir.join(format!("{name}_{debian_version}.debian.tar.xz")),
+            out_dir.join(format!("{name}_{debian_version}.dsc")),
+        ]
+    }
+
+    fn control(&self) -> String {
+        let prog = &self.conf.prog;
+        let description = self.conf.deb_description();
+        // with shlibdeps, dh_shlibdeps fills in the library packages at build time
+        let shlibs = self
+            .conf
+            .deb
+            .as_ref()
+            .is_some_and(|deb| deb.shlibdeps)
+            .then(|| "${shlibs:Depends}".to_string());
+        let depends = shlibs
+            .into_iter()
+            .chain(std::iter::once("${misc:Depends}".to_string()))
+            .chain(self.conf.depends(&Target::DebSource).iter().cloned())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let relationships: String = self
+            .conf
+            .deb
+            .iter()
+            .flat_map(|deb| deb.relationships())
+            .filter(|(field, _)| *field != "Depends")
+            .map(|(field, value)| format!("{field}: {value}\n"))
+            .collect();
+
+        // Section, Priority and Homepage describe the source package, the binary inherits them
+        let fields: String = self
+            .conf
+            .deb_fields()
+            .into_iter()
+            .map(|(field, value)| format!("{field}: {value}\n"))
+            .collect();
+
+        format!(
+            "Source: {name}\n\
+             {fields}\
+             Maintainer: {author}\n\
+             Build-Depends: {BUILD_DEPENDS}\n\
+             Standards-Version: {STANDARDS_VERSION}\n\
+             Rules-Requires-Root: no\n\
+             \n\
+             Package: {name}\n\
+             Architecture: {arch}\n\
+             Depends: {depends}\n\
+             {relationships}\
+             Description: {description}\n",
+            name = prog.name,
+            author = prog.author,
+            arch = prog.arch.deb_name(),
+        )
+    }
+
+    // the binaries are prebuilt, so the usual build/strip/shlibdeps steps are skipped
+    fn rules(&self) -> String {
+        let mut rules = String::from(
+            "#!/usr/bin/make -f\n\n\
+             %:\n\
+             \tdh $@\n\n\
+             override_dh_auto_build:\n\n\
+             override_dh_strip:\n",
+        );
+        if !self.conf.deb.as_ref().is_some_and(|deb| deb.shlibdeps) {
+            rules.push_str("\noverride_dh_shlibdeps:\n");
+        }
+
+        if self.conf.files.root.is_some() {
+            rules.push_str(&format!(
+                "\noverride_dh_install:\n\
+                 \tdh_install\n\
+                 \tcp -a {STAGED_ROOT}/. debian/{}/\n",
+                self.conf.prog.name
+            ));
+        }
+
+        let chmods = chmod_commands(
+            &entries(self.conf),
+            &format!("debian/{}", self.conf.prog.name),
+        );
+        if !chmods.is_empty() {
+            rules.push_str("\noverride_dh_fixperms:\n\tdh_fixperms\n");
+            for chmod in chmods {
+                rules.push_str(&format!("\t{chmod}\n"));
+            }
+        }
+
+        rules
+    }
+
+    // dh_install lines: `<source> <destination directory>`, both relative to the package root
+    fn install(&self) -> String {
+        let mut install = String::new();
+
+        for entry in entries(self.conf) {
+            install.push_str(&format!(
+                "{} {}\n",
+                entry.rel,
+                entry.install_dir().trim_start_matches('/')
+            ));
+        }
+
+        install
+    }
+
+    fn links(&self) -> String {
+        let mut links = String::new();
+
+        for entry in entries(self.conf) {
+            let target = entry.dest.trim_start_matches('/');
+            if let Some(link_name) = entry.link_name()
+                && target != format!("usr/bin/{link_name}")
+            {
+                links.push_str(&format!("{target} usr/bin/{link_name}\n"));
+            }
+        }
+
+        links
+    }
+
+    fn dsc(&self, debian_version: &str, files: &[PathBuf]) -> std::io::Result<String> {
+        let prog = &self.conf.prog;
+        let arch = prog.arch.deb_name();
+
+        let mut sha1 = String::new();
+        let mut sha256 = String::new();
+        let mut md5 = String::new();
+        for file in files {
+            let size = std::fs::metadata(file)?.len();
+            let name = file
+                .file_name()
+                .map(|n| n.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            sha1.push_str(&format!(" {} {size} {name}\n", digest_file::<Sha1>(file)?));
+            sha256.push_str(&format!(
+                " {} {size} {name}\n",
+                digest_file::<Sha256>(file)?
+            ));
+            md5.push_str(&format!(" {} {size} {name}\n", digest_file::<Md5>(file)?));
+        }
+
+        Ok(format!(
+            "Format: 3.0 (quilt)\n\
+             Source: {name}\n\
+             Binary: {name}\n\
+             Architecture: {arch}\n\
+             Version: {debian_version}\n\
+             Maintainer: {author}\n\
+             Standards-Version: {STANDARDS_VERSION}\n\
+             Build-Depends: {BUILD_DEPENDS}\n\
+             Package-List:\n \
+             {name} deb misc optional arch={arch}\n\
+             Checksums-Sha1:\n{sha1}\
+             Checksums-Sha256:\n{sha256}\
+             Files:\n{md5}",
+            name = prog.name,
+            author = prog.author,
+        ))
+    }
+
+    fn write_debian_tarball(
+        &self,
+        path: &Path,
+        debian_version: &str,
+        scripts: &InstallScripts,
+    ) -> std::io::Result<()> {
+        let links = self.links();
+        let mut entries = vec![
+            ("debian/control", self.control(), 0o644),
+            ("debian/rules", self.rules(), 0o755),
+            (
+                "debian/changelog",
+                changelog::render(
+                    self.conf,
+                    debian_version,
+                    changelog::distribution(self.conf),
+                )
+                .map_err(std::io::Error::other)?,
+                0o644,
+            ),
+            ("debian/install", self.install(), 0o644),
+            ("debian/source/format", "3.0 (quilt)\n".to_string(), 0o644),
+        ];
+        if !links.is_empty() {
+            entries.push(("debian/links", links, 0o644));
+        }
+        let maintainer_scripts: Vec<(String, String)> = scripts
+            .deb()
+            .into_iter()
+            .map(|(name, contents)| (format!("debian/{name}"), contents))
+            .collect();
+        for (name, contents) in &maintainer_scripts {
+            entries.push((name, contents.clone(), 0o755));
+        }
+
+        let encoder = xz2::write::XzEncoder::new(File::create(path)?, 6);
+        let mut tar = tar::Builder::new(encoder);
+        for (name, contents, mode) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_path(name)?;
+            header.set_mode(mode);
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            tar.append(&header, contents.as_bytes())?;
+        }
+
+        tar.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+impl<'a> Generator for DebSourceGenerator<'a> {
+    fn run(&self) -> Result<Artifacts, ShipError> {
+        let Some(ref version) = self.conf.prog.version else {
+            return Err("the DebSource target requires [prog].version".into());
+        };
+        let name = &self.conf.prog.name;
+        let debian_version = self.debian_version();
+
+        let out_dir = PathBuf::from(&self.conf.out.bin);
+        std::fs::create_dir_all(&out_dir)
+            .context(|| format!("failed to create output directory {}", out_dir.display()))?;
+
+        let exclude = globs::exclude_patterns(self.conf)?;
+
+        let [orig, debian, dsc_path] = self.source_paths(version);
+        atomic::write_with(&orig, |tmp| {
+            write_source_tarball(
+                tmp,
+                &format!("{name}-{version}"),
+                &entries(self.conf),
+                self.conf.files.root.as_deref(),
+                &exclude,
+            )
+        })
+        .context(|| format!("failed to write orig tarball {}", orig.display()))?;
+
+        let scripts = InstallScripts::load(self.conf)?;
+
+        atomic::write_with(&debian, |tmp| {
+            self.write_debian_tarball(tmp, &debian_version, &scripts)
+        })
+        .context(|| format!("failed to write debian tarball {}", debian.display()))?;
+
+        let dsc = self
+            .dsc(&debian_version, &[orig.clone(), debian.clone()])
+            .context(|| "failed to checksum source package files".to_string())?;
+        atomic::write(&dsc_path, dsc)
+            .context(|| format!("failed to write {}", dsc_path.display()))?;
+
+        Ok(vec![orig, debian, dsc_path])
+    }
+
+    fn plan(&self) -> Result<Plan, ShipError> {
+        let mut plan = source_tree::plan(self.conf, self.artifacts());
+        // debian/control, one stanza after the other, with continuation lines kept
+        for line in self.control().lines().filter(|line| !line.is_empty()) {
+            match (line.starts_with(' '), plan.fields.last_mut()) {
+                (true, Some((_, value))) => {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+                _ => {
+                    if let Some((field, value)) = line.split_once(": ") {
+                        plan.fields.push((field.to_string(), value.to_string()));
+                    }
+                }
+            }
+        }
+        plan.scripts = scripts::planned(
+            self.conf,
+            [
+                "debian/preinst",
+                "debian/postinst",
+                "debian/prerm",
+                "debian/postrm",
+            ],
+        );
+        plan.notes.push("the orig tarball holds the [files] as they are, the binary package is built from it with dpkg-buildpackage".to_string());
+        Ok(plan)
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        match &self.conf.prog.version {
+            Some(version) => self.source_paths(version).to_vec(),
+            None => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    cache::work_dir,
+    conf::ShipConfig,
+    icon,
+    layout::{Entry, Layout, is_executable},
+};
+
+/// Where the desktop entry generated for `name` is written
+pub fn generated_path(conf: &ShipConfig, name: &str) -> PathBuf {
+    work_dir(conf)
+        .join("desktop")
+        .join(format!("{name}.desktop"))
+}
+
+/// The command launchers should run: the link to `[prog].main`, otherwise the executable
+/// named after the program, or the first one
+pub fn main_command(conf: &ShipConfig, layout: &Layout) -> Result<String, String> {
+    let link_name = |link: &str| link.rsplit('/').next().unwrap_or(link).to_string();
+
+    if let Some(main) = &conf.prog.main {
+        let entry = layout
+            .files
+            .iter()
+            .find(|entry| entry.source == Path::new(main))
+            .ok_or_else(|| format!("[prog].main `{main}` is not one of the packaged files"))?;
+        if !is_executable(&entry.source, entry.mode) {
+            return Err(format!("[prog].main `{main}` is not an executable"));
+        }
+        // one that isn't linked onto $PATH, e.g. placed with `to`, runs by its full path
+        return Ok(layout
+            .launchers()
+            .find(|(_, target)| *target == entry.dest)
+            .map(|(link, _)| link_name(link))
+            .unwrap_or_else(|| entry.dest.clone()));
+    }
+
+    let names: Vec<String> = layout
+        .launchers()
+        .map(|(link, _)| link_name(link))
+        .collect();
+    names
+        .iter()
+        .find(|name| **name == conf.prog.name)
+        .or(names.first())
+        .cloned()
+        .ok_or_else(|| "[desktop] needs an executable in [files].paths to launch".to_string())
+}
+
+/// Renders the `[Desktop Entry]`; `exec` and `icon` are written as given
+pub fn render(conf: &ShipConfig, exec: &str, icon: &str) -> String {
+    let desktop = conf.desktop.as_ref();
+    let prog = &conf.prog;
+
+    let mut entry = format!(
+        "[Desktop Entry]\n\
+         Type=Application\n\
+         Name={}\n",
+        desktop
+            .and_then(|d| d.name.as_deref())
+            .unwrap_or(&prog.name)
+    );
+    if let Some(desktop) = desktop {
+        let mut localized: Vec<_> = desktop.localized_names.iter().collect();
+        localized.sort();
+        for (locale, name) in localized {
+            entry.push_str(&format!("Name[{locale}]={name}\n"));
+        }
+    }
+    if let Some(generic_name) = desktop.and_then(|d| d.generic_name.as_deref()) {
+        entry.push_str(&format!("GenericName={generic_name}\n"));
+    }
+    if let Some(comment) = desktop
+        .and_then(|d| d.comment.as_deref())
+        .or(prog.description.as_deref())
+    {
+        entry.push_str(&format!("Comment={comment}\n"));
+    }
+
+    let mime_types = desktop.map(|d| d.mime_types.as_slice()).unwrap_or_default();
+    // %F hands the opened files over, which only matters for programs that register file types
+    if mime_types.is_empty() {
+        entry.push_str(&format!("Exec={exec}\n"));
+    } else {
+        entry.push_str(&format!("Exec={exec} %F\n"));
+    }
+    entry.push_str(&format!("Icon={icon}\n"));
+    entry.push_str(&format!(
+        "Terminal={}\n",
+        desktop.is_some_and(|d| d.terminal)
+    ));
+
+    if let Some(categories) = desktop.map(|d| &d.categories).filter(|c| !c.is_empty()) {
+        entry.push_str(&format!("Categories={};\n", categories.join(";")));
+    }
+    if !mime_types.is_empty() {
+        entry.push_str(&format!("MimeType={};\n", mime_types.join(";")));
+    }
+    if let Some(class) = desktop.and_then(|d| d.startup_wm_class.as_deref()) {
+        entry.push_str(&format!("StartupWMClass={class}\n"));
+    }
+
+    entry
+}
+
+/// The desktop entry and themed icon for a package whose executables are linked into a
+/// directory on `$PATH`, installed under `share_dir`
+pub fn entries(conf: &ShipConfig, layout: &Layout, share_dir: &Path) -> Result<Vec<Entry>, String> {
+    let name = &conf.prog.name;
+    let command = main_command(conf, layout)?;
+
+    let mut entries = Vec::new();
+
+    // the theme resolves a bare icon name, whatever size was installed
+    let icon_name = match &conf.files.icon {
+        Some(icon) => {
+            for themed in icon::theme_icons(conf, Path::new(icon), name)? {
+                entries.push(Entry {
+                    source: themed.source,
+                    dest: format!("{}/icons/{}", share_dir.display(), themed.themed),
+                    mode: None,
+                    owner: None,
+                    group: None,
+                });
+            }
+            name.clone()
+        }
+        None => "application-x-executable".to_string(),
+    };
+
+    let path = generated_path(conf, name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+    std::fs::write(&path, render(conf, &command, &icon_name))
+        .map_err(|err| format!("failed to write desktop entry {}: {err}", path.display()))?;
+
+    entries.push(Entry {
+        source: path,
+        dest: format!("{}/applications/{name}.desktop", share_dir.display()),
+        mode: None,
+        owner: None,
+        group: None,
+    });
+
+    Ok(entries)
+}
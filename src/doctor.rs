@@ -0,0 +1,474 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    conf::{ShipConfig, Target},
+    gen_,
+    plugin::{self, EXECUTABLE_PREFIX},
+};
+
+/// An external program ship runs, how to ask it for its version and how to install it
+struct Tool {
+    name: &'static str,
+    version: &'static [&'static str], // arguments that print the version, empty if there are none
+    install: &'static str,
+}
+
+const TOOLS: &[Tool] = &[
+    Tool {
+        name: "dpkg-shlibdeps",
+        version: &["--version"],
+        install: "apt install dpkg-dev",
+    },
+    Tool {
+        name: "objcopy",
+        version: &["--version"],
+        install: "apt install binutils, or dnf install binutils",
+    },
+    Tool {
+        name: "readelf",
+        version: &["--version"],
+        install: "apt install binutils, or dnf install binutils",
+    },
+    Tool {
+        name: "lintian",
+        version: &["--version"],
+        install: "apt install lintian",
+    },
+    Tool {
+        name: "mksquashfs",
+        version: &["-version"],
+        install: "apt install squashfs-tools, dnf install squashfs-tools or brew install squashfs",
+    },
+    Tool {
+        name: "patchelf",
+        version: &["--version"],
+        install: "apt install patchelf, dnf install patchelf or brew install patchelf",
+    },
+    Tool {
+        name: "ldd",
+        version: &["--version"],
+        install: "comes with glibc, apt install libc-bin",
+    },
+    Tool {
+        name: "zsyncmake",
+        version: &["-V"],
+        install: "apt install zsync",
+    },
+    Tool {
+        name: "rsvg-convert",
+        version: &["--version"],
+        install: "apt install librsvg2-bin, dnf install librsvg2-tools or brew install librsvg",
+    },
+    Tool {
+        name: "rcedit",
+        version: &[],
+        install: "download it from https://github.com/electron/rcedit/releases, or point RCEDIT at rcedit.exe",
+    },
+    Tool {
+        name: "wine",
+        version: &["--version"],
+        install: "apt install wine, or brew install --cask wine-stable",
+    },
+    Tool {
+        name: "git",
+        version: &["--version"],
+        install: "apt install git, dnf install git or brew install git",
+    },
+    Tool {
+        name: "gpg",
+        version: &["--version"],
+        install: "apt install gnupg, dnf install gnupg2 or brew install gnupg",
+    },
+    Tool {
+        name: "minisign",
+        version: &["-v"],
+        install: "apt install minisign, or brew install minisign",
+    },
+    Tool {
+        name: "cosign",
+        version: &["version"],
+        install: "brew install cosign, or see https://docs.sigstore.dev/cosign/system_config/installation/",
+    },
+    Tool {
+        name: "osslsigncode",
+        version: &["--version"],
+        install: "apt install osslsigncode, or brew install osslsigncode",
+    },
+    Tool {
+        name: "signtool",
+        version: &[],
+        install: "install the Windows SDK and put its bin directory on PATH",
+    },
+    Tool {
+        name: "azuresigntool",
+        version: &["--version"],
+        install: "dotnet tool install --global AzureSignTool",
+    },
+    Tool {
+        name: "codesign",
+        version: &[],
+        install: "xcode-select --install",
+    },
+    Tool {
+        name: "productsign",
+        version: &[],
+        install: "xcode-select --install",
+    },
+    Tool {
+        name: "xcrun",
+        version: &["--version"],
+        install: "install Xcode 13 or later, notarytool isn't in the Command Line Tools alone",
+    },
+    Tool {
+        name: "curl",
+        version: &["--version"],
+        install: "apt install curl, or dnf install curl",
+    },
+    Tool {
+        name: "dput",
+        version: &["--version"],
+        install: "apt install dput",
+    },
+];
+
+/// What one check found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Found(String),    // the version, or where it is when it can't tell
+    Missing(String),  // how to install it
+    Optional(String), // missing, but ship gets by without it; how to install it
+    Problem(String),  // there, but not usable as configured
+    Skipped(String),  // a target ship can't build yet
+}
+
+/// A program, key or file something in the Shipfile needs, and whether it's there
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    pub needed_by: Vec<String>, // the targets and settings that need it
+    pub status: Status,
+}
+
+impl Check {
+    /// Whether the build would fail over it
+    pub fn failed(&self) -> bool {
+        matches!(self.status, Status::Missing(_) | Status::Problem(_))
+    }
+}
+
+/// Checks everything the configured targets, signing and publishing need from this
+/// machine, one entry per program, key or file, without building anything.
+pub fn run(conf: &ShipConfig) -> Vec<Check> {
+    let mut needs: Vec<(&str, String, bool)> = Vec::new();
+    let mut checks = Vec::new();
+
+    for target in &conf.out.targets {
+        let conf = &conf.for_target(target);
+        match target {
+            Target::Deb => {
+                let deb = conf.deb.as_ref();
+                if deb.is_some_and(|deb| deb.shlibdeps) {
+                    needs.push(("dpkg-shlibdeps", "Deb, [deb].shlibdeps".into(), false));
+                }
+                if deb.is_some_and(|deb| deb.strip) {
+                    needs.push(("objcopy", "Deb, [deb].strip".into(), false));
+                    needs.push(("readelf", "Deb, [deb].strip".into(), false));
+                }
+                // --check falls back to ship's own subset of its checks
+                needs.push(("lintian", "Deb, --check".into(), true));
+            }
+            Target::AppImage => {
+                let appimage = conf.appimage.clone().unwrap_or_default();
+                // the AppDir is packed into the image with it
+                needs.push(("mksquashfs", "AppImage".into(), false));
+                if appimage.bundle_libs || appimage.bundle.is_some() {
+                    needs.push(("patchelf", "AppImage, [appimage].bundle_libs".into(), false));
+                    needs.push(("ldd", "AppImage, [appimage].bundle_libs".into(), false));
+                }
+                if appimage
+                    .update_info
+                    .as_deref()
+                    .and_then(|info| info.split('|').next())
+                    .is_some_and(|transport| transport.ends_with("zsync"))
+                {
+                    needs.push((
+                        "zsyncmake",
+                        "AppImage, [appimage].update_info".into(),
+                        false,
+                    ));
+                }
+                // without it the SVG itself becomes the .DirIcon
+                if conf
+                    .files
+                    .icon
+                    .as_deref()
+                    .is_some_and(|icon| icon.ends_with(".svg"))
+                {
+                    needs.push(("rsvg-convert", "AppImage, an SVG icon".into(), true));
+                }
+            }
+            Target::Custom(name) => checks.push(custom_target(conf, name)),
+            Target::Appcast => {}
+            target if gen_::for_target(target, conf).is_none() => checks.push(Check {
+                name: format!("{target:?}"),
+                needed_by: vec!["[out].targets".into()],
+                status: Status::Skipped("not yet supported by ship, the target is skipped".into()),
+            }),
+            _ => {}
+        }
+    }
+
+    if conf.build.as_ref().is_some_and(|build| build.stamp_version) {
+        needs.push(("objcopy", "[build].stamp_version".into(), false));
+    }
+    if conf.windows.as_ref().is_some_and(|w| w.resources.is_some()) {
+        match std::env::var_os("RCEDIT") {
+            Some(path) => {
+                if cfg!(not(windows)) && Path::new(&path).extension().is_some_and(|e| e == "exe") {
+                    needs.push(("wine", "[windows.resources], RCEDIT".into(), false));
+                }
+                checks.push(file(&path.to_string_lossy(), "[windows.resources], RCEDIT"));
+            }
+            None => needs.push(("rcedit", "[windows.resources]".into(), false)),
+        }
+    }
+    if conf.changelog.as_ref().is_some_and(|c| c.from_git) {
+        needs.push(("git", "[changelog].from_git".into(), false));
+    }
+
+    if let Some(signing) = &conf.signing {
+        if signing.uses_gpg() {
+            needs.push(("gpg", "[signing]".into(), false));
+            checks.push(gpg_key(signing.key.as_deref()));
+        }
+        if let Some(minisign) = &signing.minisign {
+            needs.push(("minisign", "[signing.minisign]".into(), false));
+            checks.push(file(&minisign.key, "[signing.minisign].key"));
+        }
+        if let Some(cosign) = &signing.cosign {
+            needs.push(("cosign", "[signing.cosign]".into(), false));
+            // KMS and PKCS#11 keys are only reachable through cosign itself
+            if let Some(key) = &cosign.key
+                && !key.contains("://")
+                && !key.starts_with("pkcs11:")
+            {
+                checks.push(file(key, "[signing.cosign].key"));
+            }
+        }
+        if let Some(windows) = &signing.windows {
+            let what = "[signing.windows]".to_string();
+            if windows.azure_key_vault.is_some() {
+                needs.push(("azuresigntool", what, false));
+            } else if cfg!(windows) && (windows.cert.is_some() || windows.thumbprint.is_some()) {
+                needs.push(("signtool", what, false));
+            } else {
+                needs.push(("osslsigncode", what, false));
+            }
+            for (path, setting) in [
+                (&windows.cert, "[signing.windows].cert"),
+                (&windows.pkcs11_module, "[signing.windows].pkcs11_module"),
+                (&windows.pkcs11_cert, "[signing.windows].pkcs11_cert"),
+            ] {
+                if let Some(path) = path {
+                    checks.push(file(path, setting));
+                }
+            }
+        }
+        if let Some(macos) = &signing.macos {
+            needs.push(("codesign", "[signing.macos]".into(), false));
+            checks.push(identity(&macos.identity, macos.keychain.as_deref()));
+            if let Some(installer) = &macos.installer_identity {
+                needs.push((
+                    "productsign",
+                    "[signing.macos].installer_identity".into(),
+                    false,
+                ));
+                checks.push(identity(installer, macos.keychain.as_deref()));
+            }
+            if macos.keychain_profile.is_some() {
+                needs.push(("xcrun", "[signing.macos].keychain_profile".into(), false));
+            }
+        }
+    }
+
+    if let Some(publish) = &conf.publish {
+        if publish.github.is_some() {
+            needs.push(("curl", "[publish.github]".into(), false));
+        }
+        if publish.s3.is_some() {
+            needs.push(("curl", "[publish.s3]".into(), false));
+        }
+        if publish.http.is_some() {
+            needs.push(("curl", "[publish.http]".into(), false));
+        }
+        if publish.ppa.is_some() {
+            needs.push(("dput", "[publish.ppa]".into(), false));
+        }
+    }
+
+    // one entry per program, listing everything that needs it
+    let mut tools: Vec<Check> = Vec::new();
+    for (name, what, optional) in needs {
+        if let Some(check) = tools.iter_mut().find(|check| check.name == name) {
+            if !check.needed_by.contains(&what) {
+                check.needed_by.push(what);
+            }
+            if !optional && let Status::Optional(install) = &check.status {
+                check.status = Status::Missing(install.clone());
+            }
+            continue;
+        }
+        tools.push(Check {
+            name: name.to_string(),
+            needed_by: vec![what],
+            status: tool(name, optional),
+        });
+    }
+    tools.extend(checks);
+    tools
+}
+
+fn tool(name: &str, optional: bool) -> Status {
+    let tool = TOOLS.iter().find(|tool| tool.name == name);
+    let install = tool.map_or("", |tool| tool.install).to_string();
+    let Some(path) = plugin::on_path(name) else {
+        return if optional {
+            Status::Optional(install)
+        } else {
+            Status::Missing(install)
+        };
+    };
+    let version = tool
+        .filter(|tool| !tool.version.is_empty())
+        .and_then(|tool| version(&path, tool.version));
+    Status::Found(version.unwrap_or_else(|| path.display().to_string()))
+}
+
+// the first line it prints, on stdout or, like `minisign -v`, on stderr
+fn version(path: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new(path)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    [&output.stdout, &output.stderr]
+        .into_iter()
+        .find_map(|text| {
+            String::from_utf8_lossy(text)
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_string)
+        })
+}
+
+fn custom_target(conf: &ShipConfig, name: &str) -> Check {
+    let command = conf
+        .plugins
+        .as_ref()
+        .and_then(|plugins| plugins.get(name))
+        .map(|plugin| plugin.command.clone());
+    let status = match (command, gen_::custom(name, conf)) {
+        (_, Some(_)) => Status::Found("built in".into()),
+        // run through the shell, so only its first word can be looked up
+        (Some(command), None) => {
+            let program = command.split_whitespace().next().unwrap_or_default();
+            if program.contains('/') || plugin::on_path(program).is_some() {
+                Status::Found(format!("`{command}`"))
+            } else {
+                Status::Missing(format!(
+                    "`{program}` from [plugins.{name}].command isn't on PATH"
+                ))
+            }
+        }
+        (None, None) => match plugin::on_path(&format!("{EXECUTABLE_PREFIX}{name}")) {
+            Some(path) => Status::Found(path.display().to_string()),
+            None => Status::Missing(format!(
+                "set [plugins.{name}].command or put {EXECUTABLE_PREFIX}{name} on PATH"
+            )),
+        },
+    };
+    Check {
+        name: format!("{name} plugin"),
+        needed_by: vec![format!("{name}, [out].targets")],
+        status,
+    }
+}
+
+fn file(path: &str, setting: &str) -> Check {
+    let status = if Path::new(path).is_file() {
+        Status::Found(path.to_string())
+    } else {
+        Status::Problem(format!("{path} does not exist"))
+    };
+    Check {
+        name: "file".into(),
+        needed_by: vec![setting.to_string()],
+        status,
+    }
+}
+
+// only the secret half signs, the public one alone is no use
+fn gpg_key(key: Option<&str>) -> Check {
+    let mut cmd = Command::new("gpg");
+    cmd.args(["--batch", "--with-colons", "--list-secret-keys"]);
+    if let Some(key) = key {
+        cmd.arg(key);
+    }
+    let output = cmd.stdin(Stdio::null()).stderr(Stdio::null()).output();
+    let status = match output {
+        Ok(output) if output.status.success() => {
+            let listing = String::from_utf8_lossy(&output.stdout);
+            let user_id = listing
+                .lines()
+                .find_map(|line| line.strip_prefix("uid:"))
+                .and_then(|line| line.split(':').nth(8))
+                .filter(|user_id| !user_id.is_empty());
+            match user_id {
+                Some(user_id) => Status::Found(user_id.to_string()),
+                None => Status::Problem("gpg has no secret key to sign with".into()),
+            }
+        }
+        Ok(_) => Status::Problem(match key {
+            Some(key) => format!("gpg has no secret key for {key}"),
+            None => "gpg has no secret key to sign with".into(),
+        }),
+        Err(_) => Status::Problem("gpg isn't installed".into()),
+    };
+    Check {
+        name: "gpg key".into(),
+        needed_by: vec![match key {
+            Some(_) => "[signing].key".into(),
+            None => "[signing], gpg's default key".into(),
+        }],
+        status,
+    }
+}
+
+// a Developer ID the keychain has, with its private key
+fn identity(identity: &str, keychain: Option<&str>) -> Check {
+    let status = if cfg!(target_os = "macos") {
+        let mut cmd = Command::new("security");
+        cmd.args(["find-identity", "-v"]);
+        if let Some(keychain) = keychain {
+            cmd.arg(keychain);
+        }
+        match cmd.stdin(Stdio::null()).output() {
+            Ok(output) if String::from_utf8_lossy(&output.stdout).contains(identity) => {
+                Status::Found(identity.to_string())
+            }
+            Ok(_) => Status::Problem(format!("the keychain has no valid identity `{identity}`")),
+            Err(err) => Status::Problem(format!("failed to run security: {err}")),
+        }
+    } else {
+        Status::Problem("codesign and productsign only run on macOS".into())
+    };
+    Check {
+        name: "identity".into(),
+        needed_by: vec!["[signing.macos]".into()],
+        status,
+    }
+}
@@ -0,0 +1,228 @@
+use std::path::{Path, PathBuf};
+
+use blake2::Blake2b512;
+use sha2::Sha512;
+
+use crate::{
+    atomic,
+    checksum::digest_file,
+    conf::{ShipConfig, Target},
+    error::{Context, ShipError},
+    gen_::{Artifacts, Generator, Plan},
+    globs,
+    layout::is_executable,
+    scripts::{self, InstallScripts, inline},
+    source_tree::{self, STAGED_ROOT, chmod_commands, entries, write_source_tarball},
+};
+
+pub struct EbuildGenerator<'a> {
+    pub conf: &'a ShipConfig,
+}
+
+impl<'a> EbuildGenerator<'a> {
+    pub fn new(conf: &'a ShipConfig) -> Self {
+        Self { conf }
+    }
+
+    fn out_dir(&self) -> PathBuf {
+        PathBuf::from(&self.conf.out.bin)
+    }
+
+    fn src_install(&self) -> String {
+        let mut body = String::new();
+
+        if self.conf.files.root.is_some() {
+            body.push_str(&format!(
+                "\tcp -pPR \"${{S}}/{STAGED_ROOT}/.\" \"${{ED}}/\" || die\n"
+            ));
+        }
+
+        let entries = entries(self.conf);
+        for entry in &entries {
+            let rel = &entry.rel;
+            let install_dir = entry.install_dir();
+
+            if entry.source.is_dir() {
+                body.push_str(&format!("\tinsinto {install_dir}\n\tdoins -r {rel}\n"));
+            } else if is_executable(&entry.source, entry.mode) {
+                body.push_str(&format!("\texeinto {install_dir}\n\tdoexe {rel}\n"));
+                if let Some(link_name) = entry.link_name()
+                    && entry.dest != format!("/usr/bin/{link_name}")
+                {
+                    body.push_str(&format!("\tdosym -r {} /usr/bin/{link_name}\n", entry.dest));
+                }
+            } else {
+                body.push_str(&format!("\tinsinto {install_dir}\n\tdoins {rel}\n"));
+            }
+        }
+
+        // doins and doexe pick the modes, [files].paths may ask for others
+        for chmod in chmod_commands(&entries, "${ED}") {
+            body.push_str(&format!("\t{chmod} || die\n"));
+        }
+
+        body
+    }
+
+    fn render(&self, version: &str, scripts: &InstallScripts) -> String {
+        let ebuild = self.conf.ebuild.as_ref();
+        let prog = &self.conf.prog;
+
+        let keywords = ebuild
+            .and_then(|e| e.keywords.clone())
+            .map(|k| k.join(" "))
+            .or_else(|| prog.arch.gentoo_keyword().map(|k| format!("~{k}")))
+            .unwrap_or_default();
+
+        let mut out = String::new();
+        out.push_str("# Generated by ship, do not edit by hand\n\n");
+        out.push_str("EAPI=8\n\n");
+        out.push_str(&format!(
+            "DESCRIPTION=\"{}\"\n",
+            quote(prog.description.as_deref().unwrap_or(&prog.name))
+        ));
+        if let Some(homepage) = ebuild.and_then(|e| e.homepage.as_deref()) {
+            out.push_str(&format!("HOMEPAGE=\"{}\"\n", quote(homepage)));
+        }
+        out.push_str(&format!(
+            "SRC_URI=\"{} -> {}\"\n\n",
+            quote(ebuild.map(|e| e.src_uri.as_str()).unwrap_or_default()),
+            distfile_name(&prog.name, version)
+        ));
+        out.push_str(&format!(
+            "LICENSE=\"{}\"\n",
+            quote(
+                ebuild
+                    .and_then(|e| e.license.as_deref())
+                    .unwrap_or("all-rights-reserved")
+            )
+        ));
+        out.push_str("SLOT=\"0\"\n");
+        out.push_str(&format!("KEYWORDS=\"{keywords}\"\n"));
+        out.push_str("RESTRICT=\"strip\"\n\n");
+        let depends = self.conf.depends(&Target::Ebuild);
+        if !depends.is_empty() {
+            out.push_str(&format!("RDEPEND=\"{}\"\n\n", quote(&depends.join(" "))));
+        }
+        out.push_str(&format!(
+            "QA_PREBUILT=\"{}/*\"\n\n",
+            self.conf.install_prefix().trim_start_matches('/')
+        ));
+        out.push_str("src_install() {\n");
+        out.push_str(&self.src_install());
+        out.push_str("}\n");
+
+        for (phase, script) in [
+            ("pkg_preinst", &scripts.preinstall),
+            ("pkg_postinst", &scripts.postinstall),
+            ("pkg_prerm", &scripts.preremove),
+            ("pkg_postrm", &scripts.postremove),
+        ] {
+            if let Some(script) = script {
+                out.push_str(&format!("\n{phase}() {{\n{}}}\n", inline(script)));
+            }
+        }
+        out
+    }
+}
+
+impl<'a> Generator for EbuildGenerator<'a> {
+    fn run(&self) -> Result<Artifacts, ShipError> {
+        let Some(ref ebuild) = self.conf.ebuild else {
+            return Err("the Ebuild target requires an [ebuild] section with src_uri".into());
+        };
+        if ebuild.src_uri.is_empty() {
+            return Err("[ebuild].src_uri must not be empty".into());
+        }
+
+        let Some(ref version) = self.conf.prog.version else {
+            return Err("the Ebuild target requires [prog].version".into());
+        };
+
+        let out_dir = self.out_dir();
+        std::fs::create_dir_all(&out_dir)
+            .context(|| format!("failed to create output directory {}", out_dir.display()))?;
+
+        let exclude = globs::exclude_patterns(self.conf)?;
+
+        let distfile = out_dir.join(distfile_name(&self.conf.prog.name, version));
+        // the ebuild's default ${S} is `<name>-<version>/`
+        atomic::write_with(&distfile, |tmp| {
+            write_source_tarball(
+                tmp,
+                &format!("{}-{version}", self.conf.prog.name),
+                &entries(self.conf),
+                self.conf.files.root.as_deref(),
+                &exclude,
+            )
+        })
+        .context(|| format!("failed to write ebuild distfile {}", distfile.display()))?;
+
+        let scripts = InstallScripts::load(self.conf)?;
+
+        let ebuild_path = out_dir.join(format!("{}-{version}.ebuild", self.conf.prog.name));
+        atomic::write(&ebuild_path, self.render(version, &scripts))
+            .context(|| format!("failed to write ebuild at {}", ebuild_path.display()))?;
+
+        let manifest = manifest_entry(&distfile)
+            .context(|| format!("failed to checksum {}", distfile.display()))?;
+        let manifest_path = out_dir.join("Manifest");
+        atomic::write(&manifest_path, manifest)
+            .context(|| format!("failed to write Manifest at {}", manifest_path.display()))?;
+
+        Ok(vec![distfile, ebuild_path, manifest_path])
+    }
+
+    fn plan(&self) -> Result<Plan, ShipError> {
+        let mut plan = source_tree::plan(self.conf, self.artifacts());
+        plan.scripts = scripts::planned(
+            self.conf,
+            ["pkg_preinst", "pkg_postinst", "pkg_prerm", "pkg_postrm"],
+        );
+        plan.notes.push(
+            "the distfile holds the [files] as they are, the ebuild installs them from it"
+                .to_string(),
+        );
+        Ok(plan)
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        let Some(ref version) = self.conf.prog.version else {
+            return Vec::new();
+        };
+        let out_dir = self.out_dir();
+
+        vec![
+            out_dir.join(distfile_name(&self.conf.prog.name, version)),
+            out_dir.join(format!("{}-{version}.ebuild", self.conf.prog.name)),
+            out_dir.join("Manifest"),
+        ]
+    }
+}
+
+fn distfile_name(name: &str, version: &str) -> String {
+    format!("{name}-{version}.tar.xz")
+}
+
+fn manifest_entry(distfile: &Path) -> std::io::Result<String> {
+    let size = std::fs::metadata(distfile)?.len();
+    let blake2b = digest_file::<Blake2b512>(distfile)?;
+    let sha512 = digest_file::<Sha512>(distfile)?;
+    let name = distfile
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    Ok(format!(
+        "DIST {name} {size} BLAKE2B {blake2b} SHA512 {sha512}\n"
+    ))
+}
+
+// escapes a value for use inside a double-quoted bash string
+fn quote(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('$', "\\$")
+        .replace('`', "\\`")
+}
@@ -0,0 +1,43 @@
+/// Why a target couldn't be built
+#[derive(Debug, thiserror::Error)]
+pub enum ShipError {
+    /// A step failed, `context` says which, like `failed to write {path}`
+    #[error("{context}: {source}")]
+    Failed {
+        context: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Anything else, worded for the user by the helper that failed
+    #[error("{0}")]
+    Message(String),
+}
+
+// helpers report their errors as ready-made messages
+impl From<String> for ShipError {
+    fn from(message: String) -> Self {
+        ShipError::Message(message)
+    }
+}
+
+impl From<&str> for ShipError {
+    fn from(message: &str) -> Self {
+        ShipError::Message(message.to_string())
+    }
+}
+
+/// Says what was being done when an error happened, keeping the error as its source
+pub trait Context<T> {
+    fn context(self, context: impl FnOnce() -> String) -> Result<T, ShipError>;
+}
+
+impl<T, E> Context<T> for Result<T, E>
+where
+    E: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    fn context(self, context: impl FnOnce() -> String) -> Result<T, ShipError> {
+        self.map_err(|source| ShipError::Failed {
+            context: context(),
+            source: source.into(),
+        })
+    }
+}
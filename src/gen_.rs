@@ -1,3 +1,98 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use crate::{
+    appimage::AppImageGenerator,
+    archive::ArchiveGenerator,
+    conf::{ShipConfig, Target},
+    deb::DebGenerator,
+    debsrc::DebSourceGenerator,
+    ebuild::EbuildGenerator,
+    error::ShipError,
+    layout::Entry,
+    plugin::PluginGenerator,
+    xbps::XbpsGenerator,
+};
+
+/// Files a generator wrote
+pub type Artifacts = Vec<PathBuf>;
+
+/// What a target would write, worked out without writing anything, for `--dry-run`
+#[derive(Debug, Default)]
+pub struct Plan {
+    pub artifacts: Vec<PathBuf>,
+    pub files: Vec<Entry>, // what goes where in the package, a source may be a whole directory
+    pub symlinks: Vec<(String, String)>, // (link, target)
+    pub fields: Vec<(String, String)>, // control or package metadata fields, in the order they're written
+    pub scripts: Vec<(String, String)>, // (where it runs in the package, the [scripts] file it comes from)
+    pub notes: Vec<String>,             // what's only settled while building, e.g. generated files
+}
+
 pub trait Generator {
-    fn run(&self);
+    /// Builds the target's artifacts and returns the ones it wrote. An error fails only
+    /// this target, the others are still built.
+    fn run(&self) -> Result<Artifacts, ShipError>;
+
+    /// Files `run()` writes, known before it runs, used to tell whether a target is up to
+    /// date and by targets that describe other artifacts
+    fn artifacts(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// What `run()` would write, without writing anything. Generators that can't tell
+    /// more beforehand only list their artifacts.
+    fn plan(&self) -> Result<Plan, ShipError> {
+        Ok(Plan {
+            artifacts: self.artifacts(),
+            ..Default::default()
+        })
+    }
+}
+
+/// The generator for `target`: built-in, registered, or a plugin from the Shipfile or
+/// `PATH`, in that order. `None` for targets ship can't build yet, unknown custom targets
+/// and the Appcast feed, which needs the artifacts of the others (see
+/// [crate::appcast::AppcastGenerator::new]).
+pub fn for_target<'a>(target: &Target, conf: &'a ShipConfig) -> Option<Box<dyn Generator + 'a>> {
+    Some(match target {
+        Target::Deb => Box::new(DebGenerator::new(conf)),
+        Target::AppImage => Box::new(AppImageGenerator::new(conf)),
+        Target::Ebuild => Box::new(EbuildGenerator::new(conf)),
+        Target::Xbps => Box::new(XbpsGenerator::new(conf)),
+        Target::DebSource => Box::new(DebSourceGenerator::new(conf)),
+        Target::Archive => Box::new(ArchiveGenerator::new(conf)),
+        Target::Custom(name) => {
+            return custom(name, conf).or_else(|| {
+                PluginGenerator::find(name, conf)
+                    .map(|plugin| Box::new(plugin) as Box<dyn Generator + 'a>)
+            });
+        }
+        _ => return None,
+    })
+}
+
+/// Makes the generator of a custom target for a Shipfile
+pub type Factory = for<'a> fn(&'a ShipConfig) -> Box<dyn Generator + 'a>;
+
+static REGISTRY: Mutex<Vec<(String, Factory)>> = Mutex::new(Vec::new());
+
+/// Registers the generator for `Target::Custom(name)`, i.e. `name` in `[out].targets`,
+/// replacing an earlier registration of the same name
+pub fn register(name: &str, factory: Factory) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(|err| err.into_inner());
+    registry.retain(|(registered, _)| registered != name);
+    registry.push((name.to_string(), factory));
+}
+
+pub fn is_registered(name: &str) -> bool {
+    let registry = REGISTRY.lock().unwrap_or_else(|err| err.into_inner());
+    registry.iter().any(|(registered, _)| registered == name)
+}
+
+/// The generator registered for the custom target `name`, if any
+pub fn custom<'a>(name: &str, conf: &'a ShipConfig) -> Option<Box<dyn Generator + 'a>> {
+    let registry = REGISTRY.lock().unwrap_or_else(|err| err.into_inner());
+    registry
+        .iter()
+        .find(|(registered, _)| registered == name)
+        .map(|(_, factory)| factory(conf))
 }
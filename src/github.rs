@@ -0,0 +1,234 @@
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use serde_json::{Value, json};
+
+use crate::{
+    conf::{GithubRelease, ShipConfig},
+    git,
+    report::BuildReport,
+};
+
+const API_URL: &str = "https://api.github.com";
+
+/// Uploads the artifacts of the last build, their signatures and SBOMs, and the checksums
+/// to the GitHub Release of `tag`, creating the release if there's none yet, and returns
+/// the names of the uploaded files.
+///
+/// `tag` defaults to the tag of the checked out commit. Files already on the release are
+/// replaced, so a failed upload can simply be run again.
+pub fn publish(conf: &ShipConfig, tag: Option<&str>) -> Result<Vec<String>, String> {
+    let settings = conf
+        .publish
+        .as_ref()
+        .and_then(|publish| publish.github.clone())
+        .unwrap_or_default();
+    let repo = match &settings.repo {
+        Some(repo) => repo.clone(),
+        None => origin_repo().ok_or(
+            "no GitHub repository in [publish.github].repo, and the origin remote isn't on github.com",
+        )?,
+    };
+    let tag = match tag {
+        Some(tag) => tag.to_string(),
+        None => head_tag().ok_or("HEAD has no tag, pass --tag")?,
+    };
+    let token = token(&settings)?;
+    let api = Api {
+        url: settings
+            .api_url
+            .as_deref()
+            .unwrap_or(API_URL)
+            .trim_end_matches('/'),
+        token: &token,
+    };
+
+    let files = BuildReport::load(&conf.prog.name)?.release_files();
+    if files.is_empty() {
+        return Err("the last build has no artifacts to publish".to_string());
+    }
+
+    let release = match api.call("GET", &format!("/repos/{repo}/releases/tags/{tag}"), None)? {
+        (200, release) => release,
+        (404, _) => {
+            info!("creating release {tag} on {repo}...");
+            let body = json!({
+                "tag_name": tag,
+                "name": tag,
+                "draft": settings.draft,
+                "prerelease": settings.prerelease,
+            });
+            match api.call("POST", &format!("/repos/{repo}/releases"), Some(&body))? {
+                (201, release) => release,
+                (status, response) => {
+                    return Err(format!(
+                        "failed to create release {tag} on {repo} ({status}): {}",
+                        message(&response)
+                    ));
+                }
+            }
+        }
+        (status, response) => {
+            return Err(format!(
+                "failed to look up release {tag} on {repo} ({status}): {}",
+                message(&response)
+            ));
+        }
+    };
+    // `https://uploads.github.com/repos/o/r/releases/1/assets{?name,label}`
+    let upload_url = release["upload_url"]
+        .as_str()
+        .and_then(|url| url.split('{').next())
+        .ok_or("GitHub answered without an upload URL")?;
+    let existing = release["assets"].as_array().cloned().unwrap_or_default();
+
+    let mut uploaded = Vec::new();
+    for file in &files {
+        let name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if let Some(asset) = existing.iter().find(|asset| asset["name"] == name.as_str()) {
+            let asset_id = asset["id"].as_u64().unwrap_or_default();
+            match api.call(
+                "DELETE",
+                &format!("/repos/{repo}/releases/assets/{asset_id}"),
+                None,
+            )? {
+                (204, _) => {}
+                (status, response) => {
+                    return Err(format!(
+                        "failed to replace {name} on release {tag} ({status}): {}",
+                        message(&response)
+                    ));
+                }
+            }
+        }
+
+        info!("uploading {name}...");
+        let url = format!("{upload_url}?name={}", encode(&name));
+        match api.upload(&url, file)? {
+            (201, _) => uploaded.push(name),
+            (status, response) => {
+                return Err(format!(
+                    "failed to upload {name} to release {tag} ({status}): {}",
+                    message(&response)
+                ));
+            }
+        }
+    }
+
+    Ok(uploaded)
+}
+
+fn token(settings: &GithubRelease) -> Result<String, String> {
+    let names = match &settings.token_env {
+        Some(name) => vec![name.as_str()],
+        None => vec!["GITHUB_TOKEN", "GH_TOKEN"],
+    };
+    names
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|token| !token.is_empty()))
+        .ok_or_else(|| format!("no GitHub token in {}", names.join(" or ")))
+}
+
+/// The GitHub REST API, called with curl like every other download
+struct Api<'a> {
+    url: &'a str,
+    token: &'a str,
+}
+
+impl Api<'_> {
+    fn call(&self, method: &str, path: &str, body: Option<&Value>) -> Result<(u16, Value), String> {
+        let mut curl = Command::new("curl");
+        curl.args(["--request", method])
+            .arg(format!("{}{path}", self.url));
+        if let Some(body) = body {
+            curl.args(["--header", "Content-Type: application/json", "--data"])
+                .arg(body.to_string());
+        }
+        self.run(curl, method, path)
+    }
+
+    fn upload(&self, url: &str, file: &Path) -> Result<(u16, Value), String> {
+        let mut curl = Command::new("curl");
+        curl.args(["--request", "POST", "--header"])
+            .arg("Content-Type: application/octet-stream")
+            .arg("--data-binary")
+            .arg(format!("@{}", file.display()))
+            .arg(url);
+        self.run(curl, "POST", url)
+    }
+
+    // the token goes in on stdin rather than where `ps` would show it
+    fn run(&self, mut curl: Command, method: &str, what: &str) -> Result<(u16, Value), String> {
+        curl.args(["--silent", "--show-error", "--location"])
+            .args(["--header", "@-"])
+            .args(["--header", "Accept: application/vnd.github+json"])
+            .args(["--header", "X-GitHub-Api-Version: 2022-11-28"])
+            .args(["--write-out", "\n%{http_code}"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        let mut child = crate::log::command(&mut curl)
+            .spawn()
+            .map_err(|err| format!("failed to run curl for {method} {what}: {err}"))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, "Authorization: Bearer {}", self.token)
+                .map_err(|err| format!("failed to pass the token to curl: {err}"))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("failed to run curl for {method} {what}: {err}"))?;
+        if !output.status.success() {
+            return Err(format!("{method} {what} failed ({})", output.status));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+        let status = status
+            .trim()
+            .parse()
+            .map_err(|_| format!("{method} {what} got no HTTP status"))?;
+        Ok((status, serde_json::from_str(body).unwrap_or(Value::Null)))
+    }
+}
+
+fn message(response: &Value) -> String {
+    response["message"]
+        .as_str()
+        .unwrap_or("no details")
+        .to_string()
+}
+
+// `owner/name` from `git@github.com:owner/name.git` or `https://github.com/owner/name`
+fn origin_repo() -> Option<String> {
+    let url = git(&["remote", "get-url", "origin"])?;
+    let (_, path) = url.split_once("github.com")?;
+    let path = path
+        .trim_start_matches([':', '/'])
+        .trim_end_matches('/')
+        .trim_end_matches(".git");
+    let (owner, name) = path.split_once('/')?;
+    (!owner.is_empty() && !name.is_empty() && !name.contains('/')).then(|| path.to_string())
+}
+
+fn head_tag() -> Option<String> {
+    git(&["describe", "--tags", "--exact-match", "HEAD"])
+}
+
+// asset names go in the query string, where `+` would turn into a space
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
@@ -0,0 +1,103 @@
+use std::path::{Component, Path, PathBuf};
+
+use glob::Pattern;
+
+use crate::conf::{FileEntry, ShipConfig};
+
+/// Whether a [files].paths entry is a glob rather than a plain path
+fn is_pattern(path: &str) -> bool {
+    path.contains(['*', '?', '['])
+}
+
+/// The compiled [files].exclude patterns
+pub fn exclude_patterns(conf: &ShipConfig) -> Result<Vec<Pattern>, String> {
+    conf.files
+        .exclude
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern)
+                .map_err(|err| format!("invalid pattern `{pattern}` in [files].exclude: {err}"))
+        })
+        .collect()
+}
+
+/// Whether `path` is matched by [files].exclude, as a whole or by its file name alone,
+/// so `.DS_Store` and `*.o` work at any depth
+pub fn is_excluded(exclude: &[Pattern], path: &Path) -> bool {
+    let name = path.file_name().map(Path::new);
+    exclude
+        .iter()
+        .any(|pattern| pattern.matches_path(path) || name.is_some_and(|n| pattern.matches_path(n)))
+}
+
+/// Replaces every glob in [files].paths and the per-target `paths` by one entry per matching
+/// file, in sorted order so packages stay reproducible.
+///
+/// Has to run after the build command, which is what usually creates the matches.
+pub fn expand(conf: &mut ShipConfig) -> Result<(), String> {
+    let exclude = exclude_patterns(conf)?;
+
+    conf.files.paths = expand_paths(&conf.files.paths, &exclude)?;
+    for overrides in conf.all_overrides_mut() {
+        if let Some(paths) = &overrides.paths {
+            overrides.paths = Some(expand_paths(paths, &exclude)?);
+        }
+    }
+
+    Ok(())
+}
+
+fn expand_paths(paths: &[FileEntry], exclude: &[Pattern]) -> Result<Vec<FileEntry>, String> {
+    let mut expanded = Vec::new();
+
+    for entry in paths {
+        if !is_pattern(&entry.from) {
+            expanded.push(entry.clone());
+            continue;
+        }
+
+        let mut matches = glob::glob(&entry.from)
+            .map_err(|err| format!("invalid pattern `{}` in [files].paths: {err}", entry.from))?
+            .collect::<Result<Vec<PathBuf>, _>>()
+            .map_err(|err| format!("failed to expand `{}`: {err}", entry.from))?;
+        // directories are covered by the files below them
+        matches.retain(|path| path.is_file() && !is_excluded(exclude, path));
+        matches.sort();
+
+        if matches.is_empty() {
+            return Err(format!(
+                "pattern `{}` in [files].paths matches no files",
+                entry.from
+            ));
+        }
+
+        let base = literal_base(&entry.from);
+        for path in matches {
+            // with `to`, matches keep their path below the pattern's literal part
+            let to = entry.to.as_ref().map(|to| {
+                let rel = path.strip_prefix(&base).unwrap_or(&path);
+                format!("{}/{}", to.trim_end_matches('/'), rel.display())
+            });
+            expanded.push(FileEntry {
+                from: path.to_string_lossy().into_owned(),
+                to,
+                mode: entry.mode,
+                owner: entry.owner.clone(),
+                group: entry.group.clone(),
+            });
+        }
+    }
+
+    Ok(expanded)
+}
+
+/// The leading components of a pattern without any glob characters: `assets/**/*.png` -> `assets`
+fn literal_base(pattern: &str) -> PathBuf {
+    Path::new(pattern)
+        .components()
+        .take_while(|component| match component {
+            Component::Normal(part) => !is_pattern(&part.to_string_lossy()),
+            _ => true,
+        })
+        .collect()
+}
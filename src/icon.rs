@@ -0,0 +1,169 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use image::{
+    DynamicImage, GenericImageView, ImageFormat, RgbaImage, imageops, imageops::FilterType,
+};
+
+use crate::{cache::work_dir, conf::ShipConfig};
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Width and height of a PNG image, read from its header.
+///
+/// The IHDR chunk always comes first and starts with the width and height.
+pub fn png_size(data: &[u8]) -> Option<(u32, u32)> {
+    if !data.starts_with(PNG_SIGNATURE) || data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+/// The sizes of the hicolor theme's fixed-size directories. Icons anywhere else aren't
+/// found, so sources of other sizes are scaled to these.
+pub const THEME_SIZES: &[u32] = &[16, 24, 32, 48, 64, 128, 256, 512];
+
+/// The size AppImages show in file managers and use for `.DirIcon`
+pub const APPIMAGE_SIZE: u32 = 256;
+
+/// An icon ready to install: `source` goes to `themed`, a path inside the icon theme
+#[derive(Debug, Clone)]
+pub struct ThemedIcon {
+    pub source: PathBuf,
+    pub themed: String, // e.g. `hicolor/256x256/apps/<name>.png`
+}
+
+enum Source {
+    Svg,
+    Raster(DynamicImage),
+}
+
+fn load(icon: &Path) -> Result<Source, String> {
+    let ext = icon
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let format = match ext.as_str() {
+        "svg" => return Ok(Source::Svg),
+        "png" => ImageFormat::Png,
+        "ico" => ImageFormat::Ico,
+        _ => {
+            return Err(format!(
+                "icon {} must be a .png, .svg or .ico",
+                icon.display()
+            ));
+        }
+    };
+    let data = std::fs::read(icon)
+        .map_err(|err| format!("failed to read icon {}: {err}", icon.display()))?;
+    // an .ico holds several sizes, the decoder picks the largest
+    let image = image::load_from_memory_with_format(&data, format)
+        .map_err(|err| format!("icon {} is not a valid image: {err}", icon.display()))?;
+    Ok(Source::Raster(square(image)))
+}
+
+// themes only have square icons, anything else is centered on a transparent square
+fn square(image: DynamicImage) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    if width == height {
+        return image;
+    }
+    let side = width.max(height);
+    let mut canvas = RgbaImage::new(side, side);
+    imageops::overlay(
+        &mut canvas,
+        &image.to_rgba8(),
+        i64::from((side - width) / 2),
+        i64::from((side - height) / 2),
+    );
+    DynamicImage::ImageRgba8(canvas)
+}
+
+fn scaled(
+    conf: &ShipConfig,
+    image: &DynamicImage,
+    name: &str,
+    size: u32,
+) -> Result<PathBuf, String> {
+    let path = work_dir(conf)
+        .join("icons")
+        .join(format!("{size}x{size}"))
+        .join(format!("{name}.png"));
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+    let image = if image.width() == size {
+        image.clone()
+    } else {
+        image.resize_exact(size, size, FilterType::Lanczos3)
+    };
+    image
+        .save_with_format(&path, ImageFormat::Png)
+        .map_err(|err| format!("failed to write icon {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+/// The icons to install into the hicolor theme for `icon`: an SVG as the scalable icon,
+/// PNG and ICO images scaled to each theme size up to their own (at least the smallest)
+pub fn theme_icons(conf: &ShipConfig, icon: &Path, name: &str) -> Result<Vec<ThemedIcon>, String> {
+    let image = match load(icon)? {
+        Source::Svg => {
+            return Ok(vec![ThemedIcon {
+                source: icon.to_path_buf(),
+                themed: format!("hicolor/scalable/apps/{name}.svg"),
+            }]);
+        }
+        Source::Raster(image) => image,
+    };
+    // scaling up only blurs, so small sources get just the smallest size
+    let largest = image.width().max(THEME_SIZES[0]);
+    THEME_SIZES
+        .iter()
+        .filter(|size| **size <= largest)
+        .map(|&size| {
+            Ok(ThemedIcon {
+                source: scaled(conf, &image, name, size)?,
+                themed: format!("hicolor/{size}x{size}/apps/{name}.png"),
+            })
+        })
+        .collect()
+}
+
+/// The icon at the root of an AppDir, which `.DirIcon` links to: a 256x256 PNG, or the SVG
+/// itself. `rsvg-convert` renders SVGs to PNG when it's installed, since not every file
+/// manager reads an SVG `.DirIcon`.
+pub fn appimage_icon(conf: &ShipConfig, icon: &Path, name: &str) -> Result<PathBuf, String> {
+    match load(icon)? {
+        Source::Raster(image) => scaled(conf, &image, name, APPIMAGE_SIZE.min(image.width())),
+        Source::Svg => Ok(render_svg(conf, icon, name).unwrap_or_else(|| icon.to_path_buf())),
+    }
+}
+
+fn render_svg(conf: &ShipConfig, icon: &Path, name: &str) -> Option<PathBuf> {
+    let dir = work_dir(conf).join("icons").join("appimage");
+    std::fs::create_dir_all(&dir).ok()?;
+    let path = dir.join(format!("{name}.png"));
+    let size = APPIMAGE_SIZE.to_string();
+    let status = crate::log::command(
+        Command::new("rsvg-convert")
+            .args([
+                "--width",
+                &size,
+                "--height",
+                &size,
+                "--keep-aspect-ratio",
+                "--output",
+            ])
+            .arg(&path)
+            .arg(icon)
+            .stdin(Stdio::null()),
+    )
+    .status()
+    .ok()?;
+    status.success().then_some(path)
+}
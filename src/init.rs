@@ -0,0 +1,301 @@
+use std::{path::Path, process::Command};
+
+use toml::Value;
+
+use crate::prompt;
+
+/// What `ship init` could tell about the project in the current directory
+#[derive(Default, Debug)]
+pub struct Detected {
+    pub source: Option<&'static str>, // the manifest it was read from
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    pub description: Option<String>,
+    pub binary: Option<String>, // the built executable, relative to the project
+    pub build: Option<String>,  // the command that builds it
+}
+
+/// Values given on the command line, which win over anything detected
+#[derive(Default, Debug)]
+pub struct Answers {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub author: Option<String>,
+    pub binary: Option<String>,
+}
+
+/// Reads the name, version and executable from `Cargo.toml`, `package.json` or
+/// `CMakeLists.txt` in `dir`, whichever is found first
+pub fn detect(dir: &Path) -> Detected {
+    let mut detected = cargo(dir)
+        .or_else(|| npm(dir))
+        .or_else(|| cmake(dir))
+        .unwrap_or_default();
+    if detected.author.is_none() {
+        detected.author = git_author();
+    }
+    detected
+}
+
+/// The version of the project in `dir` and where it was read from: its manifest, like for
+/// `ship init`, otherwise the latest tag `git describe` finds
+pub fn version(dir: &Path) -> Option<(String, &'static str)> {
+    let manifest = cargo(dir).or_else(|| npm(dir)).or_else(|| cmake(dir));
+    if let Some(Detected {
+        version: Some(version),
+        source: Some(source),
+        ..
+    }) = manifest
+    {
+        return Some((version, source));
+    }
+    git_version(dir).map(|version| (version, "git describe"))
+}
+
+// `v1.2.0` is 1.2.0, and three commits later `v1.2.0-3-gabc1234` becomes 1.2.0+3.gabc1234,
+// which still sorts after 1.2.0 for dpkg and the like
+fn git_version(dir: &Path) -> Option<String> {
+    let output = crate::log::command(
+        Command::new("git")
+            .args(["describe", "--tags"])
+            .current_dir(dir),
+    )
+    .output()
+    .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let described = String::from_utf8(output.stdout).ok()?;
+    let described = described.trim();
+    let described = described.strip_prefix('v').unwrap_or(described);
+
+    let mut parts = described.rsplitn(3, '-');
+    let version = match (parts.next(), parts.next(), parts.next()) {
+        (Some(hash), Some(commits), Some(tag))
+            if hash.starts_with('g') && commits.bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            format!("{tag}+{commits}.{hash}")
+        }
+        _ => described.to_string(),
+    };
+    version
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then_some(version)
+}
+
+fn cargo(dir: &Path) -> Option<Detected> {
+    let manifest: Value =
+        toml::from_str(&std::fs::read_to_string(dir.join("Cargo.toml")).ok()?).ok()?;
+    let package = manifest.get("package")?;
+    let text = |key: &str| package.get(key).and_then(Value::as_str).map(String::from);
+
+    let name = text("name");
+    // the first [[bin]] is what gets installed, otherwise the package's own binary
+    let binary = manifest
+        .get("bin")
+        .and_then(Value::as_array)
+        .and_then(|bins| bins.first())
+        .and_then(|bin| bin.get("name"))
+        .and_then(Value::as_str)
+        .map(String::from)
+        .or_else(|| name.clone())
+        .map(|bin| format!("target/release/{bin}"));
+
+    Some(Detected {
+        source: Some("Cargo.toml"),
+        version: text("version"),
+        author: package
+            .get("authors")
+            .and_then(Value::as_array)
+            .and_then(|authors| authors.first())
+            .and_then(Value::as_str)
+            .map(String::from),
+        description: text("description"),
+        binary,
+        build: Some("cargo build --release".to_string()),
+        name,
+    })
+}
+
+fn npm(dir: &Path) -> Option<Detected> {
+    let package: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("package.json")).ok()?).ok()?;
+    let text = |key: &str| package.get(key).and_then(|v| v.as_str()).map(String::from);
+
+    // `bin` is either the script itself or a map of command names to scripts
+    let binary = match package.get("bin") {
+        Some(serde_json::Value::String(bin)) => Some(bin.clone()),
+        Some(serde_json::Value::Object(bins)) => bins
+            .values()
+            .next()
+            .and_then(|bin| bin.as_str())
+            .map(String::from),
+        _ => None,
+    };
+    // `author` is either `Name <email>` or an object with the two
+    let author = match package.get("author") {
+        Some(serde_json::Value::String(author)) => Some(author.clone()),
+        Some(serde_json::Value::Object(author)) => author
+            .get("name")
+            .and_then(|name| name.as_str())
+            .map(
+                |name| match author.get("email").and_then(|email| email.as_str()) {
+                    Some(email) => format!("{name} <{email}>"),
+                    None => name.to_string(),
+                },
+            ),
+        _ => None,
+    };
+    let build = package
+        .get("scripts")
+        .and_then(|scripts| scripts.get("build"))
+        .map(|_| "npm run build".to_string());
+
+    Some(Detected {
+        source: Some("package.json"),
+        name: text("name").map(|name| {
+            // scoped packages are named `@scope/name`
+            name.rsplit('/').next().unwrap_or(&name).to_string()
+        }),
+        version: text("version"),
+        author,
+        description: text("description"),
+        binary,
+        build,
+    })
+}
+
+fn cmake(dir: &Path) -> Option<Detected> {
+    let lists = std::fs::read_to_string(dir.join("CMakeLists.txt")).ok()?;
+
+    // `project(name VERSION 1.2.3 ...)` and `add_executable (name ...)`, case-insensitively;
+    // ASCII lowercasing keeps the byte offsets of the original
+    let lower = lists.to_ascii_lowercase();
+    let arguments = |command: &str| -> Option<Vec<String>> {
+        let start = lower.match_indices(command).find_map(|(at, _)| {
+            let word_start =
+                !lower[..at].ends_with(|c: char| c.is_ascii_alphanumeric() || c == '_');
+            let rest = &lower[at + command.len()..];
+            let args = rest.trim_start();
+            (word_start && args.starts_with('(')).then(|| lower.len() - args.len() + 1)
+        })?;
+        let end = start + lists[start..].find(')')?;
+        Some(
+            lists[start..end]
+                .split_whitespace()
+                .map(|arg| arg.trim_matches('"').to_string())
+                .collect(),
+        )
+    };
+
+    let project = arguments("project").unwrap_or_default();
+    let version = project
+        .iter()
+        .position(|arg| arg.eq_ignore_ascii_case("VERSION"))
+        .and_then(|index| project.get(index + 1))
+        .cloned();
+    let executable = arguments("add_executable").and_then(|args| args.into_iter().next());
+
+    Some(Detected {
+        source: Some("CMakeLists.txt"),
+        binary: executable
+            .clone()
+            .or_else(|| project.first().cloned())
+            .map(|bin| format!("build/{bin}")),
+        name: project.first().cloned().or(executable),
+        version,
+        build: Some("cmake -B build -DCMAKE_BUILD_TYPE=Release && cmake --build build".to_string()),
+        ..Default::default()
+    })
+}
+
+// `Name <email>` from git, for projects whose manifest names no author
+fn git_author() -> Option<String> {
+    let config = |key: &str| {
+        let output = crate::log::command(Command::new("git").args(["config", key]))
+            .output()
+            .ok()?;
+        let value = String::from_utf8(output.stdout).ok()?.trim().to_string();
+        (output.status.success() && !value.is_empty()).then_some(value)
+    };
+    let name = config("user.name")?;
+    Some(match config("user.email") {
+        Some(email) => format!("{name} <{email}>"),
+        None => name,
+    })
+}
+
+/// The Shipfile architecture of the machine ship runs on
+fn host_arch() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86" => "I386",
+        "aarch64" => "Arm64",
+        "arm" => "Armhf",
+        "riscv64" => "Riscv64",
+        "powerpc64" => "Ppc64el",
+        "s390x" => "S390x",
+        _ => "Amd64",
+    }
+}
+
+/// Writes a starter Shipfile to `path` from `answers`, falling back to what was detected,
+/// and asks for whatever is still missing when `interactive`
+pub fn run(path: &str, answers: Answers, force: bool, interactive: bool) -> Result<(), String> {
+    if Path::new(path).exists() && !force {
+        return Err(format!(
+            "{path} already exists, pass --force to overwrite it"
+        ));
+    }
+
+    let detected = detect(Path::new("."));
+    if let Some(source) = detected.source {
+        info!("detected a project in {source}");
+    }
+
+    let value = |question: &str,
+                 given: Option<String>,
+                 detected: Option<String>|
+     -> Result<Option<String>, String> {
+        if given.is_some() {
+            return Ok(given);
+        }
+        if interactive {
+            return prompt::ask_or(question, detected.as_deref()).map(Some);
+        }
+        Ok(detected)
+    };
+
+    let name = value("name", answers.name, detected.name)?
+        .ok_or("no program name detected, pass --name")?;
+    let version = value("version", answers.version, detected.version)?;
+    let author = value("author", answers.author, detected.author)?
+        .ok_or("no author detected, pass --author")?;
+    let binary =
+        value("built executable", answers.binary, detected.binary)?.unwrap_or_else(|| name.clone());
+
+    let string = |s: &str| Value::String(s.to_string()).to_string();
+    let mut shipfile = format!(
+        "[prog]\nname = {}\nauthor = {}\narch = \"{}\"\n",
+        string(&name),
+        string(&author),
+        host_arch()
+    );
+    if let Some(version) = &version {
+        shipfile.push_str(&format!("version = {}\n", string(version)));
+    }
+    if let Some(description) = &detected.description {
+        shipfile.push_str(&format!("description = {}\n", string(description)));
+    }
+    if let Some(build) = &detected.build {
+        shipfile.push_str(&format!("\n[build]\ncmd = {}\n", string(build)));
+    }
+    shipfile.push_str(&format!(
+        "\n[files]\npaths = [{}]\n\n[out]\nbin = \"dist\"\ntargets = [\"Archive\", \"Deb\"]\n",
+        string(&binary)
+    ));
+
+    std::fs::write(path, shipfile).map_err(|err| format!("failed to write {path}: {err}"))?;
+    info!("wrote {path}, run `ship` to build {name}");
+    Ok(())
+}
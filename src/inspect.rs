@@ -0,0 +1,178 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read},
+    path::Path,
+};
+
+use crate::{
+    compress::{decoder, open_decompressed},
+    conf::Target,
+};
+
+/// Maintainer scripts dpkg runs as root
+const DEB_SCRIPTS: [&str; 5] = ["preinst", "postinst", "prerm", "postrm", "config"];
+
+/// What an artifact asks of the system it's installed on, for a review before publishing
+#[derive(Default, Debug)]
+pub struct Security {
+    pub setuid: Vec<String>,       // `path (setuid)`, `path (setgid)` or both
+    pub capabilities: Vec<String>, // files carrying a security.capability xattr
+    pub root_scripts: Vec<String>, // scripts the package manager runs as root
+    pub user_scripts: Vec<String>, // scripts run by whoever installs the artifact
+    pub services: Vec<String>,     // systemd units, init scripts, runit and D-Bus services
+}
+
+/// Reads the package at `path` and collects its privileges.
+///
+/// Returns `None` for targets whose artifacts aren't packages with an install tree.
+pub fn inspect(target: &Target, path: &Path) -> Result<Option<Security>, String> {
+    let read_err = |err: std::io::Error| format!("failed to read {}: {err}", path.display());
+
+    let mut security = Security::default();
+    match target {
+        Target::Deb => {
+            let control = read_deb_member(path, "control.tar", |control| tar_paths(control))
+                .map_err(read_err)?;
+            for file in control.unwrap_or_default() {
+                let file = file.trim_start_matches("./");
+                if DEB_SCRIPTS.contains(&file) {
+                    security.root_scripts.push(file.to_string());
+                }
+            }
+            read_deb_member(path, "data.tar", |tree| scan_tree(tree, &mut security))
+                .map_err(read_err)?;
+        }
+        Target::Xbps => {
+            let data = open_decompressed(path).map_err(read_err)?;
+            scan_tree(data, &mut security).map_err(read_err)?;
+            let data = open_decompressed(path).map_err(read_err)?;
+            for file in tar_paths(data).map_err(read_err)? {
+                let file = file.trim_start_matches("./");
+                if file == "INSTALL" || file == "REMOVE" {
+                    security.root_scripts.push(file.to_string());
+                }
+            }
+        }
+        Target::Archive => {
+            let data = open_decompressed(path).map_err(read_err)?;
+            scan_tree(data, &mut security).map_err(read_err)?;
+            let data = open_decompressed(path).map_err(read_err)?;
+            for file in tar_paths(data).map_err(read_err)? {
+                if file.ends_with("/install.sh") || file.ends_with("/uninstall.sh") {
+                    security.user_scripts.push(file);
+                }
+            }
+        }
+        _ => return Ok(None),
+    }
+
+    Ok(Some(security))
+}
+
+/// Hands the first member of the .deb at `path` whose name starts with `prefix` to `read`,
+/// decompressed as it's read so the package is never loaded whole. `None` if there's no
+/// such member.
+pub fn read_deb_member<T>(
+    path: &Path,
+    prefix: &str,
+    read: impl FnOnce(&mut dyn Read) -> std::io::Result<T>,
+) -> std::io::Result<Option<T>> {
+    let mut archive = ar::Archive::new(BufReader::new(File::open(path)?));
+    while let Some(entry) = archive.next_entry() {
+        let entry = entry?;
+        if entry.header().identifier().starts_with(prefix.as_bytes()) {
+            let mut member = decoder(BufReader::new(entry))?;
+            return read(&mut member).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+fn tar_paths(data: impl Read) -> std::io::Result<Vec<String>> {
+    let mut archive = tar::Archive::new(data);
+    let mut paths = Vec::new();
+    for entry in archive.entries()? {
+        paths.push(entry?.path()?.to_string_lossy().into_owned());
+    }
+    Ok(paths)
+}
+
+fn scan_tree(data: impl Read, security: &mut Security) -> std::io::Result<()> {
+    let mut archive = tar::Archive::new(data);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = format!(
+            "/{}",
+            entry.path()?.to_string_lossy().trim_start_matches("./")
+        );
+        let header = entry.header();
+
+        if header.entry_type().is_file() {
+            let mode = header.mode()?;
+            let bits = match (mode & 0o4000 != 0, mode & 0o2000 != 0) {
+                (true, true) => Some("setuid, setgid"),
+                (true, false) => Some("setuid"),
+                (false, true) => Some("setgid"),
+                (false, false) => None,
+            };
+            if let Some(bits) = bits {
+                security.setuid.push(format!("{path} ({bits})"));
+            }
+        }
+
+        if let Some(service) = service(&path) {
+            security.services.push(service);
+        }
+
+        // GNU tar and libarchive both store xattrs as PAX records
+        if let Some(extensions) = entry.pax_extensions()? {
+            for extension in extensions {
+                if extension?
+                    .key()
+                    .is_ok_and(|key| key.ends_with("xattr.security.capability"))
+                {
+                    security.capabilities.push(path.clone());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes the service installed by `path`, if it is one
+fn service(path: &str) -> Option<String> {
+    const UNIT_DIRS: [(&str, &str); 5] = [
+        ("/lib/systemd/system/", "systemd"),
+        ("/usr/lib/systemd/system/", "systemd"),
+        ("/etc/systemd/system/", "systemd"),
+        ("/lib/systemd/user/", "systemd user"),
+        ("/usr/lib/systemd/user/", "systemd user"),
+    ];
+    for (dir, kind) in UNIT_DIRS {
+        if let Some(unit) = path.strip_prefix(dir)
+            && [".service", ".socket", ".timer", ".path"]
+                .iter()
+                .any(|ext| unit.ends_with(ext))
+        {
+            return Some(format!("{path} ({kind})"));
+        }
+    }
+
+    if path
+        .strip_prefix("/etc/init.d/")
+        .is_some_and(|s| !s.is_empty())
+    {
+        return Some(format!("{path} (init script)"));
+    }
+    // Void's runit services are directories with a `run` script
+    if path.starts_with("/etc/sv/") && path.ends_with("/run") {
+        return Some(format!("{path} (runit)"));
+    }
+    if path.starts_with("/usr/share/dbus-1/system-services/") && path.ends_with(".service") {
+        return Some(format!("{path} (D-Bus system service)"));
+    }
+
+    None
+}
@@ -0,0 +1,455 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs::File,
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use glob::Pattern;
+
+use crate::{conf::ShipConfig, globs, manpage, remote};
+
+/// A single file to be installed, with its absolute destination in the package
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub source: PathBuf,
+    pub dest: String,
+    pub mode: Option<u32>, // from [files].paths, otherwise the source file's own
+    pub owner: Option<String>, // user name or uid from [files].paths, otherwise root
+    pub group: Option<String>, // group name or gid from [files].paths, otherwise root
+}
+
+impl Entry {
+    /// Appends the file to `tar` as `name`, with the configured mode and ownership
+    pub fn append_to<W: Write>(
+        &self,
+        tar: &mut tar::Builder<W>,
+        name: impl AsRef<Path>,
+    ) -> std::io::Result<()> {
+        self.append_inspecting(tar, name, |_| {}).map(|_| ())
+    }
+
+    /// Like [Entry::append_to], handing the archived bytes to `inspect` as they're read,
+    /// so a checksum needs no second read, and returning how many there were
+    pub fn append_inspecting<W: Write>(
+        &self,
+        tar: &mut tar::Builder<W>,
+        name: impl AsRef<Path>,
+        inspect: impl FnMut(&[u8]),
+    ) -> std::io::Result<u64> {
+        let file = File::open(&self.source)?;
+        let metadata = file.metadata()?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        if let Some(mode) = self.mode {
+            header.set_mode(mode);
+        }
+        set_ownership(&mut header, self.owner.as_deref(), self.group.as_deref())?;
+        // no more than the header announces, should the file grow meanwhile
+        let mut reader = Inspecting {
+            inner: file.take(metadata.len()),
+            inspect,
+            read: 0,
+        };
+        tar.append_data(&mut header, name, &mut reader)?;
+        Ok(reader.read)
+    }
+}
+
+// passes everything read through it on to `inspect`
+struct Inspecting<R, F> {
+    inner: R,
+    inspect: F,
+    read: u64,
+}
+
+impl<R: Read, F: FnMut(&[u8])> Read for Inspecting<R, F> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        (self.inspect)(&buf[..count]);
+        self.read += count as u64;
+        Ok(count)
+    }
+}
+
+/// Sets the owner and group of a tar entry, root unless given.
+///
+/// Names are stored with id 0, since the id they get on the installing system isn't known
+/// up front. dpkg looks the name up there and only falls back to the id if it's missing,
+/// so the user or group should be created by a preinstall script.
+fn set_ownership(
+    header: &mut tar::Header,
+    owner: Option<&str>,
+    group: Option<&str>,
+) -> std::io::Result<()> {
+    match owner.map(|owner| (owner, owner.parse::<u64>())) {
+        None => header.set_uid(0),
+        Some((_, Ok(uid))) => header.set_uid(uid),
+        Some((name, Err(_))) => {
+            header.set_uid(0);
+            header.set_username(name)?;
+        }
+    }
+    match group.map(|group| (group, group.parse::<u64>())) {
+        None => header.set_gid(0),
+        Some((_, Ok(gid))) => header.set_gid(gid),
+        Some((name, Err(_))) => {
+            header.set_gid(0);
+            header.set_groupname(name)?;
+        }
+    }
+    Ok(())
+}
+
+/// The install tree shared by all Linux package generators
+#[derive(Clone, Debug, Default)]
+pub struct Layout {
+    pub files: Vec<Entry>,
+    pub symlinks: Vec<(String, String)>, // (link, target)
+}
+
+impl Layout {
+    /// Maps `[files].paths` under the install prefix and links executables into `/usr/bin`.
+    pub fn resolve(conf: &ShipConfig) -> Result<Self, String> {
+        Self::resolve_in(conf, &conf.install_prefix(), "/usr/bin")
+    }
+
+    /// Maps `[files].paths` under `prefix` and links executables into `bin_dir`.
+    ///
+    /// A staged `[files].root` is copied verbatim, its layout is the install tree.
+    pub fn resolve_in(conf: &ShipConfig, prefix: &str, bin_dir: &str) -> Result<Self, String> {
+        if !prefix.is_empty() && !prefix.starts_with('/') {
+            return Err(format!("install prefix {prefix} must be an absolute path"));
+        }
+        // `/` as the prefix installs straight into the root
+        let prefix = prefix.trim_end_matches('/');
+
+        let mut layout = Layout::default();
+        let mut seen_links: HashMap<String, String> = HashMap::new();
+        let mut executables = Vec::new();
+        let exclude = globs::exclude_patterns(conf)?;
+
+        if let Some(root) = &conf.files.root {
+            let root = Path::new(root);
+            if !root.is_dir() {
+                return Err(format!(
+                    "[files].root {} is not a directory",
+                    root.display()
+                ));
+            }
+            collect_root(&mut layout, root, "", &exclude)?;
+        }
+
+        for file in &conf.files.paths {
+            let from = Path::new(&file.from);
+            let to = file.dest(prefix);
+
+            if from.is_dir() {
+                let start = layout.files.len();
+                collect_dir(&mut layout.files, from, &to, &exclude)?;
+                for entry in &mut layout.files[start..] {
+                    entry.mode = file.mode;
+                    entry.owner = file.owner.clone();
+                    entry.group = file.group.clone();
+                }
+                continue;
+            }
+
+            if !from.exists() {
+                return Err(format!(
+                    "file {} in [files].paths does not exist",
+                    file.from
+                ));
+            }
+
+            // links are named after the destination, which `to` may rename
+            let link_name = is_executable(from, file.mode)
+                .then(|| Path::new(&to).file_name())
+                .flatten()
+                .map(|name| name.to_string_lossy().into_owned());
+
+            if let Some(link_name) = link_name {
+                let link_path = format!("{bin_dir}/{link_name}");
+
+                if link_path == to {
+                    // installed straight into the launcher directory, nothing to link
+                    executables.push(link_name);
+                } else if file.to.is_some() {
+                    // placed with `to`, e.g. a helper in libexec, where the user wants it
+                } else if let Some(existing_target) = seen_links.get(&link_path) {
+                    if existing_target != &to {
+                        return Err(format!(
+                            "conflicting binaries for {link_path}: {existing_target} and {to}"
+                        ));
+                    }
+                } else {
+                    seen_links.insert(link_path.clone(), to.clone());
+                    layout.symlinks.push((link_path, to.clone()));
+                    executables.push(link_name);
+                }
+            }
+
+            layout.files.push(Entry {
+                source: from.to_path_buf(),
+                dest: to,
+                mode: file.mode,
+                owner: file.owner.clone(),
+                group: file.group.clone(),
+            });
+        }
+
+        if conf.files.help2man {
+            let man_dir = share_dir(bin_dir).join("man").join("man1");
+
+            for name in executables {
+                layout.files.push(Entry {
+                    source: manpage::generated_path(conf, &name),
+                    dest: format!("{}/{name}.1", man_dir.display()),
+                    mode: None,
+                    owner: None,
+                    group: None,
+                });
+            }
+        }
+
+        for page in &conf.files.man {
+            if !Path::new(page).is_file() {
+                return Err(format!("manpage {page} in [files].man does not exist"));
+            }
+            layout.files.push(Entry {
+                source: manpage::compressed_path(conf, page),
+                dest: format!(
+                    "{}/{}",
+                    share_dir(bin_dir).display(),
+                    manpage::install_path(page)?
+                ),
+                mode: None,
+                owner: None,
+                group: None,
+            });
+        }
+
+        for r in &conf.files.remote {
+            layout.files.push(Entry {
+                source: remote::cached_path(r),
+                dest: format!("{prefix}/{}", r.install_path()),
+                mode: None,
+                owner: None,
+                group: None,
+            });
+        }
+
+        for entry in &mut layout.files {
+            if entry.mode.is_none() {
+                entry.mode =
+                    masked_mode(conf, &entry.source).or_else(|| guessed_mode(&entry.source));
+            }
+            trace!("{} <- {}", entry.dest, entry.source.display());
+        }
+        for (link, target) in &layout.symlinks {
+            trace!("{link} -> {target}");
+        }
+
+        Ok(layout)
+    }
+
+    /// The links that start one of the packaged files, as opposed to those a staged root
+    /// brings along, like a library's soname link
+    pub fn launchers(&self) -> impl Iterator<Item = &(String, String)> {
+        self.symlinks
+            .iter()
+            .filter(|(_, target)| self.files.iter().any(|entry| entry.dest == *target))
+    }
+
+    /// Every directory that has to exist for the files and symlinks, parents first
+    pub fn dirs(&self) -> Vec<String> {
+        let mut dirs = BTreeSet::new();
+        let dests = self
+            .files
+            .iter()
+            .map(|entry| entry.dest.as_str())
+            .chain(self.symlinks.iter().map(|(link, _)| link.as_str()));
+
+        for dest in dests {
+            let mut parent = Path::new(dest).parent();
+            while let Some(dir) = parent {
+                if dir == Path::new("/") || dir.as_os_str().is_empty() {
+                    break;
+                }
+                dirs.insert(dir.to_string_lossy().into_owned());
+                parent = dir.parent();
+            }
+        }
+
+        dirs.into_iter().collect()
+    }
+}
+
+/// The `share` directory next to `bin_dir`: /usr/bin -> /usr/share, same for the Termux prefix
+pub(crate) fn share_dir(bin_dir: &str) -> PathBuf {
+    Path::new(bin_dir)
+        .parent()
+        .map(|usr| usr.join("share"))
+        .unwrap_or_else(|| PathBuf::from("/usr/share"))
+}
+
+// walks a directory in sorted order so packages are reproducible, skipping [files].exclude
+pub(crate) fn collect_dir(
+    files: &mut Vec<Entry>,
+    from: &Path,
+    to: &str,
+    exclude: &[Pattern],
+) -> Result<(), String> {
+    let mut entries = std::fs::read_dir(from)
+        .map_err(|err| format!("failed to read directory {from:?}! {err}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("failed to read directory entry in {from:?}! {err}"))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let target = format!("{to}/{}", entry.file_name().to_string_lossy());
+        if globs::is_excluded(exclude, &path) {
+            continue;
+        }
+
+        if path.is_file() {
+            files.push(Entry {
+                source: path,
+                dest: target,
+                mode: None,
+                owner: None,
+                group: None,
+            });
+        } else if path.is_dir() {
+            collect_dir(files, &path, &target, exclude)?;
+        }
+    }
+
+    Ok(())
+}
+
+// a staged root is the literal install tree, so its symlinks stay links, dangling ones too,
+// and a linked directory isn't walked into
+fn collect_root(
+    layout: &mut Layout,
+    from: &Path,
+    to: &str,
+    exclude: &[Pattern],
+) -> Result<(), String> {
+    let mut entries = std::fs::read_dir(from)
+        .map_err(|err| format!("failed to read directory {from:?}! {err}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("failed to read directory entry in {from:?}! {err}"))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let target = format!("{to}/{}", entry.file_name().to_string_lossy());
+        if globs::is_excluded(exclude, &path) {
+            continue;
+        }
+
+        let file_type = entry
+            .file_type()
+            .map_err(|err| format!("failed to read {path:?}! {err}"))?;
+        if file_type.is_symlink() {
+            let link = std::fs::read_link(&path)
+                .map_err(|err| format!("failed to read symlink {path:?}! {err}"))?;
+            layout
+                .symlinks
+                .push((target, link.to_string_lossy().into_owned()));
+        } else if file_type.is_dir() {
+            collect_root(layout, &path, &target, exclude)?;
+        } else if file_type.is_file() {
+            layout.files.push(Entry {
+                source: path,
+                dest: target,
+                mode: None,
+                owner: None,
+                group: None,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether the file starts with the ELF magic number
+pub(crate) fn is_elf(path: impl AsRef<Path>) -> bool {
+    use std::io::Read;
+
+    let mut magic = [0u8; 4];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok()
+        && magic == *b"\x7fELF"
+}
+
+/// Whether `path` is installed as an executable: by the configured mode if there is one,
+/// otherwise by its own permissions
+pub fn is_executable(path: impl AsRef<Path>, mode: Option<u32>) -> bool {
+    match mode {
+        Some(mode) => mode & 0o111 != 0 && path.as_ref().is_file(),
+        None => executable_name(path).is_some(),
+    }
+}
+
+/// The mode of `source` with [files].umask cleared, for files installed with their own
+/// mode, or `None` if there is no umask
+pub(crate) fn masked_mode(conf: &ShipConfig, source: &Path) -> Option<u32> {
+    let umask = conf.files.umask?;
+    file_mode(source).map(|mode| mode & !umask)
+}
+
+#[cfg(unix)]
+fn file_mode(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+#[cfg(unix)]
+pub(crate) fn executable_name(path: impl AsRef<Path>) -> Option<String> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = path.as_ref();
+    let metadata = std::fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.permissions().mode() & 0o111 == 0 {
+        return None;
+    }
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_string())
+}
+
+// without permission bits, an ELF file is the only thing known to be an executable
+#[cfg(not(unix))]
+pub(crate) fn executable_name(path: impl AsRef<Path>) -> Option<String> {
+    let path = path.as_ref();
+    if !path.is_file() || !is_elf(path) {
+        return None;
+    }
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map(|name| name.to_string())
+}
+
+// the tar header takes the source file's own mode
+#[cfg(unix)]
+fn guessed_mode(_path: &Path) -> Option<u32> {
+    None
+}
+
+/// The mode a file would have on Unix, so packages built elsewhere match
+#[cfg(not(unix))]
+fn guessed_mode(path: &Path) -> Option<u32> {
+    Some(if is_elf(path) { 0o755 } else { 0o644 })
+}
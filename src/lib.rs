@@ -0,0 +1,106 @@
+//! Ship as a library, for tools that build installers without running the `ship` binary.
+//!
+//! Read a Shipfile into a [ShipConfig], get the generator of each target with
+//! [gen_::for_target] and call [Generator::run], which returns the paths it wrote;
+//! [report::ReportArtifact::new] describes each one with its size and checksum.
+//! Progress is printed through [log], [log::init] sets how much of it shows.
+
+use std::process::{Command, Stdio};
+
+#[macro_use]
+pub mod log;
+pub mod appcast;
+pub mod appimage;
+pub mod apt;
+pub mod archive;
+pub mod atomic;
+pub mod bundle;
+pub mod cache;
+pub mod changelog;
+pub mod checksum;
+pub mod completions;
+pub mod compress;
+pub mod conf;
+pub mod deb;
+pub mod debsrc;
+pub mod desktop;
+pub mod doctor;
+pub mod ebuild;
+pub mod error;
+pub mod gen_;
+pub mod github;
+pub mod globs;
+pub mod icon;
+pub mod init;
+pub mod inspect;
+pub mod layout;
+pub mod license;
+pub mod lint;
+pub mod lock;
+pub mod manifest;
+pub mod manpage;
+pub mod metainfo;
+pub mod notice;
+pub mod overlay;
+pub mod plugin;
+pub mod ppa;
+pub mod preflight;
+pub mod promote;
+pub mod prompt;
+pub mod provenance;
+pub mod release;
+pub mod remote;
+pub mod report;
+pub mod sbom;
+pub mod scripts;
+pub mod shlibdeps;
+pub mod signing;
+pub mod source_tree;
+pub mod stamp;
+pub mod strip;
+pub mod sums;
+pub mod tune;
+pub mod upload;
+pub mod vars;
+pub mod verify;
+pub mod winres;
+pub mod xbps;
+pub mod yum;
+
+pub use conf::{ShipConfig, Target};
+pub use error::ShipError;
+pub use gen_::{Artifacts, Generator};
+
+/// Runs `cmd_str` through the platform shell
+pub fn shell(cmd_str: &str) -> Command {
+    #[cfg(unix)]
+    let mut cmd_builder = Command::new("sh");
+    #[cfg(windows)]
+    let mut cmd_builder = Command::new("cmd");
+
+    #[cfg(unix)]
+    cmd_builder.arg("-c").arg(cmd_str);
+    #[cfg(windows)]
+    cmd_builder.arg("/C").arg(cmd_str);
+
+    cmd_builder
+}
+
+/// The trimmed output of `git <args>`, `None` if git isn't there, fails or prints nothing
+pub(crate) fn git(args: &[&str]) -> Option<String> {
+    let output = log::command(Command::new("git").args(args).stderr(Stdio::null()))
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    (output.status.success() && !stdout.trim().is_empty()).then(|| stdout.trim().to_string())
+}
+
+#[cfg(unix)]
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+#[cfg(windows)]
+pub fn shell_quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('"', "\"\""))
+}
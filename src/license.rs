@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+
+use crate::{cache::work_dir, conf::ShipConfig};
+
+/// Licenses Debian ships in /usr/share/common-licenses, by SPDX identifier
+const COMMON_LICENSES: &[(&str, &str)] = &[
+    ("Apache-2.0", "Apache-2.0"),
+    ("Artistic-1.0", "Artistic"),
+    ("BSD-3-Clause", "BSD"),
+    ("CC0-1.0", "CC0-1.0"),
+    ("GFDL-1.2", "GFDL-1.2"),
+    ("GFDL-1.3", "GFDL-1.3"),
+    ("GPL-1.0", "GPL-1"),
+    ("GPL-2.0", "GPL-2"),
+    ("GPL-3.0", "GPL-3"),
+    ("LGPL-2.0", "LGPL-2"),
+    ("LGPL-2.1", "LGPL-2.1"),
+    ("LGPL-3.0", "LGPL-3"),
+    ("MPL-1.1", "MPL-1.1"),
+    ("MPL-2.0", "MPL-2.0"),
+];
+
+/// What `[files].license` names: a license file, or an SPDX expression like `MIT OR Apache-2.0`
+pub enum License<'a> {
+    File(&'a Path),
+    Spdx(&'a str),
+}
+
+impl<'a> License<'a> {
+    pub fn of(conf: &'a ShipConfig) -> Result<Option<Self>, String> {
+        let Some(license) = conf.files.license.as_deref() else {
+            return Ok(None);
+        };
+
+        let path = Path::new(license);
+        if path.is_file() {
+            return Ok(Some(License::File(path)));
+        }
+        if looks_like_file(license) {
+            return Err(format!(
+                "license file {license} in [files].license does not exist"
+            ));
+        }
+        if is_spdx_expression(license) {
+            return Ok(Some(License::Spdx(license)));
+        }
+        Err(format!(
+            "[files].license `{license}` is neither a file nor an SPDX expression like MIT or GPL-3.0-or-later"
+        ))
+    }
+
+    /// The SPDX expression, for metadata fields that take one
+    pub fn spdx(&self) -> Option<&'a str> {
+        match self {
+            License::Spdx(expression) => Some(expression),
+            License::File(_) => None,
+        }
+    }
+}
+
+/// Where the copyright file is written before packaging
+pub fn generated_path(conf: &ShipConfig) -> PathBuf {
+    work_dir(conf).join("license").join("copyright")
+}
+
+/// Where the copyright file is installed, relative to `share_dir`, as Debian policy puts it
+pub fn install_path(conf: &ShipConfig) -> String {
+    format!("doc/{}/copyright", conf.prog.name)
+}
+
+/// Writes the copyright file for `[files].license`, if there is one.
+///
+/// A license file is used as written; an SPDX expression becomes a machine-readable
+/// copyright file pointing at /usr/share/common-licenses where Debian ships the text.
+pub fn write(conf: &ShipConfig) -> Result<Option<PathBuf>, String> {
+    let contents = match License::of(conf)? {
+        None => return Ok(None),
+        Some(License::File(path)) => std::fs::read(path)
+            .map_err(|err| format!("failed to read license {}: {err}", path.display()))?,
+        Some(License::Spdx(expression)) => copyright(conf, expression).into_bytes(),
+    };
+
+    let path = generated_path(conf);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+    std::fs::write(&path, contents)
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+
+    Ok(Some(path))
+}
+
+// https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/
+fn copyright(conf: &ShipConfig, expression: &str) -> String {
+    let mut out = format!(
+        "Format: https://www.debian.org/doc/packaging-manuals/copyright-format/1.0/\n\
+         Upstream-Name: {name}\n\n\
+         Files: *\n\
+         Copyright: {author}\n\
+         License: {expression}\n",
+        name = conf.prog.name,
+        author = conf.prog.author,
+    );
+
+    for id in license_ids(expression) {
+        let base = id
+            .trim_end_matches('+')
+            .trim_end_matches("-only")
+            .trim_end_matches("-or-later");
+        if let Some((_, common)) = COMMON_LICENSES.iter().find(|(spdx, _)| *spdx == base) {
+            out.push_str(&format!(
+                "\nLicense: {id}\n On Debian systems, the full text of this license can be found in\n \
+                 /usr/share/common-licenses/{common}.\n"
+            ));
+        }
+    }
+
+    out
+}
+
+// the identifiers of an expression, without operators, exceptions and parentheses
+fn license_ids(expression: &str) -> Vec<&str> {
+    let mut ids = Vec::new();
+    let mut words = expression
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|word| !word.is_empty());
+    while let Some(word) = words.next() {
+        match word {
+            "AND" | "OR" => {}
+            // `WITH` is followed by an exception, not a license
+            "WITH" => {
+                words.next();
+            }
+            id if !ids.contains(&id) => ids.push(id),
+            _ => {}
+        }
+    }
+    ids
+}
+
+// `LICENSE`, `COPYING` or `docs/license.txt` are meant as files, even though they'd parse as identifiers
+fn looks_like_file(value: &str) -> bool {
+    let upper = value.to_uppercase();
+    value.contains(['/', '\\'])
+        || (["LICENSE", "LICENCE", "COPYING"]
+            .iter()
+            .any(|name| upper.starts_with(name))
+            && !upper.starts_with("LICENSEREF-"))
+        || [".TXT", ".MD"].iter().any(|ext| upper.ends_with(ext))
+}
+
+/// Whether `value` reads as an SPDX license expression: identifiers of letters, digits,
+/// `.`, `-` and `+`, joined by AND, OR and WITH
+pub fn is_spdx_expression(value: &str) -> bool {
+    let mut expect_id = true;
+    for word in value
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|word| !word.is_empty())
+    {
+        if matches!(word, "AND" | "OR" | "WITH") {
+            if expect_id {
+                return false;
+            }
+            expect_id = true;
+        } else if word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '+' | ':'))
+            && expect_id
+        {
+            expect_id = false;
+        } else {
+            return false;
+        }
+    }
+    !expect_id
+}
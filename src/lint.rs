@@ -0,0 +1,308 @@
+use std::{
+    io::{ErrorKind, Read},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::inspect::read_deb_member;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A policy violation found in a package
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub tag: String,    // lintian's name for the problem, e.g. `dir-or-file-in-opt`
+    pub detail: String, // the offending path or field
+}
+
+impl Finding {
+    fn error(tag: &str, detail: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            tag: tag.to_string(),
+            detail: detail.into(),
+        }
+    }
+
+    fn warning(tag: &str, detail: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            tag: tag.to_string(),
+            detail: detail.into(),
+        }
+    }
+
+    pub fn print(&self, package: &str) {
+        let level = match self.severity {
+            Severity::Error => "E",
+            Severity::Warning => "W",
+        };
+        output!("{level}: {package}: {} {}", self.tag, self.detail);
+    }
+}
+
+/// Places a package must not install into (Debian policy 9.1)
+const BAD_DIRS: [(&str, &str); 5] = [
+    ("opt/", "dir-or-file-in-opt"),
+    ("usr/local/", "dir-or-file-in-usr-local"),
+    ("home/", "dir-or-file-in-home"),
+    ("tmp/", "dir-or-file-in-tmp"),
+    ("var/tmp/", "dir-or-file-in-var-tmp"),
+];
+
+/// Directories whose files are run as commands
+const COMMAND_DIRS: [&str; 4] = ["bin/", "sbin/", "usr/bin/", "usr/sbin/"];
+
+/// Checks the .deb at `path` with lintian, or with ship's own subset of its checks if
+/// lintian isn't installed. Returns the name of the checker along with what it found.
+pub fn check_deb(path: &Path) -> Result<(&'static str, Vec<Finding>), String> {
+    match lintian(path)? {
+        Some(findings) => Ok(("lintian", findings)),
+        None => Ok(("built-in checks", builtin(path)?)),
+    }
+}
+
+// `None` if lintian isn't installed
+fn lintian(path: &Path) -> Result<Option<Vec<Finding>>, String> {
+    let output = match crate::log::command(
+        Command::new("lintian")
+            .arg("--no-tag-display-limit")
+            .arg(path)
+            .stdin(Stdio::null()),
+    )
+    .output()
+    {
+        Ok(output) => output,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("failed to run lintian: {err}")),
+    };
+
+    // lintian exits 1 when it reports errors and 2 when it couldn't check the package
+    if output.status.code() != Some(0) && output.status.code() != Some(1) {
+        return Err(format!(
+            "lintian failed on {} ({}): {}",
+            path.display(),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    // lines look like `E: hello: dir-or-file-in-opt [opt/hello/]`
+    let mut findings = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let severity = match line.get(..3) {
+            Some("E: ") => Severity::Error,
+            Some("W: ") => Severity::Warning,
+            _ => continue,
+        };
+        let Some((_, rest)) = line[3..].split_once(": ") else {
+            continue;
+        };
+        let (tag, detail) = rest.split_once(' ').unwrap_or((rest, ""));
+        findings.push(Finding {
+            severity,
+            tag: tag.to_string(),
+            detail: detail.to_string(),
+        });
+    }
+    Ok(Some(findings))
+}
+
+fn builtin(path: &Path) -> Result<Vec<Finding>, String> {
+    let read_err = |err: std::io::Error| format!("failed to read {}: {err}", path.display());
+    let mut findings = Vec::new();
+
+    let fields = read_deb_member(path, "control.tar", control_file)
+        .map_err(read_err)?
+        .ok_or_else(|| format!("{} has no control archive", path.display()))?;
+    for (field, tag) in [
+        ("Package", "no-package-name"),
+        ("Version", "no-version-field"),
+        ("Architecture", "no-architecture-field"),
+        ("Maintainer", "no-maintainer-field"),
+        ("Description", "package-has-no-description"),
+    ] {
+        let present = fields.lines().any(|line| {
+            line.strip_prefix(field)
+                .and_then(|rest| rest.strip_prefix(':'))
+                .is_some_and(|value| !value.trim().is_empty())
+        });
+        if !present {
+            findings.push(Finding::error(tag, field));
+        }
+    }
+
+    read_deb_member(path, "data.tar", |data| check_tree(data, &mut findings))
+        .map_err(read_err)?
+        .ok_or_else(|| format!("{} has no data archive", path.display()))?;
+
+    Ok(findings)
+}
+
+fn control_file(control: &mut dyn Read) -> std::io::Result<String> {
+    let mut archive = tar::Archive::new(control);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy().trim_start_matches("./") == "control" {
+            let mut fields = String::new();
+            entry.read_to_string(&mut fields)?;
+            return Ok(fields);
+        }
+    }
+    Ok(String::new())
+}
+
+fn check_tree(data: impl Read, findings: &mut Vec<Finding>) -> std::io::Result<()> {
+    let mut archive = tar::Archive::new(data);
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry
+            .path()?
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+        let header = entry.header();
+        let mode = header.mode()?;
+        let is_file = header.entry_type().is_file();
+
+        if path.split('/').any(|part| part == "..") {
+            findings.push(Finding::error("tar-file-contains-parent-reference", &path));
+        }
+        // once per directory, like lintian, rather than for everything under it
+        if let Some((_, tag)) = BAD_DIRS.iter().find(|(dir, _)| path.starts_with(dir))
+            && !findings.iter().any(|finding| finding.tag == *tag)
+        {
+            findings.push(Finding::error(tag, &path));
+        }
+        if header.uid()? != 0 || header.gid()? != 0 {
+            findings.push(Finding::error(
+                "wrong-file-owner-uid-or-gid",
+                format!("{path} {}/{}", header.uid()?, header.gid()?),
+            ));
+        }
+        if !is_file && !header.entry_type().is_dir() {
+            continue;
+        }
+
+        if mode & 0o002 != 0 {
+            findings.push(Finding::error(
+                "world-writable-file",
+                format!("{path} {mode:o}"),
+            ));
+        }
+        if !is_file {
+            continue;
+        }
+        if mode & 0o6000 != 0 {
+            findings.push(Finding::warning(
+                "setuid-or-setgid-binary",
+                format!("{path} {mode:o}"),
+            ));
+        }
+        let executable = mode & 0o111 != 0;
+        if COMMAND_DIRS.iter().any(|dir| {
+            path.strip_prefix(dir)
+                .is_some_and(|name| !name.contains('/'))
+        }) && !executable
+        {
+            findings.push(Finding::error(
+                "command-not-executable",
+                format!("{path} {mode:o}"),
+            ));
+        }
+        if executable && path.starts_with("usr/share/man/") {
+            findings.push(Finding::error(
+                "executable-manpage",
+                format!("{path} {mode:o}"),
+            ));
+        }
+        if executable && path.starts_with("usr/share/doc/") {
+            findings.push(Finding::warning(
+                "executable-in-usr-share-doc",
+                format!("{path} {mode:o}"),
+            ));
+        }
+
+        // symbols belong in the -dbgsym package, only ELF files are read whole to find them
+        if !path.starts_with("usr/lib/debug/") {
+            let mut contents = Vec::new();
+            entry.by_ref().take(4).read_to_end(&mut contents)?;
+            if contents != b"\x7fELF" {
+                continue;
+            }
+            entry.read_to_end(&mut contents)?;
+            if has_symbols(&contents) {
+                findings.push(Finding::error("unstripped-binary-or-object", &path));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether `elf` still has a symbol table or debug sections
+fn has_symbols(elf: &[u8]) -> bool {
+    section_names(elf).is_some_and(|names| {
+        names
+            .iter()
+            .any(|name| name == ".symtab" || name.starts_with(".debug_"))
+    })
+}
+
+// names of the section headers of an ELF file, `None` if it isn't one or is malformed
+fn section_names(elf: &[u8]) -> Option<Vec<String>> {
+    if !elf.starts_with(b"\x7fELF") {
+        return None;
+    }
+    let wide = match elf.get(4)? {
+        1 => false,
+        2 => true,
+        _ => return None,
+    };
+    let big_endian = *elf.get(5)? == 2;
+
+    let int = |offset: usize, size: usize| -> Option<usize> {
+        let bytes = elf.get(offset..offset.checked_add(size)?)?;
+        let mut value = 0u64;
+        for i in 0..size {
+            let byte = if big_endian {
+                bytes[i]
+            } else {
+                bytes[size - 1 - i]
+            };
+            value = value << 8 | byte as u64;
+        }
+        usize::try_from(value).ok()
+    };
+    let word = if wide { 8 } else { 4 };
+
+    let (shoff, shentsize, shnum, shstrndx) = if wide {
+        (int(0x28, 8)?, int(0x3a, 2)?, int(0x3c, 2)?, int(0x3e, 2)?)
+    } else {
+        (int(0x20, 4)?, int(0x2e, 2)?, int(0x30, 2)?, int(0x32, 2)?)
+    };
+    if shnum == 0 || shstrndx >= shnum {
+        return None;
+    }
+    // sh_name is at the start of a section header, sh_offset follows sh_flags and sh_addr
+    let header = |index: usize| shoff.checked_add(index.checked_mul(shentsize)?);
+    let offset_of = |index: usize| int(header(index)? + 8 + 2 * word, word);
+    let size_of = |index: usize| int(header(index)? + 8 + 3 * word, word);
+
+    let strtab_start = offset_of(shstrndx)?;
+    let strtab = elf.get(strtab_start..strtab_start.checked_add(size_of(shstrndx)?)?)?;
+
+    let mut names = Vec::new();
+    for index in 0..shnum {
+        let name = strtab.get(int(header(index)?, 4)?..)?;
+        let end = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+        names.push(String::from_utf8_lossy(&name[..end]).into_owned());
+    }
+    Some(names)
+}
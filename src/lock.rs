@@ -0,0 +1,98 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use crate::{cache, conf::ShipConfig};
+
+/// Name of the lock file created in every locked directory
+const LOCK_FILE: &str = ".ship.lock";
+
+/// An advisory lock on a directory, released when dropped or when the process exits
+pub struct Lock {
+    _file: File,
+}
+
+/// Locks ship's state directory, the cache and every output and work directory of the variants,
+/// waiting for other runs that hold any of them.
+///
+/// Directories are locked in sorted order, so two runs over overlapping sets can't deadlock.
+pub fn lock_all(variants: &[ShipConfig]) -> Result<Vec<Lock>, String> {
+    let mut dirs = BTreeSet::new();
+    dirs.insert(PathBuf::from(".ship"));
+    dirs.insert(cache::cache_dir());
+    for conf in variants {
+        dirs.insert(out_dir(&conf.out.bin));
+        dirs.insert(cache::work_dir(conf));
+    }
+
+    // the same directory may be spelled differently, and a lock can only be taken once
+    let mut canonical = BTreeSet::new();
+    for dir in dirs {
+        std::fs::create_dir_all(&dir)
+            .map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+        canonical.insert(dir.canonicalize().unwrap_or(dir));
+    }
+
+    canonical.iter().map(|dir| lock(dir)).collect()
+}
+
+// [out].bin may name the artifact itself, e.g. `dist/app.xbps`
+pub fn out_dir(bin: &str) -> PathBuf {
+    let path = Path::new(bin);
+    match path.parent() {
+        Some(parent) if path.extension().is_some() && !path.is_dir() => {
+            if parent.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                parent.to_path_buf()
+            }
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(unix)]
+fn lock(dir: &Path) -> Result<Lock, String> {
+    use std::os::fd::AsRawFd;
+
+    let path = dir.join(LOCK_FILE);
+    let file = File::create(&path)
+        .map_err(|err| format!("failed to create lock file {}: {err}", path.display()))?;
+
+    // SAFETY: the descriptor stays open for as long as `file` lives
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::WouldBlock {
+            return Err(format!(
+                "failed to lock {}: {err} (pass --no-lock to skip locking)",
+                dir.display()
+            ));
+        }
+
+        warn!(
+            "waiting for another ship run to release {}...",
+            dir.display()
+        );
+        // SAFETY: same open descriptor, this time blocking until the other run lets go
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(format!(
+                "failed to lock {}: {}",
+                dir.display(),
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+
+    Ok(Lock { _file: file })
+}
+
+// only flock is implemented, other platforms run unlocked
+#[cfg(not(unix))]
+fn lock(dir: &Path) -> Result<Lock, String> {
+    let path = dir.join(LOCK_FILE);
+    let file = File::create(&path)
+        .map_err(|err| format!("failed to create lock file {}: {err}", path.display()))?;
+    Ok(Lock { _file: file })
+}
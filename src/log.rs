@@ -0,0 +1,231 @@
+use std::{
+    fmt,
+    fs::File,
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicI8, Ordering},
+    },
+};
+
+/// How much a message matters; `-q` shows only errors and warnings, `-v` adds debug
+/// messages and `-vv` traces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    // how verbose the output has to be for the level to show
+    fn verbosity(self) -> i8 {
+        match self {
+            Level::Error | Level::Warn => -1,
+            Level::Info => 0,
+            Level::Debug => 1,
+            Level::Trace => 2,
+        }
+    }
+
+    fn prefix(self) -> &'static str {
+        match self {
+            Level::Error => "error: ",
+            Level::Warn => "warning: ",
+            Level::Info => "",
+            Level::Debug => "debug: ",
+            Level::Trace => "trace: ",
+        }
+    }
+}
+
+static VERBOSITY: AtomicI8 = AtomicI8::new(0);
+static TIMESTAMPS: AtomicBool = AtomicBool::new(false);
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+static SCOPE: Mutex<Option<String>> = Mutex::new(None);
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// Sets up logging from the command line: `verbosity` is the number of `-v`s, or -1 for
+/// `-q`. The log file gets every message up to debug, timestamped, whatever the verbosity.
+pub fn init(verbosity: i8, timestamps: bool, log_file: Option<&Path>) -> Result<(), String> {
+    VERBOSITY.store(verbosity, Ordering::Relaxed);
+    TIMESTAMPS.store(timestamps, Ordering::Relaxed);
+
+    if let Some(path) = log_file {
+        let file = File::create(path)
+            .map_err(|err| format!("failed to create log file {}: {err}", path.display()))?;
+        *LOG_FILE.lock().unwrap_or_else(|err| err.into_inner()) = Some(file);
+    }
+    Ok(())
+}
+
+/// Sends what would go to stdout to stderr instead, so a machine-readable report printed
+/// at the end is all stdout carries
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+/// Where a command ship runs in the foreground should print, stderr if stdout is reserved
+pub fn child_stdout() -> Stdio {
+    if STDOUT_RESERVED.load(Ordering::Relaxed) {
+        Stdio::from(std::io::stderr())
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Prints a command's own output, like lint findings or a release summary, which no
+/// verbosity hides
+pub fn output(message: fmt::Arguments) {
+    if let Some(file) = LOG_FILE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_mut()
+    {
+        let now = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f");
+        writeln!(file, "{now} {message}").ok();
+    }
+    if STDOUT_RESERVED.load(Ordering::Relaxed) {
+        eprintln!("{message}");
+    } else {
+        println!("{message}");
+    }
+}
+
+/// Prefixes every message with `[name]` until the returned guard is dropped, so output
+/// from a generator can be told apart from the rest
+pub fn scope(name: impl Into<String>) -> ScopeGuard {
+    let previous = SCOPE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .replace(name.into());
+    ScopeGuard { previous }
+}
+
+pub struct ScopeGuard {
+    previous: Option<String>,
+}
+
+impl Drop for ScopeGuard {
+    fn drop(&mut self) {
+        *SCOPE.lock().unwrap_or_else(|err| err.into_inner()) = self.previous.take();
+    }
+}
+
+/// Whether messages of `level` show on the terminal, to skip work only needed for them
+pub fn enabled(level: Level) -> bool {
+    level.verbosity() <= VERBOSITY.load(Ordering::Relaxed)
+}
+
+pub fn write(level: Level, message: fmt::Arguments) {
+    let scope = SCOPE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_ref()
+        .map(|scope| format!("[{scope}] "))
+        .unwrap_or_default();
+    let line = format!("{scope}{}{message}", level.prefix());
+
+    if let Some(file) = LOG_FILE
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .as_mut()
+        && (level <= Level::Debug || enabled(level))
+    {
+        let now = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.3f");
+        writeln!(file, "{now} {line}").ok();
+    }
+
+    if !enabled(level) {
+        return;
+    }
+    let line = if TIMESTAMPS.load(Ordering::Relaxed) {
+        format!("{} {line}", chrono::Local::now().format("%H:%M:%S%.3f"))
+    } else {
+        line
+    };
+    // only plain progress goes to stdout, next to the output of commands like `inspect`
+    if level == Level::Info && !STDOUT_RESERVED.load(Ordering::Relaxed) {
+        println!("{line}");
+    } else {
+        eprintln!("{line}");
+    }
+}
+
+/// Logs the command line of `cmd` at debug level before it's run, for chaining in
+/// `log::command(Command::new("objcopy").args(args)).output()`
+pub fn command(cmd: &mut Command) -> &mut Command {
+    command_redacting(cmd, &[])
+}
+
+/// Like [command], with each argument equal to one of `secrets` logged as `***`
+pub fn command_redacting<'a>(cmd: &'a mut Command, secrets: &[&str]) -> &'a mut Command {
+    if enabled(Level::Debug) || LOG_FILE.lock().is_ok_and(|file| file.is_some()) {
+        let args: Vec<String> = cmd
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .map(|arg| {
+                if secrets.contains(&arg.as_str()) {
+                    "***".to_string()
+                } else {
+                    arg
+                }
+            })
+            .collect();
+        write(
+            Level::Debug,
+            format_args!(
+                "running {} {}",
+                cmd.get_program().to_string_lossy(),
+                args.join(" ")
+            ),
+        );
+    }
+    cmd
+}
+
+#[macro_export]
+macro_rules! output {
+    ($($arg:tt)*) => {
+        $crate::log::output(format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Error, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Warn, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Info, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Debug, format_args!($($arg)*))
+    };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        $crate::log::write($crate::log::Level::Trace, format_args!($($arg)*))
+    };
+}
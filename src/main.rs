@@ -1,123 +1,1590 @@
 use std::{
+    collections::{BTreeMap, HashMap},
     io::ErrorKind,
-    path::Path,
-    process::{Command, Stdio},
+    path::{Path, PathBuf},
+    process::Stdio,
 };
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
-use crate::{
-    appimage::AppImageGenerator,
-    conf::{ShipConfig, Target},
-    deb::DebGenerator,
+use ship::{
+    appcast::AppcastGenerator,
+    apt,
+    checksum::HashStore,
+    conf::{Build, BuildStep, ShipConfig, Target, Vars},
+    debug,
+    doctor::{self, Status},
+    error,
+    gen_::{self, Generator},
+    github, globs, info, init, inspect, layout, lint, lock, log,
+    manifest::Manifest,
+    manpage, notice, output, overlay, plugin, ppa, preflight, promote, prompt,
+    provenance::Provenance,
+    release, remote,
+    report::{BuildReport, ReportArtifact, TargetRun, TargetStatus},
+    sbom::{self, BuildInputs},
+    shell, shell_quote, signing, stamp, sums, tune, upload, vars,
+    verify::{self, Verdict},
+    warn, winres, yum,
 };
 
-pub mod conf;
-pub mod deb;
-pub mod appimage;
-pub mod gen_;
-
-use gen_::Generator as _;
-
 #[derive(Parser, Debug)]
 #[command(
     name = "ship",
     author = "Your Name <you@example.com>",
     version = "0.1.0",
     about = "Generates cross-platform installers from a Shipfile",
-    long_about = "Ship reads a Shipfile TOML configuration, resolves variables, and produces platform-specific installers. Supports dry-run mode and CLI overrides for version and targets."
+    long_about = "Ship reads a Shipfile TOML configuration, resolves variables, and produces platform-specific installers. Supports dry-run mode and CLI overrides for version, targets and output directory."
 )]
 pub struct Cli {
     /// Path to the Shipfile
     #[arg(short, long, default_value = "ship.toml", value_name = "FILE")]
     pub config: String,
 
-    /// Dry run mode — prints what would be generated without building installers
+    /// Dry run mode — prints the plan of every target without building anything: artifacts, where each file goes, symlinks, control fields and scripts
     #[arg(short = 'd', long = "dry-run")]
     pub dry_run: bool,
+
+    /// Sets a variable declared in `[vars].arg`, usable as `${KEY}` in the Shipfile
+    #[arg(long = "var", value_name = "KEY=VALUE")]
+    pub vars: Vec<String>,
+
+    /// Branding overlay applied on top of the Shipfile; repeat to build several white-labeled variants
+    #[arg(long = "overlay", value_name = "FILE")]
+    pub overlays: Vec<String>,
+
+    /// Packages this version instead of [prog].version
+    #[arg(long = "version-override", value_name = "VERSION")]
+    pub version_override: Option<String>,
+
+    /// Builds only this target instead of [out].targets; repeat for several
+    #[arg(long = "target", value_enum, value_name = "TARGET")]
+    pub targets: Vec<Target>,
+
+    /// Writes the artifacts to this directory instead of [out].bin
+    #[arg(long = "out", value_name = "DIR")]
+    pub out: Option<String>,
+
+    /// Applies the Shipfile's [profile.NAME] on top of the rest of it, e.g. `nightly`
+    #[arg(short, long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Sets any Shipfile key, e.g. `prog.version=1.2.3` or `deb.compression=zstd`; the value is read as TOML, or as a string where the setting is one
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub sets: Vec<String>,
+
+    /// Rebuilds every target, even those whose inputs are unchanged since the last build
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// Skips locking the output directories and cache against other ship runs, e.g. on filesystems without lock support
+    #[arg(long = "no-lock")]
+    pub no_lock: bool,
+
+    /// Prints more about what ship does: -v shows debug messages like the commands it runs, -vv traces every packaged file
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Prints only errors and warnings
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Starts every message with the time it was printed at
+    #[arg(long)]
+    pub timestamps: bool,
+
+    /// Also writes every message, debug ones included, to this file with timestamps
+    #[arg(long = "log-file", value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// `json` prints a report of every target's status, artifacts and timing on stdout once the build is done, and everything else on stderr
+    #[arg(long = "output-format", value_enum, default_value_t = OutputFormat::Text, value_name = "FORMAT")]
+    pub output_format: OutputFormat,
+
+    /// Fails on missing required values (variables, version, signing passphrase) instead of asking for them; use in CI
+    #[arg(long = "non-interactive")]
+    pub non_interactive: bool,
+
+    /// Stops at the first target that fails instead of building the remaining ones
+    #[arg(long = "fail-fast", overrides_with = "keep_going")]
+    pub fail_fast: bool,
+
+    /// Builds the remaining targets after one fails, which is the default; overrides an earlier --fail-fast
+    #[arg(long = "keep-going", overrides_with = "fail_fast")]
+    pub keep_going: bool,
+
+    /// Signs the SHA256SUMS written after the build with gpg's default key, into SHA256SUMS.asc
+    #[arg(long = "sign-checksums")]
+    pub sign_checksums: bool,
+
+    /// Checks every built .deb against Debian policy with lintian, or a built-in subset of its checks if it isn't installed, and fails on errors
+    #[arg(long)]
+    pub check: bool,
+
+    #[command(subcommand)]
+    pub action: Option<Action>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Action {
+    /// Writes a starter Shipfile, filled in from Cargo.toml, package.json or CMakeLists.txt when there is one
+    Init {
+        /// Program name, instead of the detected one
+        #[arg(long)]
+        name: Option<String>,
+
+        /// Program version, instead of the detected one
+        #[arg(long)]
+        version: Option<String>,
+
+        /// Author as `Name <email>`, instead of the one from the manifest or git
+        #[arg(long)]
+        author: Option<String>,
+
+        /// Path of the built executable to package, instead of the detected one
+        #[arg(long)]
+        binary: Option<String>,
+
+        /// Overwrites an existing Shipfile
+        #[arg(long)]
+        force: bool,
+    },
+
+    /// Compresses the already built payload of a target with several algorithms and levels and reports the trade-offs
+    TuneCompression {
+        /// Target whose payload is measured
+        #[arg(value_enum)]
+        target: Target,
+
+        /// Writes the smallest result into the target's section of the Shipfile
+        #[arg(long)]
+        write: bool,
+    },
+
+    /// Checks that the programs, keys and files the configured targets, signing and publishing need are there, with their versions and how to install what's missing, without building anything
+    Doctor,
+
+    /// Summarizes what each artifact of the last build asks of the system: setuid files, file capabilities, root scripts and services
+    Inspect,
+
+    /// Downloads the published artifacts and checks them against the last local build, without changing anything remotely
+    VerifyPublished,
+
+    /// Builds and packages every target, then writes checksums, signs, writes an SBOM and publishes as set in [release], ending with one summary
+    ReleaseAll,
+
+    /// Makes a staged release live by publishing its update feed with [release].promote; promote an earlier version to roll back
+    Promote {
+        /// Version whose staged update feed becomes the live one
+        version: String,
+    },
+
+    /// Uploads the artifacts of the last build, their signatures and their checksums to a GitHub Release, a bucket or a server
+    Publish {
+        /// Where to publish
+        #[arg(value_enum)]
+        to: PublishTo,
+
+        /// Tag of the GitHub Release, instead of the tag of the checked out commit
+        #[arg(long)]
+        tag: Option<String>,
+    },
+
+    /// Adds the packages of the last build to a package repository in a directory and regenerates its signed indexes, ready for static hosting
+    Repo {
+        /// Kind of repository
+        #[arg(value_enum)]
+        kind: RepoKind,
+
+        /// Directory of the repository, created if missing
+        dir: PathBuf,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PublishTo {
+    /// A GitHub Release, with the token from GITHUB_TOKEN or [publish.github].token_env
+    Github,
+    /// An S3-compatible bucket, as set in [publish.s3]
+    S3,
+    /// Any server taking HTTP PUT uploads, as set in [publish.http]
+    Http,
+    /// A Launchpad PPA or other dput target, as set in [publish.ppa]; signs and uploads the Debian source package
+    Ppa,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepoKind {
+    /// A flat APT repository: Packages, Packages.gz, Release, InRelease and Release.gpg
+    Apt,
+    /// A dnf/yum repository of every RPM in the directory: repodata/repomd.xml and its primary, filelists and other metadata
+    Rpm,
 }
 
 fn main() {
     let cli = Cli::parse();
 
-    let contents = std::fs::read_to_string(&cli.config).unwrap_or_else(|e| {
-        match e.kind() {
-            ErrorKind::NotFound => {
-                eprintln!("error: no `{}` present, terminating...", cli.config);
+    let verbosity = if cli.quiet {
+        -1
+    } else {
+        cli.verbose.min(2) as i8
+    };
+    // the JSON report has stdout to itself
+    if cli.output_format == OutputFormat::Json {
+        log::reserve_stdout();
+    }
+    log::init(verbosity, cli.timestamps, cli.log_file.as_deref()).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        std::process::exit(-1);
+    });
+
+    // there's no Shipfile to read yet
+    if let Some(Action::Init {
+        name,
+        version,
+        author,
+        binary,
+        force,
+    }) = &cli.action
+    {
+        let answers = init::Answers {
+            name: name.clone(),
+            version: version.clone(),
+            author: author.clone(),
+            binary: binary.clone(),
+        };
+        let interactive = prompt::can_prompt(cli.non_interactive);
+        init::run(&cli.config, answers, *force || cli.force, interactive).unwrap_or_else(|e| {
+            error!("{e}");
+            std::process::exit(-1);
+        });
+        return;
+    }
+
+    let mut base_doc = read_shipfile(&cli.config);
+    // overlays brand a flavor, so they go on top of the profile
+    overlay::apply_profile(&mut base_doc, cli.profile.as_deref()).unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(-1);
+    });
+    apply_cli_overrides(&mut base_doc, &cli);
+
+    let mut cli_vars = vars::parse_cli_vars(&cli.vars).unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(-1);
+    });
+
+    // only a build asks for what's missing, the other commands read its last report or
+    // the Shipfile as it is
+    let building = matches!(cli.action, None | Some(Action::ReleaseAll));
+    // answered once for the base Shipfile, which every overlay starts from
+    let interactive = prompt::can_prompt(cli.non_interactive);
+    if building {
+        prompt::fill_missing(&mut base_doc, &mut cli_vars, interactive).unwrap_or_else(|e| {
+            error!("{e}");
+            std::process::exit(-1);
+        });
+    }
+    // the first architecture of a matrix stands in for the rest outside of builds
+    let mut conf = resolve_config(
+        arch_matrix(&base_doc).swap_remove(0),
+        &mut cli_vars,
+        &cli.config,
+        interactive,
+        building,
+    );
+
+    match &cli.action {
+        Some(Action::TuneCompression { target, write }) => {
+            expand_globs(&mut conf);
+            tune_compression(&conf, target, *write, &cli.config);
+            return;
+        }
+        Some(Action::Doctor) => {
+            doctor(&conf);
+            return;
+        }
+        Some(Action::Inspect) => {
+            expand_globs(&mut conf);
+            inspect(&conf);
+            return;
+        }
+        Some(Action::VerifyPublished) => {
+            verify_published(&conf);
+            return;
+        }
+        Some(Action::Promote { version }) => {
+            promote(&conf, version);
+            return;
+        }
+        Some(Action::Publish { to, tag }) => {
+            publish(&conf, *to, tag.as_deref());
+            return;
+        }
+        Some(Action::Repo { kind, dir }) => {
+            repo(&conf, *kind, dir);
+            return;
+        }
+        Some(Action::Init { .. }) => unreachable!("handled before the Shipfile is read"),
+        Some(Action::ReleaseAll) | None => {}
+    }
+
+    // asked for up front rather than after a long build
+    let passphrase = match cli.action {
+        Some(Action::ReleaseAll) => prompt::passphrase(&conf, interactive).unwrap_or_else(|e| {
+            error!("{e}");
+            std::process::exit(-1);
+        }),
+        _ => None,
+    };
+
+    if !cli.dry_run {
+        info!("building...");
+    }
+
+    // every overlay produces its own white-labeled variant of the base Shipfile, and each
+    // of those one variant per architecture of [matrix].arch
+    let docs: Vec<(toml::Value, &str)> = if cli.overlays.is_empty() {
+        vec![(base_doc.clone(), cli.config.as_str())]
+    } else {
+        cli.overlays
+            .iter()
+            .map(|path| {
+                let mut doc = overlay::apply(&base_doc, read_shipfile(path)).unwrap_or_else(|e| {
+                    error!("invalid overlay `{path}`: {e}");
+                    std::process::exit(-1);
+                });
+                apply_cli_overrides(&mut doc, &cli);
+                (doc, path.as_str())
+            })
+            .collect()
+    };
+    let variants: Vec<ShipConfig> = docs
+        .into_iter()
+        .flat_map(|(doc, source)| arch_matrix(&doc).into_iter().map(move |doc| (doc, source)))
+        .map(|(doc, source)| resolve_config(doc, &mut cli_vars, source, interactive, true))
+        .collect();
+
+    if variants.iter().all(|conf| conf.out.targets.is_empty()) {
+        warn!("no targets!");
+        std::process::exit(0);
+    }
+
+    // parallel runs (e.g. CI retries) would clobber each other's staging dirs and artifacts
+    let _locks = if cli.no_lock || cli.dry_run {
+        Vec::new()
+    } else {
+        lock::lock_all(&variants).unwrap_or_else(|err| {
+            error!("{err}");
+            std::process::exit(-1);
+        })
+    };
+
+    // catch missing branding assets and misspelled targets before spending time on the build
+    for conf in &variants {
+        for target in &conf.out.targets {
+            if let Target::Custom(name) = target
+                && gen_::for_target(target, conf).is_none()
+            {
+                error!(
+                    "unknown target `{name}` in [out].targets, add [plugins.{name}] or put {}{name} on PATH",
+                    plugin::EXECUTABLE_PREFIX
+                );
+                std::process::exit(-1);
             }
-            ErrorKind::IsADirectory => {
-                eprintln!("error: `{}` is a directory, terminating...", cli.config);
+        }
+        for (key, path) in conf.ui_resources() {
+            if !Path::new(path).is_file() {
+                error!("{key} points to `{path}`, which does not exist");
+                std::process::exit(-1);
             }
-            _ => {
-                eprintln!("error: {e}");
+        }
+    }
+
+    // nothing is built, fetched or written, the plan only shows what would be
+    if cli.dry_run {
+        let mut failed = false;
+        for mut variant in variants {
+            // the build command usually makes what globs match, and it doesn't run here
+            if let Err(err) = globs::expand(&mut variant) {
+                warn!("{err}, so it's shown as is");
             }
+            failed |= !print_plan(&variant);
         }
+        if failed {
+            std::process::exit(-1);
+        }
+        return;
+    }
+
+    let mut packaged = Vec::new();
+    let mut reports = Vec::new();
+    let mut failed = false;
+    let mut checksummed: BTreeMap<PathBuf, (bool, Vec<ReportArtifact>)> = BTreeMap::new();
+    // variants that build the same way share one build, matrix architectures usually don't
+    let mut builds: Vec<(Option<Build>, Option<Vars>)> = Vec::new();
+    for mut variant in variants {
+        if let Some(build) = &variant.build
+            && build.has_commands()
+            && !builds.contains(&(variant.build.clone(), variant.vars.clone()))
+        {
+            run_build(build, variant.vars.as_ref(), &variant.out.targets);
+            builds.push((variant.build.clone(), variant.vars.clone()));
+        }
+
+        // globs usually match what the build just produced
+        expand_globs(&mut variant);
+        let conf = &variant;
+
+        remote::fetch_all(&conf.files.remote).unwrap_or_else(|err| {
+            error!("{err}");
+            std::process::exit(-1);
+        });
+
+        if conf.files.help2man {
+            generate_manpages(conf);
+        }
+
+        for page in &conf.files.man {
+            manpage::compress(conf, page).unwrap_or_else(|err| {
+                error!("{err}");
+                std::process::exit(-1);
+            });
+        }
+
+        if let Some(resources) = conf.windows.as_ref().and_then(|w| w.resources.as_ref()) {
+            winres::stamp(conf, Some(resources)).unwrap_or_else(|err| {
+                error!("{err}");
+                std::process::exit(-1);
+            });
+        }
+
+        if conf.build.as_ref().is_some_and(|b| b.stamp_version) {
+            stamp::stamp_all(conf).unwrap_or_else(|err| {
+                error!("{err}");
+                std::process::exit(-1);
+            });
+        }
+
+        preflight::check_disk_space(conf).unwrap_or_else(|err| {
+            error!("{err}");
+            std::process::exit(-1);
+        });
+
+        // the binaries as built, so packaging can't silently pick up anything else
+        // every target reuses the hashes of the files it shares with the others
+        let hashes = HashStore::default();
+        let provenance = Provenance::record(conf, &hashes).unwrap_or_else(|err| {
+            error!("{err}");
+            std::process::exit(-1);
+        });
 
+        let (report, succeeded) = generate(conf, &provenance, &hashes, &cli);
+        if let Some(report) = &report
+            && conf.out.checksums != Some(false)
+        {
+            let (manifest, artifacts) =
+                checksummed.entry(lock::out_dir(&conf.out.bin)).or_default();
+            *manifest |= conf.out.manifest == Some(true);
+            artifacts.extend(report.artifacts.iter().cloned());
+        }
+        reports.extend(report);
+        packaged.push(variant);
+        if !succeeded {
+            failed = true;
+            if cli.fail_fast {
+                break;
+            }
+        }
+    }
+
+    // a partial build gets no checksums, they'd pass off the old artifacts as current
+    if !failed {
+        write_checksums(&checksummed, cli.sign_checksums);
+    }
+
+    // a partial build doesn't replace the report of the last complete one, and every
+    // architecture of a program goes into its one report, which publishing reads
+    if !failed {
+        let mut merged: Vec<BuildReport> = Vec::new();
+        for report in &reports {
+            match merged.iter_mut().find(|merged| merged.name == report.name) {
+                Some(merged) => merged.merge(report.clone()),
+                None => merged.push(report.clone()),
+            }
+        }
+        for report in &merged {
+            report.save().unwrap_or_else(|err| {
+                warn!("failed to write build report: {err}");
+            });
+        }
+    }
+
+    // nothing is published from a partial build
+    if let Some(Action::ReleaseAll) = cli.action
+        && !failed
+    {
+        let mut released: Vec<&str> = Vec::new();
+        for conf in &packaged {
+            // the report has every architecture, so the first variant releases them all
+            if released.contains(&conf.prog.name.as_str()) {
+                continue;
+            }
+            released.push(&conf.prog.name);
+            let summary = release::run(conf, !builds.is_empty(), passphrase.as_deref());
+            summary.print();
+            // later variants stay unpublished rather than releasing half a set
+            if summary.failed() {
+                error!("release of {} failed", conf.prog.name);
+                std::process::exit(-1);
+            }
+        }
+    }
+
+    if cli.output_format == OutputFormat::Json {
+        let json = serde_json::json!({ "builds": reports });
+        println!("{}", serde_json::to_string_pretty(&json).unwrap());
+    }
+    if failed {
         std::process::exit(-1);
+    }
+}
+
+/// Writes SHA256SUMS, and manifest.json if asked for, into every output directory, listing
+/// the artifacts of all variants built there
+fn write_checksums(checksummed: &BTreeMap<PathBuf, (bool, Vec<ReportArtifact>)>, sign: bool) {
+    for (dir, (manifest, artifacts)) in checksummed {
+        if artifacts.is_empty() {
+            continue;
+        }
+        let sums = sums::write_sums(dir, artifacts).unwrap_or_else(|err| {
+            error!("{err}");
+            std::process::exit(-1);
+        });
+        debug!("wrote {}", sums.display());
+        if *manifest {
+            sums::write_manifest(dir, artifacts).unwrap_or_else(|err| {
+                error!("{err}");
+                std::process::exit(-1);
+            });
+        }
+        if sign {
+            let signature = sums::sign(&sums).unwrap_or_else(|err| {
+                error!("{err}");
+                std::process::exit(-1);
+            });
+            info!("signed {}", signature.display());
+        }
+    }
+}
+
+/// Prints a table of every target of `conf` with how it went, its artifacts and how long
+/// it took, or why it failed or wasn't built
+fn print_summary(conf: &ShipConfig, runs: &[TargetRun], built: &[(Target, PathBuf)]) {
+    let names: Vec<String> = conf
+        .out
+        .targets
+        .iter()
+        .map(|target| format!("{target:?}"))
+        .collect();
+    let width = names
+        .iter()
+        .map(String::len)
+        .max()
+        .unwrap_or_default()
+        .max(6);
+    match &conf.prog.version {
+        Some(version) => output!(
+            "summary of {} {version} ({}):",
+            conf.prog.name,
+            conf.prog.arch.deb_name()
+        ),
+        None => output!(
+            "summary of {} ({}):",
+            conf.prog.name,
+            conf.prog.arch.deb_name()
+        ),
+    }
+    output!(
+        "  {:<width$}  {:<10}  {:>8}  artifact",
+        "target",
+        "status",
+        "duration"
+    );
+    for (target, name) in conf.out.targets.iter().zip(&names) {
+        let Some(run) = runs.iter().find(|run| &run.target == target) else {
+            let reason = match target {
+                Target::Appcast => "not written, as a target failed",
+                _ if gen_::for_target(target, &conf.for_target(target)).is_none() => {
+                    "not yet supported"
+                }
+                _ => "not built, --fail-fast stopped at an earlier failure",
+            };
+            output!("  {name:<width$}  {:<10}  {:>8}  {reason}", "skipped", "");
+            continue;
+        };
+        let status = match run.status {
+            TargetStatus::Built => "built",
+            TargetStatus::UpToDate => "up to date",
+            TargetStatus::Restored => "restored",
+            TargetStatus::Failed => "FAILED",
+        };
+        let duration = format!("{:.1}s", run.duration_ms as f64 / 1000.0);
+        // a failed target has no artifacts, its error goes in their place
+        let details: Vec<String> = match &run.error {
+            Some(err) => vec![err.lines().next().unwrap_or_default().to_string()],
+            None => built
+                .iter()
+                .filter(|(built, _)| built == target)
+                .map(|(_, path)| path.display().to_string())
+                .collect(),
+        };
+        let mut details = details.into_iter();
+        output!(
+            "  {name:<width$}  {status:<10}  {duration:>8}  {}",
+            details.next().unwrap_or_default()
+        );
+        for detail in details {
+            output!("  {:<width$}  {:<10}  {:>8}  {detail}", "", "", "");
+        }
+    }
+}
+
+/// Prints what each target of `conf` would write for `--dry-run`, returning false if one
+/// of them can't be built as configured
+fn print_plan(conf: &ShipConfig) -> bool {
+    let mut ok = true;
+    match &conf.prog.version {
+        Some(version) => output!(
+            "plan for {} {version} ({}):",
+            conf.prog.name,
+            conf.prog.arch.deb_name()
+        ),
+        None => output!(
+            "plan for {} ({}):",
+            conf.prog.name,
+            conf.prog.arch.deb_name()
+        ),
+    }
+    if let Some(build) = &conf.build {
+        if let Some(cmd) = &build.cmd {
+            output!("  build: {cmd}");
+        }
+        for step in &build.steps {
+            output!("  build step: {}", step.cmd);
+        }
+    }
+
+    for target in &conf.out.targets {
+        let conf = &conf.for_target(target);
+        output!("  {target:?}:");
+        let Some(generator) = gen_::for_target(target, conf) else {
+            match target {
+                Target::Appcast => output!("    written last, from the artifacts of the others"),
+                _ => output!("    not yet supported, skipped"),
+            }
+            continue;
+        };
+        for notice in notice::defaults(conf, target) {
+            notice.print();
+        }
+        let plan = match generator.plan() {
+            Ok(plan) => plan,
+            Err(err) => {
+                output!("    FAILED: {err}");
+                ok = false;
+                continue;
+            }
+        };
+
+        let section = |title: &str, lines: Vec<String>| {
+            if lines.is_empty() {
+                return;
+            }
+            output!("    {title}:");
+            for line in lines {
+                output!("      {}", line.replace('\n', "\n        "));
+            }
+        };
+        section(
+            "artifacts",
+            plan.artifacts
+                .iter()
+                .map(|artifact| artifact.display().to_string())
+                .collect(),
+        );
+        section(
+            "files",
+            plan.files
+                .iter()
+                .map(|entry| {
+                    let mut line = format!("{} -> {}", entry.source.display(), entry.dest);
+                    if entry.source.is_dir() {
+                        line.push_str(" (directory)");
+                    }
+                    if let Some(mode) = entry.mode {
+                        line.push_str(&format!(" (mode {mode:04o})"));
+                    }
+                    line
+                })
+                .collect(),
+        );
+        section(
+            "symlinks",
+            plan.symlinks
+                .iter()
+                .map(|(link, target)| format!("{link} -> {target}"))
+                .collect(),
+        );
+        section(
+            "fields",
+            plan.fields
+                .iter()
+                .map(|(field, value)| format!("{field}: {value}"))
+                .collect(),
+        );
+        section(
+            "scripts",
+            plan.scripts
+                .iter()
+                .map(|(name, source)| format!("{name} <- {source}"))
+                .collect(),
+        );
+        section("notes", plan.notes);
+    }
+    ok
+}
+
+fn expand_globs(conf: &mut ShipConfig) {
+    globs::expand(conf).unwrap_or_else(|err| {
+        error!("{err}");
+        std::process::exit(-1);
+    });
+}
+
+fn run_build(build: &Build, vars: Option<&Vars>, targets: &[Target]) {
+    let vars = vars.cloned().unwrap_or_default();
+    let env = vars.env_pairs().unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(-1);
+    });
+
+    // packaging what an earlier build left behind would pass stale artifacts off as new
+    let result = match &build.cmd {
+        Some(cmd) => run_build_cmd(build, cmd, &vars, &env),
+        None => Ok(()),
+    }
+    .and_then(|()| {
+        build
+            .steps
+            .iter()
+            .try_for_each(|step| run_build_step(step, &env, targets))
     });
+    match result {
+        Ok(()) => {}
+        Err(err) if build.allow_failure => {
+            warn!("{err}, packaging anyway since [build].allow_failure is set");
+        }
+        Err(err) => {
+            error!("{err}, terminating...");
+            std::process::exit(-1);
+        }
+    }
+}
 
-    println!("building...");
+/// Runs one of `[[build.steps]]` unless none of its `only_targets` are built; the steps
+/// after a failed one don't run
+fn run_build_step(
+    step: &BuildStep,
+    env: &[(String, String)],
+    targets: &[Target],
+) -> Result<(), String> {
+    let name = step.name.as_deref().unwrap_or(&step.cmd);
+    if !step.only_targets.is_empty() && !targets.iter().any(|t| step.only_targets.contains(t)) {
+        debug!("skipping build step `{name}`, none of its only_targets are built");
+        return Ok(());
+    }
+
+    let mut cmd_builder = shell(&step.cmd);
+    cmd_builder
+        .envs(env.iter().cloned())
+        .envs(&step.env)
+        .stdin(Stdio::inherit())
+        .stdout(log::child_stdout())
+        .stderr(Stdio::inherit());
+    if let Some(cwd) = &step.cwd {
+        cmd_builder.current_dir(Path::new(cwd));
+    }
+
+    info!("build step `{name}`...");
+    let started = std::time::Instant::now();
+    let status = cmd_builder.status().unwrap_or_else(|err| {
+        error!("failed to spawn build step `{name}`: {err}, terminating...");
+        std::process::exit(-1);
+    });
+    if !status.success() {
+        return Err(format!(
+            "build step `{name}` exited with {status} after {:.1}s",
+            started.elapsed().as_secs_f64()
+        ));
+    }
+    info!(
+        "build step `{name}` done in {:.1}s",
+        started.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
 
-    let conf: ShipConfig = toml::from_str(&contents).unwrap_or_else(|e| {
-        eprintln!("Failed to parse {}: {}", cli.config, e);
+fn run_build_cmd(
+    build: &Build,
+    cmd: &str,
+    vars: &Vars,
+    env: &[(String, String)],
+) -> Result<(), String> {
+    let cmake_flags = vars.cmake_flags().unwrap_or_else(|e| {
+        error!("{e}");
         std::process::exit(-1);
     });
 
-    if conf.out.targets.is_empty() {
-        eprintln!("no targets!");
-        std::process::exit(0);
+    let mut cmd_str = cmd.to_string();
+    if !cmake_flags.is_empty() {
+        if is_cmake(cmd) {
+            for flag in &cmake_flags {
+                cmd_str.push(' ');
+                cmd_str.push_str(&shell_quote(flag));
+            }
+        } else {
+            warn!("[vars].cmake is set but the build command is not cmake; ignoring...");
+        }
+    }
+    for arg in &build.args {
+        cmd_str.push(' ');
+        cmd_str.push_str(&shell_quote(arg));
     }
 
-    // execute build command
-    if let Some(ref build) = conf.build
-        && let Some(cmd_str) = &build.cmd
-    {
-        #[cfg(unix)]
-        let mut cmd_builder = Command::new("sh");
-        #[cfg(windows)]
-        let mut cmd_builder = Command::new("cmd");
+    let mut cmd_builder = shell(&cmd_str);
+    cmd_builder
+        .envs(env.iter().cloned())
+        .stdin(Stdio::inherit())
+        .stdout(log::child_stdout())
+        .stderr(Stdio::inherit());
 
-        #[cfg(unix)]
-        cmd_builder.arg("-c").arg(cmd_str);
-        #[cfg(windows)]
-        cmd_builder.arg("/C").arg(cmd_str);
+    // set current_dir if build.cwd is Some
+    if let Some(cwd) = &build.cwd {
+        cmd_builder.current_dir(Path::new(cwd));
+    }
 
-        cmd_builder
-            .stdin(Stdio::inherit())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit());
+    debug!("running build command: {cmd_str}");
+    let mut cmd = cmd_builder.spawn().unwrap_or_else(|err| {
+        error!("failed to spawn the build command: {err}, terminating...");
+        std::process::exit(-1);
+    });
 
-        // set current_dir if build.cwd is Some
-        if let Some(cwd) = &build.cwd {
-            cmd_builder.current_dir(Path::new(cwd));
+    let status = cmd.wait().unwrap();
+    if !status.success() {
+        return Err(format!("build command exited with {status}"));
+    }
+    debug!("build command exited with {status}");
+    Ok(())
+}
+
+/// Runs the target's `post_process` command once per artifact, with `{artifact}` replaced by its path
+fn post_process(cmd: &str, target: &Target, artifacts: &[PathBuf]) -> Result<(), String> {
+    for artifact in artifacts {
+        let cmd_str = cmd.replace("{artifact}", &shell_quote(&artifact.to_string_lossy()));
+        info!("post-processing {}...", artifact.display());
+        debug!("running {cmd_str}");
+
+        let status = shell(&cmd_str)
+            .stdin(Stdio::inherit())
+            .stdout(log::child_stdout())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|err| format!("failed to run post_process for {target:?}: {err}"))?;
+        if !status.success() {
+            return Err(format!(
+                "post_process for {target:?} failed on {} ({status})",
+                artifact.display()
+            ));
         }
+    }
+    Ok(())
+}
 
-        let mut cmd = cmd_builder.spawn().unwrap_or_else(|err| {
-            eprintln!(
-                "error while spawning child process to execute build command: {err}, terminating..."
-            );
+/// Runs `[hooks].<hook>` for `target`, and for one of its artifacts after it's built
+fn run_hook(hook: &str, cmd: &str, target: &Target, artifact: Option<&Path>) -> Result<(), String> {
+    // spelled like for --target, custom targets by their own name
+    let name = match target.to_possible_value() {
+        Some(value) => value.get_name().to_string(),
+        None => target.section().to_string(),
+    };
+    let mut command = shell(cmd);
+    command
+        .env("SHIP_TARGET", name)
+        .stdin(Stdio::inherit())
+        .stdout(log::child_stdout())
+        .stderr(Stdio::inherit());
+    if let Some(artifact) = artifact {
+        command.env("SHIP_ARTIFACT", artifact);
+    }
+
+    debug!("running [hooks].{hook}: {cmd}");
+    let status = command
+        .status()
+        .map_err(|err| format!("failed to run [hooks].{hook}: {err}"))?;
+    if !status.success() {
+        return Err(match artifact {
+            Some(artifact) => format!("[hooks].{hook} failed on {} ({status})", artifact.display()),
+            None => format!("[hooks].{hook} failed ({status})"),
+        });
+    }
+    Ok(())
+}
+
+// the program is the first word of the command, e.g. `cmake -S . -B build`
+fn is_cmake(cmd: &str) -> bool {
+    cmd.split_whitespace()
+        .next()
+        .and_then(|program| Path::new(program).file_stem())
+        .is_some_and(|stem| stem == "cmake")
+}
+
+fn tune_compression(conf: &ShipConfig, target: &Target, write: bool, shipfile: &str) {
+    if !tune::supports(target) {
+        error!("target {target:?} has no payload compression to tune");
+        std::process::exit(-1);
+    }
+
+    let payload = tune::payload(&conf.for_target(target)).unwrap_or_else(|err| {
+        error!("{err}");
+        std::process::exit(-1);
+    });
+
+    let trials = tune::run_trials(&payload).unwrap_or_else(|err| {
+        error!("failed to compress payload: {err}");
+        std::process::exit(-1);
+    });
+    tune::print_report(payload.len() as u64, &trials);
+
+    if write && let Some(best) = trials.first() {
+        tune::write_choice(Path::new(shipfile), target, best).unwrap_or_else(|err| {
+            error!("{err}");
             std::process::exit(-1);
         });
+        info!(
+            "wrote {:?} level {} to [{}] in {shipfile}",
+            best.algorithm,
+            best.level,
+            target.section()
+        );
+    }
+}
+
+fn doctor(conf: &ShipConfig) {
+    let checks = doctor::run(conf);
+    if checks.is_empty() {
+        output!("nothing in the Shipfile needs an external program");
+        return;
+    }
 
-        let status = cmd.wait().unwrap();
-        println!("exited build child process with status {}", status);
+    for check in &checks {
+        let (label, detail) = match &check.status {
+            Status::Found(detail) => ("ok", detail.clone()),
+            Status::Missing(install) => ("MISSING", format!("install: {install}")),
+            Status::Optional(install) => ("optional", format!("install: {install}")),
+            Status::Problem(detail) => ("PROBLEM", detail.clone()),
+            Status::Skipped(detail) => ("skipped", detail.clone()),
+        };
+        output!("{label:<8} {:<16} {detail}", check.name);
+        output!("{:<25} for {}", "", check.needed_by.join("; "));
+    }
+
+    let failed = checks.iter().filter(|check| check.failed()).count();
+    if failed > 0 {
+        error!(
+            "{failed} of {} checks failed, the build would too",
+            checks.len()
+        );
+        std::process::exit(-1);
+    }
+}
+
+fn inspect(conf: &ShipConfig) {
+    let report = BuildReport::load(&conf.prog.name).unwrap_or_else(|err| {
+        error!("{err}");
+        std::process::exit(-1);
+    });
+
+    for artifact in &report.artifacts {
+        let security = inspect::inspect(&artifact.target, &artifact.path).unwrap_or_else(|err| {
+            error!("{err}");
+            std::process::exit(-1);
+        });
+        let Some(security) = security else {
+            continue;
+        };
+
+        output!("{} ({:?})", artifact.file, artifact.target);
+        for (label, items) in [
+            ("setuid/setgid", &security.setuid),
+            ("capabilities", &security.capabilities),
+            ("root scripts", &security.root_scripts),
+            ("user scripts", &security.user_scripts),
+            ("services", &security.services),
+        ] {
+            if items.is_empty() {
+                output!("  {label:<14} none");
+            } else {
+                output!("  {label:<14} {}", items.join(", "));
+            }
+        }
+    }
+}
+
+fn verify_published(conf: &ShipConfig) {
+    let results = verify::verify_published(conf).unwrap_or_else(|err| {
+        error!("{err}");
+        std::process::exit(-1);
+    });
+
+    let mut failed = false;
+    for (file, verdict) in &results {
+        match verdict {
+            Verdict::Ok => output!("ok        {file}"),
+            Verdict::Missing(err) => {
+                output!("missing   {file}: {err}");
+            }
+            Verdict::Truncated { expected, actual } => {
+                output!("truncated {file}: {actual} of {expected} bytes");
+            }
+            Verdict::Tampered { expected, actual } => {
+                output!("tampered  {file}: sha256 {actual}, expected {expected}");
+            }
+            Verdict::SignatureMismatch(reason) => {
+                output!("signature {file}: {reason}");
+            }
+        }
+        failed |= *verdict != Verdict::Ok;
+    }
+
+    if failed {
+        error!("published artifacts do not match the last build");
+        std::process::exit(-1);
+    }
+    info!(
+        "all {} published artifacts match the last build",
+        results.len()
+    );
+}
+
+fn promote(conf: &ShipConfig, version: &str) {
+    let feed = promote::promote(conf, version).unwrap_or_else(|err| {
+        error!("{err}");
+        std::process::exit(-1);
+    });
+    info!("{feed} now points at {} {version}", conf.prog.name);
+}
+
+fn publish(conf: &ShipConfig, to: PublishTo, tag: Option<&str>) {
+    let sections = conf.publish.as_ref();
+    let uploaded = match to {
+        PublishTo::Github => github::publish(conf, tag),
+        PublishTo::S3 => upload_release(
+            conf,
+            sections.and_then(|publish| publish.s3.as_ref().map(upload::Destination::S3)),
+            "s3",
+        ),
+        PublishTo::Http => upload_release(
+            conf,
+            sections.and_then(|publish| publish.http.as_ref().map(upload::Destination::Http)),
+            "http",
+        ),
+        PublishTo::Ppa => ppa::publish(conf),
+    }
+    .unwrap_or_else(|err| {
+        error!("{err}");
+        std::process::exit(-1);
+    });
+    info!("published {} files", uploaded.len());
+}
+
+fn upload_release(
+    conf: &ShipConfig,
+    destination: Option<upload::Destination>,
+    section: &str,
+) -> Result<Vec<String>, String> {
+    let destination =
+        destination.ok_or_else(|| format!("no [publish.{section}] section to upload with"))?;
+    let files = BuildReport::load(&conf.prog.name)?.release_files();
+    if files.is_empty() {
+        return Err("the last build has no artifacts to publish".to_string());
+    }
+    upload::upload(conf, destination, &files)
+}
+
+fn repo(conf: &ShipConfig, kind: RepoKind, dir: &Path) {
+    let written = match kind {
+        RepoKind::Apt => apt::update(conf, dir),
+        RepoKind::Rpm => yum::update(conf, dir),
+    }
+    .unwrap_or_else(|err| {
+        error!("{err}");
+        std::process::exit(-1);
+    });
+    for path in &written {
+        debug!("wrote {}", path.display());
+    }
+    info!("updated the repository in {}", dir.display());
+}
+
+fn generate_manpages(conf: &ShipConfig) {
+    for file in &conf.files.paths {
+        let from = Path::new(&file.from);
+        // named like the installed executable, which `to` may have renamed
+        let dest = file.dest("");
+        let name = Path::new(&dest)
+            .file_name()
+            .map(|name| name.to_string_lossy());
+        if let Some(name) = name.filter(|_| layout::is_executable(from, file.mode)) {
+            manpage::generate(conf, from, &name).unwrap_or_else(|err| {
+                error!("failed to generate manpage for {}: {err}", file.from);
+                std::process::exit(-1);
+            });
+        }
     }
+}
+
+fn generate(
+    conf: &ShipConfig,
+    provenance: &Provenance,
+    hashes: &HashStore,
+    cli: &Cli,
+) -> (Option<BuildReport>, bool) {
+    let mut built: Vec<(Target, PathBuf)> = Vec::new();
+    let mut notices = Vec::new();
+    let mut runs = Vec::new();
+    let mut failed = false;
+
+    // one predicate covers every artifact of the build
+    let attest = conf
+        .signing
+        .as_ref()
+        .and_then(|signing| signing.cosign.as_ref())
+        .is_some_and(|cosign| cosign.provenance == Some(true));
+    let predicate = attest.then(|| {
+        let shipfiles: Vec<&str> = std::iter::once(cli.config.as_str())
+            .chain(cli.overlays.iter().map(String::as_str))
+            .collect();
+        provenance
+            .write_predicate(conf, &shipfiles)
+            .unwrap_or_else(|err| {
+                error!("{err}");
+                std::process::exit(-1);
+            })
+    });
 
     for target in &conf.out.targets {
-        match target {
-            Target::Deb => {
-                let generator = DebGenerator::new(&conf);
+        let conf = &conf.for_target(target);
+        let Some(generator) = gen_::for_target(target, conf) else {
+            match target {
+                // the update feed describes everything else, so it runs last
+                Target::Appcast => {}
+                Target::Custom(name) => {
+                    error!("unknown target `{name}` in [out].targets");
+                    std::process::exit(-1);
+                }
+                t => warn!("target {t:?} not yet supported; skipping..."),
+            }
+            continue;
+        };
+
+        let started = std::time::Instant::now();
+        let mut finished = |status, error| {
+            runs.push(TargetRun {
+                target: target.clone(),
+                status,
+                duration_ms: started.elapsed().as_millis() as u64,
+                error,
+            })
+        };
 
-                generator.run();
+        if let Some(cmd) = conf.hooks.as_ref().and_then(|h| h.pre_package.as_ref())
+            && let Err(err) = run_hook("pre_package", cmd, target, None)
+        {
+            error!("{err}");
+            finished(TargetStatus::Failed, Some(err));
+            failed = true;
+            if cli.fail_fast {
+                break;
             }
-            Target::AppImage => {
-                let generator = AppImageGenerator::new(&conf);
+            continue;
+        }
+
+        // silently guessed defaults have picked the wrong file before, so every one is pointed out
+        for notice in notice::defaults(conf, target) {
+            notice.print();
+            notices.push(notice);
+        }
 
-                generator.run();
+        // a target whose inputs match its last build only needs rebuilding if the artifact is gone
+        let manifest = Manifest::compute(conf, hashes).ok();
+        if let (Some(manifest), Some(previous)) = (&manifest, Manifest::load(conf, target)) {
+            let changes = manifest.diff(&previous);
+            let artifacts = generator.artifacts();
+            if changes.is_empty()
+                && !cli.force
+                && !artifacts.is_empty()
+                && artifacts.iter().all(|artifact| artifact.exists())
+            {
+                info!("{target:?} is up to date; skipping...");
+                finished(TargetStatus::UpToDate, None);
+                built.extend(
+                    artifacts
+                        .into_iter()
+                        .map(|artifact| (target.clone(), artifact)),
+                );
+                continue;
+            }
+            if !changes.is_empty() {
+                changes.print(target);
+            }
+        }
+        // the same inputs may have been built before, on another branch or before a clean
+        if let Some(manifest) = &manifest
+            && !cli.force
+        {
+            let artifacts = generator.artifacts();
+            match manifest.restore(conf, target, &artifacts) {
+                Ok(true) => {
+                    info!("{target:?} restored from the build cache; skipping...");
+                    finished(TargetStatus::Restored, None);
+                    manifest.save(conf, target).unwrap_or_else(|err| {
+                        warn!("failed to record build manifest for {target:?}: {err}");
+                    });
+                    built.extend(
+                        artifacts
+                            .into_iter()
+                            .map(|artifact| (target.clone(), artifact)),
+                    );
+                    continue;
+                }
+                Ok(false) => {}
+                Err(err) => {
+                    warn!("failed to restore {target:?} from the build cache: {err}");
+                }
             }
-            t => {
-                eprintln!("target {:?} not yet supported; skipping...", t);
+        }
+
+        let _scope = log::scope(format!("{target:?}"));
+        debug!("building...");
+        let artifacts = match generator.run() {
+            Ok(artifacts) => artifacts,
+            Err(err) => {
+                error!("{err}");
+                finished(TargetStatus::Failed, Some(err.to_string()));
+                failed = true;
+                if cli.fail_fast {
+                    break;
+                }
+                continue;
+            }
+        };
+        let checked = provenance.verify(conf, target, &artifacts).and_then(|()| {
+            match conf.overrides(target).and_then(|o| o.post_process.as_ref()) {
+                Some(cmd) => post_process(cmd, target, &artifacts),
+                None => Ok(()),
+            }
+        });
+        if let Err(err) = checked {
+            error!("{err}");
+            finished(TargetStatus::Failed, Some(err));
+            failed = true;
+            if cli.fail_fast {
+                break;
+            }
+            continue;
+        }
+        let mut signatures = Vec::new();
+        if let Some(signing) = &conf.signing {
+            match signing::sign_artifacts(signing, &artifacts, predicate.as_deref()) {
+                Ok(written) => {
+                    for signature in &written {
+                        debug!("wrote {}", signature.display());
+                    }
+                    // cached with the artifacts, restoring them skips signing
+                    signatures = written;
+                }
+                Err(err) => {
+                    error!("{err}");
+                    finished(TargetStatus::Failed, Some(err));
+                    failed = true;
+                    if cli.fail_fast {
+                        break;
+                    }
+                    continue;
+                }
+            }
+        }
+        if let Some(cmd) = conf.hooks.as_ref().and_then(|h| h.post_package.as_ref())
+            && let Err(err) = artifacts
+                .iter()
+                .try_for_each(|artifact| run_hook("post_package", cmd, target, Some(artifact)))
+        {
+            error!("{err}");
+            finished(TargetStatus::Failed, Some(err));
+            failed = true;
+            if cli.fail_fast {
+                break;
+            }
+            continue;
+        }
+        debug!("built in {:.1}s", started.elapsed().as_secs_f64());
+        finished(TargetStatus::Built, None);
+        if let Some(manifest) = &manifest {
+            manifest.save(conf, target).unwrap_or_else(|err| {
+                warn!("failed to record build manifest for {target:?}: {err}");
+            });
+            manifest
+                .store(conf, target, &artifacts, &signatures)
+                .unwrap_or_else(|err| {
+                    warn!("failed to cache the build of {target:?}: {err}");
+                });
+        }
+        built.extend(
+            artifacts
+                .into_iter()
+                .map(|artifact| (target.clone(), artifact)),
+        );
+    }
+
+    if let Some(format) = conf.out.sbom {
+        let inputs = BuildInputs::detect();
+        for (target, path) in &built {
+            let written = ReportArtifact::new(target, path)
+                .map_err(|err| format!("failed to hash {}: {err}", path.display()))
+                .and_then(|artifact| {
+                    sbom::write(&conf.for_target(target), format, &artifact, &inputs, hashes)
+                });
+            match written {
+                Ok(sbom) => debug!("wrote {}", sbom.display()),
+                Err(err) => {
+                    error!("failed to write the SBOM of {}: {err}", path.display());
+                    failed = true;
+                }
             }
         }
     }
+
+    if cli.check
+        && let Err(err) = check_packages(&built)
+    {
+        error!("{err}");
+        failed = true;
+        if let Some(run) = runs.iter_mut().find(|run| run.target == Target::Deb) {
+            run.status = TargetStatus::Failed;
+            run.error = Some(err);
+        }
+    }
+
+    // a feed missing a failed target would tell users there's no update for it
+    if conf.out.targets.contains(&Target::Appcast) && !failed {
+        let generator = AppcastGenerator::new(conf, &built);
+
+        let _scope = log::scope("Appcast");
+        let started = std::time::Instant::now();
+        let result = generator.run();
+        if let Err(err) = &result {
+            error!("{err}");
+            failed = true;
+        }
+        runs.push(TargetRun {
+            target: Target::Appcast,
+            status: if failed {
+                TargetStatus::Failed
+            } else {
+                TargetStatus::Built
+            },
+            duration_ms: started.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(ToString::to_string),
+        });
+        built.extend(
+            result
+                .unwrap_or_default()
+                .into_iter()
+                .map(|artifact| (Target::Appcast, artifact)),
+        );
+    }
+
+    print_summary(conf, &runs, &built);
+    if failed {
+        let count = runs
+            .iter()
+            .filter(|run| run.status == TargetStatus::Failed)
+            .count();
+        error!("{count} of {} targets failed", conf.out.targets.len());
+    }
+
+    let report = match BuildReport::new(conf, &built, runs, notices) {
+        Ok(report) => Some(report),
+        Err(err) => {
+            warn!("failed to hash the artifacts for the build report: {err}");
+            None
+        }
+    };
+    (report, !failed)
+}
+
+/// Fails the build if any built .deb breaks Debian policy, before it's announced anywhere
+fn check_packages(built: &[(Target, PathBuf)]) -> Result<(), String> {
+    let mut errors = 0;
+    for (target, path) in built {
+        if *target != Target::Deb {
+            continue;
+        }
+        let (checker, findings) = lint::check_deb(path)?;
+        info!("checked {} with {checker}", path.display());
+
+        let package = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        for finding in &findings {
+            finding.print(&package);
+        }
+        errors += findings
+            .iter()
+            .filter(|finding| finding.severity == lint::Severity::Error)
+            .count();
+    }
+
+    if errors > 0 {
+        return Err(format!("{errors} policy errors in the built packages"));
+    }
+    Ok(())
+}
+
+fn read_shipfile(path: &str) -> toml::Value {
+    let contents = std::fs::read_to_string(path).unwrap_or_else(|e| {
+        match e.kind() {
+            ErrorKind::NotFound => {
+                error!("no `{}` present, terminating...", path);
+            }
+            ErrorKind::IsADirectory => {
+                error!("`{}` is a directory, terminating...", path);
+            }
+            _ => {
+                error!("{e}");
+            }
+        }
+
+        std::process::exit(-1);
+    });
+
+    toml::from_str(&contents).unwrap_or_else(|e| {
+        error!("failed to parse {path}: {e}");
+        std::process::exit(-1);
+    })
+}
+
+/// Puts `--set`, then `--version-override` or the detected version, `--target` and `--out`
+/// into the Shipfile before its variables are resolved, so `${version}` and the like see
+/// them too
+fn apply_cli_overrides(doc: &mut toml::Value, cli: &Cli) {
+    overlay::set(doc, &cli.sets).unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(-1);
+    });
+
+    fn section<'a>(doc: &'a mut toml::Value, name: &str) -> Option<&'a mut toml::Table> {
+        doc.as_table_mut()?
+            .entry(name)
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+    }
+
+    if let Some(version) = &cli.version_override
+        && let Some(prog) = section(doc, "prog")
+    {
+        prog.insert("version".to_string(), toml::Value::String(version.clone()));
+    }
+    resolve_auto_version(doc);
+
+    let Some(out) = section(doc, "out") else {
+        return;
+    };
+    if !cli.targets.is_empty() {
+        let targets = cli
+            .targets
+            .iter()
+            .map(|target| toml::Value::try_from(target).expect("targets serialize to strings"))
+            .collect();
+        out.insert("targets".to_string(), toml::Value::Array(targets));
+    }
+    if let Some(dir) = &cli.out {
+        out.insert("bin".to_string(), toml::Value::String(dir.clone()));
+    }
+}
+
+/// Fills in `[prog].version` from the project's manifest or git when it's `"auto"` or
+/// left out; only `"auto"` has to be found
+fn resolve_auto_version(doc: &mut toml::Value) {
+    let Some(prog) = doc.get_mut("prog").and_then(toml::Value::as_table_mut) else {
+        return;
+    };
+    let auto = match prog.get("version").and_then(toml::Value::as_str) {
+        Some("auto") => true,
+        None => false,
+        Some(_) => return,
+    };
+
+    match init::version(Path::new(".")) {
+        Some((version, source)) => {
+            info!("using version {version} from {source}");
+            prog.insert("version".to_string(), toml::Value::String(version));
+        }
+        None if auto => {
+            error!(
+                "[prog].version is \"auto\", but no version was found in Cargo.toml, package.json, CMakeLists.txt or git tags; pass --version-override"
+            );
+            std::process::exit(-1);
+        }
+        None => {}
+    }
+}
+
+fn arch_matrix(doc: &toml::Value) -> Vec<toml::Value> {
+    overlay::arch_matrix(doc).unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(-1);
+    })
+}
+
+fn resolve_config(
+    mut doc: toml::Value,
+    cli_vars: &mut HashMap<String, String>,
+    source: &str,
+    interactive: bool,
+    building: bool,
+) -> ShipConfig {
+    let resolved = if building {
+        prompt::fill_missing(&mut doc, cli_vars, interactive)
+            .and_then(|()| vars::resolve(&mut doc, cli_vars))
+    } else {
+        vars::resolve_passed(&mut doc, cli_vars)
+    };
+    resolved.unwrap_or_else(|e| {
+        error!("{e}");
+        std::process::exit(-1);
+    });
+
+    doc.try_into().unwrap_or_else(|e| {
+        error!("failed to parse {source}: {e}");
+        std::process::exit(-1);
+    })
 }
@@ -0,0 +1,314 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cache,
+    checksum::{HashStore, to_hex},
+    conf::{ShipConfig, Target},
+    layout::Layout,
+};
+
+/// Hashes of everything that goes into a target's artifact, recorded after each successful build
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct Manifest {
+    pub config: String,
+    pub files: BTreeMap<String, String>, // install path -> sha256, or `-> target` for symlinks
+    #[serde(default)]
+    pub inputs: BTreeMap<String, String>, // source path -> sha256 of files read besides the packaged ones
+    #[serde(default)]
+    pub ship: String, // version of ship that built it
+}
+
+/// What changed in a target's inputs since its last build
+#[derive(Default, Debug)]
+pub struct Changes {
+    pub config: bool,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub modified: Vec<String>,
+    pub inputs: Vec<String>, // scripts, icons and the like whose contents changed
+    pub ship: bool,
+}
+
+impl Manifest {
+    /// Hashes the resolved config and the staged install tree of `conf`
+    pub fn compute(conf: &ShipConfig, hashes: &HashStore) -> Result<Self, String> {
+        let layout = Layout::resolve(conf)?;
+
+        let mut files = BTreeMap::new();
+        for entry in &layout.files {
+            let sha256 = hashes
+                .sha256(&entry.source)
+                .map_err(|err| format!("failed to hash {}: {err}", entry.source.display()))?;
+            files.insert(entry.dest.clone(), sha256);
+        }
+        for (link, target) in &layout.symlinks {
+            files.insert(link.clone(), format!("-> {target}"));
+        }
+
+        let mut inputs = BTreeMap::new();
+        for path in input_files(conf) {
+            let sha256 = hashes
+                .sha256(Path::new(path))
+                .map_err(|err| format!("failed to hash {path}: {err}"))?;
+            inputs.insert(path.to_string(), sha256);
+        }
+
+        let config =
+            serde_json::to_vec(conf).map_err(|err| format!("failed to serialize config: {err}"))?;
+
+        Ok(Self {
+            config: to_hex(&Sha256::digest(&config)),
+            files,
+            inputs,
+            ship: env!("CARGO_PKG_VERSION").to_string(),
+        })
+    }
+
+    // overlays usually rename the program and [matrix].arch changes the architecture, so
+    // variants don't share manifests
+    fn path(conf: &ShipConfig, target: &Target) -> PathBuf {
+        let target = match target {
+            Target::Custom(name) => name.clone(),
+            target => format!("{target:?}"),
+        };
+        cache::manifest_dir().join(
+            format!(
+                "{}-{}-{target}.json",
+                conf.prog.name,
+                conf.prog.arch.deb_name()
+            )
+            .to_lowercase(),
+        )
+    }
+
+    /// The manifest written by the last successful build of `target`, if any
+    pub fn load(conf: &ShipConfig, target: &Target) -> Option<Self> {
+        let contents = std::fs::read(Self::path(conf, target)).ok()?;
+        serde_json::from_slice(&contents).ok()
+    }
+
+    pub fn save(&self, conf: &ShipConfig, target: &Target) -> std::io::Result<()> {
+        let path = Self::path(conf, target);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+
+    // content address of the artifacts built from these inputs
+    fn key(&self) -> String {
+        let manifest = serde_json::to_vec(self).unwrap_or_default();
+        to_hex(&Sha256::digest(&manifest))
+    }
+
+    fn store_dir(&self, conf: &ShipConfig, target: &Target) -> PathBuf {
+        builds_dir(conf, target).join(self.key())
+    }
+
+    /// Copies `artifacts` and their detached `signatures` into the build cache, to be restored
+    /// whenever the inputs match this manifest again, e.g. after switching back to a branch or
+    /// cleaning [out].bin
+    pub fn store(
+        &self,
+        conf: &ShipConfig,
+        target: &Target,
+        artifacts: &[PathBuf],
+        signatures: &[PathBuf],
+    ) -> std::io::Result<()> {
+        let dir = self.store_dir(conf, target);
+        let staging = dir.with_extension("partial");
+        std::fs::remove_dir_all(&staging).ok();
+        std::fs::create_dir_all(&staging)?;
+        for artifact in artifacts.iter().chain(signatures) {
+            if let Some(name) = artifact.file_name() {
+                std::fs::copy(artifact, staging.join(name))?;
+            }
+        }
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::rename(&staging, &dir)?;
+        prune(&builds_dir(conf, target))
+    }
+
+    /// Copies the artifacts built from inputs matching this manifest back into place, along
+    /// with the `<artifact>.<ext>` signatures cached next to them.
+    /// Returns false, touching nothing, unless the cache has every artifact.
+    pub fn restore(
+        &self,
+        conf: &ShipConfig,
+        target: &Target,
+        artifacts: &[PathBuf],
+    ) -> std::io::Result<bool> {
+        let dir = self.store_dir(conf, target);
+        let cached: Vec<PathBuf> = artifacts
+            .iter()
+            .filter_map(|artifact| artifact.file_name().map(|name| dir.join(name)))
+            .collect();
+        if cached.is_empty()
+            || cached.len() != artifacts.len()
+            || !cached.iter().all(|c| c.is_file())
+        {
+            return Ok(false);
+        }
+
+        for (from, to) in cached.iter().zip(artifacts) {
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            crate::atomic::write_with(to, |tmp| std::fs::copy(from, tmp).map(|_| ()))?;
+        }
+        for (from, to) in companions(&dir, artifacts)? {
+            crate::atomic::write_with(&to, |tmp| std::fs::copy(&from, tmp).map(|_| ()))?;
+        }
+        // keeps it from being the first pruned
+        std::fs::File::open(&dir)?.set_modified(std::time::SystemTime::now())?;
+        Ok(true)
+    }
+
+    pub fn diff(&self, previous: &Manifest) -> Changes {
+        let mut changes = Changes {
+            config: self.config != previous.config,
+            ..Default::default()
+        };
+
+        for (path, hash) in &self.files {
+            match previous.files.get(path) {
+                None => changes.added.push(path.clone()),
+                Some(old) if old != hash => changes.modified.push(path.clone()),
+                Some(_) => {}
+            }
+        }
+        changes.inputs = self
+            .inputs
+            .iter()
+            .filter(|(path, hash)| previous.inputs.get(*path) != Some(*hash))
+            .chain(
+                previous
+                    .inputs
+                    .iter()
+                    .filter(|(path, _)| !self.inputs.contains_key(*path)),
+            )
+            .map(|(path, _)| path.clone())
+            .collect();
+        changes.ship = self.ship != previous.ship;
+        changes.removed = previous
+            .files
+            .keys()
+            .filter(|path| !self.files.contains_key(*path))
+            .cloned()
+            .collect();
+
+        changes
+    }
+}
+
+/// How many builds of each target the cache keeps
+const CACHED_BUILDS: usize = 3;
+
+fn builds_dir(conf: &ShipConfig, target: &Target) -> PathBuf {
+    let name = Manifest::path(conf, target);
+    cache::cache_dir()
+        .join("builds")
+        .join(name.file_stem().unwrap_or_default())
+}
+
+// drops all but the most recently stored or restored builds
+fn prune(dir: &Path) -> std::io::Result<()> {
+    let mut builds = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        builds.push((entry.metadata()?.modified()?, entry.path()));
+    }
+    builds.sort();
+    for (_, path) in builds.iter().rev().skip(CACHED_BUILDS) {
+        std::fs::remove_dir_all(path)?;
+    }
+    Ok(())
+}
+
+// the cached `<artifact>.<ext>` signatures of `artifacts`, paired with where they're restored to
+fn companions(dir: &Path, artifacts: &[PathBuf]) -> std::io::Result<Vec<(PathBuf, PathBuf)>> {
+    let names: Vec<_> = artifacts
+        .iter()
+        .filter_map(|artifact| artifact.file_name())
+        .collect();
+    let mut companions = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        if names.contains(&name.as_os_str()) {
+            continue;
+        }
+        let owner = artifacts.iter().find(|artifact| {
+            artifact.file_name().is_some_and(|artifact| {
+                name.to_string_lossy()
+                    .starts_with(&format!("{}.", artifact.to_string_lossy()))
+            })
+        });
+        if let Some(owner) = owner {
+            companions.push((dir.join(&name), owner.with_file_name(&name)));
+        }
+    }
+    Ok(companions)
+}
+
+/// Files the build reads that don't end up in the install tree as they are
+fn input_files(conf: &ShipConfig) -> Vec<&str> {
+    let mut paths: Vec<&str> = Vec::new();
+    paths.extend(conf.files.icon.as_deref());
+    paths.extend(conf.files.license.as_deref());
+    if let Some(scripts) = &conf.scripts {
+        paths.extend(
+            [
+                &scripts.preinstall,
+                &scripts.postinstall,
+                &scripts.preremove,
+                &scripts.postremove,
+            ]
+            .into_iter()
+            .filter_map(|script| script.as_deref()),
+        );
+    }
+    paths.extend(conf.changelog.as_ref().and_then(|c| c.file.as_deref()));
+
+    // the license may be an SPDX expression rather than a file
+    paths.retain(|path| Path::new(path).is_file());
+    paths
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        !self.config
+            && !self.ship
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+            && self.inputs.is_empty()
+    }
+
+    pub fn print(&self, target: &Target) {
+        info!("changes for {target:?} since the last build:");
+        if self.config {
+            info!("  ~ Shipfile settings");
+        }
+        if self.ship {
+            info!("  ~ ship version");
+        }
+        for path in &self.inputs {
+            info!("  ~ {path} (not packaged)");
+        }
+        for path in &self.added {
+            info!("  + {path}");
+        }
+        for path in &self.modified {
+            info!("  ~ {path}");
+        }
+        for path in &self.removed {
+            info!("  - {path}");
+        }
+    }
+}
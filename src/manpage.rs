@@ -0,0 +1,202 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{cache::work_dir, conf::ShipConfig};
+
+/// Where the manpage generated for `name` is written
+pub fn generated_path(conf: &ShipConfig, name: &str) -> PathBuf {
+    work_dir(conf).join("man").join(format!("{name}.1"))
+}
+
+/// The section of a manpage from its file name: `ship.1` -> `1`, `Ship::Conf.3pm.gz` -> `3pm`
+pub fn section(path: &str) -> Option<&str> {
+    let name = Path::new(path).file_name()?.to_str()?;
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let (_, section) = name.rsplit_once('.')?;
+    section
+        .starts_with(|c: char| c.is_ascii_digit())
+        .then_some(section)
+}
+
+/// Where the gzipped copy of a [files].man page is written, pages that already are gzipped
+/// are used as is
+pub fn compressed_path(conf: &ShipConfig, path: &str) -> PathBuf {
+    if path.ends_with(".gz") {
+        return PathBuf::from(path);
+    }
+    work_dir(conf)
+        .join("man")
+        .join("gz")
+        .join(compressed_name(path))
+}
+
+// `docs/ship.1` -> `ship.1.gz`
+fn compressed_name(path: &str) -> String {
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string());
+    if name.ends_with(".gz") {
+        name
+    } else {
+        format!("{name}.gz")
+    }
+}
+
+/// Where a [files].man page is installed, relative to `share_dir`: `man/man1/ship.1.gz`
+pub fn install_path(path: &str) -> Result<String, String> {
+    let section = section(path).ok_or_else(|| {
+        format!("manpage {path} in [files].man has no section, name it like ship.1")
+    })?;
+    Ok(format!(
+        "man/man{}/{}",
+        &section[..1],
+        compressed_name(path)
+    ))
+}
+
+/// Gzips a [files].man page at the maximum level, as distributions expect.
+///
+/// The gzip header carries no name or timestamp, so unchanged pages compress identically.
+pub fn compress(conf: &ShipConfig, path: &str) -> Result<PathBuf, String> {
+    let out = compressed_path(conf, path);
+    if out == Path::new(path) {
+        return Ok(out);
+    }
+
+    let page =
+        std::fs::read(path).map_err(|err| format!("failed to read manpage {path}: {err}"))?;
+    if let Some(parent) = out.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+
+    let write = || -> std::io::Result<()> {
+        let mut encoder =
+            flate2::write::GzEncoder::new(File::create(&out)?, flate2::Compression::best());
+        encoder.write_all(&page)?;
+        encoder.finish()?.flush()
+    };
+    write().map_err(|err| format!("failed to write {}: {err}", out.display()))?;
+
+    Ok(out)
+}
+
+/// Runs `exe --help` (and `--version`) and renders the output as a section 1 manpage.
+pub fn generate(conf: &ShipConfig, exe: &Path, name: &str) -> Result<PathBuf, String> {
+    let help = run(exe, "--help")?;
+    let version = run(exe, "--version")
+        .ok()
+        .and_then(|v| v.lines().next().map(|l| l.trim().to_string()))
+        .filter(|v| !v.is_empty())
+        .or_else(|| {
+            conf.prog
+                .version
+                .as_ref()
+                .map(|v| format!("{} {v}", conf.prog.name))
+        })
+        .unwrap_or_else(|| conf.prog.name.clone());
+
+    let page = render(name, &version, conf.prog.description.as_deref(), &help);
+
+    let path = generated_path(conf, name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+    std::fs::write(&path, page)
+        .map_err(|err| format!("failed to write manpage {}: {err}", path.display()))?;
+
+    Ok(path)
+}
+
+fn run(exe: &Path, flag: &str) -> Result<String, String> {
+    let output = crate::log::command(Command::new(exe).arg(flag).stdin(Stdio::null()))
+        .output()
+        .map_err(|err| format!("failed to run {} {flag}: {err}", exe.display()))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "{} {flag} exited with {}",
+            exe.display(),
+            output.status
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// help2man-style: `Usage:` becomes SYNOPSIS, ahead of the DESCRIPTION, and `Heading:` lines
+// become sections
+fn render(name: &str, version: &str, description: Option<&str>, help: &str) -> String {
+    let date = chrono::Utc::now().format("%B %Y");
+    let mut page = format!(
+        ".TH {} 1 \"{date}\" \"{}\" \"User Commands\"\n.SH NAME\n{} \\- {}\n",
+        name.to_uppercase(),
+        escape(version),
+        escape(name),
+        escape(description.unwrap_or(name))
+    );
+
+    // clap puts the usage after the about text, and further forms indented below it
+    let mut synopsis = Vec::new();
+    let mut body: Vec<&str> = Vec::new();
+    let mut lines = help.lines().map(str::trim_end).peekable();
+    while let Some(line) = lines.next() {
+        let Some(usage) = line
+            .strip_prefix("Usage:")
+            .or_else(|| line.strip_prefix("usage:"))
+        else {
+            // the blank line the usage leaves behind isn't doubled
+            if !(line.is_empty() && body.last().is_some_and(|last| last.is_empty())) {
+                body.push(line);
+            }
+            continue;
+        };
+        synopsis.push(usage.trim());
+        while let Some(form) =
+            lines.next_if(|next| next.starts_with(char::is_whitespace) && !next.trim().is_empty())
+        {
+            synopsis.push(form.trim());
+        }
+    }
+
+    if !synopsis.is_empty() {
+        page.push_str(".SH SYNOPSIS\n.nf\n");
+        for usage in synopsis {
+            page.push_str(&escape(usage));
+            page.push('\n');
+        }
+        page.push_str(".fi\n");
+    }
+    page.push_str(".SH DESCRIPTION\n.nf\n");
+
+    for line in body {
+        let is_heading =
+            !line.starts_with(char::is_whitespace) && line.ends_with(':') && !line.contains("  ");
+        if is_heading {
+            let heading = line.trim_end_matches(':').to_uppercase();
+            page.push_str(&format!(".fi\n.SH {}\n.nf\n", escape(&heading)));
+            continue;
+        }
+
+        page.push_str(&escape(line));
+        page.push('\n');
+    }
+
+    page.push_str(".fi\n");
+    page
+}
+
+fn escape(line: &str) -> String {
+    let escaped = line.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with('.') || escaped.starts_with('\'') {
+        format!("\\&{escaped}")
+    } else {
+        escaped
+    }
+}
@@ -0,0 +1,170 @@
+use std::path::PathBuf;
+
+use crate::{
+    cache::work_dir,
+    conf::{Metadata, ShipConfig},
+    license::License,
+};
+
+/// AppStream component id: `[metadata].id`, otherwise `[prog].id`, otherwise the name
+pub fn component_id(conf: &ShipConfig) -> String {
+    conf.metadata
+        .as_ref()
+        .and_then(|m| m.id.clone())
+        .or_else(|| conf.prog.id.clone())
+        .unwrap_or_else(|| conf.prog.name.clone())
+}
+
+/// Where the metainfo is installed, relative to the share directory
+pub fn install_path(conf: &ShipConfig) -> String {
+    format!("metainfo/{}.appdata.xml", component_id(conf))
+}
+
+/// Renders the metainfo of a program with a desktop entry, `<name>.desktop`, or else of a
+/// command line program
+pub fn render(conf: &ShipConfig, metadata: &Metadata, desktop: bool) -> Result<String, String> {
+    let prog = &conf.prog;
+    let summary = metadata
+        .summary
+        .as_deref()
+        .or(prog.description.as_deref())
+        .ok_or("[metadata] needs a summary, or [prog].description to use as one")?;
+    let description = metadata.description.as_deref().unwrap_or(summary);
+    let name = conf
+        .desktop
+        .as_ref()
+        .and_then(|d| d.name.as_deref())
+        .unwrap_or(&prog.name);
+
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <component type=\"{}\">\n\
+         \x20 <id>{}</id>\n\
+         \x20 <metadata_license>{}</metadata_license>\n",
+        if desktop {
+            "desktop-application"
+        } else {
+            "console-application"
+        },
+        escape(&component_id(conf)),
+        escape(metadata.metadata_license.as_deref().unwrap_or("CC0-1.0")),
+    );
+    if let Some(license) = License::of(conf)?.and_then(|license| license.spdx()) {
+        xml.push_str(&format!(
+            "  <project_license>{}</project_license>\n",
+            escape(license)
+        ));
+    }
+    xml.push_str(&format!(
+        "  <name>{}</name>\n  <summary>{}</summary>\n",
+        escape(name),
+        escape(summary)
+    ));
+    xml.push_str(&format!(
+        "  <description>\n{}  </description>\n",
+        paragraphs(description, "    ")
+    ));
+
+    if desktop {
+        xml.push_str(&format!(
+            "  <launchable type=\"desktop-id\">{}.desktop</launchable>\n",
+            escape(&prog.name)
+        ));
+    } else {
+        xml.push_str(&format!(
+            "  <provides>\n    <binary>{}</binary>\n  </provides>\n",
+            escape(&prog.name)
+        ));
+    }
+    if let Some(homepage) = &metadata.homepage {
+        xml.push_str(&format!(
+            "  <url type=\"homepage\">{}</url>\n",
+            escape(homepage)
+        ));
+    }
+    // `Name <email>`, only the name is shown
+    let developer = prog.author.split('<').next().unwrap_or_default().trim();
+    if !developer.is_empty() {
+        xml.push_str(&format!(
+            "  <developer_name>{}</developer_name>\n",
+            escape(developer)
+        ));
+    }
+
+    if !metadata.screenshots.is_empty() {
+        xml.push_str("  <screenshots>\n");
+        for (index, screenshot) in metadata.screenshots.iter().enumerate() {
+            // the first one is what software centers show in the listing
+            if index == 0 {
+                xml.push_str("    <screenshot type=\"default\">\n");
+            } else {
+                xml.push_str("    <screenshot>\n");
+            }
+            if let Some(caption) = &screenshot.caption {
+                xml.push_str(&format!("      <caption>{}</caption>\n", escape(caption)));
+            }
+            xml.push_str(&format!(
+                "      <image>{}</image>\n    </screenshot>\n",
+                escape(&screenshot.image)
+            ));
+        }
+        xml.push_str("  </screenshots>\n");
+    }
+
+    if !metadata.releases.is_empty() {
+        xml.push_str("  <releases>\n");
+        for release in &metadata.releases {
+            let open = format!(
+                "<release version=\"{}\" date=\"{}\"",
+                escape(&release.version),
+                escape(&release.date)
+            );
+            match &release.description {
+                Some(description) => xml.push_str(&format!(
+                    "    {open}>\n      <description>\n{}      </description>\n    </release>\n",
+                    paragraphs(description, "        ")
+                )),
+                None => xml.push_str(&format!("    {open}/>\n")),
+            }
+        }
+        xml.push_str("  </releases>\n");
+    }
+
+    xml.push_str("</component>\n");
+    Ok(xml)
+}
+
+/// Writes the metainfo to the work directory and returns where
+pub fn write(conf: &ShipConfig, desktop: bool) -> Result<PathBuf, String> {
+    let metadata = conf
+        .metadata
+        .as_ref()
+        .ok_or("no [metadata] section to generate AppStream metainfo from")?;
+    let path = work_dir(conf)
+        .join("metainfo")
+        .join(format!("{}.appdata.xml", component_id(conf)));
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+    std::fs::write(&path, render(conf, metadata, desktop)?)
+        .map_err(|err| format!("failed to write metainfo {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+// blank lines separate paragraphs, like in the Shipfile's multi-line strings
+fn paragraphs(text: &str, indent: &str) -> String {
+    text.split("\n\n")
+        .map(|paragraph| paragraph.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|paragraph| !paragraph.is_empty())
+        .map(|paragraph| format!("{indent}<p>{}</p>\n", escape(&paragraph)))
+        .collect()
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
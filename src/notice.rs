@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    appimage,
+    conf::{InstallScope, ShipConfig, Target},
+};
+
+/// A default ship filled in for a setting the Shipfile leaves out, kept in the build report
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Notice {
+    pub target: Target,
+    pub setting: String, // what the default stands in for, e.g. "[files].icon"
+    pub value: String,   // what ship used instead
+    pub hint: String,    // how to choose explicitly
+}
+
+impl Notice {
+    pub fn print(&self) {
+        info!(
+            "note: {:?} uses {} for {} ({})",
+            self.target, self.value, self.setting, self.hint
+        );
+    }
+}
+
+/// The defaults `target` falls back on, for `conf` as [ShipConfig::for_target] resolved it
+pub fn defaults(conf: &ShipConfig, target: &Target) -> Vec<Notice> {
+    let mut notices = Vec::new();
+    let mut notice = |setting: &str, value: String, hint: &str| {
+        notices.push(Notice {
+            target: target.clone(),
+            setting: setting.to_string(),
+            value,
+            hint: hint.to_string(),
+        })
+    };
+
+    let uses_prefix = match target {
+        Target::Deb | Target::Xbps | Target::Ebuild | Target::DebSource => true,
+        // user installs go to XDG directories instead
+        Target::Archive => {
+            conf.archive
+                .as_ref()
+                .map(|archive| archive.scope)
+                .unwrap_or_default()
+                == InstallScope::System
+        }
+        _ => false,
+    };
+    if uses_prefix && conf.out.prefix.is_none() {
+        notice(
+            "[out].prefix",
+            conf.install_prefix(),
+            "set [out].prefix or the target's prefix to install elsewhere",
+        );
+    }
+
+    if *target == Target::AppImage {
+        if let Ok(Some((primary, true))) = appimage::primary_executable(conf) {
+            notice(
+                "the main executable",
+                primary.display().to_string(),
+                &format!(
+                    "no file is named {}, so the first one was picked; set [prog].main to choose it",
+                    conf.prog.name
+                ),
+            );
+        }
+        if conf.files.icon.is_none() {
+            notice(
+                "[files].icon",
+                "a generated placeholder icon".to_string(),
+                "set [files].icon or [appimage].icon",
+            );
+        }
+    }
+
+    notices
+}
@@ -0,0 +1,278 @@
+use toml::{Table, Value};
+
+use crate::conf::ShipConfig;
+
+/// Merges a branding overlay on top of a base Shipfile document.
+///
+/// Tables are merged key by key, every other value in the overlay replaces the
+/// base value. An optional top-level `[assets]` table maps paths used in the base
+/// `[files]` section to the customer-specific replacements.
+pub fn apply(base: &Value, overlay: Value) -> Result<Value, String> {
+    let Value::Table(mut overlay) = overlay else {
+        return Err("overlay must be a TOML table".to_string());
+    };
+
+    let assets = match overlay.remove("assets") {
+        Some(Value::Table(assets)) => Some(assets),
+        Some(_) => return Err("[assets] in overlay must be a table".to_string()),
+        None => None,
+    };
+
+    let mut merged = base.clone();
+    merge(&mut merged, Value::Table(overlay));
+
+    if let Some(assets) = assets {
+        replace_assets(&mut merged, &assets)?;
+    }
+
+    Ok(merged)
+}
+
+/// Merges `[profile.<name>]` over the rest of the Shipfile like an overlay, e.g. for a
+/// nightly flavor with its own build command and targets. The profile's `version_suffix`
+/// is appended to `[prog].version`.
+///
+/// Every `[profile]` table is dropped, whether one was picked or not.
+pub fn apply_profile(doc: &mut Value, name: Option<&str>) -> Result<(), String> {
+    let profiles = match doc.as_table_mut().and_then(|doc| doc.remove("profile")) {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => return Err("[profile] must be a table of profiles".to_string()),
+        None => Table::new(),
+    };
+    let Some(name) = name else {
+        return Ok(());
+    };
+
+    let mut profile = match profiles.get(name) {
+        Some(Value::Table(profile)) => profile.clone(),
+        Some(_) => return Err(format!("[profile.{name}] must be a table")),
+        None if profiles.is_empty() => {
+            return Err(format!(
+                "there is no [profile.{name}], the Shipfile has no profiles"
+            ));
+        }
+        None => {
+            let names: Vec<&str> = profiles.keys().map(String::as_str).collect();
+            return Err(format!(
+                "there is no [profile.{name}], the Shipfile has {}",
+                names.join(", ")
+            ));
+        }
+    };
+
+    let suffix = match profile.remove("version_suffix") {
+        Some(Value::String(suffix)) => Some(suffix),
+        Some(_) => return Err(format!("[profile.{name}].version_suffix must be a string")),
+        None => None,
+    };
+    merge(doc, Value::Table(profile));
+
+    if let Some(suffix) = suffix {
+        let version = doc
+            .get_mut("prog")
+            .and_then(|prog| prog.get_mut("version"))
+            .ok_or_else(|| {
+                format!("[profile.{name}].version_suffix needs [prog].version to be set")
+            })?;
+        let Value::String(version) = version else {
+            return Err("[prog].version must be a string".to_string());
+        };
+        version.push_str(&suffix);
+    }
+
+    Ok(())
+}
+
+/// Applies `--set section.key=value` overrides, each merged like a one-key overlay so
+/// setting `deb.compression.level` keeps the algorithm already set.
+///
+/// Values are parsed as TOML, so `3`, `true` and `["Deb"]` keep their types, and anything
+/// else is taken as a plain string. A value for a key the Shipfile already sets to a string
+/// stays a string, so `prog.version=2` is the version "2"; for a key it doesn't set, the
+/// string is used when the parsed type doesn't fit the setting.
+pub fn set(doc: &mut Value, overrides: &[String]) -> Result<(), String> {
+    for entry in overrides {
+        let (key, raw) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("invalid --set `{entry}`, expected KEY=VALUE"))?;
+
+        let path: Vec<&str> = key.trim().split('.').map(str::trim).collect();
+        if path.iter().any(|part| part.is_empty()) {
+            return Err(format!(
+                "invalid --set `{entry}`, expected a dotted key like prog.version"
+            ));
+        }
+
+        // no setting is a float or a date, `1.0` is a version
+        let string = Value::String(raw.to_string());
+        let parsed = match toml::from_str::<Table>(&format!("value = {raw}"))
+            .ok()
+            .and_then(|mut parsed| parsed.remove("value"))
+        {
+            Some(Value::Float(_) | Value::Datetime(_)) | None => string.clone(),
+            Some(value) => value,
+        };
+        let existing = path.iter().try_fold(&*doc, |value, part| value.get(part));
+        let value = match existing {
+            Some(Value::String(_)) => string,
+            None if !parsed.is_str() => {
+                let mut typed = doc.clone();
+                merge(&mut typed, nested(&path, parsed.clone()));
+                let mut untyped = doc.clone();
+                merge(&mut untyped, nested(&path, string.clone()));
+                if typed.try_into::<ShipConfig>().is_err()
+                    && untyped.try_into::<ShipConfig>().is_ok()
+                {
+                    string
+                } else {
+                    parsed
+                }
+            }
+            _ => parsed,
+        };
+        merge(doc, nested(&path, value));
+    }
+
+    Ok(())
+}
+
+// `value` under the dotted `path`, as a one-key overlay
+fn nested(path: &[&str], mut value: Value) -> Value {
+    for part in path.iter().rev() {
+        value = Value::Table(Table::from_iter([(part.to_string(), value)]));
+    }
+    value
+}
+
+/// One copy of the Shipfile per `[matrix].arch` entry, with `[prog].arch` set to it, so
+/// `${arch}` resolves differently in each; just the Shipfile itself without a matrix.
+///
+/// `[matrix.<arch>]` is merged over that architecture's copy like a profile, e.g. with its
+/// own `[build]` and `[files]` for a cross-compiled binary. Its `triple`, like
+/// `aarch64-unknown-linux-gnu`, becomes `[matrix].triple` and the `${triple}` built-in.
+pub fn arch_matrix(doc: &Value) -> Result<Vec<Value>, String> {
+    let Some(Value::Table(matrix)) = doc.get("matrix") else {
+        return Ok(vec![doc.clone()]);
+    };
+    let arches = match matrix.get("arch") {
+        Some(Value::Array(arches)) if !arches.is_empty() => arches,
+        _ => {
+            return Err(
+                "[matrix].arch must be a list of architectures like [\"Amd64\", \"Arm64\"]"
+                    .to_string(),
+            );
+        }
+    };
+    let names: Vec<&str> = arches.iter().filter_map(Value::as_str).collect();
+    for (key, entry) in matrix {
+        if key == "arch" {
+            continue;
+        }
+        if !names.contains(&key.as_str()) {
+            return Err(format!(
+                "[matrix.{key}] is not one of [matrix].arch ({})",
+                names.join(", ")
+            ));
+        }
+        if !entry.is_table() {
+            return Err(format!("[matrix.{key}] must be a table"));
+        }
+    }
+
+    arches
+        .iter()
+        .map(|arch| {
+            let mut entry = arch
+                .as_str()
+                .and_then(|name| matrix.get(name))
+                .and_then(Value::as_table)
+                .cloned()
+                .unwrap_or_default();
+            let mut selected =
+                Table::from_iter([("arch".to_string(), Value::Array(arches.clone()))]);
+            match entry.remove("triple") {
+                Some(Value::String(triple)) => {
+                    selected.insert("triple".to_string(), Value::String(triple));
+                }
+                Some(_) => {
+                    return Err(format!(
+                        "[matrix.{}].triple must be a string",
+                        arch.as_str().unwrap_or_default()
+                    ));
+                }
+                None => {}
+            }
+
+            let mut variant = doc.clone();
+            if let Some(doc) = variant.as_table_mut() {
+                // the other architectures' entries don't concern this variant
+                doc.insert("matrix".to_string(), Value::Table(selected));
+            }
+            merge(
+                &mut variant,
+                Value::Table(Table::from_iter([(
+                    "prog".to_string(),
+                    Value::Table(Table::from_iter([("arch".to_string(), arch.clone())])),
+                )])),
+            );
+            merge(&mut variant, Value::Table(entry));
+            Ok(variant)
+        })
+        .collect()
+}
+
+fn merge(base: &mut Value, overlay: Value) {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+// swaps asset paths in [files] for the overlay's replacements
+fn replace_assets(doc: &mut Value, assets: &Table) -> Result<(), String> {
+    let Some(files) = doc.get_mut("files").and_then(|f| f.as_table_mut()) else {
+        return Ok(());
+    };
+
+    for (from, to) in assets {
+        let to = to
+            .as_str()
+            .ok_or_else(|| format!("[assets].\"{from}\" must be a string path"))?;
+        let mut replaced = false;
+
+        if let Some(paths) = files.get_mut("paths").and_then(|p| p.as_array_mut()) {
+            for path in paths.iter_mut() {
+                if path.as_str() == Some(from.as_str()) {
+                    *path = Value::String(to.to_string());
+                    replaced = true;
+                }
+            }
+        }
+
+        for key in ["icon", "license"] {
+            if let Some(value) = files.get_mut(key)
+                && value.as_str() == Some(from.as_str())
+            {
+                *value = Value::String(to.to_string());
+                replaced = true;
+            }
+        }
+
+        if !replaced {
+            return Err(format!(
+                "[assets].\"{from}\" does not match any path in the base [files] section"
+            ));
+        }
+    }
+
+    Ok(())
+}
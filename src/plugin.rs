@@ -0,0 +1,180 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    conf::ShipConfig,
+    error::ShipError,
+    gen_::{Artifacts, Generator},
+};
+
+/// Version of the JSON exchanged with plugins, bumped on incompatible changes
+pub const PROTOCOL: u32 = 1;
+
+/// Executables on `PATH` named like this build the custom target of the rest of their name
+pub const EXECUTABLE_PREFIX: &str = "ship-gen-";
+
+/// What ship asks a plugin on its stdin
+#[derive(Serialize, Debug)]
+pub struct Request<'a> {
+    pub protocol: u32,
+    pub action: Action,
+    pub target: &'a str,
+    pub config: &'a ShipConfig, // the Shipfile as resolved for this target
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Build,     // write the artifacts
+    Artifacts, // only list the files `build` would write, to tell whether they're up to date
+}
+
+/// What a plugin answers on its stdout, either `artifacts` or `error`
+#[derive(Deserialize, Debug, Default)]
+pub struct Response {
+    #[serde(default)]
+    pub artifacts: Vec<PathBuf>, // relative to the project directory
+    pub error: Option<String>,
+}
+
+/// Builds a custom target with an external program: `[plugins.<name>].command`, run
+/// through the shell, or else `ship-gen-<name>` from `PATH`. The program gets a
+/// [Request] as JSON on stdin and prints a [Response] as JSON on stdout; its stderr goes
+/// straight to the terminal.
+pub struct PluginGenerator<'a> {
+    pub conf: &'a ShipConfig,
+    pub name: String,
+    command: PluginCommand,
+}
+
+enum PluginCommand {
+    Shell(String),
+    Executable(PathBuf),
+}
+
+impl<'a> PluginGenerator<'a> {
+    /// The plugin for the custom target `name`, if the Shipfile or `PATH` has one
+    pub fn find(name: &str, conf: &'a ShipConfig) -> Option<Self> {
+        let command = match conf.plugins.as_ref().and_then(|plugins| plugins.get(name)) {
+            Some(plugin) => PluginCommand::Shell(plugin.command.clone()),
+            None => PluginCommand::Executable(on_path(&format!("{EXECUTABLE_PREFIX}{name}"))?),
+        };
+        Some(Self {
+            conf,
+            name: name.to_string(),
+            command,
+        })
+    }
+
+    fn command(&self) -> Command {
+        match &self.command {
+            PluginCommand::Shell(cmd) => crate::shell(cmd),
+            PluginCommand::Executable(path) => Command::new(path),
+        }
+    }
+
+    fn describe(&self) -> String {
+        match &self.command {
+            PluginCommand::Shell(cmd) => format!("`{cmd}`"),
+            PluginCommand::Executable(path) => path.display().to_string(),
+        }
+    }
+
+    fn call(&self, action: Action) -> Result<Artifacts, String> {
+        let request = serde_json::to_vec(&Request {
+            protocol: PROTOCOL,
+            action,
+            target: &self.name,
+            config: self.conf,
+        })
+        .map_err(|err| format!("failed to serialize plugin request: {err}"))?;
+
+        let mut child = crate::log::command(
+            self.command()
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::inherit()),
+        )
+        .spawn()
+        .map_err(|err| format!("failed to run plugin {}: {err}", self.describe()))?;
+
+        // a plugin that doesn't read its request gets a broken pipe, which isn't an error
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&request).ok();
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| format!("failed to run plugin {}: {err}", self.describe()))?;
+
+        let response: Option<Response> = serde_json::from_slice(&output.stdout).ok();
+        if let Some(error) = response
+            .as_ref()
+            .and_then(|response| response.error.as_ref())
+        {
+            return Err(error.clone());
+        }
+        if !output.status.success() {
+            return Err(format!(
+                "plugin {} failed ({})",
+                self.describe(),
+                output.status
+            ));
+        }
+        match response {
+            Some(response) => Ok(response.artifacts),
+            None => Err(format!(
+                "plugin {} printed no valid response, expected {{\"artifacts\": [...]}}",
+                self.describe()
+            )),
+        }
+    }
+}
+
+impl<'a> Generator for PluginGenerator<'a> {
+    fn run(&self) -> Result<Artifacts, ShipError> {
+        let artifacts = self.call(Action::Build)?;
+        // a plugin claiming files it didn't write would end up in the release
+        if let Some(missing) = artifacts.iter().find(|artifact| !artifact.is_file()) {
+            return Err(format!(
+                "plugin {} reported {}, which does not exist",
+                self.describe(),
+                missing.display()
+            )
+            .into());
+        }
+        Ok(artifacts)
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        // plugins that can't tell beforehand are simply always rebuilt
+        self.call(Action::Artifacts).unwrap_or_else(|err| {
+            debug!("{err}");
+            Vec::new()
+        })
+    }
+}
+
+/// The executable `name` on `PATH`, if there is one
+pub fn on_path(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| is_runnable(path))
+}
+
+#[cfg(unix)]
+fn is_runnable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_runnable(path: &Path) -> bool {
+    path.with_extension("exe").is_file()
+}
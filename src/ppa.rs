@@ -0,0 +1,253 @@
+use std::{
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use md5::Md5;
+use sha1::Sha1;
+use sha2::Sha256;
+
+use crate::{
+    atomic,
+    checksum::digest_file,
+    compress::open_decompressed,
+    conf::{ShipConfig, Signing, Target, without_epoch},
+    report::{BuildReport, file_name},
+    signing,
+};
+
+/// Signs the Debian source package of the last build, writes its `_source.changes`, signs
+/// that too and uploads it all with dput to `[publish.ppa].target`, returning the names of
+/// the uploaded files.
+///
+/// The .dsc is signed in place, as Launchpad rejects unsigned ones; the changes entry,
+/// distribution and urgency come from debian/changelog in the debian tarball, so they
+/// always match what was packaged.
+pub fn publish(conf: &ShipConfig) -> Result<Vec<String>, String> {
+    let settings = conf
+        .publish
+        .as_ref()
+        .and_then(|publish| publish.ppa.as_ref())
+        .ok_or("no [publish.ppa] section with the dput target to upload to")?;
+    let signing = conf
+        .signing
+        .as_ref()
+        .ok_or("uploads to a PPA have to be signed, set [signing]")?;
+
+    let report = BuildReport::load(&conf.prog.name)?;
+    let sources: Vec<&Path> = report
+        .artifacts
+        .iter()
+        .filter(|artifact| artifact.target == Target::DebSource)
+        .map(|artifact| artifact.path.as_path())
+        .collect();
+    let find = |suffix: &str| {
+        sources
+            .iter()
+            .find(|path| file_name(path).contains(suffix))
+            .map(|path| path.to_path_buf())
+            .ok_or("the last build has no Debian source package, build the deb-source target first")
+    };
+    let (orig, debian, dsc) = (find(".orig.tar.")?, find(".debian.tar.")?, find(".dsc")?);
+
+    let entry = ChangelogEntry::read(&debian)?;
+    if settings.target.starts_with("ppa:")
+        && matches!(entry.distribution.as_str(), "unstable" | "UNRELEASED")
+    {
+        return Err(format!(
+            "Launchpad only takes uploads for an Ubuntu series, but debian/changelog says `{}`; set [deb].distribution, e.g. noble, and build deb-source again",
+            entry.distribution
+        ));
+    }
+
+    sign_in_place(signing, &dsc)?;
+    let changes = dsc.with_file_name(format!(
+        "{}_{}_source.changes",
+        conf.prog.name,
+        without_epoch(&entry.version)
+    ));
+    let contents = render_changes(conf, &entry, &[dsc.clone(), orig.clone(), debian.clone()])?;
+    atomic::write(&changes, contents)
+        .map_err(|err| format!("failed to write {}: {err}", changes.display()))?;
+    sign_in_place(signing, &changes)?;
+    info!("wrote {}", changes.display());
+
+    let mut dput = Command::new("dput");
+    if let Some(config) = &settings.dput_config {
+        dput.arg("--config").arg(config);
+    }
+    dput.arg(&settings.target).arg(&changes);
+    let status = crate::log::command(&mut dput)
+        .status()
+        .map_err(|err| format!("failed to run dput, is it installed? {err}"))?;
+    if !status.success() {
+        return Err(format!(
+            "dput failed to upload {} to {} ({status})",
+            file_name(&changes),
+            settings.target
+        ));
+    }
+
+    Ok([changes, dsc, orig, debian]
+        .iter()
+        .map(|path| file_name(path))
+        .collect())
+}
+
+// a file signed earlier, by a previous upload attempt, is left as it is
+fn sign_in_place(signing: &Signing, path: &Path) -> Result<(), String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+    if contents.starts_with("-----BEGIN PGP SIGNED MESSAGE-----") {
+        return Ok(());
+    }
+    let signed = atomic::temp_path(path);
+    let result = signing::clear_sign(signing, path, &signed).and_then(|()| {
+        std::fs::rename(&signed, path)
+            .map_err(|err| format!("failed to replace {}: {err}", path.display()))
+    });
+    if result.is_err() {
+        std::fs::remove_file(&signed).ok();
+    }
+    result
+}
+
+/// The newest entry of a debian/changelog
+struct ChangelogEntry {
+    header: String, // `name (version) distribution; urgency=medium`
+    version: String,
+    distribution: String,
+    urgency: String,
+    changes: Vec<String>, // the lines between the header and the ` -- ` trailer
+}
+
+impl ChangelogEntry {
+    fn read(debian_tarball: &Path) -> Result<Self, String> {
+        let read_err = |err| format!("failed to read {}: {err}", debian_tarball.display());
+        let mut archive = tar::Archive::new(open_decompressed(debian_tarball).map_err(read_err)?);
+        let mut changelog = None;
+        for entry in archive.entries().map_err(read_err)? {
+            let mut entry = entry.map_err(read_err)?;
+            if entry.path().map_err(read_err)?.as_ref() == Path::new("debian/changelog") {
+                let mut text = String::new();
+                entry.read_to_string(&mut text).map_err(read_err)?;
+                changelog = Some(text);
+                break;
+            }
+        }
+        let changelog = changelog
+            .ok_or_else(|| format!("{} has no debian/changelog", debian_tarball.display()))?;
+        Self::parse(&changelog).ok_or_else(|| {
+            format!(
+                "can't read the first entry of debian/changelog in {}",
+                debian_tarball.display()
+            )
+        })
+    }
+
+    fn parse(changelog: &str) -> Option<Self> {
+        let mut lines = changelog.lines().skip_while(|line| line.trim().is_empty());
+        let header = lines.next()?.trim_end().to_string();
+        let (_, rest) = header.split_once(" (")?;
+        let (version, rest) = rest.split_once(')')?;
+        let (distributions, options) = rest.split_once(';')?;
+        let urgency = options
+            .split(',')
+            .find_map(|option| option.trim().strip_prefix("urgency="))
+            .unwrap_or("medium");
+
+        let mut changes: Vec<String> = lines
+            .take_while(|line| !line.starts_with(" -- "))
+            .map(|line| line.trim_end().to_string())
+            .skip_while(|line| line.is_empty())
+            .collect();
+        while changes.last().is_some_and(|line| line.is_empty()) {
+            changes.pop();
+        }
+
+        Some(Self {
+            version: version.to_string(),
+            distribution: distributions.split_whitespace().next()?.to_string(),
+            urgency: urgency.to_string(),
+            changes,
+            header,
+        })
+    }
+}
+
+/// A source-only `.changes` listing `files`, as `dpkg-genchanges -S` writes it
+fn render_changes(
+    conf: &ShipConfig,
+    entry: &ChangelogEntry,
+    files: &[PathBuf],
+) -> Result<String, String> {
+    let prog = &conf.prog;
+    let fields = conf.deb_fields();
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value.clone())
+            .unwrap_or_default()
+    };
+    let (section, priority) = (field("Section"), field("Priority"));
+
+    let mut sha1 = String::new();
+    let mut sha256 = String::new();
+    let mut md5 = String::new();
+    for file in files {
+        let read_err = |err| format!("failed to read {}: {err}", file.display());
+        let size = std::fs::metadata(file).map_err(read_err)?.len();
+        let name = file_name(file);
+        sha1.push_str(&format!(
+            " {} {size} {name}\n",
+            digest_file::<Sha1>(file).map_err(read_err)?
+        ));
+        sha256.push_str(&format!(
+            " {} {size} {name}\n",
+            digest_file::<Sha256>(file).map_err(read_err)?
+        ));
+        md5.push_str(&format!(
+            " {} {size} {section} {priority} {name}\n",
+            digest_file::<Md5>(file).map_err(read_err)?
+        ));
+    }
+
+    // continuation lines, with blank ones written as ` .`
+    let mut changes = format!(" {}\n .\n", entry.header);
+    for line in &entry.changes {
+        if line.is_empty() {
+            changes.push_str(" .\n");
+        } else {
+            changes.push_str(&format!(" {line}\n"));
+        }
+    }
+    let synopsis = conf.deb_description();
+    let synopsis = synopsis.lines().next().unwrap_or_default();
+
+    Ok(format!(
+        "Format: 1.8\n\
+         Date: {date}\n\
+         Source: {name}\n\
+         Binary: {name}\n\
+         Architecture: source\n\
+         Version: {version}\n\
+         Distribution: {distribution}\n\
+         Urgency: {urgency}\n\
+         Maintainer: {author}\n\
+         Changed-By: {author}\n\
+         Description:\n \
+         {name} - {synopsis}\n\
+         Changes:\n{changes}\
+         Checksums-Sha1:\n{sha1}\
+         Checksums-Sha256:\n{sha256}\
+         Files:\n{md5}",
+        date = chrono::Utc::now().to_rfc2822(),
+        name = prog.name,
+        version = entry.version,
+        distribution = entry.distribution,
+        urgency = entry.urgency,
+        author = prog.author,
+    ))
+}
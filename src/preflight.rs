@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    cache,
+    conf::{ShipConfig, Target},
+    layout::Layout,
+};
+
+/// Headroom on top of the estimate, for metadata, archive headers and compressor overhead
+const SLACK: u64 = 1 << 20;
+
+/// Fails early if the output or work filesystem can't hold what the targets are about to write.
+///
+/// Every artifact is assumed to be as large as its uncompressed payload, since already
+/// compressed binaries barely shrink. AppImages also stage their AppDir in the work directory.
+pub fn check_disk_space(conf: &ShipConfig) -> Result<(), String> {
+    let (output, staged) = estimate(conf);
+    let out_dir = existing_ancestor(Path::new(&conf.out.bin));
+    let work_dir = existing_ancestor(&cache::work_dir(conf));
+
+    // on one filesystem, both draw from the same free space
+    let checks = if same_filesystem(&out_dir, &work_dir) {
+        vec![(out_dir, output + staged)]
+    } else {
+        vec![(out_dir, output), (work_dir, staged)]
+    };
+
+    for (dir, needed) in checks {
+        let Some(available) = available_space(&dir) else {
+            continue;
+        };
+        if needed > available {
+            return Err(format!(
+                "not enough disk space in {}: the targets need about {}, but only {} is free",
+                dir.display(),
+                human_size(needed),
+                human_size(available)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Estimated bytes written by all targets: to [out].bin, and staged in the work directory
+pub fn estimate(conf: &ShipConfig) -> (u64, u64) {
+    conf.out
+        .targets
+        .iter()
+        .map(|target| {
+            if *target == Target::Appcast {
+                return (0, 0);
+            }
+            // a config the generator rejects is reported by the generator itself
+            let payload = Layout::resolve(&conf.for_target(target))
+                .map(|layout| {
+                    layout
+                        .files
+                        .iter()
+                        .filter_map(|entry| std::fs::metadata(&entry.source).ok())
+                        .map(|metadata| metadata.len())
+                        .sum::<u64>()
+                })
+                .unwrap_or(0);
+            let staged = match target {
+                Target::AppImage => payload,
+                _ => 0,
+            };
+            // xz and gzip add a little when the input doesn't compress
+            (payload + payload / 100 + SLACK, staged)
+        })
+        .fold((0, 0), |(output, staged), (o, s)| (output + o, staged + s))
+}
+
+#[cfg(unix)]
+fn same_filesystem(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => true,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_filesystem(_a: &Path, _b: &Path) -> bool {
+    true
+}
+
+// the output directory may not exist yet, its parent's filesystem is where it will go
+fn existing_ancestor(path: &Path) -> PathBuf {
+    let mut current = if path.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        path.to_path_buf()
+    };
+    while !current.exists() {
+        match current.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => current = parent.to_path_buf(),
+            _ => return PathBuf::from("."),
+        }
+    }
+    current
+}
+
+#[cfg(unix)]
+fn available_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `stat` is a valid, writable statvfs
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return None;
+    }
+    Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+// no portable way without extra dependencies, so the check is skipped
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
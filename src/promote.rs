@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use crate::{
+    appcast, cache, conf::ShipConfig, release::run_per_file, remote, verify::SIGNATURE_EXTENSIONS,
+};
+
+/// Name the update feed of `version` is published under until it's promoted, e.g. `app-1.2-latest.json`
+pub fn staged_name(name: &str, version: &str, feed: &str) -> String {
+    format!("{name}-{version}-{feed}")
+}
+
+/// Makes the staged update feed of `version` the live one and returns the feed's name.
+///
+/// The staged feed is downloaded from the publish URL, checked to announce `version`, and
+/// handed to `[release].promote` under the live name. The feed is the only file that changes,
+/// so clients see either the old release or the new one, and promoting an earlier version
+/// rolls back the same way.
+pub fn promote(conf: &ShipConfig, version: &str) -> Result<String, String> {
+    let Some(cmd) = conf.release.as_ref().and_then(|r| r.promote.as_ref()) else {
+        return Err(
+            "promote needs [release].promote, the command that publishes the live update feed"
+                .to_string(),
+        );
+    };
+    if conf.appcast.is_none() {
+        return Err("promote needs an [appcast] section describing the update feed".to_string());
+    }
+    let Some(base_url) = conf.publish_url() else {
+        return Err(
+            "promote needs [publish].base_url or [appcast].base_url to find the staged feed"
+                .to_string(),
+        );
+    };
+
+    let feed = appcast::feed_file(conf);
+    let staged = staged_name(&conf.prog.name, version, &feed);
+
+    let scratch = cache::work_dir(conf).join("promote");
+    std::fs::remove_dir_all(&scratch).ok();
+    std::fs::create_dir_all(&scratch).map_err(|err| {
+        format!(
+            "failed to create scratch directory {}: {err}",
+            scratch.display()
+        )
+    })?;
+
+    let live = scratch.join(&feed);
+    remote::download(&format!("{base_url}/{staged}"), &live)
+        .map_err(|err| format!("{version} has no staged update feed: {err}"))?;
+    let contents = std::fs::read_to_string(&live)
+        .map_err(|err| format!("failed to read {}: {err}", live.display()))?;
+    if !appcast::feed_announces(&contents, version) {
+        return Err(format!(
+            "the staged feed {staged} does not announce version {version}"
+        ));
+    }
+
+    // a signed feed keeps its signature, published before the feed it belongs to
+    let mut files: Vec<PathBuf> = Vec::new();
+    if conf.release.as_ref().is_some_and(|r| r.sign.is_some()) {
+        for ext in SIGNATURE_EXTENSIONS {
+            let signature = scratch.join(format!("{feed}.{ext}"));
+            if remote::download(&format!("{base_url}/{staged}.{ext}"), &signature).is_ok() {
+                files.push(signature);
+                break;
+            }
+        }
+        if files.is_empty() {
+            return Err(format!("the staged feed {staged} has no signature"));
+        }
+    }
+    files.push(live);
+
+    run_per_file(cmd, &files, &[])?;
+
+    std::fs::remove_dir_all(&scratch).ok();
+    Ok(feed)
+}
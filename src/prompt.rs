@@ -0,0 +1,181 @@
+use std::{
+    collections::HashMap,
+    io::{BufRead, IsTerminal, Write},
+};
+
+use toml::Value;
+
+use crate::{conf::ShipConfig, vars};
+
+/// Targets that can't be built without `[prog].version`
+const NEEDS_VERSION: &[&str] = &["Ebuild", "DebSource", "Xbps"];
+
+/// Whether missing values may be asked for: only with a terminal on both ends and without
+/// `--non-interactive`, so CI fails right away instead of waiting for input
+pub fn can_prompt(non_interactive: bool) -> bool {
+    !non_interactive && std::io::stdin().is_terminal() && std::io::stderr().is_terminal()
+}
+
+/// Asks for the required values a Shipfile leaves out before it's resolved: `[vars].arg`
+/// declarations without a default that weren't passed with `--var`, and `[prog].version`
+/// when a target needs it.
+///
+/// Answers for variables go into `cli`, so every overlay asks at most once.
+pub fn fill_missing(
+    doc: &mut Value,
+    cli: &mut HashMap<String, String>,
+    interactive: bool,
+) -> Result<(), String> {
+    for decl in vars::declared_args(doc) {
+        let name = decl.trim();
+        if name.contains('=') || cli.contains_key(name) {
+            continue;
+        }
+        if !interactive {
+            return Err(format!(
+                "variable `{name}` is declared in [vars].arg but was not passed with --var {name}=..."
+            ));
+        }
+        let value = ask(&format!("value for `{name}`"))?;
+        cli.insert(name.to_string(), value);
+    }
+
+    let Some(needed_by) = needs_version(doc) else {
+        return Ok(());
+    };
+    if !interactive {
+        return Err(format!(
+            "{needed_by} requires [prog].version, set it in the Shipfile"
+        ));
+    }
+    let version = ask("version")?;
+    if let Some(prog) = doc.get_mut("prog").and_then(Value::as_table_mut) {
+        prog.insert("version".to_string(), Value::String(version));
+    }
+
+    Ok(())
+}
+
+// what needs the version, if it's missing
+fn needs_version(doc: &Value) -> Option<String> {
+    if doc
+        .get("prog")
+        .and_then(|prog| prog.get("version"))
+        .is_some()
+    {
+        return None;
+    }
+
+    let targets = doc
+        .get("out")
+        .and_then(|out| out.get("targets"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str);
+    for target in targets {
+        if NEEDS_VERSION.contains(&target) {
+            return Some(format!("the {target} target"));
+        }
+    }
+
+    let stamp = doc
+        .get("build")
+        .and_then(|build| build.get("stamp_version"))
+        .and_then(Value::as_bool);
+    (stamp == Some(true)).then(|| "[build].stamp_version".to_string())
+}
+
+/// The signing passphrase for `[release].sign`, from the environment variable named by
+/// `[release].passphrase_env`, asked for if it's unset
+pub fn passphrase(conf: &ShipConfig, interactive: bool) -> Result<Option<String>, String> {
+    let Some(env) = conf
+        .release
+        .as_ref()
+        .filter(|release| release.sign.is_some())
+        .and_then(|release| release.passphrase_env.as_ref())
+    else {
+        return Ok(None);
+    };
+    if let Ok(passphrase) = std::env::var(env) {
+        return Ok(Some(passphrase));
+    }
+
+    if !interactive {
+        return Err(format!(
+            "the signing passphrase is missing, set ${env} (from [release].passphrase_env)"
+        ));
+    }
+    ask_secret(&format!("signing passphrase for {}", conf.prog.name)).map(Some)
+}
+
+/// Asks on the terminal until a non-empty answer is given
+pub fn ask(question: &str) -> Result<String, String> {
+    loop {
+        eprint!("{question}: ");
+        std::io::stderr().flush().ok();
+
+        let mut answer = String::new();
+        let read = std::io::stdin()
+            .lock()
+            .read_line(&mut answer)
+            .map_err(|err| format!("failed to read {question}: {err}"))?;
+        if read == 0 {
+            return Err(format!("no {question} given"));
+        }
+
+        let answer = answer.trim();
+        if !answer.is_empty() {
+            return Ok(answer.to_string());
+        }
+    }
+}
+
+/// Like [ask], but an empty answer takes `default` when there is one
+pub fn ask_or(question: &str, default: Option<&str>) -> Result<String, String> {
+    let Some(default) = default else {
+        return ask(question);
+    };
+    eprint!("{question} [{default}]: ");
+    std::io::stderr().flush().ok();
+
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|err| format!("failed to read {question}: {err}"))?;
+    let answer = answer.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// Like [ask], without echoing the answer
+#[cfg(unix)]
+pub fn ask_secret(question: &str) -> Result<String, String> {
+    use std::os::fd::AsRawFd;
+
+    let fd = std::io::stdin().as_raw_fd();
+    // SAFETY: termios is plain data, tcgetattr fills it in for a terminal
+    let mut termios = unsafe { std::mem::zeroed::<libc::termios>() };
+    if unsafe { libc::tcgetattr(fd, &mut termios) } != 0 {
+        return ask(question);
+    }
+
+    let original = termios;
+    termios.c_lflag &= !libc::ECHO;
+    // SAFETY: termios came from tcgetattr on the same fd, with only ECHO cleared
+    if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &termios) } != 0 {
+        return ask(question);
+    }
+    let answer = ask(question);
+    // SAFETY: restores the settings tcgetattr returned for this fd
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    // the newline the user typed wasn't echoed either
+    eprintln!();
+
+    answer
+}
+
+#[cfg(not(unix))]
+pub fn ask_secret(question: &str) -> Result<String, String> {
+    ask(question)
+}
@@ -0,0 +1,443 @@
+use std::{path::PathBuf, process::Stdio};
+
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    appcast, atomic,
+    checksum::to_hex,
+    conf::{Release, ShipConfig},
+    license::License,
+    lock::out_dir,
+    promote,
+    report::{BuildReport, ReportArtifact, file_name},
+    sums, upload,
+    verify::SIGNATURE_EXTENSIONS,
+};
+
+pub enum Status {
+    Done(String),
+    Skipped(String),
+    Failed(String),
+}
+
+/// What `ship release-all` did for one variant, stage by stage
+pub struct Summary {
+    pub name: String,
+    pub version: Option<String>,
+    pub stages: Vec<(&'static str, Status)>,
+    pub artifacts: Vec<ReportArtifact>,
+    pub released: Vec<PathBuf>, // everything handed to the publish command, in order
+}
+
+impl Summary {
+    pub fn failed(&self) -> bool {
+        self.stages
+            .iter()
+            .any(|(_, status)| matches!(status, Status::Failed(_)))
+    }
+
+    pub fn print(&self) {
+        match &self.version {
+            Some(version) => output!("release of {} {version}:", self.name),
+            None => output!("release of {}:", self.name),
+        }
+        for (stage, status) in &self.stages {
+            let (label, detail) = match status {
+                Status::Done(detail) => ("done", detail),
+                Status::Skipped(detail) => ("skipped", detail),
+                Status::Failed(detail) => ("FAILED", detail),
+            };
+            output!("  {stage:<10} {label:<8} {detail}");
+        }
+
+        if !self.artifacts.is_empty() {
+            output!("artifacts:");
+            for artifact in &self.artifacts {
+                output!(
+                    "  {:<10} {} ({} bytes, sha256 {})",
+                    format!("{:?}", artifact.target),
+                    artifact.file,
+                    artifact.size,
+                    artifact.sha256
+                );
+            }
+        }
+        if !self.released.is_empty() {
+            output!("released files:");
+            for file in &self.released {
+                output!("  {}", file.display());
+            }
+        }
+    }
+}
+
+/// Runs the stages after packaging: checksums, signing, SBOM and publishing.
+///
+/// Builds on the report of the packaging run that just finished, and stops at the first failing stage.
+/// The passphrase is handed to the sign command in the variable named by `[release].passphrase_env`.
+pub fn run(conf: &ShipConfig, built: bool, passphrase: Option<&str>) -> Summary {
+    let release = conf.release.clone().unwrap_or_default();
+
+    let mut summary = Summary {
+        name: conf.prog.name.clone(),
+        version: conf.prog.version.clone(),
+        stages: Vec::new(),
+        artifacts: Vec::new(),
+        released: Vec::new(),
+    };
+
+    summary.stages.push((
+        "build",
+        if built {
+            Status::Done("ran [build]".to_string())
+        } else {
+            Status::Skipped("no [build].cmd or steps".to_string())
+        },
+    ));
+
+    let report = match BuildReport::load(&conf.prog.name) {
+        Ok(report) if !report.artifacts.is_empty() => report,
+        Ok(_) => {
+            summary.stages.push((
+                "package",
+                Status::Failed("no artifacts were produced".to_string()),
+            ));
+            return summary;
+        }
+        Err(err) => {
+            summary.stages.push(("package", Status::Failed(err)));
+            return summary;
+        }
+    };
+    summary.stages.push((
+        "package",
+        Status::Done(format!("{} artifacts", report.artifacts.len())),
+    ));
+
+    let mut files: Vec<PathBuf> = report
+        .artifacts
+        .iter()
+        .map(|artifact| artifact.path.clone())
+        .collect();
+    summary.artifacts = report.artifacts;
+
+    let context = Context {
+        conf,
+        release: &release,
+        artifacts: &summary.artifacts,
+        dir: out_dir(&conf.out.bin),
+        passphrase,
+    };
+    // the feed is staged first, so it's checksummed and signed under the name it's published as
+    let status = staging(&context, &mut files).unwrap_or_else(Status::Failed);
+    let failed = matches!(status, Status::Failed(_));
+    summary.stages.push(("staging", status));
+    if failed {
+        return summary;
+    }
+
+    let mut stages = Vec::new();
+    for (stage, run_stage) in [
+        ("checksums", checksums as Stage),
+        ("sign", sign),
+        ("sbom", sbom),
+    ] {
+        let status = run_stage(&context, &mut files).unwrap_or_else(Status::Failed);
+        let failed = matches!(status, Status::Failed(_));
+        stages.push((stage, status));
+        if failed {
+            summary.stages.extend(stages);
+            return summary;
+        }
+    }
+    summary.stages.extend(stages);
+
+    let destinations = upload::destinations(conf);
+    let status = if release.publish.is_none() && destinations.is_empty() {
+        Status::Skipped("no [release].publish command, [publish].s3 or [publish].http".to_string())
+    } else {
+        publish(conf, &release, &destinations, &files).map_or_else(Status::Failed, |()| {
+            summary.released = files.clone();
+            Status::Done(format!("{} files", files.len()))
+        })
+    };
+    summary.stages.push(("publish", status));
+
+    summary
+}
+
+// the [release].publish command, then each [publish] upload
+fn publish(
+    conf: &ShipConfig,
+    release: &Release,
+    destinations: &[upload::Destination],
+    files: &[PathBuf],
+) -> Result<(), String> {
+    if let Some(cmd) = &release.publish {
+        run_per_file(cmd, files, &[])?;
+    }
+    for &destination in destinations {
+        upload::upload(conf, destination, files)?;
+    }
+    Ok(())
+}
+
+/// What the stages between packaging and publishing work from
+struct Context<'a> {
+    conf: &'a ShipConfig,
+    release: &'a Release,
+    artifacts: &'a [ReportArtifact],
+    dir: PathBuf, // where the release files are written, next to the artifacts
+    passphrase: Option<&'a str>,
+}
+
+// every stage sees the files released so far and may add its own
+type Stage = fn(&Context, &mut Vec<PathBuf>) -> Result<Status, String>;
+
+/// File name prefix shared by the release files: `<name>-<version>`
+fn base_name(conf: &ShipConfig) -> String {
+    match &conf.prog.version {
+        Some(version) => format!("{}-{version}", conf.prog.name),
+        None => conf.prog.name.clone(),
+    }
+}
+
+fn checksums(context: &Context, files: &mut Vec<PathBuf>) -> Result<Status, String> {
+    if context.release.checksums == Some(false) {
+        return Ok(Status::Skipped("[release].checksums is false".to_string()));
+    }
+
+    // the files start with the artifacts, in order, with the feed swapped for its staged copy
+    let listed: Vec<ReportArtifact> = context
+        .artifacts
+        .iter()
+        .zip(files.iter())
+        .map(|(artifact, file)| ReportArtifact {
+            path: file.clone(),
+            file: file_name(file),
+            ..artifact.clone()
+        })
+        .collect();
+    let path = context
+        .dir
+        .join(format!("{}-SHA256SUMS", base_name(context.conf)));
+    atomic::write(&path, sums::render_sums(&context.dir, &listed))
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+
+    files.push(path.clone());
+    Ok(Status::Done(path.display().to_string()))
+}
+
+fn sign(context: &Context, files: &mut Vec<PathBuf>) -> Result<Status, String> {
+    let Some(cmd) = &context.release.sign else {
+        return Ok(Status::Skipped("no [release].sign command".to_string()));
+    };
+    let env: Vec<(&str, &str)> = context
+        .release
+        .passphrase_env
+        .as_deref()
+        .zip(context.passphrase)
+        .into_iter()
+        .collect();
+    run_per_file(cmd, files, &env)?;
+
+    // the command decides on the format, whatever detached signature it left is released too
+    let signatures: Vec<PathBuf> = files
+        .iter()
+        .flat_map(|file| {
+            SIGNATURE_EXTENSIONS.iter().map(move |ext| {
+                let mut name = file.clone().into_os_string();
+                name.push(format!(".{ext}"));
+                PathBuf::from(name)
+            })
+        })
+        .filter(|signature| signature.is_file())
+        .collect();
+    if signatures.is_empty() {
+        return Err(format!(
+            "[release].sign left no .{} file next to the artifacts",
+            SIGNATURE_EXTENSIONS.join(", .")
+        ));
+    }
+
+    let count = signatures.len();
+    files.extend(signatures);
+    Ok(Status::Done(format!("{count} signatures")))
+}
+
+fn sbom(context: &Context, files: &mut Vec<PathBuf>) -> Result<Status, String> {
+    if context.release.sbom == Some(false) {
+        return Ok(Status::Skipped("[release].sbom is false".to_string()));
+    }
+
+    let path = context
+        .dir
+        .join(format!("{}.spdx.json", base_name(context.conf)));
+    let document = serde_json::to_vec_pretty(&spdx(context.conf, context.artifacts))
+        .map_err(|err| format!("failed to serialize SBOM: {err}"))?;
+    atomic::write(&path, document)
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+
+    files.push(path.clone());
+    Ok(Status::Done(path.display().to_string()))
+}
+
+/// An SPDX 2.3 document: the program, every artifact as a package of it, and the
+/// dependencies each artifact declares
+fn spdx(conf: &ShipConfig, artifacts: &[ReportArtifact]) -> serde_json::Value {
+    let base_name = base_name(conf);
+    let download = |file: &str| match conf.publish_url() {
+        Some(base_url) => format!("{base_url}/{file}"),
+        None => "NOASSERTION".to_string(),
+    };
+
+    // the same artifacts always get the same namespace
+    let mut hasher = Sha256::new();
+    for artifact in artifacts {
+        hasher.update(artifact.sha256.as_bytes());
+    }
+    let namespace = format!(
+        "https://spdx.org/spdxdocs/{base_name}-{}",
+        to_hex(&hasher.finalize())
+    );
+
+    let declared = License::of(conf)
+        .ok()
+        .flatten()
+        .and_then(|license| license.spdx())
+        .unwrap_or("NOASSERTION");
+
+    let mut packages = vec![json!({
+        "name": conf.prog.name,
+        "SPDXID": "SPDXRef-Package",
+        "versionInfo": conf.prog.version,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": declared,
+        "copyrightText": "NOASSERTION",
+        "description": conf.prog.description,
+    })];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": "SPDXRef-Package",
+    })];
+    let mut dependencies: Vec<String> = Vec::new();
+
+    for (index, artifact) in artifacts.iter().enumerate() {
+        let id = format!("SPDXRef-Artifact-{index}");
+        packages.push(json!({
+            "name": artifact.file,
+            "SPDXID": id,
+            "versionInfo": conf.prog.version,
+            "packageFileName": artifact.file,
+            "downloadLocation": download(&artifact.file),
+            "filesAnalyzed": false,
+            "checksums": [{ "algorithm": "SHA256", "checksumValue": artifact.sha256 }],
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": declared,
+            "copyrightText": "NOASSERTION",
+        }));
+        relationships.push(json!({
+            "spdxElementId": id,
+            "relationshipType": "PACKAGE_OF",
+            "relatedSpdxElement": "SPDXRef-Package",
+        }));
+
+        for depend in conf.depends(&artifact.target) {
+            let index = match dependencies.iter().position(|d| d == depend) {
+                Some(index) => index,
+                None => {
+                    dependencies.push(depend.clone());
+                    packages.push(json!({
+                        "name": depend,
+                        "SPDXID": format!("SPDXRef-Dependency-{}", dependencies.len() - 1),
+                        "downloadLocation": "NOASSERTION",
+                        "filesAnalyzed": false,
+                        "licenseConcluded": "NOASSERTION",
+                        "licenseDeclared": "NOASSERTION",
+                        "copyrightText": "NOASSERTION",
+                    }));
+                    dependencies.len() - 1
+                }
+            };
+            relationships.push(json!({
+                "spdxElementId": id,
+                "relationshipType": "DEPENDS_ON",
+                "relatedSpdxElement": format!("SPDXRef-Dependency-{index}"),
+            }));
+        }
+    }
+
+    // SPDX has no null, unset optional fields like versionInfo are left out
+    for package in &mut packages {
+        if let Some(fields) = package.as_object_mut() {
+            fields.retain(|_, value| !value.is_null());
+        }
+    }
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": base_name,
+        "documentNamespace": namespace,
+        "creationInfo": {
+            "created": chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "creators": [format!("Tool: ship-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "relationships": relationships,
+    })
+}
+
+// with [release].promote, the update feed is published under a versioned name and left for
+// `ship promote` to make live, so clients never see a feed for a half-uploaded release
+fn staging(context: &Context, files: &mut [PathBuf]) -> Result<Status, String> {
+    if context.release.promote.is_none() {
+        return Ok(Status::Skipped("no [release].promote command".to_string()));
+    }
+    let Some(version) = &context.conf.prog.version else {
+        return Err("[release].promote requires [prog].version".to_string());
+    };
+    let feed = appcast::feed_file(context.conf);
+    let Some(file) = files.iter_mut().find(|file| file_name(file) == feed) else {
+        return Err(
+            "[release].promote needs the Appcast target to stage an update feed".to_string(),
+        );
+    };
+
+    let staged = promote::staged_name(&context.conf.prog.name, version, &feed);
+    let path = context.dir.join(&staged);
+    std::fs::copy(&*file, &path)
+        .map_err(|err| format!("failed to stage {}: {err}", file.display()))?;
+    *file = path;
+
+    Ok(Status::Done(format!(
+        "{feed} is published as {staged}, make it live with `ship promote {version}`"
+    )))
+}
+
+/// Runs `cmd` once per file with `{artifact}` replaced by its path, stopping at the first failure
+pub(crate) fn run_per_file(
+    cmd: &str,
+    files: &[PathBuf],
+    env: &[(&str, &str)],
+) -> Result<(), String> {
+    for file in files {
+        let cmd_str = cmd.replace("{artifact}", &crate::shell_quote(&file.to_string_lossy()));
+        let status = crate::shell(&cmd_str)
+            .envs(env.iter().copied())
+            .stdin(Stdio::inherit())
+            .stdout(crate::log::child_stdout())
+            .stderr(Stdio::inherit())
+            .status()
+            .map_err(|err| format!("failed to run `{cmd_str}`: {err}"))?;
+        if !status.success() {
+            return Err(format!("`{cmd_str}` failed ({status})"));
+        }
+    }
+    Ok(())
+}
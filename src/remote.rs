@@ -0,0 +1,86 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use sha2::Sha256;
+
+use crate::{cache::cache_dir, checksum::digest_file, conf::Remote};
+
+/// Where a remote file lives once downloaded and verified
+pub fn cached_path(remote: &Remote) -> PathBuf {
+    cache_dir()
+        .join("downloads")
+        .join(remote.sha256.to_lowercase())
+}
+
+/// Downloads every remote file that isn't already cached and checks its sha256.
+pub fn fetch_all(remotes: &[Remote]) -> Result<(), String> {
+    for remote in remotes {
+        fetch(remote)?;
+    }
+    Ok(())
+}
+
+fn fetch(remote: &Remote) -> Result<(), String> {
+    let path = cached_path(remote);
+    let expected = remote.sha256.to_lowercase();
+
+    if path.is_file() && sha256_of(&path)? == expected {
+        return Ok(());
+    }
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| {
+            format!(
+                "failed to create cache directory {}: {err}",
+                parent.display()
+            )
+        })?;
+    }
+
+    info!("downloading {}...", remote.url);
+
+    let partial = path.with_extension("part");
+    download(&remote.url, &partial)?;
+
+    let actual = sha256_of(&partial)?;
+    if actual != expected {
+        std::fs::remove_file(&partial).ok();
+        return Err(format!(
+            "checksum mismatch for {}: expected sha256 {expected}, got {actual}",
+            remote.url
+        ));
+    }
+
+    std::fs::rename(&partial, &path)
+        .map_err(|err| format!("failed to move download into {}: {err}", path.display()))
+}
+
+/// Downloads `url` to `dest` with curl, removing `dest` again if the transfer fails
+pub fn download(url: &str, dest: &Path) -> Result<(), String> {
+    let status = crate::log::command(
+        Command::new("curl")
+            .arg("--fail")
+            .arg("--silent")
+            .arg("--show-error")
+            .arg("--location")
+            .arg("--output")
+            .arg(dest)
+            .arg(url)
+            .stdin(Stdio::null()),
+    )
+    .status()
+    .map_err(|err| format!("failed to run curl to download {url}: {err}"))?;
+
+    if !status.success() {
+        std::fs::remove_file(dest).ok();
+        return Err(format!("download of {url} failed ({status})"));
+    }
+
+    Ok(())
+}
+
+fn sha256_of(path: &Path) -> Result<String, String> {
+    digest_file::<Sha256>(path).map_err(|err| format!("failed to hash {}: {err}", path.display()))
+}
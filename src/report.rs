@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+    cache,
+    checksum::digest_file,
+    conf::{ShipConfig, Target},
+    notice::Notice,
+    sums::{MANIFEST_FILE, SUMS_FILE},
+    verify::SIGNATURE_EXTENSIONS,
+};
+
+/// Files written next to an artifact that are released along with it
+const COMPANION_EXTENSIONS: &[&str] = &["spdx.json", "cdx.json", "intoto.bundle"];
+
+/// What the last build produced, kept so published copies can be checked against it later.
+/// Every architecture of a `[matrix]` build goes into the one report of its program.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BuildReport {
+    pub name: String,
+    pub version: Option<String>,
+    pub artifacts: Vec<ReportArtifact>,
+    #[serde(default)]
+    pub notices: Vec<Notice>, // defaults ship filled in for settings the Shipfile left out
+    #[serde(default)]
+    pub targets: Vec<TargetRun>, // what happened to each target, in build order
+}
+
+/// How a target's artifacts came to be in the last build
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TargetRun {
+    pub target: Target,
+    pub status: TargetStatus,
+    pub duration_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>, // why the target failed
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TargetStatus {
+    Built,
+    UpToDate, // its inputs and artifacts were unchanged
+    Restored, // copied from the build cache
+    Failed,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReportArtifact {
+    pub target: Target,
+    pub path: PathBuf,
+    pub file: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl ReportArtifact {
+    /// Describes the artifact at `path`, as returned by [crate::gen_::Generator::run]
+    pub fn new(target: &Target, path: &Path) -> std::io::Result<Self> {
+        Ok(Self {
+            target: target.clone(),
+            path: path.to_path_buf(),
+            file: file_name(path),
+            size: std::fs::metadata(path)?.len(),
+            sha256: digest_file::<Sha256>(path)?,
+        })
+    }
+}
+
+impl BuildReport {
+    pub fn new(
+        conf: &ShipConfig,
+        built: &[(Target, PathBuf)],
+        targets: Vec<TargetRun>,
+        notices: Vec<Notice>,
+    ) -> std::io::Result<Self> {
+        let artifacts = built
+            .iter()
+            .map(|(target, path)| ReportArtifact::new(target, path))
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self {
+            name: conf.prog.name.clone(),
+            version: conf.prog.version.clone(),
+            artifacts,
+            notices,
+            targets,
+        })
+    }
+
+    fn path(name: &str) -> PathBuf {
+        cache::report_dir().join(format!("{name}.json"))
+    }
+
+    /// The report of the last build of `name`, if there was one
+    pub fn load(name: &str) -> Result<Self, String> {
+        let path = Self::path(name);
+        let contents = std::fs::read(&path).map_err(|err| {
+            format!(
+                "no build report at {} ({err}), build the artifacts first",
+                path.display()
+            )
+        })?;
+        serde_json::from_slice(&contents)
+            .map_err(|err| format!("failed to parse build report {}: {err}", path.display()))
+    }
+
+    /// What a release of this build uploads: the artifacts with whatever signatures and
+    /// SBOMs sit next to them, then the checksums of each output directory
+    pub fn release_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        let mut dirs = Vec::new();
+        for artifact in &self.artifacts {
+            files.push(artifact.path.clone());
+            for ext in SIGNATURE_EXTENSIONS.iter().chain(COMPANION_EXTENSIONS) {
+                let mut companion = artifact.path.clone().into_os_string();
+                companion.push(format!(".{ext}"));
+                let companion = PathBuf::from(companion);
+                if companion.is_file() {
+                    files.push(companion);
+                }
+            }
+            let dir = artifact
+                .path
+                .parent()
+                .unwrap_or(Path::new(""))
+                .to_path_buf();
+            if !dirs.contains(&dir) {
+                dirs.push(dir);
+            }
+        }
+
+        let sums_signature = format!("{SUMS_FILE}.asc");
+        for dir in dirs {
+            for name in [SUMS_FILE, &sums_signature, MANIFEST_FILE] {
+                let path = dir.join(name);
+                if path.is_file() {
+                    files.push(path);
+                }
+            }
+        }
+        files
+    }
+
+    /// Adds what `other`, another variant of the same program, built to this report
+    pub fn merge(&mut self, other: BuildReport) {
+        self.artifacts.extend(other.artifacts);
+        self.notices.extend(other.notices);
+        self.targets.extend(other.targets);
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = Self::path(&self.name);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+pub fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
@@ -0,0 +1,371 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use serde_json::{Value, json};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    atomic,
+    checksum::{HashStore, digest_file, to_hex},
+    conf::{SbomFormat, ShipConfig},
+    layout::Layout,
+    report::ReportArtifact,
+};
+
+/// A crate the packaged program was built from, as locked in Cargo.lock
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub name: String,
+    pub version: String,
+    pub sha256: Option<String>, // registry crates only
+}
+
+impl Dependency {
+    pub fn purl(&self) -> String {
+        // build metadata like `1.6.2+zstd.1.5.1` has to be percent-encoded
+        format!(
+            "pkg:cargo/{}@{}",
+            self.name,
+            self.version.replace('+', "%2B")
+        )
+    }
+}
+
+/// What the artifacts were built with, as far as ship can tell from the project
+#[derive(Debug, Default)]
+pub struct BuildInputs {
+    pub toolchain: Vec<(String, String)>, // name and version, e.g. ("rustc", "1.85.0")
+    pub dependencies: Vec<Dependency>,
+}
+
+impl BuildInputs {
+    /// Reads the locked dependencies and the compiler of a Cargo project in the current
+    /// directory; other projects get neither
+    pub fn detect() -> Self {
+        let Ok(lock) = std::fs::read_to_string("Cargo.lock") else {
+            return Self::default();
+        };
+        Self {
+            toolchain: rustc_version()
+                .map(|version| vec![("rustc".to_string(), version)])
+                .unwrap_or_default(),
+            dependencies: cargo_lock(&lock),
+        }
+    }
+}
+
+// only crates from a registry or git, path dependencies are part of the project itself
+fn cargo_lock(lock: &str) -> Vec<Dependency> {
+    let Ok(lock) = toml::from_str::<toml::Table>(lock) else {
+        return Vec::new();
+    };
+    let packages = lock
+        .get("package")
+        .and_then(|packages| packages.as_array())
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    packages
+        .iter()
+        .filter(|package| package.get("source").is_some())
+        .filter_map(|package| {
+            let text = |key: &str| package.get(key)?.as_str().map(String::from);
+            Some(Dependency {
+                name: text("name")?,
+                version: text("version")?,
+                sha256: text("checksum"),
+            })
+        })
+        .collect()
+}
+
+// `rustc 1.85.0 (4d91de4e4 2025-02-17)`
+fn rustc_version() -> Option<String> {
+    let output = crate::log::command(Command::new("rustc").arg("--version").stdin(Stdio::null()))
+        .output()
+        .ok()?;
+    let stdout = String::from_utf8(output.stdout).ok()?;
+    Some(stdout.split_whitespace().nth(1)?.to_string())
+}
+
+/// A file the artifact installs, with the checksums both formats ask for
+struct PackagedFile {
+    dest: String,
+    sha256: String,
+    sha1: String,
+}
+
+fn packaged_files(conf: &ShipConfig, hashes: &HashStore) -> Result<Vec<PackagedFile>, String> {
+    let layout = Layout::resolve(conf)?;
+    layout
+        .files
+        .iter()
+        .map(|entry| {
+            let read = |err| format!("failed to hash {}: {err}", entry.source.display());
+            Ok(PackagedFile {
+                dest: entry.dest.clone(),
+                sha256: hashes.sha256(&entry.source).map_err(read)?,
+                sha1: digest_file::<Sha1>(&entry.source).map_err(read)?,
+            })
+        })
+        .collect()
+}
+
+/// Writes an SBOM in `format` next to `artifact`, as `<artifact>.spdx.json` or
+/// `<artifact>.cdx.json`, and returns where
+pub fn write(
+    conf: &ShipConfig,
+    format: SbomFormat,
+    artifact: &ReportArtifact,
+    inputs: &BuildInputs,
+    hashes: &HashStore,
+) -> Result<PathBuf, String> {
+    let files = packaged_files(conf, hashes)?;
+    let (document, extension) = match format {
+        SbomFormat::Spdx => (spdx(conf, artifact, &files, inputs), "spdx.json"),
+        SbomFormat::CycloneDx => (cyclonedx(conf, artifact, &files, inputs), "cdx.json"),
+    };
+
+    let mut path = artifact.path.clone().into_os_string();
+    path.push(format!(".{extension}"));
+    let path = PathBuf::from(path);
+    let json = serde_json::to_vec_pretty(&document)
+        .map_err(|err| format!("failed to serialize SBOM: {err}"))?;
+    atomic::write(&path, json)
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+fn created() -> String {
+    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// An SPDX 2.3 document for one artifact: the files it installs, the crates and
+/// declared packages it depends on, and the compiler that built it
+fn spdx(
+    conf: &ShipConfig,
+    artifact: &ReportArtifact,
+    files: &[PackagedFile],
+    inputs: &BuildInputs,
+) -> Value {
+    let mut packages = vec![json!({
+        "name": artifact.file,
+        "SPDXID": "SPDXRef-Artifact",
+        "versionInfo": conf.prog.version,
+        "packageFileName": artifact.file,
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": true,
+        "packageVerificationCode": { "packageVerificationCodeValue": verification_code(files) },
+        "checksums": [{ "algorithm": "SHA256", "checksumValue": artifact.sha256 }],
+        "licenseConcluded": "NOASSERTION",
+        "licenseDeclared": "NOASSERTION",
+        "copyrightText": "NOASSERTION",
+    })];
+    let mut relationships = vec![json!({
+        "spdxElementId": "SPDXRef-DOCUMENT",
+        "relationshipType": "DESCRIBES",
+        "relatedSpdxElement": "SPDXRef-Artifact",
+    })];
+    let relate = |from: &str, kind: &str, to: &str| {
+        json!({
+            "spdxElementId": from,
+            "relationshipType": kind,
+            "relatedSpdxElement": to,
+        })
+    };
+
+    let spdx_files: Vec<Value> = files
+        .iter()
+        .enumerate()
+        .map(|(index, file)| {
+            let id = format!("SPDXRef-File-{index}");
+            relationships.push(relate("SPDXRef-Artifact", "CONTAINS", &id));
+            json!({
+                "fileName": format!(".{}", file.dest),
+                "SPDXID": id,
+                "checksums": [
+                    { "algorithm": "SHA1", "checksumValue": file.sha1 },
+                    { "algorithm": "SHA256", "checksumValue": file.sha256 },
+                ],
+                "licenseConcluded": "NOASSERTION",
+                "copyrightText": "NOASSERTION",
+            })
+        })
+        .collect();
+
+    for (index, dependency) in inputs.dependencies.iter().enumerate() {
+        let id = format!("SPDXRef-Crate-{index}");
+        let mut package = json!({
+            "name": dependency.name,
+            "SPDXID": id,
+            "versionInfo": dependency.version,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+            "externalRefs": [{
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": dependency.purl(),
+            }],
+        });
+        if let Some(sha256) = &dependency.sha256 {
+            package["checksums"] = json!([{ "algorithm": "SHA256", "checksumValue": sha256 }]);
+        }
+        packages.push(package);
+        relationships.push(relate("SPDXRef-Artifact", "DEPENDS_ON", &id));
+    }
+
+    for (index, depend) in conf.depends(&artifact.target).iter().enumerate() {
+        let id = format!("SPDXRef-Declared-{index}");
+        packages.push(json!({
+            "name": depend,
+            "SPDXID": id,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+        }));
+        relationships.push(relate("SPDXRef-Artifact", "DEPENDS_ON", &id));
+    }
+
+    for (index, (name, version)) in inputs.toolchain.iter().enumerate() {
+        let id = format!("SPDXRef-Tool-{index}");
+        packages.push(json!({
+            "name": name,
+            "SPDXID": id,
+            "versionInfo": version,
+            "downloadLocation": "NOASSERTION",
+            "filesAnalyzed": false,
+            "licenseConcluded": "NOASSERTION",
+            "licenseDeclared": "NOASSERTION",
+            "copyrightText": "NOASSERTION",
+        }));
+        relationships.push(relate(&id, "BUILD_TOOL_OF", "SPDXRef-Artifact"));
+    }
+
+    // SPDX has no null, unset optional fields like versionInfo are left out
+    for package in &mut packages {
+        if let Some(fields) = package.as_object_mut() {
+            fields.retain(|_, value| !value.is_null());
+        }
+    }
+
+    // the same artifact always gets the same namespace
+    let namespace = format!(
+        "https://spdx.org/spdxdocs/{}-{}",
+        artifact.file, artifact.sha256
+    );
+
+    json!({
+        "spdxVersion": "SPDX-2.3",
+        "dataLicense": "CC0-1.0",
+        "SPDXID": "SPDXRef-DOCUMENT",
+        "name": artifact.file,
+        "documentNamespace": namespace,
+        "creationInfo": {
+            "created": created(),
+            "creators": [format!("Tool: ship-{}", env!("CARGO_PKG_VERSION"))],
+        },
+        "packages": packages,
+        "files": spdx_files,
+        "relationships": relationships,
+    })
+}
+
+// SPDX 2.3 section 7.9: the sha1 of the sorted sha1s of every file
+fn verification_code(files: &[PackagedFile]) -> String {
+    let mut sha1s: Vec<&str> = files.iter().map(|file| file.sha1.as_str()).collect();
+    sha1s.sort_unstable();
+    to_hex(&Sha1::digest(sha1s.concat().as_bytes()))
+}
+
+/// A CycloneDX 1.5 BOM for one artifact, with the same contents as the SPDX document
+fn cyclonedx(
+    conf: &ShipConfig,
+    artifact: &ReportArtifact,
+    files: &[PackagedFile],
+    inputs: &BuildInputs,
+) -> Value {
+    let mut tools = vec![json!({
+        "type": "application",
+        "name": "ship",
+        "version": env!("CARGO_PKG_VERSION"),
+    })];
+    for (name, version) in &inputs.toolchain {
+        tools.push(json!({ "type": "application", "name": name, "version": version }));
+    }
+
+    let mut components: Vec<Value> = files
+        .iter()
+        .map(|file| {
+            json!({
+                "type": "file",
+                "name": file.dest,
+                "hashes": [
+                    { "alg": "SHA-1", "content": file.sha1 },
+                    { "alg": "SHA-256", "content": file.sha256 },
+                ],
+            })
+        })
+        .collect();
+    let mut depends_on = Vec::new();
+    for dependency in &inputs.dependencies {
+        let mut component = json!({
+            "type": "library",
+            "bom-ref": dependency.purl(),
+            "name": dependency.name,
+            "version": dependency.version,
+            "purl": dependency.purl(),
+        });
+        if let Some(sha256) = &dependency.sha256 {
+            component["hashes"] = json!([{ "alg": "SHA-256", "content": sha256 }]);
+        }
+        components.push(component);
+        depends_on.push(dependency.purl());
+    }
+    for depend in conf.depends(&artifact.target) {
+        components.push(json!({ "type": "library", "bom-ref": depend, "name": depend }));
+        depends_on.push(depend.clone());
+    }
+
+    let mut component = json!({
+        "type": "application",
+        "bom-ref": artifact.file,
+        "name": conf.prog.name,
+        "hashes": [{ "alg": "SHA-256", "content": artifact.sha256 }],
+    });
+    if let Some(version) = &conf.prog.version {
+        component["version"] = json!(version);
+    }
+
+    json!({
+        "bomFormat": "CycloneDX",
+        "specVersion": "1.5",
+        "serialNumber": serial_number(&artifact.sha256),
+        "version": 1,
+        "metadata": {
+            "timestamp": created(),
+            "tools": { "components": tools },
+            "component": component,
+        },
+        "components": components,
+        "dependencies": [{ "ref": artifact.file, "dependsOn": depends_on }],
+    })
+}
+
+// a UUID taken from the artifact's checksum, so rebuilding the same artifact keeps it
+fn serial_number(sha256: &str) -> String {
+    let hex = format!("{sha256:0<32}");
+    format!(
+        "urn:uuid:{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
@@ -0,0 +1,119 @@
+use crate::conf::ShipConfig;
+
+/// Contents of the `[scripts]` files, read once so every generator embeds the same thing
+#[derive(Default, Debug)]
+pub struct InstallScripts {
+    pub preinstall: Option<String>,
+    pub postinstall: Option<String>,
+    pub preremove: Option<String>,
+    pub postremove: Option<String>,
+}
+
+impl InstallScripts {
+    pub fn load(conf: &ShipConfig) -> Result<Self, String> {
+        let Some(scripts) = &conf.scripts else {
+            return Ok(Self::default());
+        };
+
+        let read = |path: &Option<String>| -> Result<Option<String>, String> {
+            path.as_ref()
+                .map(|path| {
+                    std::fs::read_to_string(path)
+                        .map_err(|err| format!("failed to read script {path} in [scripts]: {err}"))
+                })
+                .transpose()
+        };
+
+        Ok(Self {
+            preinstall: read(&scripts.preinstall)?,
+            postinstall: read(&scripts.postinstall)?,
+            preremove: read(&scripts.preremove)?,
+            postremove: read(&scripts.postremove)?,
+        })
+    }
+
+    /// Debian maintainer script names and contents, ready for the control archive
+    pub fn deb(&self) -> Vec<(&'static str, String)> {
+        [
+            ("preinst", &self.preinstall),
+            ("postinst", &self.postinstall),
+            ("prerm", &self.preremove),
+            ("postrm", &self.postremove),
+        ]
+        .into_iter()
+        .filter_map(|(name, script)| script.as_deref().map(|s| (name, with_shebang(s))))
+        .collect()
+    }
+
+    /// An xbps INSTALL script, which is run with `pre` or `post` as its first argument
+    pub fn xbps_install(&self) -> Option<String> {
+        dispatch(&self.preinstall, &self.postinstall)
+    }
+
+    /// An xbps REMOVE script, which is run with `pre` or `post` as its first argument
+    pub fn xbps_remove(&self) -> Option<String> {
+        dispatch(&self.preremove, &self.postremove)
+    }
+}
+
+/// The `[scripts]` files that are set, each with the name `names` gives it in the package,
+/// for preinstall, postinstall, preremove and postremove in that order
+pub fn planned(conf: &ShipConfig, names: [&str; 4]) -> Vec<(String, String)> {
+    let Some(scripts) = &conf.scripts else {
+        return Vec::new();
+    };
+    [
+        &scripts.preinstall,
+        &scripts.postinstall,
+        &scripts.preremove,
+        &scripts.postremove,
+    ]
+    .into_iter()
+    .zip(names)
+    .filter_map(|(path, name)| Some((name.to_string(), path.clone()?)))
+    .collect()
+}
+
+/// Runs a script from inside another shell script without writing it to disk
+pub fn inline(script: &str) -> String {
+    format!("sh <<'SHIP_SCRIPT'\n{}\nSHIP_SCRIPT\n", script.trim_end())
+}
+
+/// Runs `snippet` at the start of the maintainer script `name`, right after the shebang of
+/// the one from [scripts] if there is one, like debhelper's `#DEBHELPER#`
+pub fn add_deb_snippet(
+    scripts: &mut Vec<(&'static str, String)>,
+    name: &'static str,
+    snippet: &str,
+) {
+    match scripts.iter_mut().find(|(script, _)| *script == name) {
+        Some((_, contents)) => {
+            let (shebang, body) = contents.split_once('\n').unwrap_or((contents, ""));
+            *contents = format!("{shebang}\n{snippet}{body}");
+        }
+        None => scripts.push((name, format!("#!/bin/sh\nset -e\n{snippet}"))),
+    }
+}
+
+fn with_shebang(script: &str) -> String {
+    if script.starts_with("#!") {
+        script.to_string()
+    } else {
+        format!("#!/bin/sh\n{script}")
+    }
+}
+
+fn dispatch(pre: &Option<String>, post: &Option<String>) -> Option<String> {
+    if pre.is_none() && post.is_none() {
+        return None;
+    }
+
+    let mut body = String::from("#!/bin/sh\ncase \"$1\" in\n");
+    for (action, script) in [("pre", pre), ("post", post)] {
+        if let Some(script) = script {
+            body.push_str(&format!("{action})\n{};;\n", inline(script)));
+        }
+    }
+    body.push_str("esac\n");
+    Some(body)
+}
@@ -0,0 +1,88 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    cache,
+    conf::ShipConfig,
+    layout::{Layout, is_elf},
+};
+
+/// Finds the packages providing the shared libraries the layout's ELF files link against.
+///
+/// Delegates to `dpkg-shlibdeps`, which maps each library to its package and the minimum
+/// version from the symbols files, so this only works on a Debian-based host.
+pub fn detect(conf: &ShipConfig, layout: &Layout) -> Result<Vec<String>, String> {
+    let mut binaries = Vec::new();
+    for entry in &layout.files {
+        if is_elf(&entry.source) {
+            let path = std::fs::canonicalize(&entry.source)
+                .map_err(|err| format!("failed to resolve {}: {err}", entry.source.display()))?;
+            binaries.push(path);
+        }
+    }
+    if binaries.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    // dpkg-shlibdeps insists on reading debian/control, even though nothing in it is used
+    let scratch = scratch_dir(conf)?;
+
+    let output = crate::log::command(
+        Command::new("dpkg-shlibdeps")
+            .arg("-O")
+            .arg("--warnings=0")
+            .args(binaries.iter().map(|path| {
+                let mut arg = std::ffi::OsString::from("-e");
+                arg.push(path);
+                arg
+            }))
+            .current_dir(&scratch)
+            .stdin(Stdio::null()),
+    )
+    .output()
+    .map_err(|err| format!("failed to run dpkg-shlibdeps (is dpkg-dev installed?): {err}"))?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "dpkg-shlibdeps failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(stdout
+        .lines()
+        .filter_map(|line| line.strip_prefix("shlibs:Depends="))
+        .flat_map(|deps| deps.split(','))
+        .map(|dep| dep.trim().to_string())
+        .filter(|dep| !dep.is_empty())
+        .collect())
+}
+
+/// Adds detected dependencies to `declared`, unless a package of the same name is already listed
+pub fn merge(declared: &[String], detected: Vec<String>) -> Vec<String> {
+    let package = |dep: &str| dep.split([' ', '(']).next().unwrap_or(dep).to_string();
+    let mut merged = declared.to_vec();
+    for dep in detected {
+        if !merged
+            .iter()
+            .any(|existing| package(existing) == package(&dep))
+        {
+            merged.push(dep);
+        }
+    }
+    merged
+}
+
+fn scratch_dir(conf: &ShipConfig) -> Result<PathBuf, String> {
+    let scratch = cache::work_dir(conf).join("shlibdeps");
+    let debian = scratch.join("debian");
+    std::fs::create_dir_all(&debian)
+        .map_err(|err| format!("failed to create {}: {err}", debian.display()))?;
+    std::fs::write(debian.join("control"), "")
+        .map_err(|err| format!("failed to write {}: {err}", debian.display()))?;
+    Ok(scratch)
+}
@@ -0,0 +1,467 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    atomic,
+    conf::{Cosign, MacosSigning, Signing, WindowsSigning},
+};
+
+// the passphrase goes through stdin, so gpg mustn't ask the agent for it
+fn gpg(signing: &Signing) -> (Command, Option<String>) {
+    let mut cmd = Command::new("gpg");
+    cmd.args(["--batch", "--yes"]);
+    if let Some(key) = &signing.key {
+        cmd.arg("--local-user").arg(key);
+    }
+    let passphrase = secret(&signing.passphrase_env);
+    if passphrase.is_some() {
+        cmd.args(["--pinentry-mode", "loopback", "--passphrase-fd", "0"]);
+    }
+    (cmd, passphrase)
+}
+
+/// Signs `input` with the `[signing]` key into a detached signature at `output`,
+/// ASCII-armored when `armor` is set
+pub fn detach_sign(
+    signing: &Signing,
+    input: &Path,
+    output: &Path,
+    armor: bool,
+) -> Result<(), String> {
+    let mode: &[&str] = if armor {
+        &["--armor", "--detach-sign"]
+    } else {
+        &["--detach-sign"]
+    };
+    sign(signing, mode, input, output)
+}
+
+/// Signs `input` with the `[signing]` key into a copy of it wrapped in the signature at
+/// `output`, like APT's InRelease
+pub fn clear_sign(signing: &Signing, input: &Path, output: &Path) -> Result<(), String> {
+    sign(signing, &["--clearsign"], input, output)
+}
+
+fn sign(signing: &Signing, mode: &[&str], input: &Path, output: &Path) -> Result<(), String> {
+    let (mut cmd, passphrase) = gpg(signing);
+    cmd.args(mode).arg("--output").arg(output).arg(input);
+    run("gpg", cmd, passphrase, input)
+}
+
+/// Signs each artifact the way its kind calls for and returns the detached signatures
+/// written next to them.
+///
+/// Signatures embedded in the file come first, Authenticode for Windows installers and
+/// codesign plus the notarization ticket for macOS disk images and installers, so the
+/// detached ones cover the file as released: gpg with `detach`, then minisign and cosign
+/// when their tables are set. With `provenance`, the path of an SLSA provenance predicate,
+/// cosign also attests it for each artifact.
+pub fn sign_artifacts(
+    signing: &Signing,
+    artifacts: &[PathBuf],
+    provenance: Option<&Path>,
+) -> Result<Vec<PathBuf>, String> {
+    let mut signatures = Vec::new();
+    for artifact in artifacts.iter().filter(|artifact| artifact.is_file()) {
+        let extension = artifact
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match (extension.as_str(), &signing.windows, &signing.macos) {
+            ("exe" | "msi", Some(windows), _) => authenticode(windows, artifact)?,
+            ("dmg", _, Some(macos)) => {
+                codesign(macos, artifact)?;
+                notarize(macos, artifact)?;
+            }
+            ("pkg", _, Some(macos)) => {
+                productsign(macos, artifact)?;
+                notarize(macos, artifact)?;
+            }
+            _ => {}
+        }
+
+        if signing.detach == Some(true) {
+            let signature = companion(artifact, "asc");
+            detach_sign(signing, artifact, &signature, true)?;
+            signatures.push(signature);
+        }
+        if let Some(minisign) = &signing.minisign {
+            let signature = companion(artifact, "minisig");
+            let mut cmd = Command::new("minisign");
+            cmd.args(["-S", "-s"])
+                .arg(&minisign.key)
+                .arg("-m")
+                .arg(artifact)
+                .arg("-x")
+                .arg(&signature);
+            run("minisign", cmd, secret(&minisign.password_env), artifact)?;
+            signatures.push(signature);
+        }
+        if let Some(cosign) = &signing.cosign {
+            let bundle = companion(artifact, "cosign.bundle");
+            let mut cmd = cosign_command(cosign, "sign-blob");
+            cmd.arg("--bundle").arg(&bundle).arg(artifact);
+            run("cosign", cmd, None, artifact)?;
+            signatures.push(bundle);
+
+            if let Some(predicate) = provenance {
+                let attestation = companion(artifact, "intoto.bundle");
+                let mut cmd = cosign_command(cosign, "attest-blob");
+                cmd.args(["--type", "slsaprovenance1", "--predicate"])
+                    .arg(predicate)
+                    .arg("--bundle")
+                    .arg(&attestation)
+                    .arg(artifact);
+                run("cosign", cmd, None, artifact)?;
+                signatures.push(attestation);
+            }
+        }
+    }
+    Ok(signatures)
+}
+
+// without a key cosign signs keylessly, with the token given or the CI's ambient one
+fn cosign_command(cosign: &Cosign, subcommand: &str) -> Command {
+    let mut cmd = Command::new("cosign");
+    cmd.args([subcommand, "--yes"]);
+    if let Some(key) = &cosign.key {
+        cmd.args(["--key", key]);
+    }
+    if let Some(password) = secret(&cosign.password_env) {
+        cmd.env("COSIGN_PASSWORD", password);
+    }
+    if let Some(token) = secret(&cosign.identity_token_env) {
+        cmd.env("SIGSTORE_ID_TOKEN", token);
+    }
+    cmd
+}
+
+const TIMESTAMP_URL: &str = "http://timestamp.digicert.com";
+
+/// Authenticode with SHA-256 and an RFC 3161 timestamp, so the signature outlives the
+/// certificate: AzureSignTool for Key Vault, signtool on Windows, osslsigncode elsewhere
+fn authenticode(windows: &WindowsSigning, artifact: &Path) -> Result<(), String> {
+    let timestamp = windows.timestamp_url.as_deref().unwrap_or(TIMESTAMP_URL);
+    let password = secret(&windows.password_env);
+
+    if let Some(vault) = &windows.azure_key_vault {
+        let mut cmd = Command::new("azuresigntool");
+        cmd.args(["sign", "--azure-key-vault-url", &vault.url])
+            .args(["--azure-key-vault-certificate", &vault.certificate])
+            .args([
+                "--timestamp-rfc3161",
+                timestamp,
+                "--timestamp-digest",
+                "sha256",
+            ])
+            .args(["--file-digest", "sha256"]);
+        // its Azure credential chain reads AZURE_CLIENT_ID, AZURE_TENANT_ID and
+        // AZURE_CLIENT_SECRET from the environment before trying the managed identity, which
+        // keeps the secret off the command line
+        cmd.arg("--azure-key-vault-managed-identity");
+        cmd.arg(artifact);
+        return run("azuresigntool", cmd, None, artifact);
+    }
+
+    if cfg!(windows) && (windows.cert.is_some() || windows.thumbprint.is_some()) {
+        let mut cmd = Command::new("signtool");
+        cmd.args(["sign", "/fd", "sha256", "/tr", timestamp, "/td", "sha256"]);
+        if let Some(thumbprint) = &windows.thumbprint {
+            cmd.args(["/sha1", thumbprint]);
+        } else if let Some(cert) = &windows.cert {
+            cmd.arg("/f").arg(cert);
+            // signtool takes the password nowhere but its arguments, so it's masked in the log
+            if let Some(password) = &password {
+                cmd.args(["/p", password]);
+            }
+        }
+        cmd.arg(artifact);
+        let redacted: Vec<&str> = password.as_deref().into_iter().collect();
+        return run_redacting("signtool", cmd, None, &redacted, artifact);
+    }
+
+    let mut key_args: Vec<String> = match (&windows.pkcs11_key, &windows.cert) {
+        (Some(key), _) => {
+            let module = windows
+                .pkcs11_module
+                .as_ref()
+                .ok_or("[signing.windows].pkcs11_key needs the library in pkcs11_module")?;
+            let certs = windows
+                .pkcs11_cert
+                .as_ref()
+                .ok_or("[signing.windows].pkcs11_key needs its certificate in pkcs11_cert")?;
+            vec![
+                "-pkcs11module".into(),
+                module.clone(),
+                "-key".into(),
+                key.clone(),
+                "-certs".into(),
+                certs.clone(),
+            ]
+        }
+        (None, Some(cert)) => vec!["-pkcs12".into(), cert.clone()],
+        (None, None) if windows.thumbprint.is_some() => {
+            return Err(
+                "[signing.windows].thumbprint needs signtool, which only runs on Windows".into(),
+            );
+        }
+        (None, None) => {
+            return Err(
+                "[signing.windows] needs a cert, thumbprint, pkcs11_key or azure_key_vault".into(),
+            );
+        }
+    };
+    if password.is_some() {
+        key_args.extend(["-readpass".into(), "/dev/stdin".into()]);
+    }
+    // signed into a copy that replaces the artifact once osslsigncode is done
+    replace_signed(artifact, |signed| {
+        let mut cmd = Command::new("osslsigncode");
+        cmd.arg("sign")
+            .args(&key_args)
+            .args(["-h", "sha256", "-ts", timestamp])
+            .arg("-in")
+            .arg(artifact)
+            .arg("-out")
+            .arg(signed);
+        run("osslsigncode", cmd, password.clone(), artifact)
+    })
+}
+
+// the hardened runtime and a secure timestamp are what notarization asks for
+fn codesign(macos: &MacosSigning, artifact: &Path) -> Result<(), String> {
+    check_team(macos, &macos.identity)?;
+    let mut cmd = Command::new("codesign");
+    cmd.args(["--force", "--timestamp", "--options", "runtime"])
+        .args(["--sign", &macos.identity]);
+    if let Some(keychain) = &macos.keychain {
+        cmd.arg("--keychain").arg(keychain);
+    }
+    cmd.arg(artifact);
+    run("codesign", cmd, None, artifact)
+}
+
+fn productsign(macos: &MacosSigning, artifact: &Path) -> Result<(), String> {
+    let identity = macos.installer_identity.as_ref().ok_or_else(|| {
+        format!(
+            "signing {} needs a Developer ID Installer identity in [signing.macos].installer_identity",
+            artifact.display()
+        )
+    })?;
+    check_team(macos, identity)?;
+    replace_signed(artifact, |signed| {
+        let mut cmd = Command::new("productsign");
+        cmd.args(["--timestamp", "--sign", identity]);
+        if let Some(keychain) = &macos.keychain {
+            cmd.arg("--keychain").arg(keychain);
+        }
+        cmd.arg(artifact).arg(signed);
+        run("productsign", cmd, None, artifact)
+    })
+}
+
+// identities are named like `Developer ID Application: Jane Doe (TEAMID)`
+fn check_team(macos: &MacosSigning, identity: &str) -> Result<(), String> {
+    match &macos.team_id {
+        Some(team) if !identity.contains(&format!("({team})")) => Err(format!(
+            "the identity `{identity}` isn't one of team {team} from [signing.macos].team_id"
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Submits a signed disk image or installer to Apple's notary service, waits for the
+/// verdict and staples the ticket to it, so Gatekeeper accepts it offline. Only with
+/// `[signing.macos].keychain_profile`.
+fn notarize(macos: &MacosSigning, artifact: &Path) -> Result<(), String> {
+    let Some(profile) = &macos.keychain_profile else {
+        return Ok(());
+    };
+    info!(
+        "notarizing {}, which usually takes a few minutes...",
+        artifact.display()
+    );
+    let submission = notarytool(macos, profile, |cmd| {
+        cmd.arg("submit").arg(artifact).arg("--wait");
+    })?;
+    let status = submission["status"].as_str().unwrap_or("unknown");
+    if status != "Accepted" {
+        let issues = submission["id"]
+            .as_str()
+            .and_then(|id| {
+                notarytool(macos, profile, |cmd| {
+                    cmd.args(["log", id]);
+                })
+                .ok()
+            })
+            .and_then(|log| log["issues"].as_array().cloned())
+            .unwrap_or_default()
+            .iter()
+            .map(|issue| {
+                format!(
+                    "\n  {}: {}",
+                    issue["path"].as_str().unwrap_or_default(),
+                    issue["message"].as_str().unwrap_or_default()
+                )
+            })
+            .collect::<String>();
+        return Err(format!(
+            "notarization of {} ended {status}{issues}",
+            artifact.display()
+        ));
+    }
+
+    let status = crate::log::command(
+        Command::new("xcrun")
+            .args(["stapler", "staple"])
+            .arg(artifact)
+            .stdin(Stdio::null())
+            .stdout(crate::log::child_stdout()),
+    )
+    .status()
+    .map_err(|err| format!("failed to run stapler for {}: {err}", artifact.display()))?;
+    if !status.success() {
+        return Err(format!(
+            "stapler failed to staple the ticket to {} ({status})",
+            artifact.display()
+        ));
+    }
+    info!("notarized {}", artifact.display());
+    Ok(())
+}
+
+// `xcrun notarytool <args>` with the stored credentials, answering in JSON
+fn notarytool(
+    macos: &MacosSigning,
+    profile: &str,
+    args: impl FnOnce(&mut Command),
+) -> Result<serde_json::Value, String> {
+    let mut cmd = Command::new("xcrun");
+    cmd.arg("notarytool");
+    args(&mut cmd);
+    cmd.args(["--keychain-profile", profile, "--output-format", "json"]);
+    if let Some(team) = &macos.team_id {
+        cmd.args(["--team-id", team]);
+    }
+    if let Some(keychain) = &macos.keychain {
+        cmd.arg("--keychain").arg(keychain);
+    }
+    let output = crate::log::command(cmd.stdin(Stdio::null()))
+        .output()
+        .map_err(|err| format!("failed to run notarytool: {err}"))?;
+    if !output.status.success() && output.stdout.is_empty() {
+        return Err(format!(
+            "notarytool failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    serde_json::from_slice(&output.stdout)
+        .map_err(|err| format!("notarytool answered with something other than JSON: {err}"))
+}
+
+fn replace_signed(
+    artifact: &Path,
+    sign: impl FnOnce(&Path) -> Result<(), String>,
+) -> Result<(), String> {
+    let signed = atomic::temp_path(artifact);
+    let result = sign(&signed).and_then(|()| {
+        std::fs::rename(&signed, artifact)
+            .map_err(|err| format!("failed to replace {}: {err}", artifact.display()))
+    });
+    if result.is_err() {
+        std::fs::remove_file(&signed).ok();
+    }
+    result
+}
+
+// `<artifact>.<ext>`
+fn companion(artifact: &Path, ext: &str) -> PathBuf {
+    let mut path = artifact.to_path_buf().into_os_string();
+    path.push(format!(".{ext}"));
+    PathBuf::from(path)
+}
+
+fn secret(env: &Option<String>) -> Option<String> {
+    env.as_ref().and_then(|env| std::env::var(env).ok())
+}
+
+// a secret goes in on stdin, otherwise the tool may ask on the terminal
+fn run(tool: &str, cmd: Command, secret: Option<String>, input: &Path) -> Result<(), String> {
+    run_redacting(tool, cmd, secret, &[], input)
+}
+
+// like run, for tools that only take a secret as an argument
+fn run_redacting(
+    tool: &str,
+    mut cmd: Command,
+    secret: Option<String>,
+    redacted: &[&str],
+    input: &Path,
+) -> Result<(), String> {
+    cmd.stdin(if secret.is_some() {
+        Stdio::piped()
+    } else {
+        Stdio::inherit()
+    })
+    .stdout(crate::log::child_stdout());
+
+    let mut child = crate::log::command_redacting(&mut cmd, redacted)
+        .spawn()
+        .map_err(|err| format!("failed to run {tool} to sign {}: {err}", input.display()))?;
+    if let (Some(secret), Some(mut stdin)) = (secret, child.stdin.take()) {
+        writeln!(stdin, "{secret}")
+            .map_err(|err| format!("failed to hand {tool} the passphrase: {err}"))?;
+    }
+    let status = child
+        .wait()
+        .map_err(|err| format!("failed to run {tool} to sign {}: {err}", input.display()))?;
+    if !status.success() {
+        return Err(format!(
+            "{tool} failed to sign {} ({status})",
+            input.display()
+        ));
+    }
+    Ok(())
+}
+
+/// The public half of the `[signing]` key, ASCII-armored, for embedding next to a signature
+pub fn public_key(signing: &Signing) -> Result<String, String> {
+    let key = match &signing.key {
+        Some(key) => key.clone(),
+        None => default_key()?,
+    };
+    let output = crate::log::command(
+        Command::new("gpg")
+            .args(["--batch", "--armor", "--export"])
+            .arg(&key)
+            .stdin(Stdio::null()),
+    )
+    .output()
+    .map_err(|err| format!("failed to run gpg to export {key}: {err}"))?;
+    let armored = String::from_utf8_lossy(&output.stdout).into_owned();
+    if !output.status.success() || armored.trim().is_empty() {
+        return Err(format!("gpg has no public key {key} to export"));
+    }
+    Ok(armored)
+}
+
+// the first secret key, which gpg signs with when no --local-user is given
+fn default_key() -> Result<String, String> {
+    let output = crate::log::command(
+        Command::new("gpg")
+            .args(["--batch", "--with-colons", "--list-secret-keys"])
+            .stdin(Stdio::null()),
+    )
+    .output()
+    .map_err(|err| format!("failed to run gpg to find the signing key: {err}"))?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.strip_prefix("fpr:"))
+        .and_then(|fields| fields.split(':').find(|field| !field.is_empty()))
+        .map(String::from)
+        .ok_or_else(|| "gpg has no secret key to sign with, set [signing].key".to_string())
+}
@@ -0,0 +1,176 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use glob::Pattern;
+
+use crate::{
+    conf::ShipConfig,
+    gen_::Plan,
+    layout::{Entry, collect_dir, is_executable},
+    remote,
+};
+
+/// Directory inside a source tarball holding a staged `[files].root`
+pub const STAGED_ROOT: &str = "image";
+
+/// A [files] entry as it appears in a source tarball, relative to its top directory
+pub struct SourceEntry {
+    pub source: PathBuf,
+    pub rel: String,
+    pub dest: String,      // absolute install path
+    pub mode: Option<u32>, // from [files].paths
+    pub mapped: bool,      // placed with `to`, so never linked into /usr/bin
+}
+
+impl SourceEntry {
+    /// Directory the entry is installed into, its destination's parent
+    pub fn install_dir(&self) -> String {
+        Path::new(&self.dest)
+            .parent()
+            .map(|parent| parent.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "/".to_string())
+    }
+
+    /// Name of the launcher in /usr/bin, if the entry is an executable that gets one
+    pub fn link_name(&self) -> Option<String> {
+        if self.mapped || self.source.is_dir() || !is_executable(&self.source, self.mode) {
+            return None;
+        }
+        Path::new(&self.dest)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+    }
+}
+
+/// Shell commands giving the entries with a configured mode that mode under `root`, as
+/// distribution tooling normalizes permissions while installing
+pub fn chmod_commands(entries: &[SourceEntry], root: &str) -> Vec<String> {
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let mode = entry.mode?;
+            let path = format!("{root}{}", entry.dest);
+            Some(if entry.source.is_dir() {
+                format!("find \"{path}\" -type f -exec chmod {mode:04o} {{}} +")
+            } else {
+                format!("chmod {mode:04o} \"{path}\"")
+            })
+        })
+        .collect()
+}
+
+/// Every [files].paths and [files].remote entry, in Shipfile order
+///
+/// Entries mapped with `to` are stored under their destination path, so the install steps
+/// never have to rename them.
+pub fn entries(conf: &ShipConfig) -> Vec<SourceEntry> {
+    let prefix = conf.install_prefix();
+    let prefix = prefix.trim_end_matches('/');
+
+    let paths = conf.files.paths.iter().map(|file| SourceEntry {
+        source: PathBuf::from(&file.from),
+        rel: match &file.to {
+            Some(to) => to.trim_start_matches('/').trim_start_matches("./"),
+            None => file.from.strip_prefix("./").unwrap_or(&file.from),
+        }
+        .to_string(),
+        dest: file.dest(prefix),
+        mode: file.mode,
+        mapped: file.to.is_some(),
+    });
+    let remotes = conf.files.remote.iter().map(|r| SourceEntry {
+        source: remote::cached_path(r),
+        rel: r.install_path(),
+        dest: format!("{prefix}/{}", r.install_path()),
+        mode: None,
+        mapped: false,
+    });
+
+    paths.chain(remotes).collect()
+}
+
+/// The plan of a source package: the entries where they get installed, the staged root
+/// if there is one, and the launchers linked into /usr/bin
+pub fn plan(conf: &ShipConfig, artifacts: Vec<PathBuf>) -> Plan {
+    let entries = entries(conf);
+    let symlinks = entries
+        .iter()
+        .filter_map(|entry| {
+            Some((
+                format!("/usr/bin/{}", entry.link_name()?),
+                entry.dest.clone(),
+            ))
+        })
+        .collect();
+    let mut files: Vec<Entry> = entries
+        .into_iter()
+        .map(|entry| Entry {
+            source: entry.source,
+            dest: entry.dest,
+            mode: entry.mode,
+            owner: None,
+            group: None,
+        })
+        .collect();
+    if let Some(root) = &conf.files.root {
+        files.push(Entry {
+            source: PathBuf::from(root),
+            dest: "/".to_string(),
+            mode: None,
+            owner: None,
+            group: None,
+        });
+    }
+    Plan {
+        artifacts,
+        files,
+        symlinks,
+        ..Default::default()
+    }
+}
+
+/// Packs the entries (and the staged root, if any) into a `.tar.xz` under `top_dir/`
+pub fn write_source_tarball(
+    path: &Path,
+    top_dir: &str,
+    entries: &[SourceEntry],
+    staged_root: Option<&str>,
+    exclude: &[Pattern],
+) -> std::io::Result<()> {
+    let encoder = xz2::write::XzEncoder::new(File::create(path)?, 6);
+    let mut tar = tar::Builder::new(encoder);
+    let root = PathBuf::from(top_dir);
+
+    // directories are walked file by file so [files].exclude applies inside them
+    let append_dir = |tar: &mut tar::Builder<_>, from: &Path, to: PathBuf| {
+        let mut files = Vec::new();
+        collect_dir(&mut files, from, &to.to_string_lossy(), exclude)
+            .map_err(std::io::Error::other)?;
+        files
+            .iter()
+            .try_for_each(|file| file.append_to(tar, &file.dest))
+    };
+
+    if let Some(staged_root) = staged_root {
+        append_dir(&mut tar, Path::new(staged_root), root.join(STAGED_ROOT))?;
+    }
+
+    for entry in entries {
+        if entry.source.is_dir() {
+            append_dir(&mut tar, &entry.source, root.join(&entry.rel))?;
+        } else {
+            let file = File::open(&entry.source)?;
+            let mut header = tar::Header::new_gnu();
+            header.set_metadata(&file.metadata()?);
+            if let Some(mode) = entry.mode {
+                header.set_mode(mode);
+            }
+            tar.append_data(&mut header, root.join(&entry.rel), file)?;
+        }
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{cache, conf::ShipConfig, layout::is_elf, winres};
+
+/// ELF note section holding the build info
+const NOTE_SECTION: &str = ".note.ship.buildinfo";
+/// Note owner, so tools can tell ship's notes apart from others
+const NOTE_OWNER: &[u8] = b"ship\0";
+
+/// Writes the [prog] name and version into every executable in [files].paths the formats allow.
+///
+/// - `.exe` files get a VERSIONINFO resource, through rcedit
+/// - `.app` bundles get CFBundleShortVersionString/CFBundleVersion in their Info.plist
+/// - ELF files get a `.note.ship.buildinfo` section, through objcopy
+///
+/// Everything is edited in place, so every artifact built afterwards reports the same version.
+pub fn stamp_all(conf: &ShipConfig) -> Result<(), String> {
+    let Some(version) = &conf.prog.version else {
+        return Err("[build].stamp_version requires [prog].version".to_string());
+    };
+
+    // [windows.resources] stamps the version on its own
+    if conf
+        .windows
+        .as_ref()
+        .and_then(|w| w.resources.as_ref())
+        .is_none()
+    {
+        winres::stamp(conf, None)?;
+    }
+
+    for file in &conf.files.paths {
+        let path = Path::new(&file.from);
+        if path.extension().is_some_and(|ext| ext == "app") && path.is_dir() {
+            stamp_info_plist(&path.join("Contents").join("Info.plist"), version)?;
+        } else if is_elf(path) {
+            stamp_elf(conf, path, version)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The text stored in the note, one `key=value` per line
+pub fn buildinfo(conf: &ShipConfig, version: &str) -> String {
+    format!(
+        "name={}\nversion={version}\narch={}\n",
+        conf.prog.name,
+        conf.prog.arch.deb_name()
+    )
+}
+
+fn stamp_elf(conf: &ShipConfig, path: &Path, version: &str) -> Result<(), String> {
+    let note_path = note_file(conf, version)?;
+
+    // removing first keeps restamping idempotent
+    let status = crate::log::command(
+        Command::new("objcopy")
+            .arg("--remove-section")
+            .arg(NOTE_SECTION)
+            .arg("--add-section")
+            .arg(format!("{NOTE_SECTION}={}", note_path.display()))
+            .arg("--set-section-flags")
+            .arg(format!("{NOTE_SECTION}=noload,readonly"))
+            .arg(path)
+            .stdin(Stdio::null()),
+    )
+    .status()
+    .map_err(|err| format!("failed to run objcopy to stamp {}: {err}", path.display()))?;
+    if !status.success() {
+        return Err(format!("objcopy failed on {} ({status})", path.display()));
+    }
+
+    Ok(())
+}
+
+// an ELF note: name size, description size, type, then the padded name and description
+fn note_file(conf: &ShipConfig, version: &str) -> Result<PathBuf, String> {
+    let desc = buildinfo(conf, version).into_bytes();
+
+    let mut note = Vec::new();
+    note.extend_from_slice(&(NOTE_OWNER.len() as u32).to_le_bytes());
+    note.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    note.extend_from_slice(&1u32.to_le_bytes());
+    note.extend_from_slice(NOTE_OWNER);
+    note.resize(note.len().next_multiple_of(4), 0);
+    note.extend_from_slice(&desc);
+    note.resize(note.len().next_multiple_of(4), 0);
+
+    let path = cache::work_dir(conf).join("buildinfo.note");
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+    }
+    std::fs::write(&path, note)
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+fn stamp_info_plist(path: &Path, version: &str) -> Result<(), String> {
+    let plist = std::fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {err}", path.display()))?;
+    if !plist.contains("<plist") {
+        return Err(format!(
+            "{} is not an XML property list, convert it with `plutil -convert xml1`",
+            path.display()
+        ));
+    }
+
+    let mut plist = plist;
+    for key in ["CFBundleShortVersionString", "CFBundleVersion"] {
+        plist = set_plist_string(&plist, key, version).ok_or_else(|| {
+            format!(
+                "failed to set {key} in {}, no top-level <dict>",
+                path.display()
+            )
+        })?;
+    }
+
+    std::fs::write(path, plist).map_err(|err| format!("failed to write {}: {err}", path.display()))
+}
+
+// replaces the <string> after `<key>key</key>`, or adds the pair at the end of the top dict
+fn set_plist_string(plist: &str, key: &str, value: &str) -> Option<String> {
+    let key_tag = format!("<key>{key}</key>");
+    let value = value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;");
+
+    if let Some(key_pos) = plist.find(&key_tag) {
+        let after_key = key_pos + key_tag.len();
+        let start = after_key + plist[after_key..].find("<string>")? + "<string>".len();
+        let end = start + plist[start..].find("</string>")?;
+        return Some(format!("{}{value}{}", &plist[..start], &plist[end..]));
+    }
+
+    let dict_end = plist.rfind("</dict>")?;
+    Some(format!(
+        "{}\t<key>{key}</key>\n\t<string>{value}</string>\n{}",
+        &plist[..dict_end],
+        &plist[dict_end..]
+    ))
+}
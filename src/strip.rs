@@ -0,0 +1,135 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use sha2::Sha256;
+
+use crate::{
+    cache,
+    checksum::digest_file,
+    conf::{ShipConfig, Target},
+    layout::{Entry, Layout, is_elf},
+};
+
+/// Debug symbols split off the binaries of a layout, for a `-dbgsym` package
+pub struct DebugInfo {
+    pub files: Vec<Entry>,      // the .debug files, under /usr/lib/debug
+    pub build_ids: Vec<String>, // of the stripped binaries, for the Build-Ids field
+}
+
+/// Whether `target` ships stripped binaries
+pub fn enabled(conf: &ShipConfig, target: &Target) -> bool {
+    *target == Target::Deb && conf.deb.as_ref().is_some_and(|deb| deb.strip)
+}
+
+/// Where the stripped copy of a binary goes, keyed by the binary's sha256 so it can be
+/// traced back to the build it came from
+pub fn stripped_path(conf: &ShipConfig, sha256: &str, source: &Path) -> PathBuf {
+    let name = source.file_name().unwrap_or(source.as_os_str());
+    cache::work_dir(conf).join("strip").join(sha256).join(name)
+}
+
+/// Replaces every ELF file of `layout` with a stripped copy and returns the debug symbols.
+///
+/// The built binaries are left as they are. Each stripped copy links to its symbols by
+/// build ID, which is where gdb and debuginfod look for them, or by path if it has none.
+/// Needs binutils (objcopy and readelf).
+pub fn split_debug(conf: &ShipConfig, layout: &mut Layout) -> Result<DebugInfo, String> {
+    let mut debug = DebugInfo {
+        files: Vec::new(),
+        build_ids: Vec::new(),
+    };
+
+    for entry in &mut layout.files {
+        if !is_elf(&entry.source) {
+            continue;
+        }
+        let sha256 = digest_file::<Sha256>(&entry.source)
+            .map_err(|err| format!("failed to hash {}: {err}", entry.source.display()))?;
+        let stripped = stripped_path(conf, &sha256, &entry.source);
+        let mut symbols = stripped.clone().into_os_string();
+        symbols.push(".debug");
+        let symbols = PathBuf::from(symbols);
+        if let Some(parent) = stripped.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+        }
+
+        objcopy(&[
+            "--only-keep-debug".as_ref(),
+            "--compress-debug-sections".as_ref(),
+            entry.source.as_os_str(),
+            symbols.as_os_str(),
+        ])?;
+        // the same sections dh_strip drops
+        let mut debuglink = std::ffi::OsString::from("--add-gnu-debuglink=");
+        debuglink.push(&symbols);
+        objcopy(&[
+            "--strip-debug".as_ref(),
+            "--strip-unneeded".as_ref(),
+            "--remove-section=.comment".as_ref(),
+            "--remove-section=.note".as_ref(),
+            debuglink.as_os_str(),
+            entry.source.as_os_str(),
+            stripped.as_os_str(),
+        ])?;
+
+        let dest = match build_id(&entry.source)? {
+            Some(id) if id.len() > 2 => {
+                let dest = format!("/usr/lib/debug/.build-id/{}/{}.debug", &id[..2], &id[2..]);
+                debug.build_ids.push(id);
+                dest
+            }
+            _ => format!("/usr/lib/debug{}.debug", entry.dest),
+        };
+        debug.files.push(Entry {
+            source: symbols,
+            dest,
+            mode: Some(0o644),
+            owner: None,
+            group: None,
+        });
+        entry.source = stripped;
+    }
+
+    Ok(debug)
+}
+
+fn objcopy(args: &[&std::ffi::OsStr]) -> Result<(), String> {
+    let output = crate::log::command(Command::new("objcopy").args(args).stdin(Stdio::null()))
+        .output()
+        .map_err(|err| format!("failed to run objcopy (is binutils installed?): {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "objcopy failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(())
+}
+
+// the GNU build ID note, as lowercase hex
+fn build_id(path: &Path) -> Result<Option<String>, String> {
+    let output = crate::log::command(
+        Command::new("readelf")
+            .arg("--notes")
+            .arg(path)
+            .stdin(Stdio::null()),
+    )
+    .output()
+    .map_err(|err| format!("failed to run readelf (is binutils installed?): {err}"))?;
+    if !output.status.success() {
+        return Err(format!(
+            "readelf failed on {} ({})",
+            path.display(),
+            output.status
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Build ID:"))
+        .map(|id| id.trim().to_lowercase()))
+}
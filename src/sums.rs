@@ -0,0 +1,76 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{atomic, report::ReportArtifact};
+
+pub const SUMS_FILE: &str = "SHA256SUMS";
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+// relative to the output directory, so `sha256sum -c` works from inside it
+fn listed_path(dir: &Path, artifact: &ReportArtifact) -> String {
+    match artifact.path.strip_prefix(dir) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => artifact.file.clone(),
+    }
+}
+
+/// Writes `SHA256SUMS` for `artifacts` into `dir`
+pub fn write_sums(dir: &Path, artifacts: &[ReportArtifact]) -> Result<PathBuf, String> {
+    let path = dir.join(SUMS_FILE);
+    atomic::write(&path, render_sums(dir, artifacts))
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+/// The checksums of `artifacts` in the format `sha256sum -c` reads from inside `dir`
+pub fn render_sums(dir: &Path, artifacts: &[ReportArtifact]) -> String {
+    artifacts
+        .iter()
+        .map(|artifact| format!("{}  {}\n", artifact.sha256, listed_path(dir, artifact)))
+        .collect()
+}
+
+/// Writes `manifest.json` into `dir`, listing the target, size and sha256 of each artifact
+pub fn write_manifest(dir: &Path, artifacts: &[ReportArtifact]) -> Result<PathBuf, String> {
+    let entries: Vec<serde_json::Value> = artifacts
+        .iter()
+        .map(|artifact| {
+            serde_json::json!({
+                "target": artifact.target,
+                "file": listed_path(dir, artifact),
+                "size": artifact.size,
+                "sha256": artifact.sha256,
+            })
+        })
+        .collect();
+    let json = serde_json::to_vec_pretty(&serde_json::json!({ "artifacts": entries }))
+        .map_err(|err| format!("failed to serialize {MANIFEST_FILE}: {err}"))?;
+    let path = dir.join(MANIFEST_FILE);
+    atomic::write(&path, json)
+        .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+    Ok(path)
+}
+
+/// Signs `path` with gpg's default key into an ASCII-armored `<path>.asc` next to it
+pub fn sign(path: &Path) -> Result<PathBuf, String> {
+    let mut signature = path.to_path_buf().into_os_string();
+    signature.push(".asc");
+    let signature = PathBuf::from(signature);
+
+    let status = crate::log::command(
+        Command::new("gpg")
+            .args(["--batch", "--yes", "--armor", "--detach-sign", "--output"])
+            .arg(&signature)
+            .arg(path)
+            .stdin(Stdio::inherit())
+            .stdout(crate::log::child_stdout()),
+    )
+    .status()
+    .map_err(|err| format!("failed to run gpg to sign {}: {err}", path.display()))?;
+    if !status.success() {
+        return Err(format!("gpg failed to sign {} ({status})", path.display()));
+    }
+    Ok(signature)
+}
@@ -0,0 +1,136 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    compress::Encoder,
+    conf::{CompressionAlgorithm, ShipConfig, Target},
+    layout::Layout,
+};
+
+/// Algorithm/level pairs worth comparing, from fastest to strongest
+const CANDIDATES: &[(CompressionAlgorithm, u32)] = &[
+    (CompressionAlgorithm::Gzip, 1),
+    (CompressionAlgorithm::Gzip, 6),
+    (CompressionAlgorithm::Gzip, 9),
+    (CompressionAlgorithm::Xz, 0),
+    (CompressionAlgorithm::Xz, 6),
+    (CompressionAlgorithm::Xz, 9),
+    (CompressionAlgorithm::Zstd, 1),
+    (CompressionAlgorithm::Zstd, 3),
+    (CompressionAlgorithm::Zstd, 9),
+    (CompressionAlgorithm::Zstd, 19),
+];
+
+pub struct Trial {
+    pub algorithm: CompressionAlgorithm,
+    pub level: u32,
+    pub size: u64,
+    pub time: Duration,
+}
+
+// only the byte count matters, the compressed output itself is thrown away
+#[derive(Default)]
+struct CountingWriter {
+    written: u64,
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Whether `target` has a payload whose compression ship controls
+pub fn supports(target: &Target) -> bool {
+    matches!(target, Target::Deb | Target::Xbps | Target::Archive)
+}
+
+/// Tars the install tree of `target` without compression, the input every trial shares
+pub fn payload(conf: &ShipConfig) -> Result<Vec<u8>, String> {
+    let layout = Layout::resolve(conf)?;
+
+    let mut tar = tar::Builder::new(Vec::new());
+    for entry in &layout.files {
+        entry
+            .append_to(&mut tar, format!(".{}", entry.dest))
+            .map_err(|err| format!("failed to read {}: {err}", entry.source.display()))?;
+    }
+    for (link, target) in &layout.symlinks {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::symlink());
+        header.set_size(0);
+        tar.append_link(&mut header, format!(".{link}"), target)
+            .map_err(|err| format!("failed to add symlink {link}: {err}"))?;
+    }
+
+    tar.into_inner()
+        .map_err(|err| format!("failed to build payload: {err}"))
+}
+
+/// Compresses `payload` with every candidate, smallest result first
+pub fn run_trials(payload: &[u8]) -> io::Result<Vec<Trial>> {
+    let mut trials = Vec::new();
+    for &(algorithm, level) in CANDIDATES {
+        let start = Instant::now();
+        let mut encoder = Encoder::new(CountingWriter::default(), algorithm, level, 1)?;
+        encoder.write_all(payload)?;
+        let size = encoder.finish()?.written;
+
+        trials.push(Trial {
+            algorithm,
+            level,
+            size,
+            time: start.elapsed(),
+        });
+    }
+
+    trials.sort_by_key(|trial| (trial.size, trial.time));
+    Ok(trials)
+}
+
+pub fn print_report(payload_size: u64, trials: &[Trial]) {
+    output!("payload: {payload_size} bytes uncompressed");
+    output!(
+        "{:<10} {:>5} {:>12} {:>7} {:>10}",
+        "algorithm",
+        "level",
+        "size",
+        "ratio",
+        "time"
+    );
+    for trial in trials {
+        output!(
+            "{:<10} {:>5} {:>12} {:>6.1}% {:>8}ms",
+            format!("{:?}", trial.algorithm),
+            trial.level,
+            trial.size,
+            trial.size as f64 * 100.0 / payload_size.max(1) as f64,
+            trial.time.as_millis()
+        );
+    }
+}
+
+/// Stores the chosen settings as `compression` in the target's section, keeping the rest of the file as written
+pub fn write_choice(shipfile: &Path, target: &Target, trial: &Trial) -> Result<(), String> {
+    let contents = std::fs::read_to_string(shipfile)
+        .map_err(|err| format!("failed to read {}: {err}", shipfile.display()))?;
+    let mut doc = contents
+        .parse::<toml_edit::DocumentMut>()
+        .map_err(|err| format!("failed to parse {}: {err}", shipfile.display()))?;
+
+    let mut compression = toml_edit::InlineTable::new();
+    compression.insert("algorithm", format!("{:?}", trial.algorithm).into());
+    compression.insert("level", i64::from(trial.level).into());
+    doc[target.section()]["compression"] = toml_edit::value(compression);
+
+    std::fs::write(shipfile, doc.to_string())
+        .map_err(|err| format!("failed to write {}: {err}", shipfile.display()))
+}
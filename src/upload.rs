@@ -0,0 +1,203 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use sha2::Sha256;
+
+use crate::{
+    checksum::digest_file,
+    conf::{HttpUpload, S3Upload, ShipConfig},
+    report::file_name,
+    vars,
+};
+
+const DEFAULT_KEY: &str = "${name}/${version}/${file}";
+
+/// A place `[publish]` uploads released files to
+#[derive(Debug, Clone, Copy)]
+pub enum Destination<'a> {
+    S3(&'a S3Upload),
+    Http(&'a HttpUpload),
+}
+
+/// Every upload destination set in `[publish]`
+pub fn destinations(conf: &ShipConfig) -> Vec<Destination<'_>> {
+    let Some(publish) = &conf.publish else {
+        return Vec::new();
+    };
+    let mut destinations = Vec::new();
+    if let Some(s3) = &publish.s3 {
+        destinations.push(Destination::S3(s3));
+    }
+    if let Some(http) = &publish.http {
+        destinations.push(Destination::Http(http));
+    }
+    destinations
+}
+
+/// Uploads `files` to `destination` one after another and returns the URL of each.
+///
+/// The object key or URL of a file comes from its template with `${file}` replaced by the
+/// file name; `${name}`, `${version}` and `${arch}` work too, like anywhere in the Shipfile.
+pub fn upload(
+    conf: &ShipConfig,
+    destination: Destination,
+    files: &[PathBuf],
+) -> Result<Vec<String>, String> {
+    let mut urls = Vec::new();
+    for file in files {
+        let name = file_name(file);
+        let url = match destination {
+            Destination::S3(s3) => {
+                let key = expand(conf, s3.key.as_deref().unwrap_or(DEFAULT_KEY), &name)?;
+                let url = s3_url(s3, &key);
+                info!("uploading {name} to s3://{}/{key}...", s3.bucket);
+                put_s3(s3, file, &url)?;
+                url
+            }
+            Destination::Http(http) => {
+                let url = expand(conf, &http.url, &name)?;
+                info!("uploading {name} to {url}...");
+                put_http(http, file, &url)?;
+                url
+            }
+        };
+        urls.push(url);
+    }
+    Ok(urls)
+}
+
+fn expand(conf: &ShipConfig, template: &str, file: &str) -> Result<String, String> {
+    let mut values = HashMap::from([
+        ("file".to_string(), encode(file)),
+        ("name".to_string(), conf.prog.name.clone()),
+        ("arch".to_string(), conf.prog.arch.deb_name().to_string()),
+    ]);
+    if let Some(version) = &conf.prog.version {
+        values.insert("version".to_string(), version.clone());
+    }
+    let expanded = vars::substitute(template, &values);
+    if expanded.contains("${") {
+        return Err(format!(
+            "`{template}` refers to a variable that isn't set, like ${{version}} without [prog].version"
+        ));
+    }
+    Ok(expanded)
+}
+
+// virtual-hosted on AWS itself, path-style on other endpoints, which is what MinIO and most
+// S3-compatible stores expect
+fn s3_url(s3: &S3Upload, key: &str) -> String {
+    let key = key.trim_start_matches('/');
+    match &s3.endpoint {
+        Some(endpoint) => format!("{}/{}/{key}", endpoint.trim_end_matches('/'), s3.bucket),
+        None => format!(
+            "https://{}.s3.{}.amazonaws.com/{key}",
+            s3.bucket,
+            s3.region.as_deref().unwrap_or("us-east-1")
+        ),
+    }
+}
+
+fn put_s3(s3: &S3Upload, file: &Path, url: &str) -> Result<(), String> {
+    let access_env = s3.access_key_env.as_deref().unwrap_or("AWS_ACCESS_KEY_ID");
+    let secret_env = s3
+        .secret_key_env
+        .as_deref()
+        .unwrap_or("AWS_SECRET_ACCESS_KEY");
+    let access_key = secret(access_env)?;
+    let secret_key = secret(secret_env)?;
+    let sha256 = digest_file::<Sha256>(file)
+        .map_err(|err| format!("failed to hash {}: {err}", file.display()))?;
+
+    let mut curl = Command::new("curl");
+    curl.arg("--aws-sigv4")
+        .arg(format!(
+            "aws:amz:{}:s3",
+            s3.region.as_deref().unwrap_or("us-east-1")
+        ))
+        .arg("--header")
+        .arg(format!("x-amz-content-sha256: {sha256}"));
+    if let Some(acl) = &s3.acl {
+        curl.arg("--header").arg(format!("x-amz-acl: {acl}"));
+    }
+    // curl signs with these, and reads them on stdin rather than where `ps` would show them
+    let mut config = format!(
+        "user = \"{}\"\n",
+        quote(&format!("{access_key}:{secret_key}"))
+    );
+    if let Ok(token) = std::env::var("AWS_SESSION_TOKEN")
+        && !token.is_empty()
+    {
+        config.push_str(&format!(
+            "header = \"{}\"\n",
+            quote(&format!("x-amz-security-token: {token}"))
+        ));
+    }
+    put(curl, file, url, &config)
+}
+
+fn put_http(http: &HttpUpload, file: &Path, url: &str) -> Result<(), String> {
+    let mut config = String::new();
+    if let Some(env) = &http.token_env {
+        config.push_str(&format!(
+            "header = \"{}\"\n",
+            quote(&format!("Authorization: Bearer {}", secret(env)?))
+        ));
+    }
+    put(Command::new("curl"), file, url, &config)
+}
+
+fn put(mut curl: Command, file: &Path, url: &str, config: &str) -> Result<(), String> {
+    curl.args(["--fail", "--silent", "--show-error", "--config", "-"])
+        .arg("--upload-file")
+        .arg(file)
+        .arg(url)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit());
+    let name = file_name(file);
+    let mut child = crate::log::command(&mut curl)
+        .spawn()
+        .map_err(|err| format!("failed to run curl to upload {name}: {err}"))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(config.as_bytes())
+            .map_err(|err| format!("failed to pass the credentials to curl: {err}"))?;
+    }
+    let status = child
+        .wait()
+        .map_err(|err| format!("failed to run curl to upload {name}: {err}"))?;
+    if !status.success() {
+        return Err(format!("upload of {name} to {url} failed ({status})"));
+    }
+    Ok(())
+}
+
+fn secret(env: &str) -> Result<String, String> {
+    std::env::var(env)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| format!("{env} is not set"))
+}
+
+// a double-quoted string in a curl config file
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// file names go into the URL path, where `+` and spaces would be read differently
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
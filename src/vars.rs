@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+
+use toml::Value;
+
+use crate::conf::Arch;
+
+/// Parses `--var key=value` flags into a map, rejecting malformed entries.
+pub fn parse_cli_vars(raw: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut vars = HashMap::new();
+
+    for entry in raw {
+        let (key, value) = entry
+            .split_once('=')
+            .ok_or_else(|| format!("invalid --var `{entry}`, expected KEY=VALUE"))?;
+
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(format!("invalid --var `{entry}`, key is empty"));
+        }
+
+        vars.insert(key.to_string(), value.to_string());
+    }
+
+    Ok(vars)
+}
+
+/// Resolves the variables declared in `[vars].arg` against the values passed on the CLI.
+///
+/// Each declaration is either `name` (required) or `name=default`.
+pub fn resolve_arg_vars(
+    declared: &[String],
+    cli: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    let mut resolved = HashMap::new();
+
+    for decl in declared {
+        let (name, default) = match decl.split_once('=') {
+            Some((name, default)) => (name.trim(), Some(default)),
+            None => (decl.trim(), None),
+        };
+
+        match (cli.get(name), default) {
+            (Some(value), _) => {
+                resolved.insert(name.to_string(), value.clone());
+            }
+            (None, Some(default)) => {
+                resolved.insert(name.to_string(), default.to_string());
+            }
+            (None, None) => {
+                return Err(format!(
+                    "variable `{name}` is declared in [vars].arg but was not passed with --var {name}=..."
+                ));
+            }
+        }
+    }
+
+    for key in cli.keys() {
+        if !resolved.contains_key(key) {
+            return Err(format!("--var `{key}` is not declared in [vars].arg"));
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Reads the raw `[vars].arg` declarations out of an unparsed Shipfile.
+pub fn declared_args(doc: &Value) -> Vec<String> {
+    doc.get("vars")
+        .and_then(|vars| vars.get("arg"))
+        .and_then(|arg| arg.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads the `KEY=VALUE` entries of `[vars].env` out of an unparsed Shipfile.
+pub fn declared_env(doc: &Value) -> HashMap<String, String> {
+    doc.get("vars")
+        .and_then(|vars| vars.get("env"))
+        .and_then(|env| env.as_array())
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str()?.split_once('='))
+                .map(|(key, value)| (key.trim().to_string(), value.to_string()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Variables every Shipfile can use without declaring them: `name`, `version`, `author` and `arch`,
+/// and `triple` in a `[matrix]` variant that sets one.
+///
+/// `arch` is spelled the way dpkg spells it, e.g. `amd64`.
+pub fn builtins(doc: &Value) -> HashMap<String, String> {
+    let mut builtins = HashMap::new();
+    let Some(prog) = doc.get("prog") else {
+        return builtins;
+    };
+
+    for key in ["name", "version", "author"] {
+        if let Some(value) = prog.get(key).and_then(|v| v.as_str()) {
+            builtins.insert(key.to_string(), value.to_string());
+        }
+    }
+    if let Some(arch) = prog
+        .get("arch")
+        .and_then(|arch| arch.clone().try_into::<Arch>().ok())
+    {
+        builtins.insert("arch".to_string(), arch.deb_name().to_string());
+    }
+    if let Some(triple) = doc
+        .get("matrix")
+        .and_then(|matrix| matrix.get("triple"))
+        .and_then(|triple| triple.as_str())
+    {
+        builtins.insert("triple".to_string(), triple.to_string());
+    }
+
+    builtins
+}
+
+/// Resolves every `${name}` reference in the document before it is parsed into a config.
+///
+/// Names are looked up in `[vars].arg` (set with `--var`), then `[vars].env`, then the
+/// built-ins, then the process environment. Built-ins are read after the `[vars]`
+/// pass, so `version = "${version}"` still works for the `${version}` built-in.
+pub fn resolve(doc: &mut Value, cli: &HashMap<String, String>) -> Result<(), String> {
+    let declared = declared_args(doc);
+    resolve_declared(doc, cli, &declared)
+}
+
+/// Like [resolve], for commands that only read the Shipfile and its last build: a
+/// `[vars].arg` that wasn't passed and has no default is left as `${name}`
+pub fn resolve_passed(doc: &mut Value, cli: &HashMap<String, String>) -> Result<(), String> {
+    let declared: Vec<String> = declared_args(doc)
+        .into_iter()
+        .filter(|decl| decl.contains('=') || cli.contains_key(decl.trim()))
+        .collect();
+    resolve_declared(doc, cli, &declared)
+}
+
+fn resolve_declared(
+    doc: &mut Value,
+    cli: &HashMap<String, String>,
+    declared: &[String],
+) -> Result<(), String> {
+    let args = resolve_arg_vars(declared, cli)?;
+    interpolate(doc, &args);
+
+    let mut vars: HashMap<String, String> = std::env::vars().collect();
+    vars.extend(builtins(doc));
+    vars.extend(declared_env(doc));
+    vars.extend(args);
+    interpolate(doc, &vars);
+
+    Ok(())
+}
+
+/// Replaces `${name}` references in every string of the document.
+///
+/// Unknown references are left untouched.
+pub fn interpolate(value: &mut Value, vars: &HashMap<String, String>) {
+    match value {
+        Value::String(s) => *s = substitute(s, vars),
+        Value::Array(arr) => arr.iter_mut().for_each(|v| interpolate(v, vars)),
+        Value::Table(table) => table.iter_mut().for_each(|(_, v)| interpolate(v, vars)),
+        _ => {}
+    }
+}
+
+/// Substitutes `${name}` references in a single string.
+pub fn substitute(input: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        match after.find('}') {
+            Some(end) => {
+                let name = &after[..end];
+                match vars.get(name) {
+                    Some(value) => out.push_str(value),
+                    None => out.push_str(&rest[start..start + 3 + end]),
+                }
+                rest = &after[end + 1..];
+            }
+            None => {
+                out.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use sha2::Sha256;
+
+use crate::{
+    cache,
+    checksum::digest_file,
+    conf::ShipConfig,
+    remote,
+    report::{BuildReport, ReportArtifact},
+};
+
+/// Detached signature extensions checked next to each artifact
+pub(crate) const SIGNATURE_EXTENSIONS: &[&str] = &["asc", "sig", "minisig", "cosign.bundle"];
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Ok,
+    Missing(String),
+    Truncated { expected: u64, actual: u64 },
+    Tampered { expected: String, actual: String },
+    SignatureMismatch(String),
+}
+
+/// Downloads every artifact of the last build from the publish URL and compares it with the report.
+///
+/// Nothing is uploaded or changed remotely.
+pub fn verify_published(conf: &ShipConfig) -> Result<Vec<(String, Verdict)>, String> {
+    let Some(base_url) = conf.publish_url() else {
+        return Err(
+            "verify-published needs [publish].base_url or [appcast].base_url to know where artifacts live"
+                .to_string(),
+        );
+    };
+    let report = BuildReport::load(&conf.prog.name)?;
+
+    let scratch = cache::work_dir(conf).join("verify");
+    std::fs::create_dir_all(&scratch).map_err(|err| {
+        format!(
+            "failed to create scratch directory {}: {err}",
+            scratch.display()
+        )
+    })?;
+
+    let mut results = Vec::new();
+    for artifact in &report.artifacts {
+        let verdict = verify_artifact(base_url, artifact, &scratch)?;
+        results.push((artifact.file.clone(), verdict));
+    }
+
+    std::fs::remove_dir_all(&scratch).ok();
+    Ok(results)
+}
+
+fn verify_artifact(
+    base_url: &str,
+    artifact: &ReportArtifact,
+    scratch: &Path,
+) -> Result<Verdict, String> {
+    let url = format!("{base_url}/{}", artifact.file);
+    let downloaded = scratch.join(&artifact.file);
+
+    info!("checking {url}...");
+    if let Err(err) = remote::download(&url, &downloaded) {
+        return Ok(Verdict::Missing(err));
+    }
+
+    let size = std::fs::metadata(&downloaded)
+        .map_err(|err| format!("failed to read {}: {err}", downloaded.display()))?
+        .len();
+    let sha256 = digest_file::<Sha256>(&downloaded)
+        .map_err(|err| format!("failed to hash {}: {err}", downloaded.display()))?;
+
+    if sha256 != artifact.sha256 {
+        // a smaller upload most likely stopped part way rather than being replaced
+        if size < artifact.size {
+            return Ok(Verdict::Truncated {
+                expected: artifact.size,
+                actual: size,
+            });
+        }
+        return Ok(Verdict::Tampered {
+            expected: artifact.sha256.clone(),
+            actual: sha256,
+        });
+    }
+
+    // signatures are only compared if one was made for the local artifact
+    for ext in SIGNATURE_EXTENSIONS {
+        let local = artifact
+            .path
+            .with_file_name(format!("{}.{ext}", artifact.file));
+        if !local.is_file() {
+            continue;
+        }
+
+        let remote_sig = scratch.join(format!("{}.{ext}", artifact.file));
+        let sig_url = format!("{url}.{ext}");
+        if remote::download(&sig_url, &remote_sig).is_err() {
+            return Ok(Verdict::SignatureMismatch(format!("{sig_url} is missing")));
+        }
+
+        let same = std::fs::read(&local).ok() == std::fs::read(&remote_sig).ok();
+        if !same {
+            return Ok(Verdict::SignatureMismatch(format!(
+                "{sig_url} differs from {}",
+                local.display()
+            )));
+        }
+    }
+
+    Ok(Verdict::Ok)
+}
@@ -0,0 +1,159 @@
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use crate::{
+    cache,
+    conf::{ShipConfig, WindowsResources},
+    icon::png_size,
+};
+
+/// Stamps the configured icon and version strings into every `.exe` in [files].paths.
+///
+/// Without [windows.resources] only the version and product name from [prog] are set.
+/// The executables are edited in place, so every installer built afterwards ships them.
+pub fn stamp(conf: &ShipConfig, resources: Option<&WindowsResources>) -> Result<(), String> {
+    let exes: Vec<&String> = conf
+        .files
+        .paths
+        .iter()
+        .map(|entry| &entry.from)
+        .filter(|path| {
+            Path::new(path)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("exe"))
+        })
+        .collect();
+    if exes.is_empty() {
+        return Ok(());
+    }
+
+    let default = WindowsResources::default();
+    let resources = resources.unwrap_or(&default);
+    let icon = icon_file(conf, &resources.icons)?;
+
+    let prog = &conf.prog;
+    let strings = [
+        ("CompanyName", resources.company.as_deref()),
+        (
+            "ProductName",
+            resources.product.as_deref().or(Some(prog.name.as_str())),
+        ),
+        (
+            "FileDescription",
+            resources
+                .description
+                .as_deref()
+                .or(prog.description.as_deref()),
+        ),
+        ("LegalCopyright", resources.copyright.as_deref()),
+    ];
+
+    for exe in exes {
+        let mut cmd = rcedit();
+        cmd.arg(exe);
+        if let Some(icon) = &icon {
+            cmd.arg("--set-icon").arg(icon);
+        }
+        if let Some(version) = &prog.version {
+            cmd.arg("--set-file-version").arg(version);
+            cmd.arg("--set-product-version").arg(version);
+        }
+        for (key, value) in strings {
+            if let Some(value) = value {
+                cmd.arg("--set-version-string").arg(key).arg(value);
+            }
+        }
+
+        let status = crate::log::command(cmd.stdin(Stdio::null()))
+            .status()
+            .map_err(|err| format!("failed to run rcedit (set RCEDIT to its path): {err}"))?;
+        if !status.success() {
+            return Err(format!("rcedit failed on {exe} ({status})"));
+        }
+    }
+
+    Ok(())
+}
+
+// RCEDIT may point at rcedit.exe, which needs wine outside of Windows
+fn rcedit() -> Command {
+    match std::env::var_os("RCEDIT") {
+        Some(path)
+            if cfg!(not(windows)) && Path::new(&path).extension().is_some_and(|e| e == "exe") =>
+        {
+            let mut cmd = Command::new("wine");
+            cmd.arg(path);
+            cmd
+        }
+        Some(path) => Command::new(path),
+        None => Command::new("rcedit"),
+    }
+}
+
+/// The .ico to embed: used as is if one was given, otherwise packed from the PNGs
+fn icon_file(conf: &ShipConfig, icons: &[String]) -> Result<Option<PathBuf>, String> {
+    match icons {
+        [] => Ok(None),
+        [single] if single.to_lowercase().ends_with(".ico") => Ok(Some(PathBuf::from(single))),
+        pngs => {
+            let out = cache::work_dir(conf)
+                .join("windows")
+                .join(format!("{}.ico", conf.prog.name));
+            if let Some(parent) = out.parent() {
+                std::fs::create_dir_all(parent)
+                    .map_err(|err| format!("failed to create {}: {err}", parent.display()))?;
+            }
+            let ico = pack_ico(pngs)?;
+            std::fs::write(&out, ico)
+                .map_err(|err| format!("failed to write {}: {err}", out.display()))?;
+            Ok(Some(out))
+        }
+    }
+}
+
+/// Packs PNG images into one .ico, which Windows picks the best size from.
+///
+/// Since Vista, icon entries may hold PNG data as is, so nothing needs re-encoding.
+pub fn pack_ico(pngs: &[String]) -> Result<Vec<u8>, String> {
+    let mut images = Vec::new();
+    for path in pngs {
+        let data =
+            std::fs::read(path).map_err(|err| format!("failed to read icon {path}: {err}"))?;
+        let (width, height) =
+            png_size(&data).ok_or_else(|| format!("icon {path} is not a PNG image"))?;
+        if width > 256 || height > 256 {
+            return Err(format!(
+                "icon {path} is {width}x{height}, .ico images can be at most 256x256"
+            ));
+        }
+        images.push((width, height, data));
+    }
+    images.sort_by_key(|(width, _, _)| *width);
+
+    let count = images.len() as u16;
+    let mut ico = Vec::new();
+    ico.extend_from_slice(&0u16.to_le_bytes()); // reserved
+    ico.extend_from_slice(&1u16.to_le_bytes()); // type: icon
+    ico.extend_from_slice(&count.to_le_bytes());
+
+    let mut offset = 6 + 16 * images.len() as u32;
+    for (width, height, data) in &images {
+        // 0 stands for 256 in the one-byte size fields
+        ico.push(if *width == 256 { 0 } else { *width as u8 });
+        ico.push(if *height == 256 { 0 } else { *height as u8 });
+        ico.push(0); // palette size
+        ico.push(0); // reserved
+        ico.extend_from_slice(&1u16.to_le_bytes()); // color planes
+        ico.extend_from_slice(&32u16.to_le_bytes()); // bits per pixel
+        ico.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&offset.to_le_bytes());
+        offset += data.len() as u32;
+    }
+    for (_, _, data) in images {
+        ico.extend_from_slice(&data);
+    }
+
+    Ok(ico)
+}
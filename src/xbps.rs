@@ -0,0 +1,375 @@
+use std::{
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use sha2::Sha256;
+
+use crate::{
+    atomic,
+    checksum::digest_file,
+    completions::{self, ZSH_SITE},
+    compress::Encoder,
+    conf::{CompressionAlgorithm, ShipConfig, Target},
+    desktop,
+    error::{Context, ShipError},
+    gen_::{Artifacts, Generator, Plan},
+    layout::{Entry, Layout, share_dir},
+    license::License,
+    scripts::{self, InstallScripts},
+};
+
+pub struct XbpsGenerator<'a> {
+    pub conf: &'a ShipConfig,
+}
+
+impl<'a> XbpsGenerator<'a> {
+    pub fn new(conf: &'a ShipConfig) -> Self {
+        Self { conf }
+    }
+
+    fn revision(&self) -> u32 {
+        self.conf
+            .xbps
+            .as_ref()
+            .and_then(|x| x.revision)
+            .unwrap_or(1)
+    }
+
+    fn xbps_output_path(&self, version: &str, arch: &str) -> PathBuf {
+        let out = Path::new(&self.conf.out.bin);
+        if out.extension().and_then(|ext| ext.to_str()) == Some("xbps") {
+            return out.to_path_buf();
+        }
+
+        out.join(format!(
+            "{}-{version}_{}.{arch}.xbps",
+            self.conf.prog.name,
+            self.revision()
+        ))
+    }
+
+    /// The string properties of props.plist, sorted by key
+    fn props(&self, version: &str, arch: &str) -> Vec<(&'static str, String)> {
+        let prog = &self.conf.prog;
+        let xbps = self.conf.xbps.as_ref();
+        let pkgver = format!("{}-{version}_{}", prog.name, self.revision());
+
+        let mut props = vec![
+            ("architecture", arch.to_string()),
+            ("maintainer", prog.author.clone()),
+            ("pkgname", prog.name.clone()),
+            ("pkgver", pkgver),
+            (
+                "short_desc",
+                prog.description
+                    .clone()
+                    .unwrap_or_else(|| prog.name.clone()),
+            ),
+            ("version", format!("{version}_{}", self.revision())),
+        ];
+        if let Some(homepage) = xbps.and_then(|x| x.homepage.as_deref()) {
+            props.push(("homepage", homepage.to_string()));
+        }
+        // xbps takes SPDX identifiers, which [files].license may hold
+        let spdx = License::of(self.conf).ok().flatten().and_then(|l| l.spdx());
+        if let Some(license) = xbps.and_then(|x| x.license.as_deref()).or(spdx) {
+            props.push(("license", license.to_string()));
+        }
+        props.sort_by_key(|(key, _)| *key);
+        props
+    }
+
+    fn version_and_arch(&self) -> Result<(&'a str, &'static str), ShipError> {
+        let Some(version) = &self.conf.prog.version else {
+            return Err("the Xbps target requires [prog].version".into());
+        };
+        if version.contains('-') {
+            return Err(format!("xbps versions must not contain `-`, got {version}").into());
+        }
+
+        let Some(arch) = self.conf.prog.arch.xbps() else {
+            return Err(format!(
+                "architecture {:?} is not supported by Void Linux",
+                self.conf.prog.arch
+            )
+            .into());
+        };
+        Ok((version, arch))
+    }
+
+    /// The files of [files] under the install prefix, and their completions
+    fn layout(&self) -> Result<Layout, String> {
+        let mut layout = Layout::resolve(self.conf)?;
+        let completions = completions::entries(self.conf, &share_dir("/usr/bin"), ZSH_SITE)?;
+        layout.files.extend(completions);
+        Ok(layout)
+    }
+
+    fn props_plist(&self, version: &str, arch: &str, installed_size: u64) -> String {
+        let mut dict: Vec<(&str, String)> = self
+            .props(version, arch)
+            .into_iter()
+            .map(|(key, value)| (key, string(&value)))
+            .collect();
+        dict.push(("installed_size", integer(installed_size)));
+        let depends = self.conf.depends(&Target::Xbps);
+        if !depends.is_empty() {
+            let items: Vec<String> = depends
+                .iter()
+                .map(|dep| format!("\t\t{}\n", string(dep)))
+                .collect();
+            dict.push((
+                "run_depends",
+                format!("<array>\n{}\t</array>", items.concat()),
+            ));
+        }
+        dict.sort_by_key(|(key, _)| *key);
+
+        plist(&dict_of(&dict, 1))
+    }
+}
+
+impl<'a> Generator for XbpsGenerator<'a> {
+    fn run(&self) -> Result<Artifacts, ShipError> {
+        let (version, arch) = self.version_and_arch()?;
+
+        let mut layout = self.layout()?;
+        if self.conf.desktop.is_some() {
+            let entries = desktop::entries(self.conf, &layout, &share_dir("/usr/bin"))?;
+            layout.files.extend(entries);
+        }
+
+        let scripts = InstallScripts::load(self.conf)?;
+
+        let mut installed_size = 0;
+        let mut file_dicts = Vec::new();
+        for entry in &layout.files {
+            let (dict, size) = file_dict(entry)
+                .context(|| format!("failed to read {}", entry.source.display()))?;
+            installed_size += size;
+            file_dicts.push(dict);
+        }
+
+        let dirs = layout.dirs();
+        let files_plist = plist(&format!(
+            "\t<key>dirs</key>\n{}\
+             \t<key>files</key>\n{}\
+             \t<key>links</key>\n{}",
+            array(
+                &dirs
+                    .iter()
+                    .map(|dir| dict_of(&[("file", string(dir))], 3))
+                    .collect::<Vec<_>>()
+            ),
+            array(&file_dicts),
+            array(
+                &layout
+                    .symlinks
+                    .iter()
+                    .map(|(link, target)| {
+                        dict_of(&[("file", string(link)), ("target", string(target))], 3)
+                    })
+                    .collect::<Vec<_>>()
+            ),
+        ));
+        let props_plist = self.props_plist(version, arch, installed_size);
+
+        let output_path = self.xbps_output_path(version, arch);
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .context(|| format!("failed to create output directory {}", parent.display()))?;
+        }
+
+        atomic::write_with(&output_path, |tmp| {
+            write_package(
+                tmp,
+                &props_plist,
+                &files_plist,
+                &dirs,
+                &layout,
+                &scripts,
+                self.conf,
+            )
+        })
+        .context(|| format!("failed to write .xbps package at {}", output_path.display()))?;
+
+        Ok(vec![output_path])
+    }
+
+    fn plan(&self) -> Result<Plan, ShipError> {
+        let (version, arch) = self.version_and_arch()?;
+        let layout = self.layout()?;
+
+        let mut fields: Vec<(String, String)> = self
+            .props(version, arch)
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value))
+            .collect();
+        let depends = self.conf.depends(&Target::Xbps);
+        if !depends.is_empty() {
+            fields.push(("run_depends".to_string(), depends.join(", ")));
+        }
+        let mut notes = Vec::new();
+        if self.conf.desktop.is_some() {
+            notes.push("adds a desktop entry from [desktop], generated while building".to_string());
+        }
+
+        Ok(Plan {
+            artifacts: self.artifacts(),
+            files: layout.files,
+            symlinks: layout.symlinks,
+            fields,
+            scripts: scripts::planned(
+                self.conf,
+                ["INSTALL pre", "INSTALL post", "REMOVE pre", "REMOVE post"],
+            ),
+            notes,
+        })
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        match (&self.conf.prog.version, self.conf.prog.arch.xbps()) {
+            (Some(version), Some(arch)) => vec![self.xbps_output_path(version, arch)],
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn file_dict(entry: &Entry) -> std::io::Result<(String, u64)> {
+    let metadata = std::fs::metadata(&entry.source)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let sha256 = digest_file::<Sha256>(&entry.source)?;
+
+    Ok((
+        dict_of(
+            &[
+                ("file", string(&entry.dest)),
+                ("mtime", integer(mtime)),
+                ("sha256", string(&sha256)),
+                ("size", integer(metadata.len())),
+            ],
+            3,
+        ),
+        metadata.len(),
+    ))
+}
+
+// scripts and metadata plists come first, as xbps-install reads them before extracting
+fn write_package(
+    path: &Path,
+    props_plist: &str,
+    files_plist: &str,
+    dirs: &[String],
+    layout: &Layout,
+    scripts: &InstallScripts,
+    conf: &ShipConfig,
+) -> std::io::Result<()> {
+    let encoder = Encoder::for_config(
+        File::create(path)?,
+        conf.compression(&Target::Xbps),
+        (CompressionAlgorithm::Zstd, 9),
+        conf.compression_threads(),
+    )?;
+    let mut tar = tar::Builder::new(encoder);
+
+    let install = scripts.xbps_install();
+    let remove = scripts.xbps_remove();
+    let metadata = [
+        ("./INSTALL", install.as_deref(), 0o755),
+        ("./REMOVE", remove.as_deref(), 0o755),
+        ("./props.plist", Some(props_plist), 0o644),
+        ("./files.plist", Some(files_plist), 0o644),
+    ];
+    for (name, contents, mode) in metadata {
+        let Some(contents) = contents else {
+            continue;
+        };
+
+        let mut header = tar::Header::new_gnu();
+        header.set_path(name)?;
+        header.set_mode(mode);
+        header.set_size(contents.len() as u64);
+        header.set_cksum();
+        tar.append(&header, contents.as_bytes())?;
+    }
+
+    for dir in dirs {
+        let mut header = tar::Header::new_gnu();
+        header.set_path(format!(".{dir}"))?;
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_mode(0o755);
+        header.set_size(0);
+        header.set_cksum();
+        tar.append(&header, std::io::empty())?;
+    }
+
+    for entry in &layout.files {
+        entry.append_to(&mut tar, format!(".{}", entry.dest))?;
+    }
+
+    for (link, target) in &layout.symlinks {
+        let mut header = tar::Header::new_gnu();
+        header.set_path(format!(".{link}"))?;
+        header.set_entry_type(tar::EntryType::symlink());
+        header.set_link_name(target)?;
+        header.set_mode(0o777);
+        header.set_size(0);
+        header.set_cksum();
+        tar.append(&header, std::io::empty())?;
+    }
+
+    tar.into_inner()?.finish()?.flush()
+}
+
+fn plist(body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple Computer//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n<dict>\n{body}</dict>\n</plist>\n"
+    )
+}
+
+fn dict_of(entries: &[(&str, String)], depth: usize) -> String {
+    let indent = "\t".repeat(depth);
+    let mut out = String::new();
+    if depth > 1 {
+        out.push_str(&format!("{}<dict>\n", "\t".repeat(depth - 1)));
+    }
+    for (key, value) in entries {
+        out.push_str(&format!(
+            "{indent}<key>{}</key>\n{indent}{value}\n",
+            escape(key)
+        ));
+    }
+    if depth > 1 {
+        out.push_str(&format!("{}</dict>\n", "\t".repeat(depth - 1)));
+    }
+    out
+}
+
+fn array(items: &[String]) -> String {
+    format!("\t<array>\n{}\t</array>\n", items.concat())
+}
+
+fn string(value: &str) -> String {
+    format!("<string>{}</string>", escape(value))
+}
+
+fn integer(value: u64) -> String {
+    format!("<integer>{value}</integer>")
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
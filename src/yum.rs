@@ -0,0 +1,615 @@
+use std::{
+    fs::File,
+    io::{BufReader, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    atomic,
+    checksum::{digest_file, to_hex},
+    conf::{ShipConfig, Target},
+    report::{BuildReport, file_name},
+    signing,
+};
+
+/// Where the metadata goes, relative to the repository
+pub const REPODATA_DIR: &str = "repodata";
+
+/// Regenerates the repodata of the dnf/yum repository in `dir` from every RPM under it,
+/// after copying in the RPMs of the last build, if there are any.
+///
+/// The metadata files are named after their checksum, so a CDN never serves a stale
+/// primary.xml.gz next to a fresh repomd.xml. repomd.xml is signed into repomd.xml.asc
+/// with `[signing]` when it's set; returns the files that were written.
+pub fn update(conf: &ShipConfig, dir: &Path) -> Result<Vec<PathBuf>, String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|err| format!("failed to create {}: {err}", dir.display()))?;
+
+    // ship builds no RPMs yet, those from other tools are simply put into the directory
+    if let Ok(report) = BuildReport::load(&conf.prog.name) {
+        for rpm in report.artifacts.iter().filter(|a| a.target == Target::Rpm) {
+            let dest = dir.join(&rpm.file);
+            atomic::write_with(&dest, |tmp| std::fs::copy(&rpm.path, tmp).map(|_| ()))
+                .map_err(|err| format!("failed to copy {} into the repository: {err}", rpm.file))?;
+            info!("added {}", rpm.file);
+        }
+    }
+
+    let mut rpms = Vec::new();
+    find_rpms(dir, &mut rpms).map_err(|err| format!("failed to read {}: {err}", dir.display()))?;
+    rpms.sort();
+    if rpms.is_empty() {
+        return Err(format!("no RPMs in {}", dir.display()));
+    }
+    let packages = rpms
+        .iter()
+        .map(|path| Package::read(dir, path))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let repodata = dir.join(REPODATA_DIR);
+    std::fs::create_dir_all(&repodata)
+        .map_err(|err| format!("failed to create {}: {err}", repodata.display()))?;
+    let timestamp = chrono::Utc::now().timestamp();
+    let mut written = Vec::new();
+    let mut data = String::new();
+    for (kind, xml) in [
+        ("primary", primary(&packages)),
+        ("filelists", filelists(&packages)),
+        ("other", other(&packages)),
+    ] {
+        let compressed =
+            gzip(&xml).map_err(|err| format!("failed to compress the {kind} metadata: {err}"))?;
+        let checksum = to_hex(&Sha256::digest(&compressed));
+        let path = repodata.join(format!("{checksum}-{kind}.xml.gz"));
+        data.push_str(&format!(
+            "  <data type=\"{kind}\">\n\
+             \x20   <checksum type=\"sha256\">{checksum}</checksum>\n\
+             \x20   <open-checksum type=\"sha256\">{}</open-checksum>\n\
+             \x20   <location href=\"{REPODATA_DIR}/{checksum}-{kind}.xml.gz\"/>\n\
+             \x20   <timestamp>{timestamp}</timestamp>\n\
+             \x20   <size>{}</size>\n\
+             \x20   <open-size>{}</open-size>\n\
+             \x20 </data>\n",
+            to_hex(&Sha256::digest(xml.as_bytes())),
+            compressed.len(),
+            xml.len(),
+        ));
+        atomic::write(&path, compressed)
+            .map_err(|err| format!("failed to write {}: {err}", path.display()))?;
+        written.push(path);
+    }
+
+    let repomd_path = repodata.join("repomd.xml");
+    let repomd = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <repomd xmlns=\"http://linux.duke.edu/metadata/repo\" xmlns:rpm=\"http://linux.duke.edu/metadata/rpm\">\n\
+         \x20 <revision>{timestamp}</revision>\n\
+         {data}\
+         </repomd>\n"
+    );
+    atomic::write(&repomd_path, repomd)
+        .map_err(|err| format!("failed to write {}: {err}", repomd_path.display()))?;
+    written.push(repomd_path.clone());
+
+    let signature = repodata.join("repomd.xml.asc");
+    match &conf.signing {
+        Some(settings) => {
+            signing::detach_sign(settings, &repomd_path, &signature, true)?;
+            written.push(signature);
+        }
+        None => {
+            std::fs::remove_file(&signature).ok();
+            warn!(
+                "no [signing] section, the repository is unsigned, set repo_gpgcheck=0 to use it"
+            );
+        }
+    }
+
+    // metadata of earlier runs, which the new repomd.xml no longer points at
+    if let Ok(entries) = std::fs::read_dir(&repodata) {
+        for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+            if path.to_string_lossy().ends_with(".xml.gz") && !written.contains(&path) {
+                std::fs::remove_file(&path).ok();
+            }
+        }
+    }
+
+    Ok(written)
+}
+
+fn gzip(xml: &str) -> std::io::Result<Vec<u8>> {
+    let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+    gz.write_all(xml.as_bytes())?;
+    gz.finish()
+}
+
+fn find_rpms(dir: &Path, rpms: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            if !path.ends_with(REPODATA_DIR) {
+                find_rpms(&path, rpms)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "rpm") {
+            rpms.push(path);
+        }
+    }
+    Ok(())
+}
+
+// header tags, from rpmtag.h
+const TAG_NAME: u32 = 1000;
+const TAG_VERSION: u32 = 1001;
+const TAG_RELEASE: u32 = 1002;
+const TAG_EPOCH: u32 = 1003;
+const TAG_SUMMARY: u32 = 1004;
+const TAG_DESCRIPTION: u32 = 1005;
+const TAG_BUILDTIME: u32 = 1006;
+const TAG_BUILDHOST: u32 = 1007;
+const TAG_SIZE: u32 = 1009;
+const TAG_VENDOR: u32 = 1011;
+const TAG_LICENSE: u32 = 1014;
+const TAG_PACKAGER: u32 = 1015;
+const TAG_GROUP: u32 = 1016;
+const TAG_URL: u32 = 1020;
+const TAG_ARCH: u32 = 1022;
+const TAG_FILEMODES: u32 = 1030;
+const TAG_SOURCERPM: u32 = 1044;
+const TAG_ARCHIVESIZE: u32 = 1046;
+const TAG_PROVIDENAME: u32 = 1047;
+const TAG_REQUIREFLAGS: u32 = 1048;
+const TAG_REQUIRENAME: u32 = 1049;
+const TAG_REQUIREVERSION: u32 = 1050;
+const TAG_CONFLICTFLAGS: u32 = 1053;
+const TAG_CONFLICTNAME: u32 = 1054;
+const TAG_CONFLICTVERSION: u32 = 1055;
+const TAG_CHANGELOGTIME: u32 = 1080;
+const TAG_CHANGELOGNAME: u32 = 1081;
+const TAG_CHANGELOGTEXT: u32 = 1082;
+const TAG_OBSOLETENAME: u32 = 1090;
+const TAG_PROVIDEFLAGS: u32 = 1112;
+const TAG_PROVIDEVERSION: u32 = 1113;
+const TAG_OBSOLETEFLAGS: u32 = 1114;
+const TAG_OBSOLETEVERSION: u32 = 1115;
+const TAG_DIRINDEXES: u32 = 1116;
+const TAG_BASENAMES: u32 = 1117;
+const TAG_DIRNAMES: u32 = 1118;
+// the payload size in the signature header, for RPMs whose main header lacks it
+const SIGTAG_PAYLOADSIZE: u32 = 1007;
+
+const HEADER_MAGIC: [u8; 3] = [0x8e, 0xad, 0xe8];
+const LEAD_SIZE: u64 = 96;
+
+/// A header structure of an RPM: the signature or the main header
+struct Header {
+    index: Vec<(u32, u32, usize, usize)>, // tag, type, offset into data, count
+    data: Vec<u8>,
+}
+
+impl Header {
+    // returns the header and how many bytes it took
+    fn read(reader: &mut impl Read) -> std::io::Result<(Self, u64)> {
+        let invalid = |what: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, what);
+        let mut intro = [0; 16];
+        reader.read_exact(&mut intro)?;
+        if intro[..3] != HEADER_MAGIC {
+            return Err(invalid("not an RPM header"));
+        }
+        let count = u32::from_be_bytes(intro[8..12].try_into().unwrap_or_default()) as usize;
+        let size = u32::from_be_bytes(intro[12..16].try_into().unwrap_or_default()) as usize;
+        if count > 0x10000 || size > 0x1000_0000 {
+            return Err(invalid("RPM header is too large"));
+        }
+
+        let mut index = vec![0; count * 16];
+        reader.read_exact(&mut index)?;
+        let mut data = vec![0; size];
+        reader.read_exact(&mut data)?;
+        let number = |bytes: &[u8]| u32::from_be_bytes(bytes.try_into().unwrap_or_default());
+        let index = index
+            .chunks_exact(16)
+            .map(|entry| {
+                (
+                    number(&entry[0..4]),
+                    number(&entry[4..8]),
+                    number(&entry[8..12]) as usize,
+                    number(&entry[12..16]) as usize,
+                )
+            })
+            .collect();
+        Ok((Self { index, data }, (16 + count * 16 + size) as u64))
+    }
+
+    fn entry(&self, tag: u32) -> Option<(u32, &[u8], usize)> {
+        let &(_, kind, offset, count) = self.index.iter().find(|entry| entry.0 == tag)?;
+        Some((kind, self.data.get(offset..)?, count))
+    }
+
+    /// A string, or the first of an array or of the translations
+    fn string(&self, tag: u32) -> Option<String> {
+        self.strings(tag).into_iter().next()
+    }
+
+    fn strings(&self, tag: u32) -> Vec<String> {
+        let Some((kind, data, count)) = self.entry(tag) else {
+            return Vec::new();
+        };
+        // STRING, STRING_ARRAY or I18NSTRING; a STRING has a count of 1
+        if !matches!(kind, 6 | 8 | 9) {
+            return Vec::new();
+        }
+        data.split(|&byte| byte == 0)
+            .take(count)
+            .map(|string| String::from_utf8_lossy(string).into_owned())
+            .collect()
+    }
+
+    fn numbers(&self, tag: u32) -> Vec<u64> {
+        let Some((kind, data, count)) = self.entry(tag) else {
+            return Vec::new();
+        };
+        let width = match kind {
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 => 8,
+            _ => return Vec::new(),
+        };
+        data.chunks_exact(width)
+            .take(count)
+            .map(|bytes| {
+                bytes
+                    .iter()
+                    .fold(0, |number, &byte| (number << 8) | byte as u64)
+            })
+            .collect()
+    }
+
+    fn number(&self, tag: u32) -> Option<u64> {
+        self.numbers(tag).into_iter().next()
+    }
+}
+
+/// A dependency, provided capability, conflict or obsoleted package
+struct Relation {
+    name: String,
+    flags: u64,
+    version: String,
+}
+
+/// What the metadata says about one RPM
+struct Package {
+    header: Header,
+    href: String,
+    pkgid: String,
+    size: u64,
+    mtime: u64,
+    header_range: (u64, u64),
+    archive_size: u64,
+}
+
+impl Package {
+    fn read(dir: &Path, path: &Path) -> Result<Self, String> {
+        let read_err = |err| format!("failed to read {}: {err}", path.display());
+        let file = File::open(path).map_err(read_err)?;
+        let metadata = file.metadata().map_err(read_err)?;
+        let mut reader = BufReader::new(file);
+
+        let mut lead = [0; LEAD_SIZE as usize];
+        reader.read_exact(&mut lead).map_err(read_err)?;
+        if lead[..4] != [0xed, 0xab, 0xee, 0xdb] {
+            return Err(format!("{} is not an RPM", path.display()));
+        }
+        let (signature, signature_size) = Header::read(&mut reader).map_err(read_err)?;
+        // the main header starts on the next 8 byte boundary
+        let padding = (8 - signature_size % 8) % 8;
+        std::io::copy(&mut (&mut reader).take(padding), &mut std::io::sink()).map_err(read_err)?;
+        let start = LEAD_SIZE + signature_size + padding;
+        let (header, header_size) = Header::read(&mut reader).map_err(read_err)?;
+
+        let href = path
+            .strip_prefix(dir)
+            .map(|relative| relative.to_string_lossy().replace('\\', "/"))
+            .unwrap_or_else(|_| file_name(path));
+        let archive_size = header
+            .number(TAG_ARCHIVESIZE)
+            .or_else(|| signature.number(SIGTAG_PAYLOADSIZE))
+            .unwrap_or_default();
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        Ok(Self {
+            header,
+            href,
+            pkgid: digest_file::<Sha256>(path).map_err(read_err)?,
+            size: metadata.len(),
+            mtime,
+            header_range: (start, start + header_size),
+            archive_size,
+        })
+    }
+
+    fn name(&self) -> String {
+        self.header.string(TAG_NAME).unwrap_or_default()
+    }
+
+    // source RPMs have no SOURCERPM and are listed as `src`
+    fn arch(&self) -> String {
+        if self.header.string(TAG_SOURCERPM).is_none() {
+            return "src".to_string();
+        }
+        self.header.string(TAG_ARCH).unwrap_or_default()
+    }
+
+    fn version(&self) -> String {
+        format!(
+            "<version epoch=\"{}\" ver=\"{}\" rel=\"{}\"/>",
+            self.header.number(TAG_EPOCH).unwrap_or_default(),
+            escape(&self.header.string(TAG_VERSION).unwrap_or_default()),
+            escape(&self.header.string(TAG_RELEASE).unwrap_or_default()),
+        )
+    }
+
+    fn relations(&self, names: u32, flags: u32, versions: u32) -> Vec<Relation> {
+        let flags = self.header.numbers(flags);
+        let versions = self.header.strings(versions);
+        self.header
+            .strings(names)
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| Relation {
+                name,
+                flags: flags.get(index).copied().unwrap_or_default(),
+                version: versions.get(index).cloned().unwrap_or_default(),
+            })
+            .collect()
+    }
+
+    /// Every path the package installs, and whether it's a directory
+    fn files(&self) -> Vec<(String, bool)> {
+        let dirs = self.header.strings(TAG_DIRNAMES);
+        let indexes = self.header.numbers(TAG_DIRINDEXES);
+        let modes = self.header.numbers(TAG_FILEMODES);
+        self.header
+            .strings(TAG_BASENAMES)
+            .into_iter()
+            .enumerate()
+            .map(|(index, base)| {
+                let dir = indexes
+                    .get(index)
+                    .and_then(|&dir| dirs.get(dir as usize))
+                    .map(String::as_str)
+                    .unwrap_or_default();
+                let is_dir = modes
+                    .get(index)
+                    .is_some_and(|mode| mode & 0o170000 == 0o040000);
+                (format!("{dir}{base}"), is_dir)
+            })
+            .collect()
+    }
+}
+
+// RPMSENSE_LESS, RPMSENSE_GREATER and RPMSENSE_EQUAL
+fn comparison(flags: u64) -> Option<&'static str> {
+    match flags & 0b1110 {
+        2 => Some("LT"),
+        4 => Some("GT"),
+        8 => Some("EQ"),
+        10 => Some("LE"),
+        12 => Some("GE"),
+        _ => None,
+    }
+}
+
+fn relation_list(tag: &str, relations: &[Relation], requires: bool) -> String {
+    // rpmlib() requirements are satisfied by rpm itself, createrepo leaves them out too
+    let entries: Vec<String> = relations
+        .iter()
+        .filter(|relation| !(requires && relation.name.starts_with("rpmlib(")))
+        .map(|relation| {
+            let mut entry = format!("      <rpm:entry name=\"{}\"", escape(&relation.name));
+            if let Some(flags) = comparison(relation.flags)
+                && !relation.version.is_empty()
+            {
+                // `[epoch:]version[-release]`
+                let (epoch, rest) = relation
+                    .version
+                    .split_once(':')
+                    .unwrap_or(("0", &relation.version));
+                let (ver, rel) = match rest.rsplit_once('-') {
+                    Some((ver, rel)) => (ver, Some(rel)),
+                    None => (rest, None),
+                };
+                entry.push_str(&format!(
+                    " flags=\"{flags}\" epoch=\"{}\" ver=\"{}\"",
+                    escape(epoch),
+                    escape(ver)
+                ));
+                if let Some(rel) = rel {
+                    entry.push_str(&format!(" rel=\"{}\"", escape(rel)));
+                }
+            }
+            // RPMSENSE_PREREQ, RPMSENSE_SCRIPT_PRE and RPMSENSE_SCRIPT_POST
+            if requires && relation.flags & (1 << 6 | 1 << 9 | 1 << 10) != 0 {
+                entry.push_str(" pre=\"1\"");
+            }
+            entry.push_str("/>\n");
+            entry
+        })
+        .collect();
+    if entries.is_empty() {
+        return String::new();
+    }
+    format!("    <rpm:{tag}>\n{}    </rpm:{tag}>\n", entries.concat())
+}
+
+// files dnf resolves dependencies on without downloading filelists, like createrepo
+fn is_primary_file(path: &str) -> bool {
+    path.contains("bin/") || path.starts_with("/etc/") || path == "/usr/lib/sendmail"
+}
+
+fn primary(packages: &[Package]) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <metadata xmlns=\"http://linux.duke.edu/metadata/common\" xmlns:rpm=\"http://linux.duke.edu/metadata/rpm\" packages=\"{}\">\n",
+        packages.len()
+    );
+    for package in packages {
+        let header = &package.header;
+        let text = |tag| escape(&header.string(tag).unwrap_or_default());
+        xml.push_str(&format!(
+            "<package type=\"rpm\">\n\
+             \x20 <name>{}</name>\n\
+             \x20 <arch>{}</arch>\n\
+             \x20 {}\n\
+             \x20 <checksum type=\"sha256\" pkgid=\"YES\">{}</checksum>\n\
+             \x20 <summary>{}</summary>\n\
+             \x20 <description>{}</description>\n\
+             \x20 <packager>{}</packager>\n\
+             \x20 <url>{}</url>\n\
+             \x20 <time file=\"{}\" build=\"{}\"/>\n\
+             \x20 <size package=\"{}\" installed=\"{}\" archive=\"{}\"/>\n\
+             \x20 <location href=\"{}\"/>\n\
+             \x20 <format>\n\
+             \x20   <rpm:license>{}</rpm:license>\n\
+             \x20   <rpm:vendor>{}</rpm:vendor>\n\
+             \x20   <rpm:group>{}</rpm:group>\n\
+             \x20   <rpm:buildhost>{}</rpm:buildhost>\n\
+             \x20   <rpm:sourcerpm>{}</rpm:sourcerpm>\n\
+             \x20   <rpm:header-range start=\"{}\" end=\"{}\"/>\n",
+            escape(&package.name()),
+            escape(&package.arch()),
+            package.version(),
+            package.pkgid,
+            text(TAG_SUMMARY),
+            text(TAG_DESCRIPTION),
+            text(TAG_PACKAGER),
+            text(TAG_URL),
+            package.mtime,
+            header.number(TAG_BUILDTIME).unwrap_or_default(),
+            package.size,
+            header.number(TAG_SIZE).unwrap_or_default(),
+            package.archive_size,
+            escape(&package.href),
+            text(TAG_LICENSE),
+            text(TAG_VENDOR),
+            text(TAG_GROUP),
+            text(TAG_BUILDHOST),
+            text(TAG_SOURCERPM),
+            package.header_range.0,
+            package.header_range.1,
+        ));
+        for (tag, names, flags, versions) in [
+            (
+                "provides",
+                TAG_PROVIDENAME,
+                TAG_PROVIDEFLAGS,
+                TAG_PROVIDEVERSION,
+            ),
+            (
+                "requires",
+                TAG_REQUIRENAME,
+                TAG_REQUIREFLAGS,
+                TAG_REQUIREVERSION,
+            ),
+            (
+                "conflicts",
+                TAG_CONFLICTNAME,
+                TAG_CONFLICTFLAGS,
+                TAG_CONFLICTVERSION,
+            ),
+            (
+                "obsoletes",
+                TAG_OBSOLETENAME,
+                TAG_OBSOLETEFLAGS,
+                TAG_OBSOLETEVERSION,
+            ),
+        ] {
+            let relations = package.relations(names, flags, versions);
+            xml.push_str(&relation_list(tag, &relations, tag == "requires"));
+        }
+        for (path, is_dir) in package.files() {
+            if is_primary_file(&path) {
+                xml.push_str(&file_element(&path, is_dir, "    "));
+            }
+        }
+        xml.push_str("  </format>\n</package>\n");
+    }
+    xml.push_str("</metadata>\n");
+    xml
+}
+
+fn filelists(packages: &[Package]) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <filelists xmlns=\"http://linux.duke.edu/metadata/filelists\" packages=\"{}\">\n",
+        packages.len()
+    );
+    for package in packages {
+        xml.push_str(&format!(
+            "<package pkgid=\"{}\" name=\"{}\" arch=\"{}\">\n  {}\n",
+            package.pkgid,
+            escape(&package.name()),
+            escape(&package.arch()),
+            package.version(),
+        ));
+        for (path, is_dir) in package.files() {
+            xml.push_str(&file_element(&path, is_dir, "  "));
+        }
+        xml.push_str("</package>\n");
+    }
+    xml.push_str("</filelists>\n");
+    xml
+}
+
+fn other(packages: &[Package]) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <otherdata xmlns=\"http://linux.duke.edu/metadata/other\" packages=\"{}\">\n",
+        packages.len()
+    );
+    for package in packages {
+        xml.push_str(&format!(
+            "<package pkgid=\"{}\" name=\"{}\" arch=\"{}\">\n  {}\n",
+            package.pkgid,
+            escape(&package.name()),
+            escape(&package.arch()),
+            package.version(),
+        ));
+        let header = &package.header;
+        let times = header.numbers(TAG_CHANGELOGTIME);
+        let texts = header.strings(TAG_CHANGELOGTEXT);
+        for (index, author) in header.strings(TAG_CHANGELOGNAME).iter().enumerate() {
+            xml.push_str(&format!(
+                "  <changelog author=\"{}\" date=\"{}\">{}</changelog>\n",
+                escape(author),
+                times.get(index).copied().unwrap_or_default(),
+                escape(texts.get(index).map(String::as_str).unwrap_or_default()),
+            ));
+        }
+        xml.push_str("</package>\n");
+    }
+    xml.push_str("</otherdata>\n");
+    xml
+}
+
+fn file_element(path: &str, is_dir: bool, indent: &str) -> String {
+    if is_dir {
+        format!("{indent}<file type=\"dir\">{}</file>\n", escape(path))
+    } else {
+        format!("{indent}<file>{}</file>\n", escape(path))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
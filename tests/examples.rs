@@ -0,0 +1,383 @@
+//! Builds the projects under examples/ with the ship binary and checks what ends up in the
+//! artifacts. Each example is copied to a scratch directory and built once per test run.
+
+use std::{
+    collections::BTreeSet,
+    io::{Cursor, Read},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+};
+
+use serde_json::Value;
+
+struct Project {
+    dir: PathBuf,
+    output: String, // stdout and stderr of the first build
+}
+
+impl Project {
+    fn build(example: &str) -> Self {
+        let source = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("examples")
+            .join(example);
+        let dir =
+            std::env::temp_dir().join(format!("ship-example-{example}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        copy_dir(&source, &dir);
+
+        let project = Project {
+            output: String::new(),
+            dir,
+        };
+        let output = project.ship(&[]);
+        Project { output, ..project }
+    }
+
+    /// Runs ship in the project and returns what it printed, failing the test if it fails
+    fn ship(&self, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_ship"))
+            .arg("--non-interactive")
+            .args(args)
+            .current_dir(&self.dir)
+            .output()
+            .expect("failed to run ship");
+        let printed = format!(
+            "{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+        assert!(
+            output.status.success(),
+            "ship {args:?} failed in {}:\n{printed}",
+            self.dir.display()
+        );
+        printed
+    }
+
+    fn artifact(&self, file: &str) -> PathBuf {
+        let path = self.dir.join("dist").join(file);
+        assert!(path.is_file(), "{} was not built", path.display());
+        path
+    }
+
+    fn report(&self, name: &str) -> Value {
+        let path = self.dir.join(".ship/reports").join(format!("{name}.json"));
+        serde_json::from_slice(&std::fs::read(&path).expect("no build report"))
+            .expect("invalid build report")
+    }
+}
+
+fn cli() -> &'static Project {
+    static CLI: OnceLock<Project> = OnceLock::new();
+    CLI.get_or_init(|| Project::build("cli"))
+}
+
+fn gui() -> &'static Project {
+    static GUI: OnceLock<Project> = OnceLock::new();
+    GUI.get_or_init(|| Project::build("gui"))
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir_all(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let dest = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &dest);
+        } else {
+            // keeps the permissions, so scripts stay executable
+            std::fs::copy(entry.path(), dest).unwrap();
+        }
+    }
+}
+
+fn decompress(name: &str, data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    if name.ends_with(".gz") {
+        flate2::read::GzDecoder::new(data)
+            .read_to_end(&mut out)
+            .unwrap();
+    } else if name.ends_with(".xz") {
+        xz2::read::XzDecoder::new(data)
+            .read_to_end(&mut out)
+            .unwrap();
+    } else if name.ends_with(".zst") || name.ends_with(".xbps") {
+        zstd::stream::copy_decode(data, &mut out).unwrap();
+    } else {
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+/// Every entry of a tarball, as (path without a leading `./`, contents, symlink target)
+fn tar_entries(tar: &[u8]) -> Vec<(String, Vec<u8>, Option<String>)> {
+    let mut archive = tar::Archive::new(Cursor::new(tar));
+    archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().to_string_lossy().into_owned();
+            let link = entry
+                .link_name()
+                .unwrap()
+                .map(|link| link.to_string_lossy().into_owned());
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents).unwrap();
+            (path.trim_start_matches("./").to_string(), contents, link)
+        })
+        .collect()
+}
+
+fn paths(entries: &[(String, Vec<u8>, Option<String>)]) -> BTreeSet<&str> {
+    entries
+        .iter()
+        .map(|(path, _, _)| path.trim_end_matches('/'))
+        .collect()
+}
+
+fn file<'a>(entries: &'a [(String, Vec<u8>, Option<String>)], path: &str) -> &'a [u8] {
+    entries
+        .iter()
+        .find(|(p, _, _)| p == path)
+        .map(|(_, contents, _)| contents.as_slice())
+        .unwrap_or_else(|| panic!("{path} is missing"))
+}
+
+fn tarball(path: &Path) -> Vec<(String, Vec<u8>, Option<String>)> {
+    let name = path.to_string_lossy();
+    tar_entries(&decompress(&name, &std::fs::read(path).unwrap()))
+}
+
+/// The entries of the data.tar.* or control.tar.* member of a .deb
+fn deb_member(path: &Path, member: &str) -> Vec<(String, Vec<u8>, Option<String>)> {
+    let mut archive = ar::Archive::new(std::fs::File::open(path).unwrap());
+    while let Some(entry) = archive.next_entry() {
+        let mut entry = entry.unwrap();
+        let name = String::from_utf8_lossy(entry.header().identifier())
+            .trim_end_matches('/')
+            .to_string();
+        if name.starts_with(member) {
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            return tar_entries(&decompress(&name, &data));
+        }
+    }
+    panic!("{} has no {member} member", path.display());
+}
+
+#[test]
+fn cli_report_lists_every_target() {
+    let report = cli().report("hello-cli");
+    let targets: BTreeSet<&str> = report["artifacts"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|artifact| artifact["target"].as_str().unwrap())
+        .collect();
+
+    assert_eq!(
+        targets,
+        BTreeSet::from(["Appcast", "Archive", "Deb", "DebSource", "Ebuild", "Xbps"])
+    );
+    assert_eq!(report["version"], "1.2.0");
+}
+
+#[test]
+fn cli_archive_installs_binary_and_manpage() {
+    let entries = tarball(&cli().artifact("hello-cli-1.2.0-amd64.tar.gz"));
+    let paths = paths(&entries);
+
+    for path in [
+        "hello-cli-1.2.0/hello-cli",
+        "hello-cli-1.2.0/share/man/man1/hello-cli.1.gz",
+        "hello-cli-1.2.0/install.sh",
+        "hello-cli-1.2.0/uninstall.sh",
+    ] {
+        assert!(paths.contains(path), "{path} missing from {paths:?}");
+    }
+}
+
+#[test]
+fn cli_deb_has_control_fields_and_docs() {
+    let deb = cli().artifact("hello-cli_1.2.0_amd64.deb");
+
+    let control = deb_member(&deb, "control.tar");
+    let fields = String::from_utf8_lossy(file(&control, "control")).into_owned();
+    for field in [
+        "Package: hello-cli",
+        "Version: 1.2.0",
+        "Section: utils",
+        "Priority: optional",
+        "Homepage: https://example.com/hello-cli",
+        "Description: Prints a greeting",
+    ] {
+        assert!(fields.contains(field), "`{field}` missing from:\n{fields}");
+    }
+    let md5sums = String::from_utf8_lossy(file(&control, "md5sums")).into_owned();
+    assert!(md5sums.contains("  opt/hello-cli/hello-cli\n"), "{md5sums}");
+
+    let data = deb_member(&deb, "data.tar");
+    let paths = paths(&data);
+    for path in [
+        "opt/hello-cli/hello-cli",
+        "usr/share/man/man1/hello-cli.1.gz",
+        "usr/share/bash-completion/completions/hello-cli",
+        "usr/share/doc/hello-cli/copyright",
+    ] {
+        assert!(paths.contains(path), "{path} missing from {paths:?}");
+    }
+    let link = data
+        .iter()
+        .find(|(path, _, _)| path == "usr/bin/hello-cli")
+        .and_then(|(_, _, link)| link.clone());
+    assert_eq!(link.as_deref(), Some("/opt/hello-cli/hello-cli"));
+
+    let copyright =
+        String::from_utf8_lossy(file(&data, "usr/share/doc/hello-cli/copyright")).into_owned();
+    assert!(copyright.contains("License: MIT"), "{copyright}");
+}
+
+#[test]
+fn cli_xbps_props() {
+    let entries = tarball(&cli().artifact("hello-cli-1.2.0_1.x86_64.xbps"));
+    let props = String::from_utf8_lossy(file(&entries, "props.plist")).into_owned();
+
+    assert!(
+        props.contains("<string>hello-cli-1.2.0_1</string>"),
+        "{props}"
+    );
+    // taken from [files].license, since [xbps].license isn't set
+    assert!(props.contains("<string>MIT</string>"), "{props}");
+    assert!(paths(&entries).contains("opt/hello-cli/hello-cli"));
+}
+
+#[test]
+fn cli_source_packages() {
+    let dsc = std::fs::read_to_string(cli().artifact("hello-cli_1.2.0-1.dsc")).unwrap();
+    assert!(dsc.contains("Version: 1.2.0-1"), "{dsc}");
+    assert!(dsc.contains("hello-cli_1.2.0.orig.tar.xz"), "{dsc}");
+
+    let debian = tarball(&cli().artifact("hello-cli_1.2.0-1.debian.tar.xz"));
+    let control = String::from_utf8_lossy(file(&debian, "debian/control")).into_owned();
+    assert!(control.contains("Section: utils"), "{control}");
+
+    let ebuild = std::fs::read_to_string(cli().artifact("hello-cli-1.2.0.ebuild")).unwrap();
+    assert!(
+        ebuild.contains("https://example.com/releases/hello-cli-1.2.0.tar.xz"),
+        "{ebuild}"
+    );
+}
+
+#[test]
+fn cli_appcast_describes_the_other_artifacts() {
+    let feed: Value =
+        serde_json::from_slice(&std::fs::read(cli().artifact("latest.json")).unwrap()).unwrap();
+    let report = cli().report("hello-cli");
+
+    assert_eq!(feed["version"], "1.2.0");
+    for artifact in feed["artifacts"].as_array().unwrap() {
+        let file = artifact["file"].as_str().unwrap();
+        assert_eq!(
+            artifact["url"],
+            format!("https://example.com/releases/{file}")
+        );
+        let reported = report["artifacts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|reported| reported["file"] == file)
+            .unwrap_or_else(|| panic!("{file} is in the feed but not the report"));
+        assert_eq!(artifact["sha256"], reported["sha256"]);
+    }
+}
+
+#[test]
+fn cli_rebuild_skips_unchanged_targets() {
+    let output = cli().ship(&[]);
+    assert!(output.contains("Xbps is up to date"), "{output}");
+    assert!(output.contains("Archive is up to date"), "{output}");
+}
+
+#[test]
+fn gui_archive_ships_assets_without_excluded_files() {
+    let entries = tarball(&gui().artifact("hello-gui-0.3.1-all.tar.gz"));
+    let paths = paths(&entries);
+
+    assert!(paths.contains("hello-gui-0.3.1/bin/hello-gui"));
+    assert!(paths.contains("hello-gui-0.3.1/assets/themes/default.css"));
+    assert!(
+        !paths.iter().any(|path| path.ends_with(".orig")),
+        "excluded file packaged: {paths:?}"
+    );
+}
+
+#[test]
+fn gui_deb_has_desktop_entry_and_icon() {
+    let deb = gui().artifact("hello-gui_0.3.1_all.deb");
+
+    let data = deb_member(&deb, "data.tar");
+    let paths = paths(&data);
+    assert!(paths.contains("usr/share/icons/hicolor/scalable/apps/hello-gui.svg"));
+    assert!(!paths.iter().any(|path| path.ends_with(".orig")));
+
+    let desktop = String::from_utf8_lossy(file(&data, "usr/share/applications/hello-gui.desktop"))
+        .into_owned();
+    for line in [
+        "Name=hello-gui",
+        "Exec=hello-gui %F",
+        "Icon=hello-gui",
+        "Categories=Utility;",
+        "MimeType=text/x-hello;",
+    ] {
+        assert!(desktop.contains(line), "`{line}` missing from:\n{desktop}");
+    }
+
+    let metainfo = String::from_utf8_lossy(file(
+        &data,
+        "usr/share/metainfo/com.example.HelloGui.appdata.xml",
+    ))
+    .into_owned();
+    for line in [
+        "<component type=\"desktop-application\">",
+        "<summary>Says hello in a window</summary>",
+        "<launchable type=\"desktop-id\">hello-gui.desktop</launchable>",
+        "<release version=\"0.3.1\" date=\"2025-01-15\"/>",
+    ] {
+        assert!(
+            metainfo.contains(line),
+            "`{line}` missing from:\n{metainfo}"
+        );
+    }
+
+    let control = deb_member(&deb, "control.tar");
+    let fields = String::from_utf8_lossy(file(&control, "control")).into_owned();
+    assert!(
+        fields.contains(
+            "Description: Says hello in a window\n hello-gui shows a greeting in a window.\n .\n"
+        ),
+        "{fields}"
+    );
+}
+
+#[test]
+fn gui_notes_the_default_prefix() {
+    let project = gui();
+    assert!(
+        project
+            .output
+            .contains("note: Xbps uses /opt/hello-gui for [out].prefix"),
+        "{}",
+        project.output
+    );
+
+    let report = project.report("hello-gui");
+    let notices = report["notices"].as_array().unwrap();
+    assert!(
+        notices
+            .iter()
+            .any(|notice| notice["target"] == "Xbps" && notice["setting"] == "[out].prefix"),
+        "{notices:?}"
+    );
+}